@@ -0,0 +1,153 @@
+//! Benchmarks for the interpreter loop.
+//!
+//! Run with `cargo bench`, optionally naming the benchmarks to run:
+//!
+//! ```text
+//! cargo bench --bench interpreter -- call_ret
+//! cargo bench --bench interpreter -- --save-baseline before
+//! cargo bench --bench interpreter -- --baseline before
+//! ```
+//!
+//! Each benchmark is timed over several samples after a warm-up run, and the
+//! median is reported with its instruction rate. Baselines are kept as JSON in
+//! `target/bench-baselines/`.
+
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{encode_image, HaltReason, VM};
+
+const CHALLENGE: &[u8] = include_bytes!("../input/challenge.bin");
+
+/// Samples taken per benchmark, after one warm-up run.
+const SAMPLES: usize = 10;
+
+struct Benchmark {
+    name: &'static str,
+    image: Vec<u8>,
+}
+
+fn benchmarks() -> Vec<Benchmark> {
+    let program = |source: &str| encode_image(&assemble(source).expect("benchmark programs should assemble"));
+    vec![
+        // The challenge binary's self-test and decryption, up to its first `in`.
+        Benchmark { name: "self_test", image: CHALLENGE.to_vec() },
+        // A million iterations of a loop of arithmetic on registers.
+        Benchmark {
+            name: "arithmetic",
+            image: program(
+                "        set r0 1000
+                 outer:  set r1 1000
+                 inner:  add r2 r2 r1
+                         mult r3 r2 31
+                         mod r4 r3 1009
+                         and r5 r4 r2
+                         or r6 r5 r3
+                         not r7 r6
+                         add r1 r1 32767
+                         jt r1 inner
+                         add r0 r0 32767
+                         jt r0 outer
+                         halt",
+            ),
+        },
+        // A million calls to a small function that pushes and pops.
+        Benchmark {
+            name: "call_ret",
+            image: program(
+                "        set r0 1000
+                 outer:  set r1 1000
+                 inner:  call leaf
+                         add r1 r1 32767
+                         jt r1 inner
+                         add r0 r0 32767
+                         jt r0 outer
+                         halt
+                 leaf:   push r1
+                         add r2 r2 1
+                         pop r1
+                         ret",
+            ),
+        },
+    ]
+}
+
+/// Runs `image` to completion (or its first `in`), returning the number of
+/// instructions executed.
+fn run(image: &[u8]) -> u64 {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(image).expect("benchmark images should load");
+    match vm.run() {
+        Ok(HaltReason::Halted(_)) | Err(_) => (),
+        Ok(reason) => panic!("benchmark stopped early: {reason:?}"),
+    }
+    black_box(vm.steps())
+}
+
+fn measure(image: &[u8]) -> (Duration, u64) {
+    let steps = run(image);
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            run(image);
+            start.elapsed()
+        })
+        .collect();
+    samples.sort();
+    (samples[SAMPLES / 2], steps)
+}
+
+fn baseline_path(name: &str) -> PathBuf {
+    let target = env::var_os("CARGO_TARGET_DIR").map_or_else(|| PathBuf::from("target"), PathBuf::from);
+    target.join("bench-baselines").join(format!("{name}.json"))
+}
+
+fn main() {
+    let mut filters = Vec::new();
+    let mut save = None;
+    let mut compare = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--save-baseline" => save = args.next(),
+            "--baseline" => compare = args.next(),
+            // Flags cargo passes to every bench target, such as `--bench`.
+            flag if flag.starts_with("--") => (),
+            _ => filters.push(arg),
+        }
+    }
+    let baseline: BTreeMap<String, f64> = compare.map_or_else(BTreeMap::new, |name| {
+        let path = baseline_path(&name);
+        let text = fs::read_to_string(&path).unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+        serde_json::from_str(&text).expect("baselines should be valid JSON")
+    });
+    let mut results = BTreeMap::new();
+    for benchmark in benchmarks() {
+        if !filters.is_empty() && !filters.iter().any(|filter| benchmark.name.contains(filter.as_str())) {
+            continue;
+        }
+        let (median, steps) = measure(&benchmark.image);
+        let seconds = median.as_secs_f64();
+        let mut line = format!(
+            "{:<12} {:>10.3} ms  {:>8.1} M instructions/s  ({steps} instructions)",
+            benchmark.name, seconds * 1e3, steps as f64 / seconds / 1e6,
+        );
+        if let Some(before) = baseline.get(benchmark.name) {
+            line.push_str(&format!("  {:+.1}% vs baseline", (seconds / before - 1.0) * 100.0));
+        }
+        println!("{line}");
+        results.insert(benchmark.name.to_string(), seconds);
+    }
+    if let Some(name) = save {
+        let path = baseline_path(&name);
+        fs::create_dir_all(path.parent().expect("baseline paths should have a parent"))
+            .and_then(|()| fs::write(&path, serde_json::to_string_pretty(&results).expect("results should serialize")))
+            .unwrap_or_else(|err| panic!("cannot write {}: {err}", path.display()));
+        println!("saved baseline to {}", path.display());
+    }
+}
@@ -0,0 +1,21 @@
+//! Feeds arbitrary bytes to the image loader and the instruction decoder.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oscon_2012_vm_challenge::disasm::disassemble;
+use oscon_2012_vm_challenge::vm::{decode_image, Operation};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(words) = decode_image(data) else {
+        return;
+    };
+    for start in 0..words.len() {
+        if let Some(operation) = Operation::decode(&words[start..]) {
+            assert_eq!(operation.encode(), words[start..start + operation.size() as usize]);
+        }
+    }
+    for line in disassemble(&words) {
+        let _ = line.to_string();
+    }
+});
@@ -0,0 +1,23 @@
+//! Loads arbitrary bytes as a program and runs it for a bounded number of
+//! steps. Faults are fine; panics are bugs.
+
+#![no_main]
+
+use std::io::{self, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use oscon_2012_vm_challenge::vm::VM;
+
+/// Enough to get through loops of a few thousand iterations while keeping
+/// each run short.
+const MAX_STEPS: u64 = 100_000;
+
+fuzz_target!(|data: &[u8]| {
+    // The first bytes of the input become the program's input, so `in` has
+    // something to read.
+    let (input, image) = data.split_at(data.len().min(16));
+    let mut vm = VM::new(Cursor::new(input.to_vec()), io::sink());
+    if vm.load(image).is_ok() {
+        let _ = vm.run_for(MAX_STEPS);
+    }
+});
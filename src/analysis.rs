@@ -0,0 +1,12 @@
+//! Static analyses over memory images.
+
+pub mod callgraph;
+pub mod cfg;
+pub mod deadcode;
+pub mod decompile;
+pub mod functions;
+pub mod loops;
+pub mod strings;
+pub mod teleporter;
+pub mod validate;
+pub mod xrefs;
@@ -0,0 +1,100 @@
+//! Call graphs combining static `call` targets with calls observed at runtime.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::cfg::Cfg;
+use crate::symbols::Symbols;
+use crate::vm::ObservedCall;
+
+/// What is known about calls from one function to another.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CallEdge {
+    /// Whether a literal `call` between the two appears in the static CFG.
+    #[serde(rename = "static")]
+    pub is_static: bool,
+    /// How many times the call was executed while logging.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    pub functions: BTreeSet<u16>,
+    /// Edges keyed by (caller, callee) function entry.
+    pub edges: BTreeMap<(u16, u16), CallEdge>,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    caller: u16,
+    callee: u16,
+    #[serde(flatten)]
+    edge: &'a CallEdge,
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    functions: &'a BTreeSet<u16>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+impl CallGraph {
+    /// The calls between the functions of `cfg`.
+    pub fn from_cfg(cfg: &Cfg) -> Self {
+        let mut graph = CallGraph { functions: cfg.entries.clone(), ..Default::default() };
+        for &entry in &cfg.entries {
+            for block in cfg.function(entry).blocks.values() {
+                for &callee in &block.calls {
+                    graph.edges.entry((entry, callee)).or_default().is_static = true;
+                }
+            }
+        }
+        graph
+    }
+
+    /// Adds calls observed at runtime, such as those from [`VM::call_log`].
+    ///
+    /// [`VM::call_log`]: crate::vm::VM::call_log
+    pub fn add_observed(&mut self, calls: &BTreeMap<ObservedCall, u64>) {
+        for (call, &count) in calls {
+            self.functions.insert(call.caller);
+            self.functions.insert(call.target);
+            self.edges.entry((call.caller, call.target)).or_default().count += count;
+        }
+    }
+
+    /// Writes the graph in Graphviz DOT format, labelling functions with
+    /// their names in `symbols`. Edges that were only found statically are
+    /// dashed; executed edges are labelled with their count.
+    pub fn write_dot(&self, symbols: &Symbols, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "digraph calls {{")?;
+        writeln!(out, "  node [shape=box fontname=monospace];")?;
+        for function in &self.functions {
+            writeln!(out, "  f{function} [label=\"{}\"];", symbols.describe(*function))?;
+        }
+        for (&(caller, callee), edge) in &self.edges {
+            let attributes = match (edge.is_static, edge.count) {
+                (_, 0) => " [style=dashed color=gray]".to_string(),
+                (true, count) => format!(" [label={count}]"),
+                (false, count) => format!(" [label={count} color=blue]"),
+            };
+            writeln!(out, "  f{caller} -> f{callee}{attributes};")?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Writes the graph as JSON: `{"functions": [...], "edges": [{"caller",
+    /// "callee", "static", "count"}, ...]}`.
+    pub fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        let graph = JsonGraph {
+            functions: &self.functions,
+            edges: self.edges.iter()
+                .map(|(&(caller, callee), edge)| JsonEdge { caller, callee, edge })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(&mut *out, &graph)?;
+        writeln!(out)
+    }
+}
@@ -0,0 +1,203 @@
+//! Basic blocks and control-flow graphs.
+//!
+//! Blocks are discovered by recursive traversal from a set of entry points,
+//! so data regions that are never reached are not decoded. Only literal jump
+//! and call targets are followed; register-indirect transfers end a block
+//! without successors.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::symbols::Symbols;
+use crate::vm::Operation;
+
+/// How control reaches a successor block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// An unconditional `jmp`.
+    Jump,
+    /// The taken side of `jt`/`jf`.
+    Taken,
+    /// The not-taken side of `jt`/`jf`, or falling into the next block.
+    Fallthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// A straight-line run of instructions with a single entry at `start`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// The address just past the last instruction.
+    pub end: u16,
+    pub instructions: Vec<(u16, Operation)>,
+    pub successors: Vec<Edge>,
+    /// Literal targets of `call`s made from this block.
+    pub calls: Vec<u16>,
+}
+
+impl BasicBlock {
+    pub fn last(&self) -> Option<&(u16, Operation)> {
+        self.instructions.last()
+    }
+}
+
+/// A control-flow graph: basic blocks keyed by start address, plus the
+/// function entry points they were discovered from.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub entries: BTreeSet<u16>,
+}
+
+fn decode(words: &[u16], address: u16) -> Option<Operation> {
+    Operation::decode(words.get(address as usize..)?)
+}
+
+/// The literal control-flow targets of `operation` at `address`, excluding
+/// calls, and whether execution can continue with the next instruction.
+fn flow(operation: &Operation, address: u16) -> (Vec<Edge>, bool) {
+    let next = address.wrapping_add(operation.size());
+    let literal = |target: u16| (target < 32_768).then_some(target);
+    match *operation {
+        Operation::Jmp(target) => (
+            literal(target).map(|target| Edge { target, kind: EdgeKind::Jump }).into_iter().collect(),
+            false,
+        ),
+        Operation::Jt(_, target) | Operation::Jf(_, target) => {
+            let mut edges: Vec<Edge> = literal(target)
+                .map(|target| Edge { target, kind: EdgeKind::Taken })
+                .into_iter()
+                .collect();
+            edges.push(Edge { target: next, kind: EdgeKind::Fallthrough });
+            (edges, false)
+        },
+        Operation::Halt | Operation::Ret => (vec![], false),
+        _ => (vec![], true),
+    }
+}
+
+impl Cfg {
+    /// Builds the CFG reachable from `entries`, treating every literal `call`
+    /// target as another entry.
+    pub fn build(words: &[u16], entries: &[u16]) -> Self {
+        let mut leaders: BTreeSet<u16> = entries.iter().copied().collect();
+        let mut functions: BTreeSet<u16> = leaders.clone();
+        let mut visited = BTreeSet::new();
+        let mut worklist: Vec<u16> = entries.to_vec();
+        while let Some(mut address) = worklist.pop() {
+            while visited.insert(address) {
+                let Some(operation) = decode(words, address) else {
+                    break;
+                };
+                if let Operation::Call(target) = operation {
+                    if target < 32_768 && functions.insert(target) {
+                        leaders.insert(target);
+                        worklist.push(target);
+                    }
+                }
+                let (edges, continues) = flow(&operation, address);
+                for edge in &edges {
+                    leaders.insert(edge.target);
+                    worklist.push(edge.target);
+                }
+                if !continues {
+                    break;
+                }
+                address = address.wrapping_add(operation.size());
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut block = BasicBlock {
+                start,
+                end: start,
+                instructions: vec![],
+                successors: vec![],
+                calls: vec![],
+            };
+            let mut address = start;
+            while let Some(operation) = decode(words, address) {
+                block.instructions.push((address, operation));
+                if let Operation::Call(target) = operation {
+                    if target < 32_768 {
+                        block.calls.push(target);
+                    }
+                }
+                let (edges, continues) = flow(&operation, address);
+                address = address.wrapping_add(operation.size());
+                block.successors = edges;
+                if !continues {
+                    break;
+                }
+                if leaders.contains(&address) {
+                    block.successors.push(Edge { target: address, kind: EdgeKind::Fallthrough });
+                    break;
+                }
+            }
+            block.end = address;
+            blocks.insert(start, block);
+        }
+        Cfg { blocks, entries: functions }
+    }
+
+    /// The blocks reachable from `entry` without following calls.
+    pub fn function(&self, entry: u16) -> Self {
+        let mut blocks = BTreeMap::new();
+        let mut worklist = vec![entry];
+        while let Some(start) = worklist.pop() {
+            if blocks.contains_key(&start) {
+                continue;
+            }
+            if let Some(block) = self.blocks.get(&start) {
+                worklist.extend(block.successors.iter().map(|edge| edge.target));
+                blocks.insert(start, block.clone());
+            }
+        }
+        Cfg { blocks, entries: BTreeSet::from([entry]) }
+    }
+
+    /// Writes the graph in Graphviz DOT format, labelling blocks and targets
+    /// with their names in `symbols`. Call edges are drawn dashed when both
+    /// ends are in the graph.
+    pub fn write_dot(&self, symbols: &Symbols, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "  node [shape=box fontname=monospace];")?;
+        for block in self.blocks.values() {
+            let mut label = symbols.name(block.start).map_or(String::new(), |name| format!("{name}:\\l"));
+            for (address, operation) in &block.instructions {
+                match symbols.target(operation) {
+                    Some(name) => label.push_str(&format!("{address:5}: {operation} ; {name}\\l")),
+                    None => label.push_str(&format!("{address:5}: {operation}\\l")),
+                }
+            }
+            let style = if self.entries.contains(&block.start) { " style=bold" } else { "" };
+            writeln!(out, "  b{} [label=\"{}\"{}];", block.start, label.replace('"', "\\\""), style)?;
+        }
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                if !self.blocks.contains_key(&edge.target) {
+                    continue;
+                }
+                let attributes = match edge.kind {
+                    EdgeKind::Jump => "",
+                    EdgeKind::Taken => " [label=T color=darkgreen]",
+                    EdgeKind::Fallthrough if block.successors.len() > 1 => " [label=F color=red]",
+                    EdgeKind::Fallthrough => "",
+                };
+                writeln!(out, "  b{} -> b{}{};", block.start, edge.target, attributes)?;
+            }
+            for target in &block.calls {
+                if self.blocks.contains_key(target) {
+                    writeln!(out, "  b{} -> b{} [style=dashed];", block.start, target)?;
+                }
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,152 @@
+//! Code that never executed and data that was never read, from static
+//! reachability combined with the coverage of a run.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use super::functions::Functions;
+use crate::disasm::{self, Line};
+use crate::vm::{Coverage, Operation};
+
+/// What a word of memory was used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    /// Part of an instruction that executed.
+    Executed,
+    /// Part of an instruction reachable from the entry points that never
+    /// executed.
+    Unexecuted,
+    /// Outside reachable code, and read by `rmem`.
+    Read,
+    /// Outside reachable code, and never executed or read: unused data, or
+    /// code only reached in ways the analysis cannot see, such as code that
+    /// is still encrypted.
+    Unused,
+}
+
+impl Usage {
+    /// The mark for the usage in an annotated disassembly.
+    pub fn marker(self) -> char {
+        match self {
+            Usage::Executed => '+',
+            Usage::Unexecuted => '-',
+            Usage::Read => 'r',
+            Usage::Unused => '.',
+        }
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Usage::Executed => "executed",
+            Usage::Unexecuted => "reachable code never executed",
+            Usage::Read => "data read",
+            Usage::Unused => "never executed or read",
+        })
+    }
+}
+
+/// A run of adjacent words with the same usage; `end` is inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16,
+    pub usage: Usage,
+}
+
+impl Region {
+    /// The number of words in the region.
+    pub fn words(&self) -> usize {
+        (self.end - self.start) as usize + 1
+    }
+}
+
+/// The usage of every word of a memory image.
+#[derive(Debug, Clone)]
+pub struct DeadCode {
+    words: Vec<u16>,
+    usage: Vec<Usage>,
+}
+
+impl DeadCode {
+    /// Classifies each of `words`: code in `functions` or that `coverage`
+    /// saw execute, and data that `coverage` saw read.
+    pub fn analyze(words: &[u16], functions: &Functions, coverage: &Coverage) -> Self {
+        let mut usage = vec![Usage::Unused; words.len()];
+        let mut mark = |address: u16, size: u16, class: Usage| {
+            let end = (address as usize + size as usize).min(words.len());
+            for word in usage.get_mut(address as usize..end).into_iter().flatten() {
+                // Execution wins over reachability, which wins over reads.
+                if *word != Usage::Executed && (class == Usage::Executed || *word != Usage::Unexecuted) {
+                    *word = class;
+                }
+            }
+        };
+        for (address, operation) in functions.cfg.blocks.values().flat_map(|block| block.instructions.iter()) {
+            mark(*address, operation.size(), Usage::Unexecuted);
+        }
+        for address in coverage.addresses().take_while(|&address| (address as usize) < words.len()) {
+            let size = Operation::decode(&words[address as usize..]).map_or(1, |operation| operation.size());
+            mark(address, size, Usage::Executed);
+        }
+        for address in 0..words.len() as u16 {
+            if coverage.is_read(address) {
+                mark(address, 1, Usage::Read);
+            }
+        }
+        DeadCode { words: words.to_vec(), usage }
+    }
+
+    /// The usage of the word at `address`.
+    pub fn usage(&self, address: u16) -> Option<Usage> {
+        self.usage.get(address as usize).copied()
+    }
+
+    /// The runs of words with the same usage, in address order.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (address, &usage) in self.usage.iter().enumerate() {
+            let address = address as u16;
+            match regions.last_mut() {
+                Some(region) if region.usage == usage => region.end = address,
+                _ => regions.push(Region { start: address, end: address, usage }),
+            }
+        }
+        regions
+    }
+
+    /// Writes the number of words of each usage, the regions of code that
+    /// never executed and of memory never used, then the image disassembled
+    /// with each line marked with its usage.
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        let regions = self.regions();
+        writeln!(out, "words loaded: {}", self.words.len())?;
+        for usage in [Usage::Executed, Usage::Unexecuted, Usage::Read, Usage::Unused] {
+            let count = self.usage.iter().filter(|&&other| other == usage).count();
+            writeln!(out, "  {} {usage:<30} {count:>6}", usage.marker())?;
+        }
+        for usage in [Usage::Unexecuted, Usage::Unused] {
+            writeln!(out)?;
+            writeln!(out, "{usage}:")?;
+            for region in regions.iter().filter(|region| region.usage == usage) {
+                writeln!(out, "  {:5}..={:<5} {:>6} words", region.start, region.end, region.words())?;
+            }
+        }
+        writeln!(out)?;
+        writeln!(out, "annotated disassembly (+ executed, - never executed, r read, . unused):")?;
+        let mut address = 0;
+        while let Some(mut line) = disasm::disassemble_at(&self.words, address) {
+            // Don't let a line straddle a change of usage, so data next to
+            // code shows as data and the sweep stays aligned with real code.
+            let usage = self.usage[address as usize];
+            let len = line.words.len() as u16;
+            if (1..len).any(|k| self.usage(address + k) != Some(usage)) || usage == Usage::Read {
+                line = Line { address, words: vec![line.words[0]], operation: None };
+            }
+            writeln!(out, "{} {line}", usage.marker())?;
+            address += line.words.len() as u16;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+//! Lifts functions to rough C-like pseudo-code.
+//!
+//! Each instruction becomes one statement. Back edges that nest properly are
+//! turned into `do { } while (cond);` or `loop { }` blocks; every other
+//! transfer stays a `goto`. The header of each natural loop gets a comment
+//! with its back edges and nesting depth, whether or not the loop could be
+//! structured. Arithmetic in the output is modulo 32768, as on
+//! the VM.
+
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use super::cfg::{BasicBlock, Cfg, EdgeKind};
+use crate::vm::{format_operand, Operation};
+
+/// A back edge from the block at `latch` to the block at `header`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Loop {
+    header: u16,
+    latch: u16,
+    conditional: bool,
+}
+
+impl Loop {
+    fn contains(&self, other: &Loop) -> bool {
+        self.header <= other.header && other.latch <= self.latch
+    }
+
+    fn disjoint(&self, other: &Loop) -> bool {
+        self.latch < other.header || other.latch < self.header
+    }
+}
+
+/// The back edges of `cfg` that can be printed as structured loops, outermost
+/// first.
+fn find_loops(cfg: &Cfg) -> Vec<Loop> {
+    let mut candidates: Vec<Loop> = cfg
+        .blocks
+        .values()
+        .flat_map(|block| {
+            block.successors.iter().filter_map(move |edge| {
+                let back = edge.kind != EdgeKind::Fallthrough
+                    && edge.target <= block.start
+                    && cfg.blocks.contains_key(&edge.target);
+                back.then_some(Loop {
+                    header: edge.target,
+                    latch: block.start,
+                    conditional: edge.kind == EdgeKind::Taken,
+                })
+            })
+        })
+        .collect();
+    candidates.sort_by_key(|l| (l.header, std::cmp::Reverse(l.latch)));
+    let mut loops: Vec<Loop> = Vec::new();
+    for candidate in candidates {
+        if loops.iter().all(|l| l.contains(&candidate) || l.disjoint(&candidate)) {
+            loops.push(candidate);
+        }
+    }
+    loops
+}
+
+fn label(address: u16) -> String {
+    format!("L_{address}")
+}
+
+fn value(operand: u16) -> String {
+    format_operand(operand)
+}
+
+fn call_target(operand: u16) -> String {
+    if operand < 32_768 {
+        format!("fn_{operand}()")
+    } else {
+        format!("(*{})()", format_operand(operand))
+    }
+}
+
+fn condition(operation: &Operation) -> Option<String> {
+    match *operation {
+        Operation::Jt(a, _) => Some(value(a)),
+        Operation::Jf(a, _) => Some(format!("!{}", value(a))),
+        _ => None,
+    }
+}
+
+/// The statement for every instruction except control transfers, which the
+/// caller prints.
+fn statement(operation: &Operation) -> Option<String> {
+    let binary = |a: u16, b: u16, op: &str, c: u16| {
+        format!("{} = {} {op} {};", value(a), value(b), value(c))
+    };
+    Some(match *operation {
+        Operation::Halt => "halt();".to_string(),
+        Operation::Set(a, b) => format!("{} = {};", value(a), value(b)),
+        Operation::Push(a) => format!("push({});", value(a)),
+        Operation::Pop(a) => format!("{} = pop();", value(a)),
+        Operation::Eq(a, b, c) => binary(a, b, "==", c),
+        Operation::Gt(a, b, c) => binary(a, b, ">", c),
+        // Adding a large literal is how the programs subtract.
+        Operation::Add(a, b, c) if (16_384..32_768).contains(&c) => {
+            format!("{} = {} - {};", value(a), value(b), 32_768 - c)
+        }
+        Operation::Add(a, b, c) => binary(a, b, "+", c),
+        Operation::Mult(a, b, c) => binary(a, b, "*", c),
+        Operation::Mod(a, b, c) => binary(a, b, "%", c),
+        Operation::And(a, b, c) => binary(a, b, "&", c),
+        Operation::Or(a, b, c) => binary(a, b, "|", c),
+        Operation::Not(a, b) => format!("{} = ~{};", value(a), value(b)),
+        Operation::Rmem(a, b) => format!("{} = mem[{}];", value(a), value(b)),
+        Operation::Wmem(a, b) => format!("mem[{}] = {};", value(a), value(b)),
+        Operation::Call(a) => format!("{};", call_target(a)),
+        Operation::Ret => "return;".to_string(),
+        Operation::Out(a) => format!("putchar({});", value(a)),
+        Operation::In(a) => format!("{} = getchar();", value(a)),
+        Operation::Noop | Operation::Jmp(_) | Operation::Jt(..) | Operation::Jf(..) => {
+            return None
+        }
+    })
+}
+
+/// Writes pseudo-code for the function starting at `entry`, following only
+/// the blocks reachable from it without calls.
+pub fn decompile(cfg: &Cfg, entry: u16, out: &mut dyn Write) -> io::Result<()> {
+    let function = cfg.function(entry);
+    let loops = find_loops(&function);
+    let natural = super::loops::find_loops(cfg, entry);
+    let blocks: Vec<&BasicBlock> = function.blocks.values().collect();
+
+    // Only blocks targeted by a printed goto need a label.
+    let mut targets = BTreeSet::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let next = blocks.get(i + 1).map(|b| b.start);
+        for edge in &block.successors {
+            let closes_loop = edge.kind != EdgeKind::Fallthrough
+                && loops.iter().any(|l| l.latch == block.start && l.header == edge.target);
+            let falls_into_next = edge.kind != EdgeKind::Taken && Some(edge.target) == next;
+            if !closes_loop && !falls_into_next {
+                targets.insert(edge.target);
+            }
+        }
+    }
+
+    writeln!(out, "fn_{entry}() {{")?;
+    let mut depth = 1;
+    for (i, block) in blocks.iter().enumerate() {
+        if targets.contains(&block.start) {
+            writeln!(out, "{:width$}{}:", "", label(block.start), width = 4 * (depth - 1))?;
+        }
+        for l in natural.iter().filter(|l| l.header == block.start) {
+            writeln!(out, "{:width$}// {l}", "", width = 4 * depth)?;
+        }
+        for l in loops.iter().filter(|l| l.header == block.start) {
+            let keyword = if l.conditional { "do" } else { "loop" };
+            writeln!(out, "{:width$}{keyword} {{", "", width = 4 * depth)?;
+            depth += 1;
+        }
+        let indent = 4 * depth;
+        // Runs of literal `out`s are printed as one string.
+        let mut text = String::new();
+        for (_, operation) in &block.instructions {
+            if let Operation::Out(a) = *operation {
+                if a < 128 {
+                    text.push(a as u8 as char);
+                    continue;
+                }
+            }
+            if !text.is_empty() {
+                writeln!(out, "{:indent$}print({:?});", "", std::mem::take(&mut text))?;
+            }
+            if let Some(statement) = statement(operation) {
+                writeln!(out, "{:indent$}{statement}", "")?;
+            }
+        }
+        if !text.is_empty() {
+            writeln!(out, "{:indent$}print({text:?});", "")?;
+        }
+
+        let closing: Vec<&Loop> = loops.iter().filter(|l| l.latch == block.start).collect();
+        let last = block.last().map(|&(_, operation)| operation);
+        let next = blocks.get(i + 1).map(|b| b.start);
+        for edge in &block.successors {
+            let cond = last.as_ref().and_then(condition);
+            match edge.kind {
+                EdgeKind::Taken if closing.iter().any(|l| l.header == edge.target) => (),
+                EdgeKind::Jump if closing.iter().any(|l| l.header == edge.target) => (),
+                EdgeKind::Taken => {
+                    let cond = cond.unwrap_or_default();
+                    writeln!(out, "{:indent$}if ({cond}) goto {};", "", label(edge.target))?;
+                }
+                EdgeKind::Jump | EdgeKind::Fallthrough if Some(edge.target) == next => (),
+                EdgeKind::Jump | EdgeKind::Fallthrough => {
+                    writeln!(out, "{:indent$}goto {};", "", label(edge.target))?
+                }
+            }
+        }
+        if let Some(Operation::Jmp(target)) = last {
+            if target >= 32_768 {
+                writeln!(out, "{:indent$}goto *{};", "", value(target))?;
+            }
+        }
+        for l in closing.iter().rev() {
+            depth -= 1;
+            let indent = 4 * depth;
+            if l.conditional {
+                let cond = last.as_ref().and_then(condition).unwrap_or_default();
+                writeln!(out, "{:indent$}}} while ({cond});", "")?;
+            } else {
+                writeln!(out, "{:indent$}}}", "")?;
+            }
+        }
+    }
+    writeln!(out, "}}")
+}
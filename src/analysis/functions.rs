@@ -0,0 +1,214 @@
+//! Function boundaries: where functions start and which blocks they span.
+//!
+//! Entries are the given entry points, literal `call` targets, and code
+//! directly after a block that cannot fall through (usually a `ret`) that
+//! nothing else reaches, which is how functions only called through a
+//! register show up. Such a candidate is only kept if the code from it
+//! decodes cleanly and returns.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::cfg::{BasicBlock, Cfg};
+use crate::symbols::Symbols;
+use crate::vm::Operation;
+
+/// How a function entry was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// One of the entry points the analysis started from.
+    Entry,
+    /// The literal target of a `call`.
+    Call,
+    /// Code just after a block that returns, halts, or jumps away.
+    AfterReturn,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Origin::Entry => "entry",
+            Origin::Call => "call",
+            Origin::AfterReturn => "after-ret",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub entry: u16,
+    pub origin: Origin,
+    /// The start addresses of the blocks reachable from `entry` without
+    /// following calls.
+    pub blocks: Vec<u16>,
+    /// The lowest address in the function's blocks, and the address just past
+    /// the highest. Other code may lie in between.
+    pub start: u16,
+    pub end: u16,
+    pub instructions: usize,
+    /// Literal targets of the function's `call`s.
+    pub calls: BTreeSet<u16>,
+    /// Whether any path reaches a `ret`.
+    pub returns: bool,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:5}: {:<9} {:5}-{:<5} {:3} blocks {:4} instructions",
+            self.entry,
+            self.origin,
+            self.start,
+            self.end.saturating_sub(1),
+            self.blocks.len(),
+            self.instructions,
+        )?;
+        if !self.returns {
+            write!(f, ", never returns")?;
+        }
+        Ok(())
+    }
+}
+
+/// The functions found in a memory image, with the CFG they were found in.
+#[derive(Debug, Clone, Default)]
+pub struct Functions {
+    pub cfg: Cfg,
+    functions: BTreeMap<u16, Function>,
+}
+
+/// Whether the blocks reachable from `entry` all decode and some path
+/// returns.
+fn plausible(cfg: &Cfg, entry: u16) -> bool {
+    let function = cfg.function(entry);
+    let mut returns = false;
+    for block in function.blocks.values() {
+        match block.last() {
+            None => return false,
+            Some((_, Operation::Ret)) => returns = true,
+            Some((_, Operation::Halt | Operation::Jmp(_))) => (),
+            // Only a word that does not decode ends a block with no way on.
+            Some(_) if block.successors.is_empty() => return false,
+            Some(_) => (),
+        }
+    }
+    returns
+}
+
+/// The address just past `block` if execution cannot continue there.
+fn after_end(block: &BasicBlock) -> Option<u16> {
+    match block.last()?.1 {
+        Operation::Ret | Operation::Halt | Operation::Jmp(_) => Some(block.end),
+        _ => None,
+    }
+}
+
+impl Functions {
+    /// Finds the functions reachable from `entries` in `words`.
+    pub fn detect(words: &[u16], entries: &[u16]) -> Self {
+        let mut origins: BTreeMap<u16, Origin> = entries.iter().map(|&entry| (entry, Origin::Entry)).collect();
+        let mut rejected = BTreeSet::new();
+        let mut cfg = Cfg::build(words, entries);
+        loop {
+            let covered = |address: u16| {
+                cfg.blocks.range(..=address).next_back().is_some_and(|(_, block)| address < block.end)
+            };
+            let candidates: Vec<u16> = cfg.blocks.values()
+                .filter_map(after_end)
+                .filter(|&address| !covered(address) && !rejected.contains(&address))
+                .collect();
+            let mut found = false;
+            for candidate in candidates {
+                let mut entries: Vec<u16> = origins.keys().copied().collect();
+                entries.push(candidate);
+                let trial = Cfg::build(words, &entries);
+                if plausible(&trial, candidate) {
+                    origins.insert(candidate, Origin::AfterReturn);
+                    cfg = trial;
+                    found = true;
+                    break;
+                }
+                rejected.insert(candidate);
+            }
+            if !found {
+                break;
+            }
+        }
+        let functions = cfg.entries.iter()
+            .map(|&entry| {
+                let origin = origins.get(&entry).copied().unwrap_or(Origin::Call);
+                (entry, Self::function(&cfg, entry, origin))
+            })
+            .collect();
+        Functions { cfg, functions }
+    }
+
+    /// Like [`detect`](Self::detect), also covering the code at `address`,
+    /// such as a saved state's instruction pointer. It only becomes an entry
+    /// if no function found from `entries` contains it.
+    pub fn detect_including(words: &[u16], entries: &[u16], address: u16) -> Self {
+        let functions = Self::detect(words, entries);
+        if functions.containing(address).is_some() {
+            return functions;
+        }
+        Self::detect(words, &[entries, &[address]].concat())
+    }
+
+    fn function(cfg: &Cfg, entry: u16, origin: Origin) -> Function {
+        let blocks = cfg.function(entry).blocks;
+        Function {
+            entry,
+            origin,
+            start: blocks.keys().next().copied().unwrap_or(entry),
+            end: blocks.values().map(|block| block.end).max().unwrap_or(entry),
+            instructions: blocks.values().map(|block| block.instructions.len()).sum(),
+            calls: blocks.values().flat_map(|block| block.calls.iter().copied()).collect(),
+            returns: blocks.values().any(|block| matches!(block.last(), Some((_, Operation::Ret)))),
+            blocks: blocks.into_keys().collect(),
+        }
+    }
+
+    /// The function starting at `entry`.
+    pub fn get(&self, entry: u16) -> Option<&Function> {
+        self.functions.get(&entry)
+    }
+
+    /// The functions in order of their entry points.
+    pub fn iter(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// The function with a block containing `address`; where blocks are
+    /// shared, the one with the closest entry at or before it.
+    pub fn containing(&self, address: u16) -> Option<&Function> {
+        let (&start, block) = self.cfg.blocks.range(..=address).next_back()?;
+        if address >= block.end {
+            return None;
+        }
+        self.functions.values()
+            .filter(|function| function.blocks.binary_search(&start).is_ok())
+            .min_by_key(|function| address.wrapping_sub(function.entry))
+    }
+
+    /// `symbols` with `fn_ENTRY` names added for the functions it leaves
+    /// unnamed.
+    pub fn name_in(&self, symbols: &Symbols) -> Symbols {
+        let mut named = symbols.clone();
+        for entry in self.functions.keys() {
+            let name = format!("fn_{entry}");
+            if named.name(*entry).is_none() && named.address(&name).is_none() {
+                named.insert(*entry, &name).expect("Generated names should be valid.");
+            }
+        }
+        named
+    }
+}
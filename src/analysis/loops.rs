@@ -0,0 +1,172 @@
+//! Natural loops: a header block that dominates every block of the loop,
+//! and the back edges that return to it.
+//!
+//! A block dominates another if every path from the function's entry to the
+//! other passes through it. An edge whose target dominates its source is a
+//! back edge, and the loop it closes is the target plus every block that can
+//! reach the source without passing through the target.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::cfg::{Cfg, EdgeKind};
+use super::functions::Functions;
+use crate::vm::{Operation, Profile};
+
+/// A natural loop, merging every back edge to the same header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The entry of the function the loop was found in.
+    pub function: u16,
+    pub header: u16,
+    /// The addresses of the instructions that jump or fall back to the
+    /// header.
+    pub back_edges: Vec<u16>,
+    /// The start addresses of the blocks in the loop, including the header.
+    pub blocks: BTreeSet<u16>,
+    /// How many other loops enclose this one; 0 for an outermost loop.
+    pub depth: usize,
+}
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let back_edges: Vec<String> = self.back_edges.iter().map(u16::to_string).collect();
+        write!(
+            f,
+            "loop at {}: depth {}, {} blocks, back edges from {}",
+            self.header,
+            self.depth,
+            self.blocks.len(),
+            back_edges.join(" "),
+        )
+    }
+}
+
+/// How often a loop ran while profiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TripCount {
+    /// How many times the header executed: one per iteration.
+    pub iterations: u64,
+    /// How many times control entered the loop from outside.
+    pub entries: u64,
+}
+
+impl TripCount {
+    /// The average number of iterations each time the loop was entered.
+    pub fn average(&self) -> f64 {
+        self.iterations as f64 / self.entries.max(1) as f64
+    }
+}
+
+/// The dominators of each block reachable from `entry` in `cfg`.
+fn dominators(cfg: &Cfg, entry: u16) -> BTreeMap<u16, BTreeSet<u16>> {
+    let blocks: BTreeSet<u16> = cfg.blocks.keys().copied().collect();
+    let mut predecessors: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for block in cfg.blocks.values() {
+        for edge in &block.successors {
+            predecessors.entry(edge.target).or_default().push(block.start);
+        }
+    }
+    let mut dominators: BTreeMap<u16, BTreeSet<u16>> = blocks.iter()
+        .map(|&block| (block, if block == entry { BTreeSet::from([entry]) } else { blocks.clone() }))
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in blocks.iter().filter(|&&block| block != entry) {
+            let mut common: Option<BTreeSet<u16>> = None;
+            for predecessor in predecessors.get(&block).into_iter().flatten() {
+                let theirs = &dominators[predecessor];
+                common = Some(match common {
+                    Some(common) => common.intersection(theirs).copied().collect(),
+                    None => theirs.clone(),
+                });
+            }
+            let mut new = common.unwrap_or_default();
+            new.insert(block);
+            if new != dominators[&block] {
+                dominators.insert(block, new);
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+/// The natural loops of the function starting at `entry`, in order of their
+/// headers, with depths counted within the function.
+pub fn find_loops(cfg: &Cfg, entry: u16) -> Vec<Loop> {
+    let function = cfg.function(entry);
+    let dominators = dominators(&function, entry);
+    let mut predecessors: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for block in function.blocks.values() {
+        for edge in &block.successors {
+            predecessors.entry(edge.target).or_default().push(block.start);
+        }
+    }
+    let mut loops: BTreeMap<u16, Loop> = BTreeMap::new();
+    for block in function.blocks.values() {
+        for edge in &block.successors {
+            if !dominators.get(&block.start).is_some_and(|dominators| dominators.contains(&edge.target)) {
+                continue;
+            }
+            let header = edge.target;
+            let found = loops.entry(header).or_insert_with(|| Loop {
+                function: entry,
+                header,
+                back_edges: Vec::new(),
+                blocks: BTreeSet::from([header]),
+                depth: 0,
+            });
+            found.back_edges.push(block.last().map_or(block.start, |&(address, _)| address));
+            let mut worklist = vec![block.start];
+            while let Some(member) = worklist.pop() {
+                if found.blocks.insert(member) {
+                    worklist.extend(predecessors.get(&member).into_iter().flatten());
+                }
+            }
+        }
+    }
+    let mut loops: Vec<Loop> = loops.into_values().collect();
+    for idx in 0..loops.len() {
+        let header = loops[idx].header;
+        loops[idx].depth = loops.iter().filter(|other| other.header != header && other.blocks.contains(&header)).count();
+    }
+    loops
+}
+
+/// The natural loops of every function, each found once even where
+/// functions share code.
+pub fn detect(functions: &Functions) -> Vec<Loop> {
+    let mut headers = BTreeSet::new();
+    let mut loops: Vec<Loop> = functions.iter()
+        .flat_map(|function| find_loops(&functions.cfg, function.entry))
+        .filter(|found| headers.insert(found.header))
+        .collect();
+    loops.sort_by_key(|found| found.header);
+    loops
+}
+
+impl Loop {
+    /// How often the loop ran, from the execution counts in `profile`. Each
+    /// back edge's count comes from how often the instruction it leaves
+    /// executed and, for `jt` and `jf`, how often the branch was taken.
+    pub fn trip_count(&self, cfg: &Cfg, profile: &Profile) -> TripCount {
+        let iterations = profile.count_at(self.header);
+        let back: u64 = self.back_edges.iter()
+            .map(|&address| {
+                let executed = profile.count_at(address);
+                let block = cfg.blocks.range(..=address).next_back().map(|(_, block)| block);
+                let kind = block.and_then(|block| block.successors.iter().find(|edge| edge.target == self.header));
+                match (block.and_then(|block| block.last()), kind.map(|edge| edge.kind)) {
+                    (Some((_, Operation::Jt(..) | Operation::Jf(..))), Some(EdgeKind::Taken)) => profile.taken_at(address),
+                    (Some((_, Operation::Jt(..) | Operation::Jf(..))), Some(EdgeKind::Fallthrough)) => {
+                        executed.saturating_sub(profile.taken_at(address))
+                    },
+                    _ => executed,
+                }
+            })
+            .sum();
+        TripCount { iterations, entries: iterations.saturating_sub(back) }
+    }
+}
@@ -0,0 +1,108 @@
+//! Candidate strings in a memory image, either printed directly by runs of
+//! `out` instructions or stored as character data.
+
+use std::fmt;
+
+use crate::vm::Operation;
+
+/// How a string was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringKind {
+    /// Consecutive `out` instructions with literal operands.
+    Out,
+    /// Character data preceded by a word holding its length.
+    Prefixed,
+    /// A run of printable words with no length prefix.
+    Data,
+}
+
+impl fmt::Display for StringKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StringKind::Out => "out",
+            StringKind::Prefixed => "pstr",
+            StringKind::Data => "data",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundString {
+    /// The address of the first `out` instruction, or of the length prefix
+    /// or first character for data.
+    pub address: u16,
+    pub kind: StringKind,
+    pub text: String,
+}
+
+impl fmt::Display for FoundString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:5}: {:<4} {:?}", self.address, self.kind, self.text)
+    }
+}
+
+/// Whether `word` is a character worth including in a candidate string.
+fn is_text(word: u16) -> bool {
+    matches!(word, 0x20..=0x7e | 0x0a)
+}
+
+/// Finds runs of at least `min_len` characters in `words`, sorted by address.
+pub fn find_strings(words: &[u16], min_len: usize) -> Vec<FoundString> {
+    let mut found = find_out_runs(words, min_len);
+    found.extend(find_data(words, min_len));
+    found.sort_by_key(|string| string.address);
+    found
+}
+
+fn find_out_runs(words: &[u16], min_len: usize) -> Vec<FoundString> {
+    let mut found = Vec::new();
+    let mut address = 0;
+    while address < words.len() {
+        let start = address;
+        let mut text = String::new();
+        while let Some(Operation::Out(a)) = words.get(address..).and_then(Operation::decode) {
+            if !is_text(a) {
+                break;
+            }
+            text.push(a as u8 as char);
+            address += 2;
+        }
+        if text.len() >= min_len {
+            found.push(FoundString { address: start as u16, kind: StringKind::Out, text });
+        } else {
+            address = start + 1;
+        }
+    }
+    found
+}
+
+fn find_data(words: &[u16], min_len: usize) -> Vec<FoundString> {
+    let mut found = Vec::new();
+    let mut address = 0;
+    while address < words.len() {
+        let start = address;
+        // Stored strings are a length word followed by that many characters.
+        let len = words[address] as usize;
+        let (kind, chars) = match words.get(address + 1..address + 1 + len) {
+            Some(chars) if len > 0 && chars.iter().all(|&word| is_text(word)) => {
+                address += 1 + len;
+                (StringKind::Prefixed, chars)
+            }
+            _ if is_text(words[address]) => {
+                while address < words.len() && is_text(words[address]) {
+                    address += 1;
+                }
+                (StringKind::Data, &words[start..address])
+            }
+            _ => {
+                address += 1;
+                continue;
+            }
+        };
+        if chars.len() >= min_len {
+            let text = chars.iter().map(|&word| word as u8 as char).collect();
+            found.push(FoundString { address: start as u16, kind, text });
+        }
+    }
+    found
+}
@@ -0,0 +1,131 @@
+//! The teleporter's confirmation check.
+//!
+//! Using the teleporter with a nonzero r7 calls a recursive, Ackermann-like
+//! routine and compares its result with a constant. Run directly it takes
+//! far too long, so this module finds the routine and its call site, computes
+//! the routine natively to search for an r7 that passes, and builds patches
+//! that skip it, either at the routine's entry or at the call site.
+
+use crate::vm::{Operation, Patch, MEMORY_SIZE};
+
+const R0: u16 = 32_768;
+const R1: u16 = 32_769;
+const R7: u16 = 32_775;
+
+/// Where the confirmation routine is called and what it must return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeleporterCheck {
+    /// The entry of the recursive routine.
+    pub routine: u16,
+    /// The address of the `call` that runs it.
+    pub call_site: u16,
+    /// The initial values of r0 and r1.
+    pub args: (u16, u16),
+    /// The value r0 is compared against after the call.
+    pub expected: u16,
+}
+
+fn decode(words: &[u16], address: usize) -> Option<Operation> {
+    Operation::decode(words.get(address..)?)
+}
+
+/// Whether the routine at `entry` calls itself and reads r7, within the
+/// straight-line code that follows it.
+fn is_confirmation_routine(words: &[u16], entry: u16) -> bool {
+    let mut address = entry as usize;
+    let (mut recursive, mut reads_r7) = (false, false);
+    for _ in 0..32 {
+        let Some(operation) = decode(words, address) else {
+            break;
+        };
+        recursive |= operation == Operation::Call(entry);
+        reads_r7 |= operation.sources().contains(&R7);
+        address += operation.size() as usize;
+    }
+    recursive && reads_r7
+}
+
+/// The literal `(r0, r1)` set by the two instructions just before `call_site`.
+fn call_args(words: &[u16], call_site: usize) -> Option<(u16, u16)> {
+    let (mut r0, mut r1) = (None, None);
+    for address in [call_site.checked_sub(6)?, call_site - 3] {
+        match decode(words, address)? {
+            Operation::Set(R0, value) if value < R0 => r0 = Some(value),
+            Operation::Set(R1, value) if value < R0 => r1 = Some(value),
+            _ => return None,
+        }
+    }
+    Some((r0?, r1?))
+}
+
+/// Finds the confirmation routine and the call site that checks its result in
+/// a decrypted memory image.
+pub fn find_check(words: &[u16]) -> Option<TeleporterCheck> {
+    (0..words.len()).find_map(|call_site| {
+        let Some(Operation::Call(routine)) = decode(words, call_site) else {
+            return None;
+        };
+        if routine as usize >= MEMORY_SIZE || !is_confirmation_routine(words, routine) {
+            return None;
+        }
+        let args = call_args(words, call_site)?;
+        let expected = match decode(words, call_site + 2)? {
+            Operation::Eq(_, R0, value) | Operation::Eq(_, value, R0) if value < R0 => value,
+            _ => return None,
+        };
+        Some(TeleporterCheck { routine, call_site: call_site as u16, args, expected })
+    })
+}
+
+/// Computes the confirmation routine natively:
+///
+/// ```text
+/// f(0, n) = n + 1
+/// f(m, 0) = f(m - 1, r7)
+/// f(m, n) = f(m - 1, f(m, n - 1))
+/// ```
+///
+/// with all arithmetic modulo 32768. Each level is memoized as a table over
+/// every `n`, built from the level below.
+pub fn confirmation(m: u16, n: u16, r7: u16) -> u16 {
+    let mut level: Vec<u16> = (0..MEMORY_SIZE as u16).map(|n| (n + 1) % R0).collect();
+    for depth in 1..=m {
+        // Only the first n + 1 entries of the last level are needed.
+        let len = if depth == m { n as usize + 1 } else { MEMORY_SIZE };
+        let mut next = vec![0; len];
+        next[0] = level[r7 as usize];
+        for idx in 1..len {
+            next[idx] = level[next[idx - 1] as usize];
+        }
+        if depth == m {
+            return next[n as usize];
+        }
+        level = next;
+    }
+    level[n as usize]
+}
+
+impl TeleporterCheck {
+    /// The smallest nonzero r7 for which the routine returns the expected value.
+    pub fn solve(&self) -> Option<u16> {
+        let (m, n) = self.args;
+        (1..R0).find(|&r7| confirmation(m, n, r7) == self.expected)
+    }
+
+    /// A patch replacing the call and the two `set`s before it with
+    /// `set r0 <expected>; set r1 <n>; noop; noop`, so the routine never runs.
+    pub fn call_site_patch(&self) -> Patch {
+        let mut words = Operation::Set(R0, self.expected).encode();
+        words.extend(Operation::Set(R1, self.args.1).encode());
+        words.extend(Operation::Noop.encode());
+        words.extend(Operation::Noop.encode());
+        Patch { address: self.call_site - 6, words }
+    }
+
+    /// A patch replacing the start of the routine with `set r0 <expected>; ret`.
+    pub fn patch(&self) -> Patch {
+        let mut words = Operation::Set(R0, self.expected).encode();
+        words.extend(Operation::Ret.encode());
+        Patch { address: self.routine, words }
+    }
+}
@@ -0,0 +1,206 @@
+//! Sanity checks on an image before it is run: that it is whole, that every
+//! word is one the VM accepts, and that its code starts out decoding.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Write};
+
+use super::cfg::Cfg;
+use crate::vm::{DecodeError, Operation, MEMORY_SIZE};
+
+/// The shortest run of words outside reachable code reported as data.
+const MIN_DATA_REGION: usize = 16;
+
+/// How many out-of-range addresses a report lists.
+const LISTED_ADDRESSES: usize = 8;
+
+/// Something wrong with an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Empty,
+    /// The image has an odd number of bytes.
+    DanglingByte,
+    /// The image has more words than fit in memory.
+    TooLarge { words: usize },
+    /// Words above 32775, which are neither numbers nor registers.
+    OutOfRange { addresses: Vec<u16> },
+    /// An instruction in the code starting at address 0 does not decode.
+    BadEntry { address: u16, error: DecodeError },
+    /// The code starting at address 0 runs off the end of the image.
+    TruncatedEntry { address: u16 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Empty => write!(f, "the image is empty"),
+            Problem::DanglingByte => write!(f, "the image has an odd number of bytes, so its last is not part of a word"),
+            Problem::TooLarge { words } => write!(f, "the image has {words} words, more than the {MEMORY_SIZE} in memory"),
+            Problem::OutOfRange { addresses } => {
+                let listed: Vec<String> = addresses.iter().take(LISTED_ADDRESSES).map(u16::to_string).collect();
+                let more = if addresses.len() > LISTED_ADDRESSES { ", ..." } else { "" };
+                write!(f, "{} words above 32775, at {}{more}", addresses.len(), listed.join(", "))
+            },
+            Problem::BadEntry { address, error } => {
+                write!(f, "the instruction at {address}, run from address 0, does not decode: {error}")
+            },
+            Problem::TruncatedEntry { address } => {
+                write!(f, "the instruction at {address}, run from address 0, runs past the end of the image")
+            },
+        }
+    }
+}
+
+/// The result of checking an image, with statistics about its contents.
+///
+/// ```
+/// use oscon_2012_vm_challenge::analysis::validate::{Problem, Validation};
+///
+/// // out 'A'; halt
+/// let validation = Validation::check(&[19, 0, 65, 0, 0, 0]);
+/// assert!(validation.is_ok());
+/// assert_eq!(validation.entry_instructions, 2);
+/// // jt r0 6, cut short.
+/// let validation = Validation::check(&[7, 0, 0, 128]);
+/// assert_eq!(validation.problems, [Problem::TruncatedEntry { address: 0 }]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validation {
+    pub bytes: usize,
+    pub words: usize,
+    pub problems: Vec<Problem>,
+    /// The number of instructions that decode from address 0 up to the first
+    /// `jmp`, `ret`, or `halt`.
+    pub entry_instructions: usize,
+    /// How many instructions reachable from address 0 have each opcode.
+    pub opcodes: BTreeMap<u16, usize>,
+    /// The number of words in instructions reachable from address 0.
+    pub code_words: usize,
+    /// Long runs of words outside reachable code, inclusive: data, or code
+    /// only reached in ways a static analysis cannot see, such as code that
+    /// is decrypted at run time.
+    pub data_regions: Vec<(u16, u16)>,
+}
+
+impl Validation {
+    /// Checks the little-endian `image`.
+    pub fn check(image: &[u8]) -> Self {
+        let mut problems = Vec::new();
+        if image.is_empty() {
+            problems.push(Problem::Empty);
+        }
+        if image.len() % 2 == 1 {
+            problems.push(Problem::DanglingByte);
+        }
+        let mut words: Vec<u16> = image.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        if words.len() > MEMORY_SIZE {
+            problems.push(Problem::TooLarge { words: words.len() });
+            words.truncate(MEMORY_SIZE);
+        }
+        let addresses: Vec<u16> = (0..).zip(&words).filter(|&(_, &word)| word > 32_775).map(|(address, _)| address).collect();
+        if !addresses.is_empty() {
+            problems.push(Problem::OutOfRange { addresses });
+        }
+        let (entry_instructions, entry_problem) = match words.is_empty() {
+            true => (0, None),
+            false => check_entry(&words),
+        };
+        problems.extend(entry_problem);
+
+        let mut opcodes = BTreeMap::new();
+        let mut code = BTreeSet::new();
+        for block in Cfg::build(&words, &[0]).blocks.values() {
+            for (address, operation) in &block.instructions {
+                *opcodes.entry(operation.opcode()).or_default() += 1;
+                code.extend((0..operation.size()).map(|offset| address.wrapping_add(offset)));
+            }
+        }
+        let mut data_regions = Vec::new();
+        let mut start = None;
+        for address in 0..=words.len() as u16 {
+            match (start, address as usize == words.len() || code.contains(&address)) {
+                (None, false) => start = Some(address),
+                (Some(first), true) => {
+                    if (address - first) as usize >= MIN_DATA_REGION {
+                        data_regions.push((first, address - 1));
+                    }
+                    start = None;
+                },
+                _ => (),
+            }
+        }
+        Validation {
+            bytes: image.len(),
+            words: words.len(),
+            problems,
+            entry_instructions,
+            opcodes,
+            code_words: code.len(),
+            data_regions,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Writes the statistics, then the problems found.
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{} bytes, {} words", self.bytes, self.words)?;
+        writeln!(out, "{} instructions decode from address 0", self.entry_instructions)?;
+        let instructions: usize = self.opcodes.values().sum();
+        writeln!(out, "{instructions} instructions in {} words reachable from address 0", self.code_words)?;
+        let mut opcodes: Vec<(u16, usize)> = self.opcodes.iter().map(|(&opcode, &count)| (opcode, count)).collect();
+        opcodes.sort_by_key(|&(opcode, count)| (std::cmp::Reverse(count), opcode));
+        for (opcode, count) in opcodes {
+            writeln!(out, "  {:<4} {count:6}", Operation::mnemonic_for(opcode).unwrap_or("?"))?;
+        }
+        if !self.data_regions.is_empty() {
+            writeln!(out, "suspected data:")?;
+            for &(start, end) in &self.data_regions {
+                writeln!(out, "  {start:5}-{end:<5} {:6} words", end - start + 1)?;
+            }
+        }
+        match self.problems.len() {
+            0 => writeln!(out, "ok"),
+            count => {
+                writeln!(out, "{count} problem{}:", if count == 1 { "" } else { "s" })?;
+                for problem in &self.problems {
+                    writeln!(out, "  {problem}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Decodes straight-line code from address 0 up to the first instruction
+/// that does not continue, returning how many instructions decoded and the
+/// problem that stopped it early, if any.
+fn check_entry(words: &[u16]) -> (usize, Option<Problem>) {
+    let mut address = 0;
+    let mut count = 0;
+    loop {
+        let truncated = Problem::TruncatedEntry { address: address as u16 };
+        let Some(&opcode) = words.get(address) else {
+            return (count, Some(truncated));
+        };
+        let Some(arguments) = Operation::num_arguments(opcode) else {
+            let error = DecodeError::InvalidOpcode { opcode };
+            return (count, Some(Problem::BadEntry { address: address as u16, error }));
+        };
+        let Some(args) = words.get(address + 1..address + 1 + arguments as usize) else {
+            return (count, Some(truncated));
+        };
+        match Operation::new(opcode, args) {
+            Err(error) => return (count, Some(Problem::BadEntry { address: address as u16, error })),
+            Ok(operation) => {
+                count += 1;
+                if matches!(operation, Operation::Jmp(_) | Operation::Ret | Operation::Halt) {
+                    return (count, None);
+                }
+                address += operation.size() as usize;
+            },
+        }
+    }
+}
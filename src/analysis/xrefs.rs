@@ -0,0 +1,155 @@
+//! Cross-references: which instructions jump to, call, read, or write each
+//! address, found statically in a CFG and observed at runtime.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::cfg::Cfg;
+use crate::vm::{Hook, Operation, VM};
+
+/// How an instruction refers to an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum XrefKind {
+    /// A taken `jmp`, `jt`, or `jf`.
+    Jump,
+    Call,
+    /// An `rmem` from the address.
+    Read,
+    /// A `wmem` to the address.
+    Write,
+}
+
+impl fmt::Display for XrefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            XrefKind::Jump => "jump",
+            XrefKind::Call => "call",
+            XrefKind::Read => "read",
+            XrefKind::Write => "write",
+        })
+    }
+}
+
+/// A reference from the instruction at `from` to the address `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Xref {
+    pub from: u16,
+    pub to: u16,
+    pub kind: XrefKind,
+}
+
+/// What is known about one reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XrefSource {
+    /// Whether the reference is a literal operand of reachable code.
+    pub is_static: bool,
+    /// How many times it was executed while logging.
+    pub count: u64,
+}
+
+impl fmt::Display for XrefSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_static {
+            write!(f, "static, ")?;
+        }
+        match self.count {
+            0 => write!(f, "never run"),
+            1 => write!(f, "run once"),
+            count => write!(f, "run {count} times"),
+        }
+    }
+}
+
+/// The literal reference `operation` at `address` makes, if any.
+fn literal(address: u16, operation: &Operation) -> Option<Xref> {
+    let (to, kind) = match *operation {
+        Operation::Jmp(to) | Operation::Jt(_, to) | Operation::Jf(_, to) => (to, XrefKind::Jump),
+        Operation::Call(to) => (to, XrefKind::Call),
+        Operation::Rmem(_, to) => (to, XrefKind::Read),
+        Operation::Wmem(to, _) => (to, XrefKind::Write),
+        _ => return None,
+    };
+    (to < 32_768).then_some(Xref { from: address, to, kind })
+}
+
+/// Cross-references indexed by the address referred to.
+#[derive(Debug, Clone, Default)]
+pub struct Xrefs {
+    refs: BTreeMap<u16, BTreeMap<(u16, XrefKind), XrefSource>>,
+}
+
+impl Xrefs {
+    /// The literal references made by the instructions in `cfg`.
+    pub fn from_cfg(cfg: &Cfg) -> Self {
+        let mut xrefs = Xrefs::default();
+        let instructions = cfg.blocks.values().flat_map(|block| block.instructions.iter());
+        for xref in instructions.filter_map(|(address, operation)| literal(*address, operation)) {
+            xrefs.source(xref).is_static = true;
+        }
+        xrefs
+    }
+
+    fn source(&mut self, xref: Xref) -> &mut XrefSource {
+        self.refs.entry(xref.to).or_default().entry((xref.from, xref.kind)).or_default()
+    }
+
+    /// Adds the references counted by an [`XrefLog`].
+    pub fn add_observed(&mut self, log: &XrefLog) {
+        for (&xref, &count) in &log.counts {
+            self.source(xref).count += count;
+        }
+    }
+
+    /// The references to `address`, by referring address and kind.
+    pub fn to(&self, address: u16) -> Vec<(Xref, XrefSource)> {
+        self.refs.get(&address).into_iter()
+            .flatten()
+            .map(|(&(from, kind), &source)| (Xref { from, to: address, kind }, source))
+            .collect()
+    }
+}
+
+/// Counts the references executed while it is registered as a [`Hook`],
+/// including those through registers: the targets of taken jumps and calls,
+/// and the addresses `rmem` and `wmem` access.
+#[derive(Debug, Clone, Default)]
+pub struct XrefLog {
+    counts: BTreeMap<Xref, u64>,
+}
+
+impl XrefLog {
+    pub fn counts(&self) -> &BTreeMap<Xref, u64> {
+        &self.counts
+    }
+}
+
+/// The value of the operand `word`: a literal, or the register it names.
+fn value(vm: &VM, word: u16) -> u16 {
+    match word {
+        32_768..=32_775 => vm.registers()[(word - 32_768) as usize],
+        word => word,
+    }
+}
+
+impl Hook for XrefLog {
+    fn before_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        let (to, kind) = match *operation {
+            Operation::Rmem(_, from) => (value(vm, from), XrefKind::Read),
+            Operation::Wmem(to, _) => (value(vm, to), XrefKind::Write),
+            _ => return,
+        };
+        *self.counts.entry(Xref { from: address, to, kind }).or_default() += 1;
+    }
+
+    fn after_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        let kind = match operation {
+            Operation::Jmp(_) | Operation::Jt(..) | Operation::Jf(..) => XrefKind::Jump,
+            Operation::Call(_) => XrefKind::Call,
+            _ => return,
+        };
+        let to = vm.instruction_ptr();
+        if to != address.wrapping_add(operation.size()) || kind == XrefKind::Call {
+            *self.counts.entry(Xref { from: address, to, kind }).or_default() += 1;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! A two-pass assembler for the dialect printed by the disassembler.
+//!
+//! Each line holds an optional `label:` followed by an optional instruction.
+//! Operands are registers (`r0`..`r7`), numbers (`42`, `0x2a`), character
+//! literals (`'a'`, `'\n'`), or labels. `data` emits its operands verbatim, and
+//! `;` starts a comment.
+//!
+//! ```text
+//! start:  set r0 3
+//! loop:   out 'x'
+//!         add r0 r0 32767   ; r0 -= 1
+//!         jt r0 loop
+//!         halt
+//! ```
+
+use std::collections::HashMap;
+use std::{error, fmt};
+
+use crate::vm::Operation;
+
+/// An error at a (1-based) source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+struct Statement<'a> {
+    line: usize,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles `source` into words to be loaded at address 0.
+pub fn assemble(source: &str) -> Result<Vec<u16>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut address = 0usize;
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let error = |message: String| AsmError { line, message };
+        let mut tokens = tokenize(text).map_err(error)?;
+        while let Some(label) = tokens.first().and_then(|token| token.strip_suffix(':')) {
+            if !is_identifier(label) {
+                return Err(error(format!("invalid label `{label}`")));
+            }
+            if labels.insert(label, address).is_some() {
+                return Err(error(format!("duplicate label `{label}`")));
+            }
+            tokens.remove(0);
+        }
+        let Some((&mnemonic, operands)) = tokens.split_first() else {
+            continue;
+        };
+        address += match mnemonic {
+            "data" => operands.len(),
+            _ => {
+                let opcode = opcode_for(mnemonic)
+                    .ok_or_else(|| error(format!("unknown mnemonic `{mnemonic}`")))?;
+                let expected = Operation::num_arguments(opcode).unwrap() as usize;
+                if operands.len() != expected {
+                    return Err(error(format!(
+                        "`{mnemonic}` takes {expected} operands, found {}", operands.len(),
+                    )));
+                }
+                1 + expected
+            },
+        };
+        statements.push(Statement { line, mnemonic, operands: operands.to_vec() });
+    }
+    if address > crate::vm::MEMORY_SIZE {
+        return Err(AsmError {
+            line: statements.last().map_or(0, |statement| statement.line),
+            message: format!("program of {address} words does not fit in memory"),
+        });
+    }
+
+    let mut words = Vec::with_capacity(address);
+    for statement in statements {
+        if let Some(opcode) = opcode_for(statement.mnemonic) {
+            words.push(opcode);
+        }
+        for operand in statement.operands {
+            let word = parse_operand(operand, &labels)
+                .map_err(|message| AsmError { line: statement.line, message })?;
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+pub(crate) fn opcode_for(mnemonic: &str) -> Option<u16> {
+    (0..).map_while(Operation::mnemonic_for)
+        .position(|candidate| candidate == mnemonic)
+        .map(|opcode| opcode as u16)
+}
+
+pub(crate) fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Splits a line into tokens separated by whitespace or commas, keeping
+/// character literals intact and dropping comments.
+fn tokenize(text: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() || rest.starts_with(';') {
+            return Ok(tokens);
+        }
+        let len = if let Some(body) = rest.strip_prefix('\'') {
+            let escaped = body.starts_with('\\');
+            let close = body.char_indices()
+                .skip(if escaped { 2 } else { 1 })
+                .find(|&(_, c)| c == '\'')
+                .ok_or_else(|| format!("unterminated character literal in `{rest}`"))?;
+            close.0 + 2
+        } else {
+            rest.find(|c: char| c.is_whitespace() || c == ',' || c == ';').unwrap_or(rest.len())
+        };
+        tokens.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+}
+
+fn parse_operand(token: &str, labels: &HashMap<&str, usize>) -> Result<u16, String> {
+    if let Some(register) = token.strip_prefix('r').and_then(|n| n.parse::<u16>().ok()) {
+        if register < 8 {
+            return Ok(32_768 + register);
+        }
+        return Err(format!("no such register `{token}`"));
+    }
+    if let Some(literal) = token.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        let value = match literal {
+            "\\n" => '\n',
+            "\\t" => '\t',
+            "\\\\" => '\\',
+            "\\'" => '\'',
+            _ => {
+                let mut chars = literal.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(format!("invalid character literal {token}")),
+                }
+            },
+        };
+        return u8::try_from(value).map(u16::from)
+            .map_err(|_| format!("character literal {token} is not a byte"));
+    }
+    let value = if let Some(hex) = token.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+        token.parse().ok()
+    } else if let Some(&address) = labels.get(token) {
+        return Ok(address as u16);
+    } else {
+        return Err(format!("undefined label `{token}`"));
+    };
+    match value {
+        Some(value) if value < 32_768 => Ok(value),
+        _ => Err(format!("invalid number `{token}`")),
+    }
+}
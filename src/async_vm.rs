@@ -0,0 +1,126 @@
+//! Driving the VM from an async runtime.
+//!
+//! [`AsyncVm`] runs the machine in slices of instructions, yielding to the
+//! executor between them. When the program waits for input it awaits the
+//! next chunk from an [`AsyncInput`], and it hands output to an
+//! [`AsyncOutput`] before waiting for input, between slices, and when the run
+//! ends. No thread is ever blocked on I/O.
+//!
+//! The traits are plain `Future`-returning methods, so any runtime's readers
+//! and writers can be adapted with a few lines. A [`VM`] is not `Send`, so on
+//! a multithreaded runtime such as tokio's, run it on a `LocalSet` or a
+//! dedicated current-thread runtime.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::vm::{VmError, VmEvent, VM};
+
+/// Instructions executed between yields to the executor.
+pub const DEFAULT_SLICE: u32 = 10_000;
+
+/// Where `in` gets its bytes when run by an [`AsyncVm`].
+pub trait AsyncInput {
+    /// The next chunk of input, typically a line, or `None` at the end of
+    /// input.
+    fn read(&mut self) -> impl Future<Output = io::Result<Option<Vec<u8>>>>;
+}
+
+/// Where `out` sends its bytes when run by an [`AsyncVm`].
+pub trait AsyncOutput {
+    fn write(&mut self, bytes: &[u8]) -> impl Future<Output = io::Result<()>>;
+}
+
+/// A future that is pending once, letting the executor run other tasks.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// A VM run by an async task. Load a program into [`vm_mut`](Self::vm_mut),
+/// then [`run`](Self::run) it.
+pub struct AsyncVm {
+    vm: VM,
+    slice: u32,
+}
+
+impl Default for AsyncVm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncVm {
+    /// Creates an empty VM. Its own input and output are unused: `run` feeds
+    /// it input and collects its output.
+    pub fn new() -> Self {
+        Self { vm: VM::new(io::empty(), io::sink()), slice: DEFAULT_SLICE }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// Sets how many instructions run between yields to the executor.
+    pub fn set_slice(&mut self, instructions: u32) {
+        self.slice = instructions.max(1);
+    }
+
+    /// Runs until the program halts. Fails with
+    /// [`VmError::InputExhausted`] if the program wants input after `input`
+    /// has ended. Output printed before a fault is still written.
+    pub async fn run(&mut self, input: &mut impl AsyncInput, output: &mut impl AsyncOutput) -> Result<(), VmError> {
+        let mut pending = Vec::new();
+        let result = self.run_slices(input, output, &mut pending).await;
+        if !pending.is_empty() {
+            output.write(&pending).await?;
+        }
+        result
+    }
+
+    async fn run_slices(
+        &mut self,
+        input: &mut impl AsyncInput,
+        output: &mut impl AsyncOutput,
+        pending: &mut Vec<u8>,
+    ) -> Result<(), VmError> {
+        loop {
+            for _ in 0..self.slice {
+                match self.vm.step()? {
+                    VmEvent::Continued => (),
+                    VmEvent::Output(byte) => pending.push(byte),
+                    VmEvent::Halted => return Ok(()),
+                    VmEvent::NeedsInput => {
+                        output.write(pending).await?;
+                        pending.clear();
+                        match input.read().await? {
+                            Some(bytes) => self.vm.provide_input(&bytes),
+                            None => return Err(VmError::InputExhausted { address: self.vm.instruction_ptr() }),
+                        }
+                    },
+                }
+            }
+            if !pending.is_empty() {
+                output.write(pending).await?;
+                pending.clear();
+            }
+            YieldNow(false).await;
+        }
+    }
+}
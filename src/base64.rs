@@ -0,0 +1,40 @@
+//! Standard base64 (RFC 4648, with padding), as used by WebSocket handshakes
+//! and the Debug Adapter Protocol's memory requests.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `data` with `=` padding.
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes `text`, ignoring whitespace. Padding is optional. Returns `None` if
+/// `text` holds anything else outside the alphabet.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .take_while(|&byte| byte != b'=')
+        .map(|byte| ALPHABET.iter().position(|&digit| digit == byte).map(|idx| idx as u8))
+        .collect::<Option<_>>()?;
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, &digit)| bits | (digit as u32) << (18 - 6 * idx));
+        decoded.extend(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(decoded)
+}
@@ -0,0 +1,273 @@
+//! Compact binary execution traces, for runs too long to trace as text.
+//!
+//! A trace is the magic bytes `VMTRACE1` followed by one record per executed
+//! instruction. Each record starts with a byte holding the opcode in its low
+//! five bits and flags in the rest; everything else is delta encoded, so a
+//! typical record is a single byte:
+//!
+//! - `ADDRESS`: the address is not the one after the previous instruction,
+//!   and follows as a zigzag varint of the difference.
+//! - `OPERANDS`: the instruction differs from the one last executed at this
+//!   address (or none has been), and its operands follow as varints.
+//! - `REGISTERS`: registers changed since the previous record; a byte with
+//!   a bit per changed register follows, then each new value as a varint.
+//!
+//! The opcode `0x1f` instead introduces a varint step count, for
+//! the first record and wherever steps were skipped. Registers are recorded
+//! as they were before each instruction ran. The format compresses well
+//! further with a general-purpose compressor such as zstd.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use crate::vm::{Executed, Hook, Operation, MEMORY_SIZE, VM};
+
+const MAGIC: &[u8; 8] = b"VMTRACE1";
+
+const ADDRESS: u8 = 0x20;
+const OPERANDS: u8 = 0x40;
+const REGISTERS: u8 = 0x80;
+const OPCODE_MASK: u8 = 0x1f;
+
+/// The opcode of a record that sets the step count.
+const STEP_MARKER: u8 = 0x1f;
+
+/// What the writer and reader both know about the trace so far, from which
+/// each record is a delta.
+struct Context {
+    step: Option<u64>,
+    next_address: u16,
+    registers: [u16; 8],
+    operations: Vec<Option<Operation>>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self { step: None, next_address: 0, registers: [0; 8], operations: vec![None; MEMORY_SIZE] }
+    }
+}
+
+impl Context {
+    fn advance(&mut self, executed: &Executed) {
+        self.step = Some(executed.step + 1);
+        self.next_address = executed.address.wrapping_add(executed.operation.size());
+        self.registers = executed.registers;
+        self.operations[executed.address as usize % MEMORY_SIZE] = Some(executed.operation);
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(input)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint is too long"))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_word(input: &mut impl Read) -> io::Result<u16> {
+    u16::try_from(read_varint(input)?).map_err(|_| invalid("word out of range"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid trace: {message}"))
+}
+
+struct WriterState {
+    out: Box<dyn Write>,
+    context: Context,
+    record: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl WriterState {
+    fn write(&mut self, executed: &Executed) -> io::Result<()> {
+        let record = &mut self.record;
+        record.clear();
+        if self.context.step != Some(executed.step) {
+            record.push(STEP_MARKER);
+            write_varint(record, executed.step);
+        }
+        let mut header = executed.operation.opcode() as u8;
+        let header_at = record.len();
+        record.push(0);
+        if executed.address != self.context.next_address {
+            header |= ADDRESS;
+            let delta = executed.address.wrapping_sub(self.context.next_address) as i16;
+            write_varint(record, ((delta << 1) ^ (delta >> 15)) as u16 as u64);
+        }
+        if self.context.operations[executed.address as usize % MEMORY_SIZE] != Some(executed.operation) {
+            header |= OPERANDS;
+            for arg in executed.operation.args() {
+                write_varint(record, u64::from(arg));
+            }
+        }
+        let changed = (0..8).filter(|&idx| executed.registers[idx] != self.context.registers[idx]);
+        let mask = changed.clone().fold(0u8, |mask, idx| mask | 1 << idx);
+        if mask != 0 {
+            header |= REGISTERS;
+            record.push(mask);
+            for idx in changed {
+                write_varint(record, u64::from(executed.registers[idx]));
+            }
+        }
+        record[header_at] = header;
+        self.out.write_all(record)?;
+        self.context.advance(executed);
+        Ok(())
+    }
+}
+
+/// Writes a binary trace while registered as a [`Hook`]; call
+/// [`finish`](Self::finish) when the run stops.
+#[derive(Clone)]
+pub struct TraceWriter(Rc<RefCell<WriterState>>);
+
+impl TraceWriter {
+    pub fn new(mut out: Box<dyn Write>) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self(Rc::new(RefCell::new(WriterState {
+            out,
+            context: Context::default(),
+            record: Vec::new(),
+            error: None,
+        }))))
+    }
+
+    /// Appends one instruction to the trace.
+    pub fn record(&self, executed: &Executed) -> io::Result<()> {
+        self.0.borrow_mut().write(executed)
+    }
+
+    /// Flushes the trace, returning the first error writing it.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+        state.out.flush()
+    }
+}
+
+impl Hook for TraceWriter {
+    fn before_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        let mut state = self.0.borrow_mut();
+        // An `in` waiting for input comes round again once it has some.
+        if state.error.is_some() || state.context.step == Some(vm.steps() + 1) {
+            return;
+        }
+        let executed = Executed { step: vm.steps(), address, operation: *operation, registers: *vm.registers() };
+        if let Err(err) = state.write(&executed) {
+            state.error = Some(err);
+        }
+    }
+}
+
+/// Reads the records of a binary trace in order.
+///
+/// ```
+/// use oscon_2012_vm_challenge::bintrace::{TraceReader, TraceWriter};
+/// use oscon_2012_vm_challenge::vm::{encode_image, VM};
+///
+/// # struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+/// # impl std::io::Write for Shared {
+/// #     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.borrow_mut().write(buf) }
+/// #     fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+/// # }
+/// let bytes = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+/// let writer = TraceWriter::new(Box::new(Shared(bytes.clone()))).unwrap();
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // set r0 3; add r0 r0 32767; jt r0 3; halt
+/// vm.load(&encode_image(&[1, 32_768, 3, 9, 32_768, 32_768, 32_767, 7, 32_768, 3, 0])).unwrap();
+/// vm.add_hook(writer.clone());
+/// vm.run().unwrap();
+/// writer.finish().unwrap();
+///
+/// let bytes = bytes.borrow();
+/// let records: Vec<_> = TraceReader::new(&bytes[..]).unwrap().collect::<Result<_, _>>().unwrap();
+/// let path: Vec<(u64, u16, u16)> = records.iter().map(|executed| (executed.step, executed.address, executed.registers[0])).collect();
+/// assert_eq!(path, [(0, 0, 0), (1, 3, 3), (2, 7, 2), (3, 3, 2), (4, 7, 1), (5, 3, 1), (6, 7, 0), (7, 10, 0)]);
+/// ```
+pub struct TraceReader<R: Read> {
+    input: R,
+    context: Context,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Checks the magic bytes at the start of `input`.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a binary trace"));
+        }
+        Ok(Self { input, context: Context::default() })
+    }
+
+    fn read_record(&mut self, mut header: u8) -> io::Result<Executed> {
+        if header & OPCODE_MASK == STEP_MARKER {
+            self.context.step = Some(read_varint(&mut self.input)?);
+            header = read_byte(&mut self.input)?;
+        }
+        let step = self.context.step.ok_or_else(|| invalid("the first record has no step count"))?;
+        let mut address = self.context.next_address;
+        if header & ADDRESS != 0 {
+            let zigzag = read_word(&mut self.input)?;
+            let delta = (zigzag >> 1) as i16 ^ -((zigzag & 1) as i16);
+            address = address.wrapping_add(delta as u16);
+        }
+        let opcode = u16::from(header & OPCODE_MASK);
+        let operation = if header & OPERANDS != 0 {
+            let count = Operation::num_arguments(opcode).ok_or_else(|| invalid("unknown opcode"))?;
+            let args = (0..count).map(|_| read_word(&mut self.input)).collect::<io::Result<Vec<u16>>>()?;
+            Operation::new(opcode, &args).map_err(|err| invalid(&err.to_string()))?
+        } else {
+            self.context.operations[address as usize % MEMORY_SIZE]
+                .filter(|operation| operation.opcode() == opcode)
+                .ok_or_else(|| invalid("a repeated instruction was never recorded"))?
+        };
+        let mut registers = self.context.registers;
+        if header & REGISTERS != 0 {
+            let mask = read_byte(&mut self.input)?;
+            for (idx, register) in registers.iter_mut().enumerate() {
+                if mask & 1 << idx != 0 {
+                    *register = read_word(&mut self.input)?;
+                }
+            }
+        }
+        let executed = Executed { step, address, operation, registers };
+        self.context.advance(&executed);
+        Ok(executed)
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Executed>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = match read_byte(&mut self.input) {
+            Ok(header) => header,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.read_record(header))
+    }
+}
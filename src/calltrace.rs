@@ -0,0 +1,165 @@
+//! Function calls as Chrome trace events, for viewing a run as a flame chart
+//! in Perfetto or `chrome://tracing`.
+//!
+//! Each frame on the VM's shadow call stack becomes a duration: a `B` event
+//! when the `call` executes and an `E` event when its `ret` does. Timestamps
+//! count instructions, one per microsecond, the format's time unit, so a
+//! microsecond in the viewer is one instruction.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use serde_json::json;
+
+use crate::symbols::Symbols;
+use crate::vm::{Frame, Hook, Operation, VM};
+
+struct State {
+    out: Box<dyn Write>,
+    symbols: Symbols,
+    // The frames with a `B` event and no `E` yet, with the call depth each
+    // was opened at.
+    open: Vec<(usize, Frame)>,
+    started: bool,
+    events: u64,
+    last_step: u64,
+    error: Option<io::Error>,
+}
+
+impl State {
+    fn event(&mut self, phase: &str, frame: &Frame, step: u64) -> io::Result<()> {
+        let name = match self.symbols.name(frame.target) {
+            Some(name) => name.to_string(),
+            None => format!("fn_{}", frame.target),
+        };
+        let event = json!({
+            "name": name,
+            "cat": "call",
+            "ph": phase,
+            "ts": step,
+            "pid": 1,
+            "tid": 1,
+            "args": { "target": frame.target, "call_site": frame.call_site },
+        });
+        let separator = if self.events == 0 { "" } else { ",\n" };
+        self.events += 1;
+        write!(self.out, "{separator}{event}")
+    }
+
+    fn open(&mut self, depth: usize, frame: Frame, step: u64) -> io::Result<()> {
+        self.event("B", &frame, step)?;
+        self.open.push((depth, frame));
+        Ok(())
+    }
+
+    /// Closes the frames deeper than `depth`, which also catches frames a
+    /// program abandons without returning.
+    fn close_to(&mut self, depth: usize, step: u64) -> io::Result<()> {
+        while self.open.last().is_some_and(|&(open, _)| open > depth) {
+            let (_, frame) = self.open.pop().expect("There should be an open frame.");
+            self.event("E", &frame, step)?;
+        }
+        Ok(())
+    }
+
+    fn observe(&mut self, vm: &VM, operation: &Operation) -> io::Result<()> {
+        let frames = vm.call_stack();
+        self.last_step = vm.steps();
+        if !self.started {
+            self.started = true;
+            writeln!(self.out, "{{\"traceEvents\":[")?;
+            // Frames already open when tracing started, outermost first;
+            // the newest may be the `call` just executed.
+            let existing = frames.len() - usize::from(matches!(operation, Operation::Call(_)));
+            for (idx, &frame) in frames[..existing].iter().enumerate() {
+                self.open(idx + 1, frame, vm.steps().saturating_sub(1))?;
+            }
+        }
+        // Frames the VM dropped without a `ret`, as when a run is restarted
+        // at a function with the stacks emptied.
+        if self.open.last().is_some_and(|&(depth, _)| depth > frames.len()) && !matches!(operation, Operation::Ret) {
+            self.close_to(frames.len(), vm.steps().saturating_sub(1))?;
+        }
+        match operation {
+            Operation::Call(_) if !frames.is_empty() => self.open(frames.len(), frames[frames.len() - 1], vm.steps()),
+            Operation::Ret => self.close_to(frames.len(), vm.steps()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes the calls a VM makes as a Chrome trace while registered as a
+/// [`Hook`]; call [`finish`](Self::finish) when the run stops.
+///
+/// ```
+/// use oscon_2012_vm_challenge::calltrace::CallTrace;
+/// use oscon_2012_vm_challenge::symbols::Symbols;
+/// use oscon_2012_vm_challenge::vm::{encode_image, VM};
+///
+/// # struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+/// # impl std::io::Write for Shared {
+/// #     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.borrow_mut().write(buf) }
+/// #     fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+/// # }
+/// let bytes = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+/// let trace = CallTrace::new(Box::new(Shared(bytes.clone())), Symbols::parse("3 = twice").unwrap());
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // call 3; halt; 3: noop; ret
+/// vm.load(&encode_image(&[17, 3, 0, 21, 18])).unwrap();
+/// vm.add_hook(trace.clone());
+/// vm.run().unwrap();
+/// trace.finish().unwrap();
+///
+/// let json: serde_json::Value = serde_json::from_slice(&bytes.borrow()).unwrap();
+/// let events: Vec<(&str, &str, u64)> = json["traceEvents"].as_array().unwrap().iter()
+///     .map(|event| (event["name"].as_str().unwrap(), event["ph"].as_str().unwrap(), event["ts"].as_u64().unwrap()))
+///     .collect();
+/// assert_eq!(events, [("twice", "B", 1), ("twice", "E", 3)]);
+/// assert!(json.get("displayTimeUnit").is_none());
+/// ```
+#[derive(Clone)]
+pub struct CallTrace(Rc<RefCell<State>>);
+
+impl CallTrace {
+    /// Writes the trace to `out`, naming functions with `symbols`.
+    pub fn new(out: Box<dyn Write>, symbols: Symbols) -> Self {
+        Self(Rc::new(RefCell::new(State {
+            out,
+            symbols,
+            open: Vec::new(),
+            started: false,
+            events: 0,
+            last_step: 0,
+            error: None,
+        })))
+    }
+
+    /// Ends the frames still open at the last step seen, completes the JSON,
+    /// and flushes it, returning the first error writing it.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+        if !state.started {
+            writeln!(state.out, "{{\"traceEvents\":[")?;
+        }
+        let step = state.last_step;
+        state.close_to(0, step)?;
+        writeln!(state.out, "\n]}}")?;
+        state.out.flush()
+    }
+}
+
+impl Hook for CallTrace {
+    fn after_op(&mut self, vm: &VM, _address: u16, operation: &Operation) {
+        let mut state = self.0.borrow_mut();
+        if state.error.is_some() {
+            return;
+        }
+        if let Err(err) = state.observe(vm, operation) {
+            state.error = Some(err);
+        }
+    }
+}
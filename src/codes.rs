@@ -0,0 +1,132 @@
+//! Recognizing the challenge's twelve-character codes in program output.
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// Whether `word` looks like a challenge code: twelve letters and digits,
+/// mixing upper and lower case the way English words do not.
+pub fn is_code(word: &str) -> bool {
+    let upper = word.chars().filter(char::is_ascii_uppercase).count();
+    let lower = word.chars().filter(char::is_ascii_lowercase).count();
+    word.len() == 12 && word.chars().all(|c| c.is_ascii_alphanumeric()) && upper >= 2 && lower >= 1
+}
+
+/// The codes in `text`, in order.
+pub fn find_codes(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| is_code(word))
+        .map(str::to_string)
+        .collect()
+}
+
+/// How `code` reads in a mirror: reversed, with the glyphs that mirror into
+/// each other (`b`/`d` and `p`/`q`) swapped.
+pub fn mirror(code: &str) -> String {
+    code.chars()
+        .rev()
+        .map(|c| match c {
+            'b' => 'd',
+            'd' => 'b',
+            'p' => 'q',
+            'q' => 'p',
+            c => c,
+        })
+        .collect()
+}
+
+/// A code seen in the output, and when it was printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundCode {
+    pub code: String,
+    /// The address of the `out` that finished the line holding the code.
+    pub address: u16,
+    /// The number of instructions executed by then.
+    pub steps: u64,
+    /// The last line of input read before the code was printed.
+    pub after_input: Option<String>,
+    /// Whether `code` has been transformed with [`mirror`] from what was
+    /// printed.
+    pub mirrored: bool,
+}
+
+impl FoundCode {
+    /// Replaces the code with its mirror image, or restores it if it was
+    /// already mirrored.
+    pub fn mirror(&mut self) {
+        self.code = mirror(&self.code);
+        self.mirrored = !self.mirrored;
+    }
+}
+
+impl fmt::Display for FoundCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at step {}, address {}, ", self.code, self.steps, self.address)?;
+        match &self.after_input {
+            Some(input) => write!(f, "after `{input}`")?,
+            None => write!(f, "before any input")?,
+        }
+        if self.mirrored {
+            write!(f, " (read in a mirror)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects codes from output as it is printed, one line at a time.
+#[derive(Debug, Clone, Default)]
+pub struct CodeScanner {
+    line: String,
+    last_input: Option<String>,
+    codes: Vec<FoundCode>,
+}
+
+impl CodeScanner {
+    /// Adds a printed byte, scanning the line when it ends.
+    pub fn push(&mut self, byte: u8, address: u16, steps: u64) {
+        if byte == b'\n' {
+            self.scan_line(address, steps);
+        } else {
+            self.line.push(byte as char);
+        }
+    }
+
+    /// Notes a line of input, which labels the codes printed after it. Any
+    /// partial line of output is scanned first.
+    pub fn input(&mut self, line: &str, address: u16, steps: u64) {
+        self.scan_line(address, steps);
+        self.last_input = Some(line.trim_end().to_string());
+    }
+
+    fn scan_line(&mut self, address: u16, steps: u64) {
+        for code in find_codes(&self.line) {
+            self.codes.push(FoundCode {
+                code,
+                address,
+                steps,
+                after_input: self.last_input.clone(),
+                mirrored: false,
+            });
+        }
+        self.line.clear();
+    }
+
+    /// The codes found so far, in the order they were printed.
+    pub fn codes(&self) -> &[FoundCode] {
+        &self.codes
+    }
+
+    /// Mirrors the most recent code, which the challenge shows reflected.
+    /// Returns `false` if no code has been found.
+    pub fn mirror_last_code(&mut self) -> bool {
+        self.codes.last_mut().map(FoundCode::mirror).is_some()
+    }
+
+    /// Writes a numbered list of the codes found.
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "codes found: {}", self.codes.len())?;
+        for (idx, found) in self.codes.iter().enumerate() {
+            writeln!(out, "  {}. {found}", idx + 1)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,668 @@
+//! A Debug Adapter Protocol server, so editors such as VS Code can debug
+//! programs running on the VM.
+//!
+//! The adapter speaks DAP over any byte stream, normally its own stdin and
+//! stdout. Because those carry the protocol, the program's output is sent as
+//! `output` events, and its input comes from the launch configuration's
+//! `input` file and from lines typed in the editor's debug console. When the
+//! program waits for input the adapter stops with reason `pause`; typing a
+//! line resumes it.
+//!
+//! The source view is a disassembly of memory, served by `source` requests.
+//! It is rebuilt whenever execution stops at an address it does not cover, as
+//! happens once the challenge decrypts itself. Breakpoints may be set on its
+//! lines, or by address through instruction breakpoints.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::base64;
+use crate::disasm::{self, Line};
+use crate::vm::{HaltReason, Operation, VmError, VmEvent, MEMORY_SIZE, VM};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const STACK_REFERENCE: u64 = 2;
+
+/// The number of instructions run between checks for requests such as `pause`.
+const RUN_CHUNK: u64 = 10_000;
+
+/// Reads one `Content-Length`-framed message. Returns `None` at end of input.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length"))?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn number(value: &Value) -> Option<u64> {
+    value.as_u64().or_else(|| value.as_str().and_then(|text| parse_address(text).map(u64::from)))
+}
+
+/// Parses a memory or instruction reference: a decimal or `0x`-prefixed
+/// word address.
+fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let address = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    ((address as usize) < MEMORY_SIZE).then_some(address)
+}
+
+/// A failed request, reported back to the editor.
+struct RequestError(String);
+
+impl<T: ToString> From<T> for RequestError {
+    fn from(err: T) -> Self {
+        RequestError(err.to_string())
+    }
+}
+
+type RequestResult = Result<Value, RequestError>;
+
+/// How the program is being run between requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Stopped,
+    Continue,
+    /// Run until the call depth drops to this depth.
+    StepOver(i32),
+    StepOut(i32),
+}
+
+/// The disassembly shown as the program's source.
+#[derive(Default)]
+struct Listing {
+    reference: u64,
+    lines: Vec<Line>,
+    by_address: BTreeMap<u16, usize>,
+}
+
+impl Listing {
+    fn build(vm: &VM, reference: u64) -> Self {
+        let lines = disasm::disassemble(&vm.memory_image());
+        let by_address = lines.iter().enumerate().map(|(idx, line)| (line.address, idx + 1)).collect();
+        Self { reference, lines, by_address }
+    }
+
+    fn text(&self) -> String {
+        self.lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    fn source(&self) -> Value {
+        json!({ "name": "disassembly", "sourceReference": self.reference })
+    }
+}
+
+/// One debugging session: a VM and the connection to the editor.
+pub struct DapServer {
+    requests: Receiver<Value>,
+    out: Box<dyn Write>,
+    seq: u64,
+    vm: Option<VM>,
+    output: Rc<RefCell<Vec<u8>>>,
+    listing: Listing,
+    /// Breakpoints set on listing lines, by address.
+    source_breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+    mode: RunMode,
+    waiting_for_input: bool,
+    /// Events raised while handling a request, sent after its response.
+    events: Vec<(&'static str, Value)>,
+    done: bool,
+}
+
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl DapServer {
+    /// Creates a server reading requests from `input` (on a thread of its own,
+    /// so `pause` arrives while the program runs) and writing responses and
+    /// events to `out`.
+    pub fn new(input: impl Read + Send + 'static, out: Box<dyn Write>) -> Self {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests,
+            out,
+            seq: 0,
+            vm: None,
+            output: Rc::default(),
+            listing: Listing::default(),
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            stop_on_entry: false,
+            mode: RunMode::Stopped,
+            waiting_for_input: false,
+            events: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Serves requests until the editor disconnects.
+    pub fn serve(&mut self) -> io::Result<()> {
+        while !self.done {
+            if self.mode == RunMode::Stopped {
+                match self.requests.recv() {
+                    Ok(message) => self.dispatch(message)?,
+                    Err(_) => break,
+                }
+                continue;
+            }
+            match self.requests.try_recv() {
+                Ok(message) => self.dispatch(message)?,
+                Err(TryRecvError::Empty) => self.run_chunk()?,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.out.flush()
+    }
+
+    fn event(&mut self, event: &'static str, body: Value) {
+        self.events.push((event, body));
+    }
+
+    fn send_events(&mut self) -> io::Result<()> {
+        for (event, body) in std::mem::take(&mut self.events) {
+            self.send(json!({ "type": "event", "event": event, "body": body }))?;
+        }
+        Ok(())
+    }
+
+    fn dispatch(&mut self, message: Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+        let command = message["command"].as_str().unwrap_or_default().to_string();
+        let args = message.get("arguments").cloned().unwrap_or(Value::Null);
+        let result = self.handle(&command, &args);
+        let mut response = json!({
+            "type": "response",
+            "request_seq": message["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(RequestError(message)) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+        self.send_events()?;
+        if command == "disconnect" {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    fn vm(&mut self) -> Result<&mut VM, RequestError> {
+        self.vm.as_mut().ok_or_else(|| RequestError("no program has been launched".to_string()))
+    }
+
+    fn handle(&mut self, command: &str, args: &Value) -> RequestResult {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsSetVariable": true,
+                "supportsSteppingGranularity": true,
+            })),
+            "launch" => {
+                self.launch(args)?;
+                // Breakpoints can only be placed once there is a listing.
+                self.event("initialized", json!({}));
+                Ok(Value::Null)
+            },
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stopped("entry", None);
+                } else {
+                    self.resume(RunMode::Continue);
+                }
+                Ok(Value::Null)
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "vm" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ] })),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "source" => Ok(json!({ "content": self.listing.text(), "mimeType": "text/x-asm" })),
+            "continue" => {
+                self.vm()?;
+                self.resume(RunMode::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" => {
+                let vm = self.vm()?;
+                let mode = match vm.next_operation() {
+                    Ok(Operation::Call(_)) => RunMode::StepOver(vm.call_depth()),
+                    _ => return self.step_instruction().map(|()| Value::Null),
+                };
+                self.resume(mode);
+                Ok(Value::Null)
+            },
+            "stepIn" => self.step_instruction().map(|()| Value::Null),
+            "stepOut" => {
+                let depth = self.vm()?.call_depth();
+                self.resume(RunMode::StepOut(depth));
+                Ok(Value::Null)
+            },
+            "pause" => {
+                if self.mode != RunMode::Stopped {
+                    self.stopped("pause", None);
+                }
+                Ok(Value::Null)
+            },
+            "evaluate" => self.evaluate(args),
+            "disassemble" => self.disassemble(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(RequestError(format!("unsupported request `{command}`"))),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), RequestError> {
+        let program = args["program"].as_str().ok_or("`program` is required")?;
+        let mut vm = VM::new(io::empty(), SharedOutput(self.output.clone()));
+        match args["state"].as_str() {
+            Some(state) => vm.load_state(Path::new(state))?,
+            None => vm.load_file(Path::new(program))?,
+        }
+        if let Some(input) = args["input"].as_str() {
+            vm.provide_input(&std::fs::read(input).map_err(|err| format!("{input}: {err}"))?);
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.listing = Listing::build(&vm, 1);
+        self.vm = Some(vm);
+        Ok(())
+    }
+
+    /// Makes the VM's breakpoints the union of the source and instruction
+    /// breakpoints.
+    fn sync_breakpoints(&mut self) -> Result<(), RequestError> {
+        let wanted: BTreeSet<u16> = self.source_breakpoints.union(&self.instruction_breakpoints).copied().collect();
+        let vm = self.vm()?;
+        let existing: Vec<u16> = vm.breakpoints().collect();
+        for address in existing {
+            vm.clear_breakpoint(address);
+        }
+        for address in wanted {
+            vm.set_breakpoint(address);
+        }
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> RequestResult {
+        let lines = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut addresses = BTreeSet::new();
+        let breakpoints: Vec<Value> = lines.iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+                match line.checked_sub(1).and_then(|idx| self.listing.lines.get(idx)) {
+                    Some(listed) => {
+                        addresses.insert(listed.address);
+                        json!({
+                            "verified": true,
+                            "line": line,
+                            "source": self.listing.source(),
+                            "instructionReference": listed.address.to_string(),
+                        })
+                    },
+                    None => json!({ "verified": false, "line": line, "message": "no instruction on this line" }),
+                }
+            })
+            .collect();
+        self.source_breakpoints = addresses;
+        self.sync_breakpoints()?;
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> RequestResult {
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut addresses = BTreeSet::new();
+        let breakpoints: Vec<Value> = requested.iter()
+            .map(|breakpoint| {
+                let address = breakpoint["instructionReference"].as_str()
+                    .and_then(parse_address)
+                    .and_then(|address| {
+                        let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                        u16::try_from(address as i64 + offset).ok().filter(|&address| (address as usize) < MEMORY_SIZE)
+                    });
+                match address {
+                    Some(address) => {
+                        addresses.insert(address);
+                        json!({ "verified": true, "instructionReference": address.to_string() })
+                    },
+                    None => json!({ "verified": false, "message": "invalid instruction reference" }),
+                }
+            })
+            .collect();
+        self.instruction_breakpoints = addresses;
+        self.sync_breakpoints()?;
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn frame(&self, id: usize, name: String, address: u16) -> Value {
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": address.to_string(),
+        });
+        if let Some(&line) = self.listing.by_address.get(&address) {
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+            frame["source"] = self.listing.source();
+        }
+        frame
+    }
+
+    /// The active calls, innermost first, each named after the function it is
+    /// in.
+    fn stack_trace(&mut self) -> RequestResult {
+        let vm = self.vm()?;
+        let calls = vm.call_stack().to_vec();
+        let mut address = vm.instruction_ptr();
+        let mut frames = Vec::new();
+        for depth in (0..=calls.len()).rev() {
+            let name = match depth {
+                0 => "main".to_string(),
+                _ => format!("fn_{}", calls[depth - 1].target),
+            };
+            frames.push(self.frame(frames.len(), name, address));
+            if depth > 0 {
+                address = calls[depth - 1].call_site;
+            }
+        }
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&mut self, args: &Value) -> RequestResult {
+        let vm = self.vm()?;
+        let variable = |name: String, value: u16| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 });
+        let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => vm.registers().iter()
+                .enumerate()
+                .map(|(idx, &value)| variable(format!("r{idx}"), value))
+                .chain([variable("pc".to_string(), vm.instruction_ptr())])
+                .collect(),
+            Some(STACK_REFERENCE) => vm.stack().iter()
+                .rev()
+                .enumerate()
+                .map(|(idx, &value)| variable(format!("[{idx}]"), value))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn set_variable(&mut self, args: &Value) -> RequestResult {
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REFERENCE) {
+            return Err(RequestError("only registers can be changed".to_string()));
+        }
+        let name = args["name"].as_str().unwrap_or_default();
+        let value = args["value"].as_str()
+            .and_then(parse_address)
+            .ok_or("values must be numbers below 32768")?;
+        let vm = self.vm()?;
+        match name.strip_prefix('r').and_then(|idx| idx.parse::<usize>().ok()).filter(|&idx| idx < 8) {
+            Some(idx) => vm.registers_mut()[idx] = value,
+            None if name == "pc" => vm.set_instruction_ptr(value),
+            None => return Err(RequestError(format!("unknown register `{name}`"))),
+        }
+        Ok(json!({ "value": value.to_string() }))
+    }
+
+    fn resume(&mut self, mode: RunMode) {
+        self.mode = mode;
+        self.waiting_for_input = false;
+    }
+
+    fn step_instruction(&mut self) -> Result<(), RequestError> {
+        let result = self.vm()?.step();
+        self.send_output();
+        match result {
+            Ok(VmEvent::Halted) => self.exited(),
+            Ok(VmEvent::NeedsInput) => self.waiting_for_input(),
+            Ok(_) => self.stopped("step", None),
+            Err(err) => self.stopped("exception", Some(err.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Runs the program a little further in the current mode.
+    fn run_chunk(&mut self) -> io::Result<()> {
+        let mode = self.mode;
+        let Some(vm) = self.vm.as_mut() else {
+            self.mode = RunMode::Stopped;
+            return Ok(());
+        };
+        let result = match mode {
+            RunMode::Stopped => return Ok(()),
+            RunMode::Continue => vm.run_for(RUN_CHUNK),
+            RunMode::StepOver(depth) | RunMode::StepOut(depth) => {
+                let mut steps = 0;
+                let returned = move |vm: &VM| match mode {
+                    RunMode::StepOver(_) => vm.call_depth() <= depth,
+                    _ => vm.call_depth() < depth,
+                };
+                let result = vm.run_until(|vm| {
+                    steps += 1;
+                    steps >= RUN_CHUNK || returned(vm)
+                });
+                match result {
+                    Ok(HaltReason::Condition) if !returned(vm) => Ok(HaltReason::StepLimit),
+                    result => result,
+                }
+            },
+        };
+        self.send_output();
+        match result {
+            Ok(HaltReason::StepLimit | HaltReason::OutOfFuel) => (),
+            Ok(HaltReason::Condition) => self.stopped("step", None),
+            Ok(HaltReason::Breakpoint(_)) => self.stopped("breakpoint", None),
+            Ok(HaltReason::Watchpoint(_)) => self.stopped("data breakpoint", None),
+            Ok(HaltReason::Interrupted) => self.stopped("pause", None),
+            Ok(HaltReason::InvalidOpcode { address, opcode }) => {
+                self.stopped("exception", Some(format!("invalid opcode {opcode} at address {address}")))
+            },
+            Ok(HaltReason::Halted(_)) => self.exited(),
+            Err(VmError::InputExhausted { .. }) => self.waiting_for_input(),
+            Err(err) => self.stopped("exception", Some(err.to_string())),
+        }
+        self.send_events()
+    }
+
+    /// Queues what the program printed as an `output` event.
+    fn send_output(&mut self) {
+        let bytes = std::mem::take(&mut *self.output.borrow_mut());
+        if !bytes.is_empty() {
+            let text = String::from_utf8_lossy(&bytes).into_owned();
+            self.event("output", json!({ "category": "stdout", "output": text }));
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        self.mode = RunMode::Stopped;
+        if let Some(vm) = &self.vm {
+            if !self.listing.by_address.contains_key(&vm.instruction_ptr()) {
+                self.listing = Listing::build(vm, self.listing.reference + 1);
+            }
+        }
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+
+    fn waiting_for_input(&mut self) {
+        self.waiting_for_input = true;
+        let text = "waiting for input: type a line in the debug console".to_string();
+        self.stopped("pause", Some(text.clone()));
+        self.event("output", json!({ "category": "console", "output": format!("{text}\n") }));
+    }
+
+    fn exited(&mut self) {
+        self.mode = RunMode::Stopped;
+        self.event("exited", json!({ "exitCode": 0 }));
+        self.event("terminated", json!({}));
+    }
+
+    /// Evaluates `r0`..`r7`, `pc`, or `[addr]`. In the debug console, anything
+    /// else is sent to the program as a line of input.
+    fn evaluate(&mut self, args: &Value) -> RequestResult {
+        let expression = args["expression"].as_str().unwrap_or_default().trim();
+        let vm = self.vm()?;
+        let value = if let Some(idx) = expression.strip_prefix('r').and_then(|idx| idx.parse::<usize>().ok()).filter(|&idx| idx < 8) {
+            Some(vm.registers()[idx])
+        } else if expression == "pc" {
+            Some(vm.instruction_ptr())
+        } else {
+            expression.strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .and_then(parse_address)
+                .map(|address| vm.memory(address).unwrap_or(0))
+        };
+        if let Some(value) = value {
+            return Ok(json!({ "result": value.to_string(), "variablesReference": 0 }));
+        }
+        if args["context"] != "repl" {
+            return Err(RequestError(format!("cannot evaluate `{expression}`")));
+        }
+        vm.provide_input(format!("{expression}\n").as_bytes());
+        if self.waiting_for_input {
+            self.resume(RunMode::Continue);
+            self.event("continued", json!({ "threadId": THREAD_ID, "allThreadsContinued": true }));
+        }
+        Ok(json!({ "result": "", "variablesReference": 0 }))
+    }
+
+    fn disassemble(&mut self, args: &Value) -> RequestResult {
+        let address = number(&args["memoryReference"]).ok_or("invalid memory reference")? as u16;
+        let offset = args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0) as i64;
+        let lines = disasm::disassemble(&self.vm()?.memory_image());
+        let start = lines.partition_point(|line| line.address < address) as i64 + offset;
+        let instructions: Vec<Value> = (start..start + count)
+            .map(|idx| match usize::try_from(idx).ok().and_then(|idx| lines.get(idx)) {
+                Some(line) => {
+                    let bytes: String = line.words.iter().map(|word| format!("{word:04x} ")).collect();
+                    let instruction = match line.operation {
+                        Some(operation) => operation.to_string(),
+                        None => format!("data {}", line.words[0]),
+                    };
+                    let mut value = json!({
+                        "address": line.address.to_string(),
+                        "instructionBytes": bytes.trim_end(),
+                        "instruction": instruction,
+                    });
+                    if let Some(&listed) = self.listing.by_address.get(&line.address) {
+                        value["location"] = self.listing.source();
+                        value["line"] = json!(listed);
+                    }
+                    value
+                },
+                None => json!({ "address": "0", "instruction": "", "presentationHint": "invalid" }),
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    /// Reads memory as little-endian bytes: word `n` is bytes `2n` and `2n+1`
+    /// from the start of memory, and `offset` and `count` are in bytes.
+    fn read_memory(&mut self, args: &Value) -> RequestResult {
+        let address = number(&args["memoryReference"]).ok_or("invalid memory reference")? as i64;
+        let start = 2 * address + args["offset"].as_i64().unwrap_or(0);
+        let count = args["count"].as_u64().unwrap_or(0) as i64;
+        let end = (start + count).clamp(0, 2 * MEMORY_SIZE as i64);
+        let start = start.clamp(0, end);
+        let vm = self.vm()?;
+        let bytes: Vec<u8> = (start..end)
+            .map(|byte| vm.memory((byte / 2) as u16).unwrap_or(0).to_le_bytes()[(byte % 2) as usize])
+            .collect();
+        Ok(json!({
+            "address": (start / 2).to_string(),
+            "data": base64::encode(&bytes),
+            "unreadableBytes": count - bytes.len() as i64,
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> RequestResult {
+        let address = number(&args["memoryReference"]).ok_or("invalid memory reference")? as i64;
+        let start = 2 * address + args["offset"].as_i64().unwrap_or(0);
+        let data = args["data"].as_str().and_then(base64::decode).ok_or("`data` is not valid base64")?;
+        if start < 0 || start as usize + data.len() > 2 * MEMORY_SIZE {
+            return Err(RequestError("write runs outside memory".to_string()));
+        }
+        let vm = self.vm()?;
+        for (byte, value) in (start as usize..).zip(&data) {
+            let address = (byte / 2) as u16;
+            let mut word = vm.memory(address).unwrap_or(0).to_le_bytes();
+            word[byte % 2] = *value;
+            vm.set_memory(address, u16::from_le_bytes(word));
+        }
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+}
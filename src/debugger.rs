@@ -0,0 +1,1246 @@
+//! An interactive, line-oriented debugger wrapped around a [`VM`].
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::analysis::functions::Functions;
+use crate::asm::is_identifier;
+use crate::analysis::strings::find_strings;
+use crate::analysis::xrefs::{XrefLog, Xrefs};
+use crate::disasm;
+use crate::hexdump::hexdump;
+use crate::lineedit::LineEditor;
+use crate::log::{self, Level};
+use crate::symbols::Symbols;
+use self::expr::Expr;
+use self::input_break::{InputBreak, InputBreakMode};
+use self::macros::Macro;
+use self::op_break::OpBreak;
+use self::output_break::{OutputBreaks, OutputPattern};
+use self::tracepoint::{Template, Tracepoints};
+use crate::vm::{self, CheckpointConfig, HaltReason, HookId, Operation, VmError, VmEvent, Watchpoint, MEMORY_SIZE, VM};
+
+const HELP: &str = "\
+commands:
+  continue | c              run until a breakpoint or halt
+  step | s [n]              execute n instructions (default 1)
+  next | n                  step, running any call through to its return
+  record [on [n]|off]       record the last n (default 1000000) instructions
+                            so they can be reversed
+  reverse-step | rs [n]     undo n instructions (default 1)
+  reverse-continue | rc     undo instructions until a breakpoint
+  checkpoint <n> [k] | off  checkpoint every n instructions, keeping the
+                            last k (default 10)
+  checkpoints               list checkpoints, most recent first
+  rollback [k]              restore the kth most recent checkpoint
+  finish | fin              run until the current function returns
+  until | u <addr>          run until ip reaches addr, without setting a
+                            breakpoint there
+  advance <n>               run n more instructions, unless something else
+                            stops the program first
+  break | b <addr> [if <cond>]
+                            set a breakpoint, stopping there only when cond
+                            is nonzero
+  delete | d <addr>         clear a breakpoint
+  breakpoints | info break  list breakpoints
+  trace <addr> <message>    write message to the trace output each time addr
+                            is reached, without stopping; {expr} in it is
+                            replaced by the value of a condition-style
+                            expression, {expr:x} in hex, {expr:c} as a
+                            character
+  untrace <addr>            remove a tracepoint
+  break-output \"text\"|/re/
+                            stop as soon as the program prints text, or a
+                            line matching the regular expression re
+  break-output [off]        list the output breaks, or remove them all
+  break-op <op> [operand...]
+                            stop before every instruction with mnemonic op;
+                            each operand may be * (any), r (any register),
+                            a register, or a value or range lo-hi that the
+                            operand's value must be in
+  break-op [off]            list the opcode breaks, or remove them all
+  break-input [once|off]    stop whenever the program waits for a line of
+                            input, only the next time, or no longer
+  input <text>              queue a line of input for the program
+  tracepoints | info trace  list tracepoints
+  watch <loc>               stop after writes to loc: an address, a range
+                            start-end (inclusive), or a register r0..r7
+  unwatch <loc>             remove a watchpoint
+  watchpoints | info watch  list watchpoints
+  registers | regs          show the instruction pointer and registers
+  stack                     show the stack, top first
+  backtrace | bt            show the active calls, innermost first
+  list | l [addr] [n]       disassemble n instructions from addr (default ip)
+  display [n|off]           after each stop, disassemble n lines (default 9)
+                            around ip instead of just the next instruction
+  mem dump <addr> <n> [file]
+                            hex dump n words from addr, or write them to
+                            file as a raw binary
+  search <v> [v...]         find a sequence of words in memory
+  search \"text\"             find characters stored one per word
+  search str <text>         find decoded strings containing text
+  profile [on|off|n]        enable or disable profiling, or show the n
+                            hottest addresses (default 20)
+  xrefs <addr>              show the instructions that jump to, call, read,
+                            or write addr
+  xrefs on|off              also record the references executed from now on,
+                            including those through registers
+  label <addr> <name>       name an address
+  unlabel <addr>            remove the name of an address
+  labels                    list named addresses
+  labels save [file]        write the names to a symbol file (default the
+                            file they were loaded from)
+  labels load <file>        add the names in a symbol file
+  define <name> [cmd; ...]  define a command that runs the given commands,
+                            or the lines up to `end` that follow; $1..$9
+                            in them stand for its arguments, $* for all
+  undefine <name>           remove a defined command
+  macros                    list the defined commands
+  source <file>             run the debugger commands in a file, one per
+                            line; blank lines and lines starting with #
+                            are skipped, and quit ends the file
+  save <file>               save the VM state to a file
+  load <file>               restore the VM state from a file
+  help | h                  show this message
+  quit | q                  exit the debugger
+addresses and counts may be decimal or 0x-prefixed hex; addresses may
+also be names, optionally with an offset (name+n)
+conditions are C-like expressions over numbers, names, r0..r7, ip, sp (the
+number of values on the stack), depth (the call depth), steps, and
+mem[addr], e.g. r0 == 6 && mem[0x17b4] != 0";
+
+mod complete;
+pub mod expr;
+pub mod input_break;
+pub mod macros;
+pub mod op_break;
+pub mod output_break;
+pub mod tracepoint;
+
+const PROMPT: &str = "(vmdbg) ";
+/// The short names of commands, which macros may not take.
+const ALIASES: &[&str] = &["b", "bt", "c", "d", "fin", "h", "l", "n", "q", "rc", "regs", "rs", "s", "u"];
+/// How deeply macros may call each other, to stop runaway recursion.
+const MAX_MACRO_DEPTH: usize = 16;
+/// How deeply `source` may run scripts that source others, for the same
+/// reason.
+const MAX_SOURCE_DEPTH: usize = 16;
+const DEFAULT_RECORDING: usize = 1_000_000;
+const DEFAULT_DISPLAY: usize = 9;
+
+/// A parsed debugger command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Step(u32),
+    Next,
+    Finish,
+    Until(u16),
+    Advance(u64),
+    Record(Option<usize>),
+    RecordStatus,
+    ReverseStep(u32),
+    ReverseContinue,
+    Checkpoint(Option<CheckpointConfig>),
+    Checkpoints,
+    Rollback(usize),
+    /// Sets a breakpoint, replacing the condition of one already there.
+    Break(u16, Option<Expr>),
+    Delete(u16),
+    Breakpoints,
+    Trace(u16, Template),
+    Untrace(u16),
+    Tracepoints,
+    BreakOutput(OutputPattern),
+    OutputBreaks,
+    ClearOutputBreaks,
+    BreakOp(OpBreak),
+    OpBreaks,
+    ClearOpBreaks,
+    /// Stops at prompts in the given mode, or no longer with `None`.
+    BreakInput(Option<InputBreakMode>),
+    Input(String),
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    Watchpoints,
+    Registers,
+    Stack,
+    Backtrace,
+    List(Option<u16>, u16),
+    Display(Option<usize>),
+    MemDump(u16, u16, Option<PathBuf>),
+    Search(SearchPattern),
+    Profile(Option<bool>, usize),
+    Xrefs(u16),
+    XrefLogging(bool),
+    Label(u16, String),
+    Unlabel(u16),
+    Labels,
+    SaveLabels(Option<PathBuf>),
+    LoadLabels(PathBuf),
+    /// Defines a macro, with its body given on the following lines when
+    /// `None`.
+    Define(String, Option<Vec<String>>),
+    Undefine(String),
+    Macros,
+    Source(PathBuf),
+    Save(PathBuf),
+    Load(PathBuf),
+    Help,
+    Quit,
+}
+
+/// What the `search` command looks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchPattern {
+    /// Consecutive words with these values.
+    Words(Vec<u16>),
+    /// Consecutive words holding these characters.
+    Text(String),
+    /// Strings found by [`find_strings`] that contain this text.
+    String(String),
+}
+
+impl SearchPattern {
+    /// Parses the arguments of `search`: numbers, a quoted string, or `str`
+    /// followed by text.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(quoted) = text.strip_prefix('"') {
+            let quoted = quoted.strip_suffix('"').ok_or("unterminated string")?;
+            return match quoted.is_empty() {
+                true => Err("empty search string".to_string()),
+                false => Ok(SearchPattern::Text(quoted.to_string())),
+            };
+        }
+        if let Some(rest) = text.strip_prefix("str ") {
+            let rest = rest.trim();
+            let rest = rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).unwrap_or(rest);
+            return Ok(SearchPattern::String(rest.to_string()));
+        }
+        let words = text.split_whitespace().map(parse_number).collect::<Result<Vec<u16>, _>>()?;
+        if words.is_empty() {
+            return Err("usage: search <value>... | search \"text\" | search str <text>".to_string());
+        }
+        Ok(SearchPattern::Words(words))
+    }
+}
+
+const MAX_SEARCH_RESULTS: usize = 50;
+
+impl Command {
+    /// Parses a command line. Returns `Ok(None)` for a blank line.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        Self::parse_with(line, &Symbols::default())
+    }
+
+    /// Like [`parse`](Self::parse), also accepting the names in `symbols`
+    /// wherever an address is expected.
+    pub fn parse_with(line: &str, symbols: &Symbols) -> Result<Option<Self>, String> {
+        let address = |text: &str| parse_address(text, symbols);
+        let watchpoint = |text: &str| parse_watchpoint_with(text, symbols);
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        if name == "search" {
+            let rest = line.trim_start().strip_prefix("search").unwrap_or_default();
+            return SearchPattern::parse(rest).map(|pattern| Some(Command::Search(pattern)));
+        }
+        let args: Vec<&str> = words.collect();
+        let command = match (name, args.as_slice()) {
+            ("continue" | "c", []) => Command::Continue,
+            ("step" | "s", []) => Command::Step(1),
+            ("step" | "s", [n]) => Command::Step(parse_number(n)?),
+            ("next" | "n", []) => Command::Next,
+            ("finish" | "fin", []) => Command::Finish,
+            ("record", []) => Command::RecordStatus,
+            ("record", ["on"]) => Command::Record(Some(DEFAULT_RECORDING)),
+            ("record", ["on", n]) => Command::Record(Some(parse_number(n)?)),
+            ("record", ["off"]) => Command::Record(None),
+            ("reverse-step" | "rs", []) => Command::ReverseStep(1),
+            ("reverse-step" | "rs", [n]) => Command::ReverseStep(parse_number(n)?),
+            ("reverse-continue" | "rc", []) => Command::ReverseContinue,
+            ("checkpoint", ["off"]) => Command::Checkpoint(None),
+            ("checkpoint", [n]) => Command::Checkpoint(Some(CheckpointConfig {
+                interval: parse_number(n)?,
+                capacity: 10,
+            })),
+            ("checkpoint", [n, k]) => Command::Checkpoint(Some(CheckpointConfig {
+                interval: parse_number(n)?,
+                capacity: parse_number(k)?,
+            })),
+            ("checkpoints", []) => Command::Checkpoints,
+            ("until" | "u", [addr]) => Command::Until(address(addr)?),
+            ("advance", [n]) => Command::Advance(parse_number(n)?),
+            ("rollback", []) => Command::Rollback(1),
+            ("rollback", [k]) => Command::Rollback(parse_number(k)?),
+            ("break" | "b", [addr]) => Command::Break(address(addr)?, None),
+            ("break" | "b", [addr, "if", _, ..]) => {
+                Command::Break(address(addr)?, Some(Expr::parse(after_words(line, 3), symbols)?))
+            },
+            ("delete" | "d", [addr]) => Command::Delete(address(addr)?),
+            ("breakpoints", []) | ("info", ["break" | "breakpoints"]) => Command::Breakpoints,
+            ("trace", [addr, _, ..]) => Command::Trace(address(addr)?, Template::parse(after_words(line, 2), symbols)?),
+            ("untrace", [addr]) => Command::Untrace(address(addr)?),
+            ("tracepoints", []) | ("info", ["trace" | "tracepoints"]) => Command::Tracepoints,
+            ("break-output", []) => Command::OutputBreaks,
+            ("break-output", ["off"]) => Command::ClearOutputBreaks,
+            ("break-op", []) => Command::OpBreaks,
+            ("break-op", ["off"]) => Command::ClearOpBreaks,
+            ("break-op", [_, ..]) => Command::BreakOp(OpBreak::parse(after_words(line, 1), symbols)?),
+            ("break-input", []) => Command::BreakInput(Some(InputBreakMode::Always)),
+            ("break-input", ["once"]) => Command::BreakInput(Some(InputBreakMode::Once)),
+            ("break-input", ["off"]) => Command::BreakInput(None),
+            ("input", [_, ..]) => Command::Input(after_words(line, 1).to_string()),
+            ("break-output", [_, ..]) => Command::BreakOutput(OutputPattern::parse(after_words(line, 1))?),
+            ("watch", [location]) => Command::Watch(watchpoint(location)?),
+            ("unwatch", [location]) => Command::Unwatch(watchpoint(location)?),
+            ("watchpoints", []) | ("info", ["watch" | "watchpoints"]) => Command::Watchpoints,
+            ("registers" | "regs", []) => Command::Registers,
+            ("stack", []) => Command::Stack,
+            ("backtrace" | "bt", []) => Command::Backtrace,
+            ("list" | "l", []) => Command::List(None, 10),
+            ("list" | "l", [addr]) => Command::List(Some(address(addr)?), 10),
+            ("list" | "l", [addr, n]) => Command::List(Some(address(addr)?), parse_number(n)?),
+            ("display", []) => Command::Display(Some(DEFAULT_DISPLAY)),
+            ("display", ["off"]) => Command::Display(None),
+            ("display", [n]) => Command::Display(Some(parse_number(n)?)),
+            ("mem", ["dump", addr, n]) => Command::MemDump(address(addr)?, parse_number(n)?, None),
+            ("mem", ["dump", addr, n, path]) => {
+                Command::MemDump(address(addr)?, parse_number(n)?, Some(PathBuf::from(path)))
+            },
+            ("profile", []) => Command::Profile(None, 20),
+            ("profile", ["on"]) => Command::Profile(Some(true), 20),
+            ("profile", ["off"]) => Command::Profile(Some(false), 20),
+            ("profile", [n]) => Command::Profile(None, parse_number(n)?),
+            ("xrefs", ["on"]) => Command::XrefLogging(true),
+            ("xrefs", ["off"]) => Command::XrefLogging(false),
+            ("xrefs", [addr]) => Command::Xrefs(address(addr)?),
+            ("label", [addr, name]) => Command::Label(address(addr)?, name.to_string()),
+            ("unlabel", [addr]) => Command::Unlabel(address(addr)?),
+            ("labels", []) => Command::Labels,
+            ("labels", ["save"]) => Command::SaveLabels(None),
+            ("labels", ["save", path]) => Command::SaveLabels(Some(PathBuf::from(path))),
+            ("labels", ["load", path]) => Command::LoadLabels(PathBuf::from(path)),
+            ("define", [name]) => Command::Define(name.to_string(), None),
+            ("define", [name, _, ..]) => {
+                let body = after_words(line, 2).split(';').map(str::trim).filter(|line| !line.is_empty());
+                Command::Define(name.to_string(), Some(body.map(str::to_string).collect()))
+            },
+            ("undefine", [name]) => Command::Undefine(name.to_string()),
+            ("macros", []) => Command::Macros,
+            ("source", [path]) => Command::Source(PathBuf::from(path)),
+            ("save", [path]) => Command::Save(PathBuf::from(path)),
+            ("load", [path]) => Command::Load(PathBuf::from(path)),
+            ("help" | "h", []) => Command::Help,
+            ("quit" | "q", []) => Command::Quit,
+            _ => return Err(format!("unrecognized command `{}`; try `help`", line.trim())),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// The macro name if `line` starts a definition whose body follows it.
+fn multiline_define(line: &str) -> Option<&str> {
+    match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["define", name] => Some(name),
+        _ => None,
+    }
+}
+
+/// The rest of `line` after its first `n` words.
+fn after_words(line: &str, n: usize) -> &str {
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        rest = rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..].trim_start();
+    }
+    rest.trim_end()
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+pub fn parse_number<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("invalid number `{text}`"))
+}
+
+/// Parses a number, a name in `symbols`, or a name plus an offset
+/// (`print+3`) into an address.
+pub fn parse_address(text: &str, symbols: &Symbols) -> Result<u16, String> {
+    let (name, offset) = match text.split_once('+') {
+        Some((name, offset)) => (name, parse_number::<u16>(offset)?),
+        None => (text, 0),
+    };
+    match symbols.address(name) {
+        Some(address) => address.checked_add(offset).ok_or_else(|| format!("address `{text}` is out of range")),
+        None if text.starts_with(|c: char| c.is_ascii_digit()) => parse_number(text),
+        None => Err(format!("no symbol `{name}`")),
+    }
+}
+
+/// Parses `r3`, `100`, or `100-110` (inclusive) into a watchpoint.
+pub fn parse_watchpoint(text: &str) -> Result<Watchpoint, String> {
+    parse_watchpoint_with(text, &Symbols::default())
+}
+
+/// Like [`parse_watchpoint`], also accepting addresses as they are written
+/// for [`parse_address`].
+fn parse_watchpoint_with(text: &str, symbols: &Symbols) -> Result<Watchpoint, String> {
+    if let Some(register) = text.strip_prefix('r').filter(|register| register.starts_with(|c: char| c.is_ascii_digit())) {
+        return match parse_number(register)? {
+            register @ 0..=7 => Ok(Watchpoint::Register(register)),
+            _ => Err(format!("no such register `{text}`")),
+        };
+    }
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse_address(start, symbols)?, parse_address(end, symbols)?),
+        None => {
+            let address = parse_address(text, symbols)?;
+            (address, address)
+        },
+    };
+    if start > end {
+        return Err(format!("empty range `{text}`"));
+    }
+    Ok(Watchpoint::Memory { start, end })
+}
+
+/// Drives a VM from debugger commands, writing reports to `out`.
+pub struct Debugger {
+    vm: VM,
+    out: Box<dyn Write>,
+    // Addresses given in commands so far, offered again by completion.
+    addresses: BTreeSet<u16>,
+    // How many lines `display` shows around the instruction pointer.
+    display: Option<usize>,
+    symbols: Symbols,
+    // Where `labels save` writes by default.
+    symbols_path: Option<PathBuf>,
+    // The references recorded since `xrefs on`, and the hook recording them.
+    xref_log: Option<(Rc<RefCell<XrefLog>>, HookId)>,
+    // The conditions of conditional breakpoints, by address.
+    conditions: BTreeMap<u16, Expr>,
+    // The tracepoints, and the hook running them while there are any.
+    tracepoints: Rc<RefCell<Tracepoints>>,
+    tracepoint_hook: Option<HookId>,
+    // The output patterns to stop at, and the hook watching for them while
+    // there are any.
+    output_breaks: Rc<RefCell<OutputBreaks>>,
+    output_break_hook: Option<HookId>,
+    op_breaks: Vec<OpBreak>,
+    macros: BTreeMap<String, Macro>,
+    // How to stop at prompts, the hook tracking them, and its ID.
+    input_break: Option<(InputBreakMode, Rc<RefCell<InputBreak>>, HookId)>,
+    // How many scripts are running, each sourced by the one before.
+    source_depth: usize,
+}
+
+impl Debugger {
+    pub fn new(vm: VM, out: Box<dyn Write>) -> Self {
+        Self {
+            vm,
+            out,
+            addresses: BTreeSet::new(),
+            display: None,
+            symbols: Symbols::default(),
+            symbols_path: None,
+            xref_log: None,
+            conditions: BTreeMap::new(),
+            tracepoints: Rc::new(RefCell::new(Tracepoints::new(Box::new(io::stderr())))),
+            tracepoint_hook: None,
+            output_breaks: Rc::new(RefCell::new(OutputBreaks::default())),
+            output_break_hook: None,
+            op_breaks: Vec::new(),
+            macros: BTreeMap::new(),
+            input_break: None,
+            source_depth: 0,
+        }
+    }
+
+    /// Writes tracepoint messages to `out` instead of stderr.
+    pub fn set_trace_output(&mut self, out: Box<dyn Write>) {
+        self.tracepoints.borrow_mut().set_output(out);
+    }
+
+    /// Uses `symbols` for names in commands and listings, saving them back
+    /// to `path` by default.
+    pub fn set_symbols(&mut self, symbols: Symbols, path: Option<PathBuf>) {
+        self.symbols = symbols;
+        self.symbols_path = path;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// Reads and executes commands until `quit` or end of input. `read_line`
+    /// appends the next line to its buffer and returns the number of bytes read,
+    /// like [`io::BufRead::read_line`].
+    pub fn repl(&mut self, mut read_line: impl FnMut(&mut String) -> io::Result<usize>) -> io::Result<()> {
+        self.run_repl(|_, line| read_line(line))
+    }
+
+    /// Like [`repl`](Self::repl), reading commands through `editor` with Tab
+    /// completing from [`complete`](Self::complete).
+    pub fn repl_with_editor(&mut self, editor: &mut LineEditor) -> io::Result<()> {
+        editor.set_prompt(PROMPT);
+        self.run_repl(|debugger, line| match editor.read_line_with(&mut |text| debugger.complete(text))? {
+            Some(text) => {
+                line.push_str(&text);
+                line.push('\n');
+                Ok(line.len())
+            },
+            None => Ok(0),
+        })
+    }
+
+    /// Runs the commands in the file at `path`, as for the `source`
+    /// command. Returns `false` if the file ends with `quit`, and an error if
+    /// it cannot be read.
+    pub fn source(&mut self, path: &Path) -> io::Result<bool> {
+        let script = fs::read_to_string(path)?;
+        self.run_script(&script, &path.display().to_string())
+    }
+
+    /// Runs the commands in `script`, one per line, reporting errors with
+    /// `name` and the line number. Returns `false` on `quit`. Refuses to run
+    /// past [`MAX_SOURCE_DEPTH`] scripts deep.
+    fn run_script(&mut self, script: &str, name: &str) -> io::Result<bool> {
+        if self.source_depth == MAX_SOURCE_DEPTH {
+            writeln!(self.out, "{name}: source nested too deeply")?;
+            return Ok(true);
+        }
+        self.source_depth += 1;
+        let result = self.run_script_lines(script, name);
+        self.source_depth -= 1;
+        result
+    }
+
+    fn run_script_lines(&mut self, script: &str, name: &str) -> io::Result<bool> {
+        let mut lines = script.lines().enumerate().filter(|(_, line)| !line.trim_start().starts_with('#'));
+        while let Some((idx, line)) = lines.next() {
+            let context = format!("{name}:{}: ", idx + 1);
+            if let Some(macro_name) = multiline_define(line) {
+                let body: Vec<String> = lines.by_ref()
+                    .map(|(_, line)| line.trim())
+                    .take_while(|line| *line != "end")
+                    .map(str::to_string)
+                    .collect();
+                self.execute(Command::Define(macro_name.to_string(), Some(body)))?;
+                continue;
+            }
+            if !self.run_line(line, &context, 0)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_repl(&mut self, mut read_line: impl FnMut(&Self, &mut String) -> io::Result<usize>) -> io::Result<()> {
+        self.show_location()?;
+        loop {
+            write!(self.out, "{PROMPT}")?;
+            self.out.flush()?;
+            let mut line = String::new();
+            if read_line(self, &mut line)? == 0 {
+                return Ok(());
+            }
+            if let Some(name) = multiline_define(&line) {
+                writeln!(self.out, "enter the commands for {name}, one per line, then `end`")?;
+                let mut body = Vec::new();
+                loop {
+                    write!(self.out, "> ")?;
+                    self.out.flush()?;
+                    let mut line = String::new();
+                    if read_line(self, &mut line)? == 0 || line.trim() == "end" {
+                        break;
+                    }
+                    body.push(line.trim().to_string());
+                }
+                self.execute(Command::Define(name.to_string(), Some(body)))?;
+                continue;
+            }
+            if !self.run_line(&line, "", 0)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs one command line, expanding macros, and writes any error after
+    /// `context`. Returns `false` on `quit`.
+    fn run_line(&mut self, line: &str, context: &str, depth: usize) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        if let Some(found) = words.next().and_then(|name| self.macros.get(name)) {
+            if depth == MAX_MACRO_DEPTH {
+                writeln!(self.out, "{context}macros nested more than {MAX_MACRO_DEPTH} deep")?;
+                return Ok(true);
+            }
+            let args: Vec<&str> = words.collect();
+            match found.expand(&args) {
+                Ok(lines) => {
+                    for line in lines {
+                        if !self.run_line(&line, context, depth + 1)? {
+                            return Ok(false);
+                        }
+                    }
+                },
+                Err(message) => writeln!(self.out, "{context}{message}")?,
+            }
+            return Ok(true);
+        }
+        match Command::parse_with(line, &self.symbols) {
+            Ok(Some(Command::Quit)) => return Ok(false),
+            Ok(Some(command)) => self.execute(command)?,
+            Ok(None) => (),
+            Err(message) => writeln!(self.out, "{context}{message}")?,
+        }
+        Ok(true)
+    }
+
+    /// Executes a single command.
+    pub fn execute(&mut self, command: Command) -> io::Result<()> {
+        self.remember_addresses(&command);
+        match command {
+            Command::Continue => {
+                if self.vm.is_halted() {
+                    return writeln!(self.out, "the program has halted");
+                }
+                let result = self.run(|_| false);
+                self.report(result)?;
+            },
+            Command::Step(n) => {
+                for _ in 0..n {
+                    if self.vm.is_halted() {
+                        break;
+                    }
+                    match self.vm.step() {
+                        Ok(VmEvent::NeedsInput) => {
+                            let address = self.vm.instruction_ptr();
+                            return self.report(Err(VmError::InputExhausted { address }));
+                        },
+                        Ok(_) => (),
+                        Err(err) => return self.report(Err(err)),
+                    }
+                    if let Some(hit) = self.vm.take_watch_hit() {
+                        self.vm.flush_output()?;
+                        return self.report(Ok(HaltReason::Watchpoint(hit)));
+                    }
+                    if self.output_breaks.borrow().is_hit() {
+                        self.vm.flush_output()?;
+                        return self.report(Ok(HaltReason::Condition));
+                    }
+                }
+                self.vm.flush_output()?;
+                if self.vm.is_halted() {
+                    return self.report(Ok(HaltReason::Halted(self.vm.halt_cause().unwrap_or_default())));
+                }
+                self.show_location()?;
+            },
+            Command::Next => {
+                if !matches!(self.vm.next_operation(), Ok(Operation::Call(_))) {
+                    return self.execute(Command::Step(1));
+                }
+                let depth = self.vm.call_depth();
+                let result = self.run(|vm| vm.call_depth() <= depth);
+                self.report(result)?;
+            },
+            Command::Finish => {
+                let depth = self.vm.call_depth();
+                let result = self.run(|vm| vm.call_depth() < depth);
+                self.report(result)?;
+            },
+            Command::Until(address) => {
+                if self.vm.is_halted() {
+                    return writeln!(self.out, "the program has halted");
+                }
+                let result = self.run(|vm| vm.instruction_ptr() == address);
+                self.report(result)?;
+            },
+            Command::Advance(n) => {
+                if self.vm.is_halted() {
+                    return writeln!(self.out, "the program has halted");
+                }
+                let target = self.vm.steps().saturating_add(n);
+                let result = self.run(|vm| vm.steps() >= target);
+                self.report(result)?;
+            },
+            Command::Record(capacity) => {
+                self.vm.set_recording(capacity);
+                match capacity {
+                    Some(n) => writeln!(self.out, "recording the last {n} instructions")?,
+                    None => writeln!(self.out, "recording stopped")?,
+                }
+            },
+            Command::RecordStatus => {
+                if self.vm.is_recording() {
+                    writeln!(self.out, "{} instructions can be reversed", self.vm.recorded_steps())?;
+                } else {
+                    writeln!(self.out, "not recording; use `record on`")?;
+                }
+            },
+            Command::ReverseStep(n) => {
+                for _ in 0..n {
+                    if !self.vm.step_back() {
+                        writeln!(self.out, "reached the start of the recording")?;
+                        break;
+                    }
+                }
+                self.show_location()?;
+            },
+            Command::ReverseContinue => {
+                let mut stop = self.vm.run_back();
+                while stop.is_some_and(|address| !self.condition_holds(address)) {
+                    stop = self.vm.run_back();
+                }
+                match stop {
+                    Some(address) => writeln!(self.out, "breakpoint at {address}")?,
+                    None => writeln!(self.out, "reached the start of the recording")?,
+                }
+                self.show_location()?;
+            },
+            Command::Checkpoint(config) => {
+                self.vm.set_checkpoints(config);
+                match config {
+                    Some(CheckpointConfig { interval, capacity }) => writeln!(
+                        self.out, "checkpointing every {interval} instructions, keeping {capacity}",
+                    )?,
+                    None => writeln!(self.out, "checkpoints disabled")?,
+                }
+            },
+            Command::Checkpoints => {
+                let steps = self.vm.checkpoint_steps();
+                if steps.is_empty() {
+                    writeln!(self.out, "no checkpoints")?;
+                }
+                for (idx, steps) in steps.iter().enumerate() {
+                    writeln!(self.out, "  {}: step {steps}", idx + 1)?;
+                }
+            },
+            Command::Rollback(k) => {
+                if self.vm.rollback(k) {
+                    writeln!(self.out, "rolled back to step {}", self.vm.steps())?;
+                    self.show_location()?;
+                } else {
+                    writeln!(self.out, "no checkpoint {k}")?;
+                }
+            },
+            Command::Break(address, condition) => {
+                let location = self.symbols.describe(address);
+                let is_new = self.vm.set_breakpoint(address);
+                let changed = match condition {
+                    Some(condition) => self.conditions.insert(address, condition.clone()).as_ref() != Some(&condition),
+                    None => self.conditions.remove(&address).is_some(),
+                };
+                match (is_new, changed) {
+                    (true, _) => writeln!(self.out, "breakpoint set at {location}")?,
+                    (false, true) => writeln!(self.out, "breakpoint condition at {location} changed")?,
+                    (false, false) => writeln!(self.out, "breakpoint already set at {location}")?,
+                }
+            },
+            Command::Delete(address) => {
+                let location = self.symbols.describe(address);
+                self.conditions.remove(&address);
+                if self.vm.clear_breakpoint(address) {
+                    writeln!(self.out, "breakpoint at {location} deleted")?;
+                } else {
+                    writeln!(self.out, "no breakpoint at {location}")?;
+                }
+            },
+            Command::Breakpoints => {
+                let breakpoints: Vec<u16> = self.vm.breakpoints().collect();
+                if breakpoints.is_empty() {
+                    writeln!(self.out, "no breakpoints")?;
+                }
+                for address in breakpoints {
+                    match self.conditions.get(&address) {
+                        Some(condition) => writeln!(self.out, "  {} if {condition}", self.symbols.describe(address))?,
+                        None => writeln!(self.out, "  {}", self.symbols.describe(address))?,
+                    }
+                }
+            },
+            Command::Trace(address, message) => {
+                let location = self.symbols.describe(address);
+                match self.tracepoints.borrow_mut().insert(address, message) {
+                    Some(_) => writeln!(self.out, "tracepoint message at {location} changed")?,
+                    None => writeln!(self.out, "tracepoint set at {location}")?,
+                }
+                self.tracepoint_hook.get_or_insert_with(|| self.vm.add_hook(self.tracepoints.clone()));
+            },
+            Command::Untrace(address) => {
+                let location = self.symbols.describe(address);
+                let mut tracepoints = self.tracepoints.borrow_mut();
+                match tracepoints.remove(address) {
+                    Some(_) => writeln!(self.out, "tracepoint at {location} deleted")?,
+                    None => writeln!(self.out, "no tracepoint at {location}")?,
+                }
+                if tracepoints.is_empty() {
+                    if let Some(id) = self.tracepoint_hook.take() {
+                        self.vm.remove_hook(id);
+                    }
+                }
+            },
+            Command::Tracepoints => {
+                let tracepoints = self.tracepoints.borrow();
+                if tracepoints.is_empty() {
+                    writeln!(self.out, "no tracepoints")?;
+                }
+                for (address, message) in tracepoints.iter() {
+                    writeln!(self.out, "  {}: {message}", self.symbols.describe(address))?;
+                }
+            },
+            Command::BreakOutput(pattern) => {
+                if self.output_breaks.borrow_mut().add(pattern.clone()) {
+                    writeln!(self.out, "stopping when the program prints {pattern}")?;
+                } else {
+                    writeln!(self.out, "already stopping at {pattern}")?;
+                }
+                self.output_break_hook.get_or_insert_with(|| self.vm.add_hook(self.output_breaks.clone()));
+            },
+            Command::OutputBreaks => {
+                let output_breaks = self.output_breaks.borrow();
+                if output_breaks.patterns().is_empty() {
+                    writeln!(self.out, "no output breaks")?;
+                }
+                for pattern in output_breaks.patterns() {
+                    writeln!(self.out, "  {pattern}")?;
+                }
+            },
+            Command::ClearOutputBreaks => {
+                self.output_breaks.borrow_mut().clear();
+                if let Some(id) = self.output_break_hook.take() {
+                    self.vm.remove_hook(id);
+                }
+                writeln!(self.out, "output breaks removed")?;
+            },
+            Command::BreakOp(op_break) => {
+                if self.op_breaks.contains(&op_break) {
+                    writeln!(self.out, "already stopping before {op_break}")?;
+                } else {
+                    writeln!(self.out, "stopping before {op_break}")?;
+                    self.op_breaks.push(op_break);
+                }
+            },
+            Command::OpBreaks => {
+                if self.op_breaks.is_empty() {
+                    writeln!(self.out, "no opcode breaks")?;
+                }
+                for op_break in &self.op_breaks {
+                    writeln!(self.out, "  {op_break}")?;
+                }
+            },
+            Command::ClearOpBreaks => {
+                self.op_breaks.clear();
+                writeln!(self.out, "opcode breaks removed")?;
+            },
+            Command::BreakInput(Some(mode)) => {
+                match &mut self.input_break {
+                    Some((current, _, _)) => *current = mode,
+                    None => {
+                        let tracker = Rc::new(RefCell::new(InputBreak::default()));
+                        let id = self.vm.add_hook(tracker.clone());
+                        self.input_break = Some((mode, tracker, id));
+                    },
+                }
+                match mode {
+                    InputBreakMode::Once => writeln!(self.out, "stopping at the next prompt")?,
+                    InputBreakMode::Always => writeln!(self.out, "stopping at every prompt")?,
+                }
+            },
+            Command::BreakInput(None) => {
+                if let Some((_, _, id)) = self.input_break.take() {
+                    self.vm.remove_hook(id);
+                }
+                writeln!(self.out, "no longer stopping at prompts")?;
+            },
+            Command::Input(text) => {
+                self.vm.provide_input(format!("{text}\n").as_bytes());
+                writeln!(self.out, "queued {} bytes of input", text.len() + 1)?;
+            },
+            Command::Watch(watchpoint) => {
+                if self.vm.add_watchpoint(watchpoint) {
+                    writeln!(self.out, "watching {watchpoint}")?;
+                } else {
+                    writeln!(self.out, "already watching {watchpoint}")?;
+                }
+            },
+            Command::Unwatch(watchpoint) => {
+                if self.vm.remove_watchpoint(watchpoint) {
+                    writeln!(self.out, "no longer watching {watchpoint}")?;
+                } else {
+                    writeln!(self.out, "not watching {watchpoint}")?;
+                }
+            },
+            Command::Watchpoints => {
+                if self.vm.watchpoints().is_empty() {
+                    writeln!(self.out, "no watchpoints")?;
+                }
+                for watchpoint in self.vm.watchpoints() {
+                    writeln!(self.out, "  {watchpoint}")?;
+                }
+            },
+            Command::Registers => {
+                writeln!(self.out, "ip = {}", self.vm.instruction_ptr())?;
+                for (idx, value) in self.vm.registers().iter().enumerate() {
+                    writeln!(self.out, "r{idx} = {value}")?;
+                }
+            },
+            Command::Stack => {
+                if self.vm.stack().is_empty() {
+                    writeln!(self.out, "stack is empty")?;
+                }
+                for (depth, value) in self.vm.stack().iter().rev().enumerate() {
+                    writeln!(self.out, "  #{depth}: {value}")?;
+                }
+            },
+            Command::Backtrace => {
+                let frames = self.vm.call_stack();
+                let function = |depth: usize| {
+                    frames.get(depth).map_or("<entry>".to_string(), |frame| self.symbols.describe(frame.target))
+                };
+                writeln!(self.out, "#0  {:5} in {}", self.vm.instruction_ptr(), function(frames.len().wrapping_sub(1)))?;
+                for (idx, frame) in frames.iter().enumerate().rev() {
+                    let depth = frames.len() - idx;
+                    writeln!(
+                        self.out, "#{depth:<2} {:5} in {} (returns to {})",
+                        frame.call_site, function(idx.wrapping_sub(1)), frame.return_address,
+                    )?;
+                }
+            },
+            Command::List(address, n) => {
+                let mut address = address.unwrap_or(self.vm.instruction_ptr());
+                for _ in 0..n {
+                    let Some(operation) = self.vm.operation_at(address) else {
+                        break;
+                    };
+                    self.show_operation(address)?;
+                    address = address.wrapping_add(operation.size());
+                }
+            },
+            Command::Display(rows) => {
+                self.display = rows;
+                self.show_location()?;
+            },
+            Command::MemDump(start, len, path) => {
+                let end = (start as usize + len as usize).min(MEMORY_SIZE);
+                let words: Vec<Option<u16>> = (start as usize..end)
+                    .map(|address| self.vm.memory(address as u16))
+                    .collect();
+                match path {
+                    Some(path) => {
+                        let words: Vec<u16> = words.iter().map(|word| word.unwrap_or(0)).collect();
+                        match fs::write(&path, vm::encode_image(&words)) {
+                            Ok(()) => writeln!(self.out, "{} words written to {}", words.len(), path.display())?,
+                            Err(err) => writeln!(self.out, "could not write {}: {err}", path.display())?,
+                        }
+                    },
+                    None => hexdump(start, &words, &mut self.out)?,
+                }
+            },
+            Command::Search(pattern) => self.search(&pattern)?,
+            Command::Profile(Some(enabled), _) => {
+                self.vm.set_profiling(enabled);
+                writeln!(self.out, "profiling {}", if enabled { "enabled" } else { "disabled" })?;
+            },
+            Command::Profile(None, top) => match self.vm.profile() {
+                Some(profile) => profile.report(&self.vm, top, &mut self.out)?,
+                None => writeln!(self.out, "profiling is disabled; use `profile on`")?,
+            },
+            Command::Xrefs(address) => self.xrefs(address)?,
+            Command::XrefLogging(true) => {
+                if self.xref_log.is_none() {
+                    let log = Rc::new(RefCell::new(XrefLog::default()));
+                    let id = self.vm.add_hook(log.clone());
+                    self.xref_log = Some((log, id));
+                }
+                writeln!(self.out, "recording references")?;
+            },
+            Command::XrefLogging(false) => {
+                if let Some((_, id)) = self.xref_log.take() {
+                    self.vm.remove_hook(id);
+                }
+                writeln!(self.out, "no longer recording references")?;
+            },
+            Command::Label(address, name) => match self.symbols.insert(address, &name) {
+                Ok(()) => writeln!(self.out, "{address} is now {name}")?,
+                Err(message) => writeln!(self.out, "{message}")?,
+            },
+            Command::Unlabel(address) => match self.symbols.remove(address) {
+                Some(name) => writeln!(self.out, "{address} is no longer {name}")?,
+                None => writeln!(self.out, "{address} has no name")?,
+            },
+            Command::Labels => {
+                if self.symbols.is_empty() {
+                    writeln!(self.out, "no labels")?;
+                }
+                for (address, name) in self.symbols.iter() {
+                    writeln!(self.out, "  {address:5} {name}")?;
+                }
+            },
+            Command::SaveLabels(path) => {
+                let Some(path) = path.or_else(|| self.symbols_path.clone()) else {
+                    return writeln!(self.out, "no symbol file was loaded; use `labels save <file>`");
+                };
+                match fs::write(&path, self.symbols.to_string()) {
+                    Ok(()) => writeln!(self.out, "{} labels written to {}", self.symbols.len(), path.display())?,
+                    Err(err) => writeln!(self.out, "could not write {}: {err}", path.display())?,
+                }
+            },
+            Command::LoadLabels(path) => {
+                let loaded = fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| Symbols::parse(&text).map_err(|err| err.to_string()));
+                match loaded {
+                    Ok(symbols) => {
+                        for (address, name) in symbols.iter() {
+                            self.symbols.insert(address, name).expect("Parsed names should be valid.");
+                        }
+                        writeln!(self.out, "{} labels loaded from {}", symbols.len(), path.display())?;
+                        self.symbols_path.get_or_insert(path);
+                    },
+                    Err(err) => writeln!(self.out, "could not load {}: {err}", path.display())?,
+                }
+            },
+            Command::Define(name, Some(body)) => {
+                if !is_identifier(&name) || complete::COMMANDS.contains(&name.as_str()) || ALIASES.contains(&name.as_str()) {
+                    return writeln!(self.out, "`{name}` cannot be a command name");
+                }
+                match self.macros.insert(name.clone(), Macro::new(body)) {
+                    Some(_) => writeln!(self.out, "{name} redefined")?,
+                    None => writeln!(self.out, "{name} defined")?,
+                }
+            },
+            Command::Define(name, None) => {
+                writeln!(self.out, "usage: define {name} <command>[; <command>...]")?;
+            },
+            Command::Undefine(name) => match self.macros.remove(&name) {
+                Some(_) => writeln!(self.out, "{name} undefined")?,
+                None => writeln!(self.out, "no command {name} defined")?,
+            },
+            Command::Macros => {
+                if self.macros.is_empty() {
+                    writeln!(self.out, "no commands defined")?;
+                }
+                for (name, found) in &self.macros {
+                    writeln!(self.out, "  {name}")?;
+                    for line in found.body() {
+                        writeln!(self.out, "    {line}")?;
+                    }
+                }
+            },
+            Command::Source(path) => match fs::read_to_string(&path) {
+                Ok(script) => {
+                    self.run_script(&script, &path.display().to_string())?;
+                },
+                Err(err) => writeln!(self.out, "could not read {}: {err}", path.display())?,
+            },
+            Command::Save(path) => match self.vm.save_state(&path) {
+                Ok(()) => writeln!(self.out, "state saved to {}", path.display())?,
+                Err(err) => writeln!(self.out, "could not save state: {err}")?,
+            },
+            Command::Load(path) => match self.vm.load_state(&path) {
+                Ok(()) => self.show_location()?,
+                Err(err) => writeln!(self.out, "could not load state: {err}")?,
+            },
+            Command::Help => writeln!(self.out, "{HELP}")?,
+            Command::Quit => (),
+        }
+        Ok(())
+    }
+
+    /// Runs the VM until `stop` returns `true`, the program prints text an
+    /// output break is waiting for, the next instruction matches an opcode
+    /// break, it waits at a prompt with `break-input` on, or it stops for
+    /// another reason, resuming past breakpoints whose conditions are false.
+    fn run(&mut self, mut stop: impl FnMut(&VM) -> bool) -> Result<HaltReason, VmError> {
+        let output_breaks = self.output_breaks.clone();
+        output_breaks.borrow_mut().take_hit();
+        let input_break = self.input_break.as_ref().map(|(_, tracker, _)| tracker.clone());
+        let at_prompt = |vm: &VM| input_break.as_ref().is_some_and(|tracker| tracker.borrow().is_waiting(vm));
+        let op_breaks = self.op_breaks.clone();
+        let at_op = |vm: &VM| op_breaks.iter().any(|op_break| op_break.matches(vm));
+        loop {
+            let result = self.vm.run_until(|vm| {
+                stop(vm) || output_breaks.borrow().is_hit() || at_op(vm) || at_prompt(vm)
+            });
+            match result {
+                Ok(HaltReason::Breakpoint(address)) if !self.condition_holds(address) => (),
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether a breakpoint at `address` should stop: it has no condition, its
+    /// condition is true, or its condition could not be evaluated.
+    fn condition_holds(&self, address: u16) -> bool {
+        self.conditions.get(&address).is_none_or(|condition| condition.is_true(&self.vm) != Ok(false))
+    }
+
+    fn report(&mut self, result: Result<HaltReason, VmError>) -> io::Result<()> {
+        match result {
+            Ok(HaltReason::Halted(_)) => writeln!(self.out, "the program has halted"),
+            Ok(HaltReason::Breakpoint(address)) => {
+                log::event(Level::Debug, "debugger", "breakpoint", &[("address", &address), ("steps", &self.vm.steps())]);
+                writeln!(self.out, "breakpoint at {}", self.symbols.describe(address))?;
+                if let Some(Err(err)) = self.conditions.get(&address).map(|condition| condition.eval(&self.vm)) {
+                    writeln!(self.out, "could not evaluate condition `{}`: {err}", self.conditions[&address])?;
+                }
+                self.show_location()
+            },
+            Ok(HaltReason::Watchpoint(hit)) => {
+                writeln!(self.out, "watchpoint: {hit}")?;
+                self.show_location()
+            },
+            Ok(HaltReason::Interrupted) => {
+                writeln!(self.out, "interrupted")?;
+                self.show_location()
+            },
+            Ok(HaltReason::InvalidOpcode { address, opcode }) => {
+                writeln!(self.out, "invalid opcode {opcode} at {address}")?;
+                self.show_location()
+            },
+            Ok(HaltReason::Condition) => {
+                let mut output_breaks = self.output_breaks.borrow_mut();
+                if let Some(pattern) = output_breaks.take_hit() {
+                    let newline = if output_breaks.mid_line() { "\n" } else { "" };
+                    writeln!(self.out, "{newline}output matched {pattern}")?;
+                }
+                drop(output_breaks);
+                if let Some(op_break) = self.op_breaks.iter().find(|op_break| op_break.matches(&self.vm)) {
+                    writeln!(self.out, "instruction matches {op_break}")?;
+                }
+                if let Some((mode, tracker, id)) = &self.input_break {
+                    if tracker.borrow().is_waiting(&self.vm) {
+                        writeln!(self.out, "waiting for input")?;
+                        if *mode == InputBreakMode::Once {
+                            self.vm.remove_hook(*id);
+                            self.input_break = None;
+                        }
+                    }
+                }
+                self.show_location()
+            },
+            Ok(HaltReason::StepLimit | HaltReason::OutOfFuel) => self.show_location(),
+            Err(err) => {
+                log::event(Level::Debug, "debugger", "fault", &[("address", &self.vm.instruction_ptr()), ("error", &err)]);
+                writeln!(self.out, "fault: {err}")?;
+                self.show_location()
+            },
+        }
+    }
+
+    fn search(&mut self, pattern: &SearchPattern) -> io::Result<()> {
+        let memory = self.vm.memory_image();
+        let matches: Vec<String> = match pattern {
+            SearchPattern::Words(needle) => find_words(&memory, needle)
+                .map(|address| format!("{address:5}"))
+                .collect(),
+            SearchPattern::Text(text) => {
+                let needle: Vec<u16> = text.bytes().map(u16::from).collect();
+                find_words(&memory, &needle)
+                    .map(|address| format!("{address:5}"))
+                    .collect()
+            },
+            SearchPattern::String(text) => find_strings(&memory, 1)
+                .into_iter()
+                .filter(|string| string.text.contains(text.as_str()))
+                .map(|string| string.to_string())
+                .collect(),
+        };
+        if matches.is_empty() {
+            return writeln!(self.out, "no matches");
+        }
+        for line in matches.iter().take(MAX_SEARCH_RESULTS) {
+            writeln!(self.out, "{line}")?;
+        }
+        if matches.len() > MAX_SEARCH_RESULTS {
+            writeln!(self.out, "... {} more", matches.len() - MAX_SEARCH_RESULTS)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the references to `address` in the code reachable from address
+    /// 0 and the active calls, plus those recorded since `xrefs on`.
+    fn xrefs(&mut self, address: u16) -> io::Result<()> {
+        let entries: Vec<u16> = [0].into_iter().chain(self.vm.call_stack().iter().map(|frame| frame.target)).collect();
+        let functions = Functions::detect_including(&self.vm.memory_image(), &entries, self.vm.instruction_ptr());
+        let mut xrefs = Xrefs::from_cfg(&functions.cfg);
+        if let Some((log, _)) = &self.xref_log {
+            xrefs.add_observed(&log.borrow());
+        }
+        let refs = xrefs.to(address);
+        if refs.is_empty() {
+            writeln!(self.out, "no references to {}", self.symbols.describe(address))?;
+        } else {
+            writeln!(self.out, "references to {}:", self.symbols.describe(address))?;
+        }
+        for (xref, source) in refs {
+            let function = functions.containing(xref.from)
+                .map_or_else(|| "?".to_string(), |function| self.symbols.describe(function.entry));
+            writeln!(self.out, "  {:5} in {function}: {:<5} ({source})", xref.from, xref.kind)?;
+        }
+        if self.xref_log.is_none() {
+            writeln!(self.out, "only literal references in reachable code; use `xrefs on` to record others")?;
+        }
+        Ok(())
+    }
+
+    /// Shows the next instruction or, with `display` on, the disassembly
+    /// around it, decoded afresh so rewritten code shows as it now is.
+    fn show_location(&mut self) -> io::Result<()> {
+        let ip = self.vm.instruction_ptr();
+        let Some(rows) = self.display else {
+            return self.show_operation(ip);
+        };
+        for line in disasm::around(&self.vm.memory_image(), ip, rows / 2, rows) {
+            let marker = match (line.address == ip, self.vm.breakpoints().any(|address| address == line.address)) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            if let Some(name) = self.symbols.name(line.address) {
+                writeln!(self.out, "  {name}:")?;
+            }
+            writeln!(self.out, "{marker}{}", line.annotated(&self.symbols))?;
+        }
+        Ok(())
+    }
+
+    fn show_operation(&mut self, address: u16) -> io::Result<()> {
+        let marker = if address == self.vm.instruction_ptr() { "=>" } else { "  " };
+        if let Some(name) = self.symbols.name(address) {
+            writeln!(self.out, "   {name}:")?;
+        }
+        match self.vm.operation_at(address) {
+            Some(operation) => match self.symbols.target(&operation) {
+                Some(name) => writeln!(self.out, "{marker} {address:5}: {:<24} ; {name}", operation.to_string()),
+                None => writeln!(self.out, "{marker} {address:5}: {operation}"),
+            },
+            None => writeln!(self.out, "{marker} {address:5}: <invalid>"),
+        }
+    }
+}
+
+/// The addresses at which `needle` occurs in `memory`.
+fn find_words<'a>(memory: &'a [u16], needle: &'a [u16]) -> impl Iterator<Item = u16> + 'a {
+    memory.windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(address, _)| address as u16)
+}
@@ -0,0 +1,112 @@
+//! Tab completion for debugger command lines.
+
+use std::fs;
+use std::path::Path;
+
+use super::{Command, Debugger};
+use crate::vm::{Operation, Watchpoint};
+
+/// Command names offered when completing the first word.
+pub(super) const COMMANDS: &[&str] = &[
+    "advance", "backtrace", "break", "break-input", "break-op", "break-output", "breakpoints", "checkpoint",
+    "checkpoints", "continue", "define", "delete", "display", "finish", "help", "info", "input", "label",
+    "labels", "list", "load", "macros", "mem", "next", "profile", "quit", "record", "registers",
+    "reverse-continue", "reverse-step", "rollback", "save", "search", "source", "stack", "step", "trace",
+    "tracepoints", "undefine", "unlabel", "until", "untrace", "unwatch", "watch", "watchpoints", "xrefs",
+];
+
+const REGISTERS: &[&str] = &["r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7"];
+
+impl Debugger {
+    /// The completions for the word ending `line`, which is a command line up
+    /// to the cursor: command names, keywords, register names, file names for
+    /// `save` and `load`, and symbol names and addresses the session has
+    /// used, written in hex if the word starts with `0x`.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let word = match line.ends_with(char::is_whitespace) {
+            true => "",
+            false => words.pop().unwrap_or_default(),
+        };
+        let candidates: Vec<String> = match words.as_slice() {
+            [] => COMMANDS.iter().map(|name| name.to_string()).chain(self.macros.keys().cloned()).collect(),
+            ["undefine"] => self.macros.keys().cloned().collect(),
+            ["record" | "profile"] => keywords(&["on", "off"]),
+            ["xrefs"] => keywords(&["on", "off"]).into_iter().chain(self.address_completions(word)).collect(),
+            ["checkpoint" | "display" | "break-output"] => keywords(&["off"]),
+            ["break-op"] => {
+                let mnemonics = (0..).map_while(Operation::mnemonic_for).map(str::to_string);
+                keywords(&["off"]).into_iter().chain(mnemonics).collect()
+            },
+            ["break-input"] => keywords(&["once", "off"]),
+            ["info"] => keywords(&["break", "trace", "watch"]),
+            ["mem"] => keywords(&["dump"]),
+            ["labels"] => keywords(&["load", "save"]),
+            ["save" | "load" | "source"] | ["labels", "save" | "load"] => return complete_path(word),
+            ["mem", "dump", _, _] => return complete_path(word),
+            ["watch" | "unwatch"] => keywords(REGISTERS).into_iter().chain(self.address_completions(word)).collect(),
+            ["break" | "b", _] => keywords(&["if"]),
+            ["delete" | "d"] => self.vm.breakpoints().map(|address| format_address(address, word)).collect(),
+            ["untrace"] => self.tracepoints.borrow().iter().map(|(address, _)| format_address(address, word)).collect(),
+            ["break" | "b" | "trace" | "until" | "u" | "list" | "l" | "label" | "unlabel"] | ["mem", "dump"] => {
+                self.address_completions(word)
+            },
+            _ => Vec::new(),
+        };
+        candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect()
+    }
+
+    /// Notes the addresses `command` refers to, for completion.
+    pub(super) fn remember_addresses(&mut self, command: &Command) {
+        let address = match *command {
+            Command::Break(address, _) | Command::Delete(address) | Command::Unlabel(address) => address,
+            Command::Trace(address, _) | Command::Untrace(address) | Command::Until(address) => address,
+            Command::Label(address, _) | Command::Xrefs(address) => address,
+            Command::List(Some(address), _) | Command::MemDump(address, _, _) => address,
+            Command::Watch(Watchpoint::Memory { start, .. }) => start,
+            _ => return,
+        };
+        self.addresses.insert(address);
+    }
+
+    fn address_completions(&self, word: &str) -> Vec<String> {
+        let mut addresses = self.addresses.clone();
+        addresses.extend(self.vm.breakpoints());
+        addresses.insert(self.vm.instruction_ptr());
+        let names = self.symbols.iter().map(|(_, name)| name.to_string());
+        addresses.into_iter().map(|address| format_address(address, word)).chain(names).collect()
+    }
+}
+
+fn keywords(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// Writes `address` in the same base as `word`.
+fn format_address(address: u16, word: &str) -> String {
+    match word.starts_with("0x") {
+        true => format!("0x{address:x}"),
+        false => address.to_string(),
+    }
+}
+
+/// The entries of the directory named by `word` that start with its last
+/// component, with `/` after directories.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(idx) => (&word[..=idx], &word[idx + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = entries.filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            name.starts_with(prefix).then(|| format!("{dir}{name}{suffix}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
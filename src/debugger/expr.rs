@@ -0,0 +1,272 @@
+//! Expressions over the state of a VM, for breakpoint conditions.
+//!
+//! The syntax is C-like: decimal and `0x` hex numbers, symbol names, the
+//! registers `r0`..`r7`, `ip`, `sp` (the number of values on the stack),
+//! `depth` (the call depth), `steps` (instructions executed), and `mem[e]`,
+//! combined with `|| && | ^ & == != < <= > >= + - * / %` and the prefix
+//! operators `! - ~`, with C's precedence. Values are 64-bit signed integers
+//! and comparisons yield 0 or 1, so `r0 + r1 > 32767` can detect overflow.
+
+use std::fmt;
+
+use crate::symbols::Symbols;
+use crate::vm::{MEMORY_SIZE, VM};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// The binary operators at each precedence level, loosest first.
+const LEVELS: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[("<=", BinaryOp::Le), ("<", BinaryOp::Lt), (">=", BinaryOp::Ge), (">", BinaryOp::Gt)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(usize),
+    Ip,
+    Sp,
+    Depth,
+    Steps,
+    Memory(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// A parsed expression, displayed as it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    /// Parses `text`, resolving names in `symbols` to their addresses.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::debugger::expr::Expr;
+    /// use oscon_2012_vm_challenge::symbols::Symbols;
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.registers_mut()[0] = 6;
+    /// vm.set_memory(0x17b4, 1);
+    /// let condition = Expr::parse("r0 == 6 && mem[0x17b4] != 0", &Symbols::default()).unwrap();
+    /// assert_eq!(condition.is_true(&vm), Ok(true));
+    /// assert_eq!(Expr::parse("(r0 + 2) * 3", &Symbols::default()).unwrap().eval(&vm), Ok(24));
+    /// assert!(Expr::parse("r0 ==", &Symbols::default()).is_err());
+    /// ```
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let mut parser = Parser { text: text.trim(), pos: 0, symbols };
+        if parser.text.is_empty() {
+            return Err("empty expression".to_string());
+        }
+        let root = parser.binary(0)?;
+        parser.skip_space();
+        if parser.pos < parser.text.len() {
+            return Err(format!("unexpected `{}` in expression", &parser.text[parser.pos..]));
+        }
+        Ok(Expr { source: parser.text.to_string(), root })
+    }
+
+    /// The value of the expression in the current state of `vm`.
+    pub fn eval(&self, vm: &VM) -> Result<i64, String> {
+        eval(&self.root, vm)
+    }
+
+    /// Whether the expression is nonzero in the current state of `vm`.
+    pub fn is_true(&self, vm: &VM) -> Result<bool, String> {
+        self.eval(vm).map(|value| value != 0)
+    }
+}
+
+fn eval(node: &Node, vm: &VM) -> Result<i64, String> {
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Register(idx) => vm.registers()[*idx].into(),
+        Node::Ip => vm.instruction_ptr().into(),
+        Node::Sp => vm.stack().len() as i64,
+        Node::Depth => vm.call_depth().into(),
+        Node::Steps => vm.steps() as i64,
+        Node::Memory(address) => {
+            let address = eval(address, vm)?;
+            if !(0..MEMORY_SIZE as i64).contains(&address) {
+                return Err(format!("address {address} is out of range"));
+            }
+            vm.memory(address as u16).unwrap_or(0).into()
+        },
+        Node::Unary(op, operand) => {
+            let value = eval(operand, vm)?;
+            match op {
+                UnaryOp::Not => (value == 0).into(),
+                UnaryOp::Negate => value.wrapping_neg(),
+                UnaryOp::Complement => !value,
+            }
+        },
+        Node::Binary(BinaryOp::And, lhs, rhs) => (eval(lhs, vm)? != 0 && eval(rhs, vm)? != 0).into(),
+        Node::Binary(BinaryOp::Or, lhs, rhs) => (eval(lhs, vm)? != 0 || eval(rhs, vm)? != 0).into(),
+        Node::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, vm)?, eval(rhs, vm)?);
+            match op {
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::Eq => (lhs == rhs).into(),
+                BinaryOp::Ne => (lhs != rhs).into(),
+                BinaryOp::Lt => (lhs < rhs).into(),
+                BinaryOp::Le => (lhs <= rhs).into(),
+                BinaryOp::Gt => (lhs > rhs).into(),
+                BinaryOp::Ge => (lhs >= rhs).into(),
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err("division by zero".to_string()),
+                BinaryOp::Div => lhs.wrapping_div(rhs),
+                BinaryOp::Rem => lhs.wrapping_rem(rhs),
+                BinaryOp::And | BinaryOp::Or => unreachable!("Logical operators short-circuit above."),
+            }
+        },
+    })
+}
+
+/// A recursive-descent parser over `text`, with one function per
+/// precedence level of [`LEVELS`].
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    symbols: &'a Symbols,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next, but not a prefix of a longer
+    /// operator, so `|` does not match the start of `||`.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let Some(after) = self.rest().strip_prefix(token) else {
+            return false;
+        };
+        let longer = matches!((token, after.chars().next()), ("|", Some('|')) | ("&", Some('&')) | ("<" | ">" | "!", Some('=')));
+        if !longer {
+            self.pos += token.len();
+        }
+        !longer
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        'operands: loop {
+            for &(token, op) in operators.iter() {
+                if self.eat(token) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'operands;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        for (token, op) in [("!", UnaryOp::Not), ("-", UnaryOp::Negate), ("~", UnaryOp::Complement)] {
+            if self.eat(token) {
+                return Ok(Node::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        self.skip_space();
+        if self.eat("(") {
+            let node = self.binary(0)?;
+            return match self.eat(")") {
+                true => Ok(node),
+                false => Err("missing `)` in expression".to_string()),
+            };
+        }
+        let rest = self.rest();
+        let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let word = &rest[..len];
+        if word.is_empty() {
+            return Err(match rest.chars().next() {
+                Some(c) => format!("unexpected `{c}` in expression"),
+                None => "unexpected end of expression".to_string(),
+            });
+        }
+        self.pos += len;
+        Ok(match word {
+            "ip" => Node::Ip,
+            "sp" => Node::Sp,
+            "depth" => Node::Depth,
+            "steps" => Node::Steps,
+            "mem" => {
+                if !self.eat("[") {
+                    return Err("expected `[` after `mem`".to_string());
+                }
+                let address = self.binary(0)?;
+                if !self.eat("]") {
+                    return Err("missing `]` in expression".to_string());
+                }
+                Node::Memory(Box::new(address))
+            },
+            _ if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                Node::Number(super::parse_number(word)?)
+            },
+            _ => match (word.strip_prefix('r').and_then(|idx| idx.parse::<usize>().ok()), self.symbols.address(word)) {
+                (Some(idx @ 0..=7), _) => Node::Register(idx),
+                (_, Some(address)) => Node::Number(address.into()),
+                _ => return Err(format!("unknown name `{word}` in expression")),
+            },
+        })
+    }
+}
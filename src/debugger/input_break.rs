@@ -0,0 +1,46 @@
+//! Stopping when the program waits at a prompt.
+
+use crate::vm::{Hook, Operation, VM};
+
+/// Whether an input break lasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputBreakMode {
+    /// Stop at the next prompt only.
+    Once,
+    /// Stop at every prompt.
+    Always,
+}
+
+/// Tracks, while registered as a [`Hook`], whether the next `in` would read
+/// the first character of a line: the program's first `in`, or the first
+/// after one that read a newline. The game reads a command one character at
+/// a time, so stopping at every `in` would stop once per character.
+#[derive(Debug, Clone)]
+pub struct InputBreak {
+    at_line_start: bool,
+}
+
+impl Default for InputBreak {
+    fn default() -> Self {
+        Self { at_line_start: true }
+    }
+}
+
+impl InputBreak {
+    /// Whether `vm` is about to read the first character of a line.
+    pub fn is_waiting(&self, vm: &VM) -> bool {
+        self.at_line_start && matches!(vm.next_operation(), Ok(Operation::In(_)))
+    }
+}
+
+impl Hook for InputBreak {
+    fn after_op(&mut self, vm: &VM, _address: u16, operation: &Operation) {
+        if let Operation::In(target) = *operation {
+            let read = match target {
+                32_768..=32_775 => vm.registers()[(target - 32_768) as usize],
+                address => vm.memory(address).unwrap_or(0),
+            };
+            self.at_line_start = read == u16::from(b'\n');
+        }
+    }
+}
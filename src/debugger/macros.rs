@@ -0,0 +1,53 @@
+//! User-defined commands: named sequences of debugger commands.
+
+/// A sequence of command lines in which `$1` to `$9` stand for the
+/// arguments the macro is called with and `$*` for all of them.
+///
+/// ```
+/// use oscon_2012_vm_challenge::debugger::macros::Macro;
+///
+/// let inspect = Macro::new(vec!["regs".to_string(), "list $1 $2".to_string(), "xrefs $1".to_string()]);
+/// assert_eq!(inspect.expand(&["1752", "5"]).unwrap(), ["regs", "list 1752 5", "xrefs 1752"]);
+/// assert!(inspect.expand(&["1752"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    body: Vec<String>,
+}
+
+impl Macro {
+    pub fn new(body: Vec<String>) -> Self {
+        Self { body }
+    }
+
+    pub fn body(&self) -> &[String] {
+        &self.body
+    }
+
+    /// The body with the placeholders replaced by `args`. It is an error to
+    /// refer to an argument that was not given.
+    pub fn expand(&self, args: &[&str]) -> Result<Vec<String>, String> {
+        self.body.iter().map(|line| expand_line(line, args)).collect()
+    }
+}
+
+fn expand_line(line: &str, args: &[&str]) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().copied()) {
+            ('$', Some('*')) => {
+                chars.next();
+                expanded.push_str(&args.join(" "));
+            },
+            ('$', Some(digit @ '1'..='9')) => {
+                chars.next();
+                let idx = digit as usize - '1' as usize;
+                let arg = args.get(idx).ok_or_else(|| format!("missing argument ${digit}"))?;
+                expanded.push_str(arg);
+            },
+            (c, _) => expanded.push(c),
+        }
+    }
+    Ok(expanded)
+}
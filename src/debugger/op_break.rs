@@ -0,0 +1,130 @@
+//! Stopping before instructions with a given opcode, wherever they are.
+
+use std::fmt;
+
+use super::{parse_address, parse_number};
+use crate::asm::opcode_for;
+use crate::symbols::Symbols;
+use crate::vm::{format_operand, Operation, VM};
+
+/// What one operand of an [`OpBreak`] must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandPattern {
+    /// `*`: anything.
+    Any,
+    /// `r`: any register, e.g. the target of an indirect `call`.
+    AnyRegister,
+    /// `r0`..`r7`: that register.
+    Register(u16),
+    /// A number, name, or inclusive range `lo-hi`, matched against the
+    /// operand's value: a literal, or the contents of the register it names.
+    Values(u16, u16),
+}
+
+impl OperandPattern {
+    fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        Ok(match text {
+            "*" => OperandPattern::Any,
+            "r" => OperandPattern::AnyRegister,
+            _ => match text.strip_prefix('r').map(parse_number::<u16>) {
+                Some(Ok(register @ 0..=7)) => OperandPattern::Register(32_768 + register),
+                Some(Ok(_)) => return Err(format!("no such register `{text}`")),
+                _ => {
+                    let (low, high) = match text.split_once('-') {
+                        Some((low, high)) => (parse_address(low, symbols)?, parse_address(high, symbols)?),
+                        None => (parse_address(text, symbols)?, parse_address(text, symbols)?),
+                    };
+                    if low > high {
+                        return Err(format!("empty range `{text}`"));
+                    }
+                    OperandPattern::Values(low, high)
+                },
+            },
+        })
+    }
+
+    fn matches(self, vm: &VM, word: u16) -> bool {
+        let is_register = (32_768..=32_775).contains(&word);
+        match self {
+            OperandPattern::Any => true,
+            OperandPattern::AnyRegister => is_register,
+            OperandPattern::Register(register) => word == register,
+            OperandPattern::Values(low, high) => {
+                let value = if is_register { vm.registers()[(word - 32_768) as usize] } else { word };
+                (low..=high).contains(&value)
+            },
+        }
+    }
+}
+
+impl fmt::Display for OperandPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            OperandPattern::Any => write!(f, "*"),
+            OperandPattern::AnyRegister => write!(f, "r"),
+            OperandPattern::Register(register) => write!(f, "{}", format_operand(register)),
+            OperandPattern::Values(low, high) if low == high => write!(f, "{low}"),
+            OperandPattern::Values(low, high) => write!(f, "{low}-{high}"),
+        }
+    }
+}
+
+/// Stops before any instruction with an opcode, optionally only where its
+/// leading operands match patterns.
+///
+/// ```
+/// use oscon_2012_vm_challenge::debugger::op_break::OpBreak;
+/// use oscon_2012_vm_challenge::symbols::Symbols;
+/// use oscon_2012_vm_challenge::vm::VM;
+///
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // call r1; wmem r0 5
+/// vm.load(&[17, 0, 1, 128, 16, 0, 0, 128, 5, 0]).unwrap();
+/// vm.registers_mut()[0] = 300;
+/// let indirect_call = OpBreak::parse("call r", &Symbols::default()).unwrap();
+/// assert!(indirect_call.matches(&vm));
+/// let code_write = OpBreak::parse("wmem 0-299", &Symbols::default()).unwrap();
+/// vm.set_instruction_ptr(2);
+/// assert!(!code_write.matches(&vm));
+/// assert!(OpBreak::parse("jmp 1 2", &Symbols::default()).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpBreak {
+    opcode: u16,
+    operands: Vec<OperandPattern>,
+}
+
+impl OpBreak {
+    /// Parses a mnemonic followed by up to one pattern per operand: `*`,
+    /// `r`, a register, or a value or range as an address is written.
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let mut words = text.split_whitespace();
+        let mnemonic = words.next().ok_or("usage: break-op <mnemonic> [operand...]")?;
+        let opcode = opcode_for(mnemonic).ok_or_else(|| format!("unknown opcode `{mnemonic}`"))?;
+        let operands = words.map(|word| OperandPattern::parse(word, symbols)).collect::<Result<Vec<_>, _>>()?;
+        let arguments = Operation::num_arguments(opcode).unwrap_or(0);
+        if operands.len() > arguments as usize {
+            return Err(format!("`{mnemonic}` has {arguments} operands"));
+        }
+        Ok(OpBreak { opcode, operands })
+    }
+
+    /// Whether the next instruction `vm` will execute matches.
+    pub fn matches(&self, vm: &VM) -> bool {
+        let Ok(operation) = vm.next_operation() else {
+            return false;
+        };
+        operation.opcode() == self.opcode
+            && self.operands.iter().zip(operation.args()).all(|(pattern, word)| pattern.matches(vm, word))
+    }
+}
+
+impl fmt::Display for OpBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Operation::mnemonic_for(self.opcode).unwrap_or("?"))?;
+        for operand in &self.operands {
+            write!(f, " {operand}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! Stopping when the program prints given text.
+
+use std::fmt;
+
+use crate::expect::regex::Regex;
+use crate::vm::{Hook, Operation, VM};
+
+/// How much recent output is kept to match against.
+const WINDOW: usize = 4096;
+
+/// Text to stop at: a literal substring, or a regular expression matched
+/// against the line being printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputPattern {
+    Text(String),
+    Regex(Regex),
+}
+
+impl OutputPattern {
+    /// Parses `"text"` as a substring and `/pattern/` as a regular
+    /// expression. Bare text is a substring too.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::debugger::output_break::OutputPattern;
+    ///
+    /// let pattern = OutputPattern::parse("\"You find a strange\"").unwrap();
+    /// assert_eq!(pattern, OutputPattern::Text("You find a strange".to_string()));
+    /// assert!(matches!(OutputPattern::parse("/code: \\w+/"), Ok(OutputPattern::Regex(_))));
+    /// assert!(OutputPattern::parse("\"\"").is_err());
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(pattern) = text.strip_prefix('/').and_then(|text| text.strip_suffix('/')) {
+            return Ok(OutputPattern::Regex(Regex::new(pattern)?));
+        }
+        let text = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text);
+        match text.is_empty() {
+            true => Err("empty output pattern".to_string()),
+            false => Ok(OutputPattern::Text(text.to_string())),
+        }
+    }
+
+    /// Whether the output in `recent` matches, counting only a match that
+    /// ends with its last byte, so each match is reported once, as soon as
+    /// it is printed.
+    fn matches(&self, recent: &[u8]) -> bool {
+        match self {
+            OutputPattern::Text(text) => recent.ends_with(text.as_bytes()),
+            OutputPattern::Regex(regex) => {
+                // The line being printed, with the newline ending it if that
+                // was the last byte, so `$` can match.
+                let body = &recent[..recent.len().saturating_sub(1)];
+                let line = &recent[body.iter().rposition(|&byte| byte == b'\n').map_or(0, |idx| idx + 1)..];
+                regex.find(line).is_some_and(|(_, end)| end == line.len())
+            },
+        }
+    }
+}
+
+impl fmt::Display for OutputPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputPattern::Text(text) => write!(f, "{text:?}"),
+            OutputPattern::Regex(regex) => write!(f, "/{regex}/"),
+        }
+    }
+}
+
+/// Watches the program's output for any of a set of patterns while
+/// registered as a [`Hook`], noting the first that matches.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBreaks {
+    patterns: Vec<OutputPattern>,
+    recent: Vec<u8>,
+    hit: Option<OutputPattern>,
+    mid_line: bool,
+}
+
+impl OutputBreaks {
+    /// Adds `pattern`, returning `false` if it was already there.
+    pub fn add(&mut self, pattern: OutputPattern) -> bool {
+        if self.patterns.contains(&pattern) {
+            return false;
+        }
+        self.patterns.push(pattern);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.patterns.clear();
+        self.recent.clear();
+        self.hit = None;
+    }
+
+    pub fn patterns(&self) -> &[OutputPattern] {
+        &self.patterns
+    }
+
+    /// Whether the last byte printed was not a newline.
+    pub fn mid_line(&self) -> bool {
+        self.mid_line
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// The pattern that matched since the last call, if any.
+    pub fn take_hit(&mut self) -> Option<OutputPattern> {
+        self.hit.take()
+    }
+}
+
+impl Hook for OutputBreaks {
+    fn after_op(&mut self, vm: &VM, _address: u16, operation: &Operation) {
+        let Operation::Out(value) = *operation else {
+            return;
+        };
+        let byte = match value {
+            32_768..=32_775 => vm.registers()[(value - 32_768) as usize] as u8,
+            value => value as u8,
+        };
+        if self.recent.len() == WINDOW {
+            self.recent.drain(..WINDOW / 2);
+        }
+        self.recent.push(byte);
+        self.mid_line = byte != b'\n';
+        if let Some(pattern) = self.patterns.iter().find(|pattern| pattern.matches(&self.recent)) {
+            self.hit = Some(pattern.clone());
+            // Text already matched can't match again.
+            self.recent.clear();
+        }
+    }
+}
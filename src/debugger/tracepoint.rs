@@ -0,0 +1,156 @@
+//! Tracepoints: messages written whenever execution reaches an address,
+//! without stopping.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+use super::expr::Expr;
+use crate::symbols::Symbols;
+use crate::vm::{Hook, Operation, VM};
+
+/// How an interpolated value is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Decimal,
+    Hex,
+    Char,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Value(Expr, Style),
+}
+
+/// A message with `{expr}` placeholders for [`Expr`] values, written in hex
+/// as `{expr:x}` or as a character as `{expr:c}`. `{{` and `}}` are literal
+/// braces, and quotes around the whole message are dropped.
+///
+/// ```
+/// use oscon_2012_vm_challenge::debugger::tracepoint::Template;
+/// use oscon_2012_vm_challenge::symbols::Symbols;
+/// use oscon_2012_vm_challenge::vm::VM;
+///
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// vm.registers_mut()[1] = 0x17ca;
+/// vm.set_memory(0x17ca, 'A' as u16);
+/// let template = Template::parse("\"r1={r1:x} {{{mem[r1]:c}}}\"", &Symbols::default()).unwrap();
+/// assert_eq!(template.render(&vm), "r1=17ca {A}");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    pieces: Vec<Piece>,
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Template {
+    /// Parses `text`, resolving names in the placeholders with `symbols`.
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let source = text.trim();
+        let text = source.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(source);
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(idx) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..idx]);
+            let (brace, after) = rest[idx..].split_at(1);
+            if let Some(after) = after.strip_prefix(brace) {
+                literal.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err("unmatched `}` in message; write `}}` for a brace".to_string());
+            }
+            let (placeholder, after) = after.split_once('}').ok_or("unterminated `{` in message")?;
+            let (expr, style) = match placeholder.rsplit_once(':') {
+                Some((expr, "x")) => (expr, Style::Hex),
+                Some((expr, "c")) => (expr, Style::Char),
+                Some((expr, "d")) => (expr, Style::Decimal),
+                Some((_, style)) => return Err(format!("unknown format `{style}`; use x, c, or d")),
+                None => (placeholder, Style::Decimal),
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut literal)));
+            }
+            pieces.push(Piece::Value(Expr::parse(expr, symbols)?, style));
+            rest = after;
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        Ok(Template { source: source.to_string(), pieces })
+    }
+
+    /// The message with its placeholders filled in from `vm`. A value that
+    /// cannot be evaluated is written as `<error>` with the reason.
+    pub fn render(&self, vm: &VM) -> String {
+        let mut message = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => message.push_str(text),
+                Piece::Value(expr, style) => match (expr.eval(vm), style) {
+                    (Ok(value), Style::Decimal) => message.push_str(&value.to_string()),
+                    (Ok(value), Style::Hex) => message.push_str(&format!("{value:x}")),
+                    (Ok(value), Style::Char) => {
+                        message.push(u32::try_from(value).ok().and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER))
+                    },
+                    (Err(err), _) => message.push_str(&format!("<error: {err}>")),
+                },
+            }
+        }
+        message
+    }
+}
+
+/// The tracepoints set in a debugger, writing their messages to `out` while
+/// registered as a [`Hook`].
+pub struct Tracepoints {
+    messages: BTreeMap<u16, Template>,
+    out: Box<dyn Write>,
+}
+
+impl Tracepoints {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self { messages: BTreeMap::new(), out }
+    }
+
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+    }
+
+    /// Sets the message for `address`, returning the one it replaces.
+    pub fn insert(&mut self, address: u16, message: Template) -> Option<Template> {
+        self.messages.insert(address, message)
+    }
+
+    pub fn remove(&mut self, address: u16) -> Option<Template> {
+        self.messages.remove(&address)
+    }
+
+    /// The tracepoints in order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Template)> {
+        self.messages.iter().map(|(&address, message)| (address, message))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+impl Hook for Tracepoints {
+    fn before_op(&mut self, vm: &VM, address: u16, _operation: &Operation) {
+        if let Some(message) = self.messages.get(&address) {
+            // A broken trace output must not stop the program being traced.
+            let _ = writeln!(self.out, "{}", message.render(vm));
+        }
+    }
+}
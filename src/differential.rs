@@ -0,0 +1,106 @@
+//! Running two machines in lockstep and reporting where they diverge.
+//!
+//! Both machines execute the same number of instructions, one at a time, and
+//! must report the same [`VmEvent`] for each. Every `interval` instructions,
+//! and when the run ends, their full states are compared too. This checks
+//! that two implementations of the same semantics, such as memory backends or
+//! instruction caches, or a VM with and without instrumentation, really do
+//! the same thing.
+
+use std::{error, fmt};
+
+use crate::vm::{SnapshotDiff, VmError, VmEvent, VM};
+
+/// How two machines that agreed throughout stopped.
+#[derive(Debug)]
+pub enum Agreement {
+    /// Both halted.
+    Halted,
+    /// Both are waiting for input.
+    NeedsInput,
+    /// Both faulted with the same error.
+    Faulted(VmError),
+    /// Both executed the allotted number of instructions.
+    StepLimit,
+}
+
+/// The first difference between the two machines.
+#[derive(Debug)]
+pub enum Divergence {
+    /// The instruction executed at `steps` reported different events.
+    Event { steps: u64, left: String, right: String },
+    /// The states differed when compared at `steps`, having last matched at
+    /// `agreed`.
+    State { agreed: u64, steps: u64, diff: Box<SnapshotDiff> },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Event { steps, left, right } => {
+                write!(f, "after {steps} steps, the left machine reported {left} but the right one {right}")
+            },
+            Divergence::State { agreed, steps, diff } => {
+                writeln!(f, "the machines diverged between steps {agreed} and {steps} (left -> right):")?;
+                write!(f, "{diff}")
+            },
+        }
+    }
+}
+
+impl error::Error for Divergence {}
+
+/// Steps `left` and `right` together for at most `max_steps` instructions,
+/// comparing their states every `interval` of them.
+pub fn run_lockstep(left: &mut VM, right: &mut VM, interval: u64, max_steps: u64) -> Result<Agreement, Divergence> {
+    let interval = interval.max(1);
+    let mut agreed = compare(left, right, left.steps(), left.steps())?;
+    let start = left.steps();
+    let outcome = loop {
+        let steps = left.steps() - start;
+        if steps >= max_steps {
+            break Agreement::StepLimit;
+        }
+        let outcome = match (left.step(), right.step()) {
+            (Ok(VmEvent::Halted), Ok(VmEvent::Halted)) => Some(Agreement::Halted),
+            (Ok(VmEvent::NeedsInput), Ok(VmEvent::NeedsInput)) => Some(Agreement::NeedsInput),
+            (Ok(a), Ok(b)) if a == b => None,
+            (Err(a), Err(b)) if a.to_string() == b.to_string() => Some(Agreement::Faulted(a)),
+            (a, b) => {
+                return Err(Divergence::Event { steps: left.steps(), left: describe(&a), right: describe(&b) });
+            },
+        };
+        if let Some(outcome) = outcome {
+            break outcome;
+        }
+        if (steps + 1).is_multiple_of(interval) {
+            agreed = compare(left, right, agreed, left.steps())?;
+        }
+    };
+    compare(left, right, agreed, left.steps())?;
+    Ok(outcome)
+}
+
+/// Checks that the two machines are in the same state, returning `steps` as
+/// the new point of agreement.
+fn compare(left: &VM, right: &VM, agreed: u64, steps: u64) -> Result<u64, Divergence> {
+    let diff = left.snapshot().diff(&right.snapshot());
+    let same = diff.steps.0 == diff.steps.1
+        && diff.instruction_ptr.is_none()
+        && diff.registers.is_empty()
+        && diff.memory.is_empty()
+        && diff.stack_removed.is_empty()
+        && diff.stack_added.is_empty()
+        && left.is_halted() == right.is_halted();
+    match same {
+        true => Ok(steps),
+        false => Err(Divergence::State { agreed, steps, diff: Box::new(diff) }),
+    }
+}
+
+fn describe(result: &Result<VmEvent, VmError>) -> String {
+    match result {
+        Ok(event) => format!("{event:?}"),
+        Err(err) => format!("an error ({err})"),
+    }
+}
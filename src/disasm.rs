@@ -0,0 +1,119 @@
+//! Linear-sweep disassembly of memory images.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::symbols::Symbols;
+use crate::vm::Operation;
+
+/// One disassembled line: an instruction, or a single word that does not
+/// decode and is treated as data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub words: Vec<u16>,
+    pub operation: Option<Operation>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw = self.words.iter()
+            .map(|word| format!("{word:04x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "{:5}: {:<20}", self.address, raw)?;
+        match self.operation {
+            Some(Operation::Out(value)) if value < 128 => {
+                write!(f, "out {value:<14} ; {:?}", value as u8 as char)
+            },
+            Some(operation) => write!(f, "{operation}"),
+            None => write!(f, "data {}", self.words[0]),
+        }
+    }
+}
+
+impl Line {
+    /// The line, with the name of the address it jumps or calls to in a
+    /// comment.
+    pub fn annotated(&self, symbols: &Symbols) -> String {
+        match self.operation.as_ref().and_then(|operation| symbols.target(operation)) {
+            Some(name) => format!("{:<46}; {name}", self.to_string()),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// Writes `lines` as a listing, with a `name:` line before each named
+/// address, jump and call targets named in comments, and each of the `notes`
+/// for an address as a comment line before it.
+pub fn write_listing(
+    lines: &[Line],
+    symbols: &Symbols,
+    notes: &BTreeMap<u16, Vec<String>>,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    for line in lines {
+        if let Some(name) = symbols.name(line.address) {
+            writeln!(out, "{name}:")?;
+        }
+        for note in notes.get(&line.address).into_iter().flatten() {
+            writeln!(out, "       ; {note}")?;
+        }
+        writeln!(out, "{}", line.annotated(symbols))?;
+    }
+    Ok(())
+}
+
+/// Decodes the instruction or data word at `address`.
+pub fn disassemble_at(words: &[u16], address: u16) -> Option<Line> {
+    let rest = words.get(address as usize..).filter(|rest| !rest.is_empty())?;
+    let line = match Operation::decode(rest) {
+        Some(operation) => Line {
+            address,
+            words: rest[..operation.size() as usize].to_vec(),
+            operation: Some(operation),
+        },
+        None => Line { address, words: vec![rest[0]], operation: None },
+    };
+    Some(line)
+}
+
+/// Disassembles up to `rows` lines for a view that follows the instruction
+/// pointer: up to `before` lines leading up to `address`, then the code
+/// decoded from `address` itself. The leading lines come from a sweep of the
+/// stretch just before `address`, which may not line up with it exactly.
+/// Nothing is cached, so the lines always match the current `words`.
+pub fn around(words: &[u16], address: u16, before: usize, rows: usize) -> Vec<Line> {
+    let mut sweep = Vec::new();
+    let mut at = address.saturating_sub(4 * before as u16 + 8);
+    while at < address {
+        let Some(line) = disassemble_at(words, at) else {
+            break;
+        };
+        at = at.wrapping_add(line.words.len() as u16);
+        sweep.push(line);
+    }
+    let mut lines = sweep.split_off(sweep.len().saturating_sub(before));
+    let mut at = address;
+    while lines.len() < rows {
+        let Some(line) = disassemble_at(words, at) else {
+            break;
+        };
+        at = at.wrapping_add(line.words.len() as u16);
+        lines.push(line);
+    }
+    lines
+}
+
+/// Disassembles `words` from address 0, decoding each instruction directly
+/// after the previous one.
+pub fn disassemble(words: &[u16]) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = 0;
+    while let Some(line) = disassemble_at(words, address) {
+        address += line.words.len() as u16;
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,216 @@
+//! Driving a program with an expect-style script.
+//!
+//! A script is a list of commands, one per line:
+//!
+//! ```text
+//! # Blank lines and lines starting with `#` are ignored.
+//! timeout 5            # seconds each following `expect` may wait
+//! expect What do you do\?
+//! send take tablet
+//! expect ^Taken\.$
+//! ```
+//!
+//! `expect` runs the program until its output, since the end of the last
+//! match, matches the [regular expression](regex::Regex) that follows.
+//! `send` queues the rest of the line, plus a newline, as input. The script
+//! fails if the program halts, faults, or waits for input before an `expect`
+//! matches, or if the timeout passes first.
+
+pub mod regex;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+use crate::vm::{VmError, VmEvent, VM};
+use regex::Regex;
+
+/// How long an `expect` waits unless the script says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Instructions executed between checks of the output and the clock.
+const CHECK_INTERVAL: u32 = 10_000;
+
+/// A script command.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Expect(Regex),
+    Send(String),
+    Timeout(Duration),
+}
+
+/// A parsed script: its commands with their line numbers.
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub steps: Vec<(usize, Step)>,
+}
+
+impl Script {
+    /// Parses a script, reporting the first bad line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line_number = idx + 1;
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (command, rest) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+            let step = match command {
+                "expect" => Step::Expect(Regex::new(rest).map_err(|err| format!("line {line_number}: {err}"))?),
+                "send" => Step::Send(rest.to_string()),
+                "timeout" => {
+                    let seconds = rest.split('#').next().unwrap_or_default().trim().parse::<f64>().ok()
+                        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                        .ok_or_else(|| format!("line {line_number}: invalid timeout `{rest}`"))?;
+                    Step::Timeout(Duration::from_secs_f64(seconds))
+                },
+                _ => return Err(format!("line {line_number}: unknown command `{command}`")),
+            };
+            steps.push((line_number, step));
+        }
+        Ok(Self { steps })
+    }
+}
+
+/// Why a script stopped early.
+#[derive(Debug)]
+pub enum ExpectError {
+    /// The pattern on `line` had not matched when its timeout passed.
+    Timeout { line: usize, pattern: Regex },
+    /// The program halted before the pattern on `line` matched.
+    Halted { line: usize, pattern: Regex },
+    /// The program asked for input before the pattern on `line` matched.
+    NeedsInput { line: usize, pattern: Regex },
+    /// The program faulted.
+    Vm(VmError),
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectError::Timeout { line, pattern } => write!(f, "line {line}: timed out waiting for `{pattern}`"),
+            ExpectError::Halted { line, pattern } => {
+                write!(f, "line {line}: the program halted before printing `{pattern}`")
+            },
+            ExpectError::NeedsInput { line, pattern } => {
+                write!(f, "line {line}: the program is waiting for input but has not printed `{pattern}`")
+            },
+            ExpectError::Vm(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl error::Error for ExpectError {}
+
+impl From<VmError> for ExpectError {
+    fn from(err: VmError) -> Self {
+        ExpectError::Vm(err)
+    }
+}
+
+impl From<io::Error> for ExpectError {
+    fn from(err: io::Error) -> Self {
+        ExpectError::Vm(VmError::Io(err))
+    }
+}
+
+#[derive(Clone, Default)]
+struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A VM run by a script. Load a program into [`vm_mut`](Self::vm_mut), then
+/// [`run`](Self::run) the script.
+pub struct Expect {
+    vm: VM,
+    output: OutputBuffer,
+    // How much of the output the last match consumed.
+    matched: usize,
+    // How much of the output has been copied to the echo writer.
+    echoed: usize,
+}
+
+impl Default for Expect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Expect {
+    pub fn new() -> Self {
+        let output = OutputBuffer::default();
+        Self { vm: VM::new(io::empty(), output.clone()), output, matched: 0, echoed: 0 }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// Runs `script`, copying the program's output and the lines sent to
+    /// `echo` as it goes.
+    pub fn run(&mut self, script: &Script, echo: &mut dyn Write) -> Result<(), ExpectError> {
+        let mut timeout = DEFAULT_TIMEOUT;
+        for (line, step) in &script.steps {
+            match step {
+                Step::Expect(pattern) => self.expect(*line, pattern, timeout, echo)?,
+                Step::Send(text) => {
+                    self.vm.provide_input(format!("{text}\n").as_bytes());
+                    writeln!(echo, "{text}")?;
+                },
+                Step::Timeout(duration) => timeout = *duration,
+            }
+        }
+        echo.flush()?;
+        Ok(())
+    }
+
+    fn expect(&mut self, line: usize, pattern: &Regex, timeout: Duration, echo: &mut dyn Write) -> Result<(), ExpectError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut stopped = None;
+            for _ in 0..CHECK_INTERVAL {
+                match self.vm.step()? {
+                    VmEvent::Continued | VmEvent::Output(_) => (),
+                    VmEvent::NeedsInput => {
+                        stopped = Some(ExpectError::NeedsInput { line, pattern: pattern.clone() });
+                        break;
+                    },
+                    VmEvent::Halted => {
+                        stopped = Some(ExpectError::Halted { line, pattern: pattern.clone() });
+                        break;
+                    },
+                }
+            }
+            let output = self.output.0.borrow();
+            echo.write_all(&output[self.echoed..])?;
+            self.echoed = output.len();
+            echo.flush()?;
+            if let Some((_, end)) = pattern.find(&output[self.matched..]) {
+                self.matched += end;
+                return Ok(());
+            }
+            if let Some(err) = stopped {
+                return Err(err);
+            }
+            if Instant::now() >= deadline {
+                return Err(ExpectError::Timeout { line, pattern: pattern.clone() });
+            }
+        }
+    }
+}
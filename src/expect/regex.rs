@@ -0,0 +1,299 @@
+//! A small backtracking regular expression matcher for `expect` patterns.
+//!
+//! Supports literals, `.`, character classes (`[a-z]`, `[^,]`), the escapes
+//! `\d \w \s \D \W \S \n \t`, groups with alternation (`(north|south)`), the
+//! greedy quantifiers `* + ? {m} {m,} {m,n}`, and the anchors `^` and `$`,
+//! which match at the start of a line and before a newline. `$` does not
+//! match at the end of the text, which may be the middle of a line the
+//! program has not finished printing. Matching is over bytes.
+
+use std::fmt;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Byte(u8),
+    /// Any byte but a newline.
+    Any,
+    Class { ranges: Vec<(u8, u8)>, negated: bool },
+    LineStart,
+    LineEnd,
+    Group(Vec<Vec<Node>>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+/// A compiled pattern.
+///
+/// ```
+/// use oscon_2012_vm_challenge::expect::regex::Regex;
+///
+/// let pattern = Regex::new(r#"writing "(\w+)" on"#).unwrap();
+/// let text = b"You find yourself writing \"abc123\" on the tablet.";
+/// assert_eq!(pattern.find(text), Some((18, 37)));
+/// assert!(!Regex::new("^tablet").unwrap().is_match(text));
+/// ```
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    alternatives: Vec<Vec<Node>>,
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Regex({:?})", self.source)
+    }
+}
+
+/// Patterns are equal if they were written the same way.
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Regex {}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+const DIGIT: &[(u8, u8)] = &[(b'0', b'9')];
+const WORD: &[(u8, u8)] = &[(b'0', b'9'), (b'A', b'Z'), (b'_', b'_'), (b'a', b'z')];
+const SPACE: &[(u8, u8)] = &[(b'\t', b'\r'), (b' ', b' ')];
+
+struct Parser<'a> {
+    pattern: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at offset {}", self.pos)
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(byte) = self.peek() {
+            if byte == b'|' || byte == b')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let byte = self.next().expect("sequence should only ask for an atom before the end");
+        let node = match byte {
+            b'(' => {
+                let alternatives = self.alternatives()?;
+                if self.next() != Some(b')') {
+                    return Err(self.error("unclosed group"));
+                }
+                Node::Group(alternatives)
+            },
+            b'[' => self.class()?,
+            b'.' => Node::Any,
+            b'^' => Node::LineStart,
+            b'$' => Node::LineEnd,
+            b'\\' => self.escape()?,
+            b'*' | b'+' | b'?' | b'{' => return Err(self.error("nothing to repeat")),
+            byte => Node::Byte(byte),
+        };
+        Ok(node)
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let class = |ranges: &[(u8, u8)], negated| Node::Class { ranges: ranges.to_vec(), negated };
+        let node = match self.next().ok_or_else(|| self.error("trailing backslash"))? {
+            b'd' => class(DIGIT, false),
+            b'D' => class(DIGIT, true),
+            b'w' => class(WORD, false),
+            b'W' => class(WORD, true),
+            b's' => class(SPACE, false),
+            b'S' => class(SPACE, true),
+            b'n' => Node::Byte(b'\n'),
+            b't' => Node::Byte(b'\t'),
+            byte => Node::Byte(byte),
+        };
+        Ok(node)
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let byte = self.next().ok_or_else(|| self.error("unclosed character class"))?;
+            let low = match byte {
+                b']' if !first => break,
+                b'\\' => match self.escape()? {
+                    Node::Byte(byte) => byte,
+                    Node::Class { ranges: escaped, negated: false } => {
+                        ranges.extend(escaped);
+                        first = false;
+                        continue;
+                    },
+                    _ => return Err(self.error("negated escape inside a character class")),
+                },
+                byte => byte,
+            };
+            first = false;
+            let high = match (self.peek(), self.pattern.get(self.pos + 1)) {
+                (Some(b'-'), Some(&high)) if high != b']' => {
+                    self.pos += 2;
+                    high
+                },
+                _ => low,
+            };
+            if high < low {
+                return Err(self.error("backwards range in character class"));
+            }
+            ranges.push((low, high));
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn quantified(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some(b'*') => (0, None),
+            Some(b'+') => (1, None),
+            Some(b'?') => (0, Some(1)),
+            Some(b'{') => return self.counted(node),
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        self.repeat(node, min, max)
+    }
+
+    fn counted(&mut self, node: Node) -> Result<Node, String> {
+        let close = self.pattern[self.pos..].iter().position(|&byte| byte == b'}')
+            .ok_or_else(|| self.error("unclosed repetition count"))?;
+        let body = String::from_utf8_lossy(&self.pattern[self.pos + 1..self.pos + close]).into_owned();
+        let number = |text: &str| text.trim().parse::<usize>().map_err(|_| self.error("invalid repetition count"));
+        let (min, max) = match body.split_once(',') {
+            None => (number(&body)?, Some(number(&body)?)),
+            Some((min, "")) => (number(min)?, None),
+            Some((min, max)) => (number(min)?, Some(number(max)?)),
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("repetition count out of order"));
+        }
+        self.pos += close + 1;
+        self.repeat(node, min, max)
+    }
+
+    fn repeat(&mut self, node: Node, min: usize, max: Option<usize>) -> Result<Node, String> {
+        if matches!(self.peek(), Some(b'*' | b'+' | b'?' | b'{')) {
+            return Err(self.error("lazy and nested quantifiers are not supported"));
+        }
+        Ok(Node::Repeat { node: Box::new(node), min, max })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser { pattern: pattern.as_bytes(), pos: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < pattern.len() {
+            return Err(parser.error("unmatched `)`"));
+        }
+        Ok(Self { source: pattern.to_string(), alternatives })
+    }
+
+    /// The start and end of the leftmost match in `text`.
+    pub fn find(&self, text: &[u8]) -> Option<(usize, usize)> {
+        (0..=text.len()).find_map(|start| {
+            let mut end = None;
+            let matched = self.alternatives.iter().any(|sequence| {
+                match_sequence(sequence, text, start, &mut |pos| {
+                    end = Some(pos);
+                    true
+                })
+            });
+            matched.then(|| (start, end.expect("a match should record its end")))
+        })
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        self.find(text).is_some()
+    }
+}
+
+/// Matches `nodes` at `pos`, calling `then` with each possible end until it
+/// accepts one.
+fn match_sequence(nodes: &[Node], text: &[u8], pos: usize, then: &mut dyn FnMut(usize) -> bool) -> bool {
+    let Some((node, rest)) = nodes.split_first() else {
+        return then(pos);
+    };
+    match node {
+        repeat @ Node::Repeat { .. } => match_repeat(repeat, 0, rest, text, pos, then),
+        node => match_node(node, text, pos, &mut |next| match_sequence(rest, text, next, then)),
+    }
+}
+
+fn match_node(node: &Node, text: &[u8], pos: usize, then: &mut dyn FnMut(usize) -> bool) -> bool {
+    let byte = text.get(pos).copied();
+    match node {
+        Node::Byte(expected) => byte == Some(*expected) && then(pos + 1),
+        Node::Any => byte.is_some_and(|byte| byte != b'\n') && then(pos + 1),
+        Node::Class { ranges, negated } => {
+            byte.is_some_and(|byte| ranges.iter().any(|&(low, high)| (low..=high).contains(&byte)) != *negated)
+                && then(pos + 1)
+        },
+        Node::LineStart => (pos == 0 || text[pos - 1] == b'\n') && then(pos),
+        Node::LineEnd => byte == Some(b'\n') && then(pos),
+        Node::Group(alternatives) => alternatives.iter().any(|sequence| match_sequence(sequence, text, pos, then)),
+        repeat @ Node::Repeat { .. } => match_repeat(repeat, 0, &[], text, pos, then),
+    }
+}
+
+/// Having matched a `repeat` node's body `count` times, greedily matches it
+/// as many more times as allowed, then `rest`.
+fn match_repeat(
+    repeat: &Node,
+    count: usize,
+    rest: &[Node],
+    text: &[u8],
+    pos: usize,
+    then: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let Node::Repeat { node, min, max } = repeat else {
+        unreachable!("match_repeat should only be given repeat nodes");
+    };
+    let min = *min;
+    if max.is_none_or(|max| count < max) {
+        let more = match_node(node, text, pos, &mut |next| {
+            // An empty repetition only helps towards the minimum.
+            (next != pos || count < min) && match_repeat(repeat, count + 1, rest, text, next, then)
+        });
+        if more {
+            return true;
+        }
+    }
+    count >= min && match_sequence(rest, text, pos, then)
+}
@@ -0,0 +1,165 @@
+//! Instruction counts by call path, written as folded stacks for flame graph
+//! tools such as inferno, `flamegraph.pl`, and speedscope.
+//!
+//! Each line is a path of functions from the outermost call in, separated by
+//! `;`, then the number of instructions executed in the last of them:
+//!
+//! ```text
+//! top;fn_1307;fn_1309 512
+//! ```
+//!
+//! Instructions outside any call are counted under `top`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::symbols::Symbols;
+use crate::vm::{Frame, Hook, Operation, VM};
+
+/// The root of every path.
+const TOP: &str = "top";
+
+/// A call path: the function it ends in, the path it was called from, and the
+/// instructions executed while it was innermost.
+struct Node {
+    target: u16,
+    parent: usize,
+    depth: usize,
+    children: HashMap<u16, usize>,
+    instructions: u64,
+}
+
+struct State {
+    // A tree of call paths, with the root at 0.
+    nodes: Vec<Node>,
+    current: usize,
+    // The step counted but not yet finished, since an `in` waiting for input
+    // comes round again once it has some.
+    counted: Option<u64>,
+}
+
+impl State {
+    fn enter(&mut self, target: u16) {
+        let parent = self.current;
+        let next = self.nodes.len();
+        let depth = self.nodes[parent].depth + 1;
+        self.current = *self.nodes[parent].children.entry(target).or_insert(next);
+        if self.current == next {
+            self.nodes.push(Node { target, parent, depth, children: HashMap::new(), instructions: 0 });
+        }
+    }
+
+    /// Whether the current path ends like the VM's shadow call stack, which
+    /// is cheap enough to check before every instruction.
+    fn matches_top(&self, frames: &[Frame]) -> bool {
+        let node = &self.nodes[self.current];
+        node.depth == frames.len() && frames.last().is_none_or(|frame| frame.target == node.target)
+    }
+
+    /// Moves to the path matching the VM's shadow call stack, keeping the part
+    /// the two have in common. Usually that is all but the frame of a `call`
+    /// or `ret`, but restoring a state or stepping back can change the call
+    /// stack arbitrarily.
+    fn follow(&mut self, frames: &[Frame]) {
+        let mut path = Vec::with_capacity(self.nodes[self.current].depth);
+        let mut node = self.current;
+        while node != 0 {
+            path.push(node);
+            node = self.nodes[node].parent;
+        }
+        path.reverse();
+        let common = path.iter().zip(frames).take_while(|&(&node, frame)| self.nodes[node].target == frame.target).count();
+        self.current = if common == 0 { 0 } else { path[common - 1] };
+        for frame in &frames[common..] {
+            self.enter(frame.target);
+        }
+    }
+
+    fn path(&self, mut node: usize, symbols: &Symbols) -> String {
+        let mut names = Vec::new();
+        while node != 0 {
+            let target = self.nodes[node].target;
+            names.push(symbols.name(target).map_or_else(|| format!("fn_{target}"), str::to_string));
+            node = self.nodes[node].parent;
+        }
+        names.push(TOP.to_string());
+        names.reverse();
+        names.join(";")
+    }
+}
+
+/// Counts the instructions a VM executes by call path while registered as a
+/// [`Hook`].
+///
+/// ```
+/// use oscon_2012_vm_challenge::flamegraph::FoldedStacks;
+/// use oscon_2012_vm_challenge::symbols::Symbols;
+/// use oscon_2012_vm_challenge::vm::{encode_image, VM};
+///
+/// let stacks = FoldedStacks::new();
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // call 5; call 5; halt; 5: call 8; ret; 8: noop; ret
+/// vm.load(&encode_image(&[17, 5, 17, 5, 0, 17, 8, 18, 21, 18])).unwrap();
+/// vm.add_hook(stacks.clone());
+/// vm.run().unwrap();
+/// let mut folded = Vec::new();
+/// stacks.write(&Symbols::parse("5 = outer").unwrap(), &mut folded).unwrap();
+/// assert_eq!(String::from_utf8(folded).unwrap(), "top 3\ntop;outer 4\ntop;outer;fn_8 4\n");
+/// ```
+#[derive(Clone)]
+pub struct FoldedStacks(Rc<RefCell<State>>);
+
+impl Default for FoldedStacks {
+    fn default() -> Self {
+        let root = Node { target: 0, parent: 0, depth: 0, children: HashMap::new(), instructions: 0 };
+        Self(Rc::new(RefCell::new(State { nodes: vec![root], current: 0, counted: None })))
+    }
+}
+
+impl FoldedStacks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a line per call path that executed any instructions, sorted by
+    /// path, naming functions with `symbols`.
+    pub fn write(&self, symbols: &Symbols, out: &mut dyn Write) -> io::Result<()> {
+        let state = self.0.borrow();
+        let mut lines: Vec<(String, u64)> = (0..state.nodes.len())
+            .filter(|&node| state.nodes[node].instructions > 0)
+            .map(|node| (state.path(node, symbols), state.nodes[node].instructions))
+            .collect();
+        lines.sort();
+        for (path, instructions) in lines {
+            writeln!(out, "{path} {instructions}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Hook for FoldedStacks {
+    fn before_op(&mut self, vm: &VM, _address: u16, _operation: &Operation) {
+        let mut state = self.0.borrow_mut();
+        // Frames already open when counting started, or changed other than
+        // by `call` and `ret`.
+        if !state.matches_top(vm.call_stack()) {
+            state.follow(vm.call_stack());
+        }
+        if state.counted == Some(vm.steps()) {
+            return;
+        }
+        state.counted = Some(vm.steps());
+        let current = state.current;
+        state.nodes[current].instructions += 1;
+    }
+
+    fn after_op(&mut self, vm: &VM, _address: u16, operation: &Operation) {
+        let mut state = self.0.borrow_mut();
+        state.counted = None;
+        if matches!(operation, Operation::Call(_) | Operation::Ret) {
+            state.follow(vm.call_stack());
+        }
+    }
+}
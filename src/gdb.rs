@@ -0,0 +1,346 @@
+//! A GDB remote serial protocol stub, so `gdb` can attach to the VM with
+//! `target remote`.
+//!
+//! GDB sees a little-endian machine with byte addresses: VM word `n` is at
+//! bytes `2n` and `2n + 1`, so `pc` and breakpoint addresses are doubled and
+//! halved on the way. The target description names the registers `r0`..`r7`
+//! and `pc`, all 16 bits wide.
+//! Words never written read as zero. The program's own input and output stay
+//! on the VM's streams, so the game is played in the stub's terminal while
+//! GDB runs in another.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use crate::log::{self, Level};
+use crate::vm::{HaltReason, VmError, VmEvent, MEMORY_SIZE, VM};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.synacor.core">
+    <reg name="r0" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="r1" bitsize="16" type="uint16"/>
+    <reg name="r2" bitsize="16" type="uint16"/>
+    <reg name="r3" bitsize="16" type="uint16"/>
+    <reg name="r4" bitsize="16" type="uint16"/>
+    <reg name="r5" bitsize="16" type="uint16"/>
+    <reg name="r6" bitsize="16" type="uint16"/>
+    <reg name="r7" bitsize="16" type="uint16"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// The number of instructions run between checks for an interrupt from GDB.
+const INTERRUPT_POLL_STEPS: u64 = 10_000;
+
+/// SIGTRAP, reported for breakpoints, steps, and interrupts.
+const SIGTRAP: u8 = 5;
+/// SIGILL, reported when the program faults.
+const SIGILL: u8 = 4;
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok()).collect()
+}
+
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// Parses `addr,len` as sent by `m` and `M`.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+/// One GDB connection driving a VM.
+pub struct GdbStub<'a> {
+    vm: &'a mut VM,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    ack: bool,
+}
+
+/// What the stub should do after answering a packet.
+enum Next {
+    Continue,
+    Detach,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(vm: &'a mut VM, stream: TcpStream) -> io::Result<Self> {
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self { vm, reader, writer: stream, ack: true })
+    }
+
+    /// Answers packets until GDB detaches, kills the program, or disconnects.
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            if let Next::Detach = self.handle(&packet)? {
+                break;
+            }
+        }
+        self.vm.flush_output()
+    }
+
+    /// Reads the next `$payload#checksum` packet, acknowledging it. Returns
+    /// `None` when GDB disconnects.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0u8; 1];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            // Acks, nacks, and interrupts outside a run carry nothing to answer.
+            if byte[0] != b'$' {
+                continue;
+            }
+            let mut payload = Vec::new();
+            self.reader.read_until(b'#', &mut payload)?;
+            if payload.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0u8; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let actual = payload.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            if self.ack {
+                self.writer.write_all(if expected == Some(actual) { b"+" } else { b"-" })?;
+            }
+            if expected == Some(actual) {
+                return Ok(Some(String::from_utf8_lossy(&payload).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, payload: &str) -> io::Result<()> {
+        let checksum = payload.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.writer, "${payload}#{checksum:02x}")?;
+        self.writer.flush()
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<Next> {
+        let reply = match packet.split_at(packet.len().min(1)) {
+            ("?", _) => format!("S{SIGTRAP:02x}"),
+            ("g", _) => self.read_registers(),
+            ("G", hex) => self.write_registers(hex),
+            ("p", regnum) => self.read_register(regnum),
+            ("P", assignment) => self.write_register(assignment),
+            ("m", range) => self.read_memory(range),
+            ("M", args) => self.write_memory(args),
+            ("Z" | "z", args) => self.breakpoint(packet.starts_with('Z'), args),
+            ("c", _) => self.resume(false)?,
+            ("s", _) => self.resume(true)?,
+            ("D", _) => {
+                self.send("OK")?;
+                return Ok(Next::Detach);
+            },
+            ("k", _) => return Ok(Next::Detach),
+            ("Q", _) if packet == "QStartNoAckMode" => {
+                self.send("OK")?;
+                self.ack = false;
+                return Ok(Next::Continue);
+            },
+            ("H", _) => "OK".to_string(),
+            ("T", _) => "OK".to_string(),
+            _ => self.query(packet)?,
+        };
+        self.send(&reply)?;
+        Ok(Next::Continue)
+    }
+
+    fn query(&mut self, packet: &str) -> io::Result<String> {
+        if packet.starts_with("qSupported") {
+            return Ok("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string());
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_range(args) else {
+                return Ok("E01".to_string());
+            };
+            let rest = TARGET_XML.get(offset.min(TARGET_XML.len())..).unwrap_or_default();
+            return Ok(match rest.get(..len) {
+                Some(chunk) if len < rest.len() => format!("m{chunk}"),
+                _ => format!("l{rest}"),
+            });
+        }
+        Ok(match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => self.resume(false)?,
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => self.resume(true)?,
+            // An empty reply tells GDB the packet is not supported.
+            _ => String::new(),
+        })
+    }
+
+    /// Runs until a breakpoint, a halt, a fault, or an interrupt from GDB, or
+    /// for a single instruction, and returns the stop reply.
+    fn resume(&mut self, single_step: bool) -> io::Result<String> {
+        let result = if single_step {
+            self.vm.step().map(|event| match event {
+                VmEvent::Halted => HaltReason::Halted(self.vm.halt_cause().unwrap_or_default()),
+                _ => HaltReason::StepLimit,
+            })
+        } else {
+            self.run_until_interrupted()
+        };
+        self.vm.flush_output()?;
+        Ok(match result {
+            Ok(HaltReason::Halted(_)) => "W00".to_string(),
+            Ok(HaltReason::InvalidOpcode { .. }) => format!("S{SIGILL:02x}"),
+            Ok(_) => format!("S{SIGTRAP:02x}"),
+            Err(VmError::InputExhausted { .. }) => "W00".to_string(),
+            Err(err) => {
+                log::event(Level::Error, "gdb", "fault", &[("address", &self.vm.instruction_ptr()), ("error", &err)]);
+                format!("S{SIGILL:02x}")
+            },
+        })
+    }
+
+    fn run_until_interrupted(&mut self) -> Result<HaltReason, VmError> {
+        loop {
+            match self.vm.run_for(INTERRUPT_POLL_STEPS)? {
+                HaltReason::StepLimit if !self.interrupted()? => (),
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    /// Whether GDB has sent an interrupt (Ctrl-C, a raw 0x03 byte) without
+    /// waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(self.reader.buffer().contains(&0x03));
+        }
+        self.writer.set_nonblocking(true)?;
+        let mut byte = [0u8; 1];
+        let result = self.writer.peek(&mut byte);
+        self.writer.set_nonblocking(false)?;
+        match result {
+            Ok(1) if byte[0] == 0x03 => {
+                self.reader.read_exact(&mut byte)?;
+                Ok(true)
+            },
+            Ok(_) => Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn register_bytes(&self) -> Vec<u8> {
+        self.vm.registers().iter()
+            .chain([2 * self.vm.instruction_ptr()].iter())
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn read_registers(&self) -> String {
+        hex_bytes(&self.register_bytes())
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        match parse_hex_bytes(hex) {
+            Some(bytes) if bytes.len() == 18 => {
+                for (regnum, value) in bytes.chunks(2).enumerate() {
+                    self.set_register(regnum, u16::from_le_bytes([value[0], value[1]]));
+                }
+                "OK".to_string()
+            },
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_register(&self, regnum: &str) -> String {
+        match parse_hex(regnum).filter(|&regnum| regnum <= 8) {
+            Some(regnum) => hex_bytes(&self.register_bytes()[2 * regnum..2 * regnum + 2]),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let parsed = assignment.split_once('=').and_then(|(regnum, value)| {
+            let regnum = parse_hex(regnum).filter(|&regnum| regnum <= 8)?;
+            let value = parse_hex_bytes(value).filter(|bytes| bytes.len() == 2)?;
+            Some((regnum, u16::from_le_bytes([value[0], value[1]])))
+        });
+        match parsed {
+            Some((regnum, value)) => {
+                self.set_register(regnum, value);
+                "OK".to_string()
+            },
+            None => "E01".to_string(),
+        }
+    }
+
+    fn set_register(&mut self, regnum: usize, value: u16) {
+        match regnum {
+            8 => self.vm.set_instruction_ptr(value / 2),
+            _ => self.vm.registers_mut()[regnum] = value,
+        }
+    }
+
+    fn read_memory(&self, range: &str) -> String {
+        let Some((start, len)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        let end = (start + len).min(2 * MEMORY_SIZE);
+        let bytes: Vec<u8> = (start.min(end)..end)
+            .map(|byte| {
+                let word = self.vm.memory((byte / 2) as u16).unwrap_or(0);
+                word.to_le_bytes()[byte % 2]
+            })
+            .collect();
+        if bytes.is_empty() && len > 0 {
+            return "E01".to_string();
+        }
+        hex_bytes(&bytes)
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, hex)| {
+            let (start, len) = parse_range(range)?;
+            let bytes = parse_hex_bytes(hex).filter(|bytes| bytes.len() == len)?;
+            (start + len <= 2 * MEMORY_SIZE).then_some((start, bytes))
+        });
+        let Some((start, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        for (byte, value) in (start..).zip(bytes) {
+            let address = (byte / 2) as u16;
+            let mut word = self.vm.memory(address).unwrap_or(0).to_le_bytes();
+            word[byte % 2] = value;
+            self.vm.set_memory(address, u16::from_le_bytes(word));
+        }
+        "OK".to_string()
+    }
+
+    /// Handles `Z0`/`z0` (software) and `Z1`/`z1` (hardware) breakpoints,
+    /// which are the same thing here.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some("0" | "1"), Some(address)) = (fields.next(), fields.next().and_then(parse_hex)) else {
+            return String::new();
+        };
+        if address >= 2 * MEMORY_SIZE {
+            return "E01".to_string();
+        }
+        let address = (address / 2) as u16;
+        if insert {
+            self.vm.set_breakpoint(address);
+        } else {
+            self.vm.clear_breakpoint(address);
+        }
+        "OK".to_string()
+    }
+}
@@ -0,0 +1,31 @@
+//! Hex dumps of word-addressed memory.
+
+use std::io::{self, Write};
+
+const WORDS_PER_LINE: usize = 8;
+
+/// Writes `words`, the memory starting at `start`, eight words per line: the
+/// address, each word in hex, and the low byte of each word as a character.
+/// Never-written words are shown as `----`.
+pub fn hexdump(start: u16, words: &[Option<u16>], out: &mut dyn Write) -> io::Result<()> {
+    for (idx, chunk) in words.chunks(WORDS_PER_LINE).enumerate() {
+        let address = start as usize + idx * WORDS_PER_LINE;
+        let mut hex = String::new();
+        let mut text = String::new();
+        for word in chunk {
+            match word {
+                Some(word) => {
+                    hex.push_str(&format!(" {word:04x}"));
+                    let byte = *word as u8;
+                    text.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+                },
+                None => {
+                    hex.push_str(" ----");
+                    text.push(' ');
+                },
+            }
+        }
+        writeln!(out, "{address:5}:{hex:<width$}  |{text}|", width = 5 * WORDS_PER_LINE)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! An implementation of the Synacor OSCON 2012 challenge architecture.
+//!
+//! The [`vm::VM`] type can be embedded directly:
+//!
+//! ```no_run
+//! use oscon_2012_vm_challenge::vm::VM;
+//!
+//! let mut vm = VM::default();
+//! vm.load(&std::fs::read("input/challenge.bin").unwrap()).unwrap();
+//! vm.run().unwrap();
+//! ```
+//!
+//! Embedders that need control between instructions can drive it with
+//! [`vm::VM::step`] instead:
+//!
+//! ```no_run
+//! use oscon_2012_vm_challenge::vm::{VmEvent, VM};
+//!
+//! let mut vm = VM::new(std::io::empty(), std::io::sink());
+//! vm.load(&std::fs::read("input/challenge.bin").unwrap()).unwrap();
+//! loop {
+//!     match vm.step().unwrap() {
+//!         VmEvent::Output(byte) => print!("{}", byte as char),
+//!         VmEvent::NeedsInput => vm.provide_input(b"look\n"),
+//!         VmEvent::Halted => break,
+//!         VmEvent::Continued => (),
+//!     }
+//! }
+//! ```
+
+pub mod analysis;
+pub mod asm;
+pub mod async_vm;
+pub mod base64;
+pub mod bintrace;
+pub mod calltrace;
+pub mod codes;
+pub mod dap;
+pub mod debugger;
+pub mod differential;
+pub mod disasm;
+pub mod expect;
+pub mod flamegraph;
+pub mod gdb;
+pub mod hexdump;
+pub mod lineedit;
+pub mod log;
+pub mod recompile;
+pub mod replay;
+pub mod serve;
+pub mod signals;
+pub mod solve;
+pub mod symbols;
+pub mod transcript;
+pub mod tui;
+pub mod vm;
+pub mod wasm;
+#[cfg(feature = "web")]
+pub mod web;
@@ -0,0 +1,517 @@
+//! A small line editor for input typed at a terminal.
+//!
+//! While a line is being read the terminal is switched out of canonical mode
+//! with `stty`, and keys are handled here: arrows and the usual Emacs control
+//! keys move and edit, up and down walk the history, Ctrl-C abandons the line,
+//! Ctrl-D on an empty line ends input, and Tab asks a caller-supplied function
+//! for completions. The terminal is put back as soon as the line is accepted.
+//! Accepted lines can be appended to a history file so they are available
+//! again in the next session.
+//!
+//! [`LineInput`] is the plain alternative: it leaves the terminal alone and
+//! only gathers whole lines, applying any backspaces they contain.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The most history lines kept in memory and loaded from the history file.
+const MAX_HISTORY: usize = 1000;
+
+/// Reads edited lines from stdin, echoing to stdout. Also usable as a
+/// [`Read`] over the accepted lines, newline-terminated, to drive a VM.
+pub struct LineEditor {
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+    prompt: String,
+    // The rest of the last accepted line, for `Read`.
+    pending: VecDeque<u8>,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Puts the terminal's settings back when dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> io::Result<Self> {
+        let output = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other("stty could not read the terminal settings"));
+        }
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "-iexten", "min", "1", "time", "0"])?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<()> {
+    let status = Command::new("stty").args(args).stdin(Stdio::inherit()).status()?;
+    if !status.success() {
+        return Err(io::Error::other("stty could not change the terminal settings"));
+    }
+    Ok(())
+}
+
+/// A key, decoded from the bytes the terminal sends for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    Complete,
+    Interrupt,
+    EndOfInput,
+    Ignored,
+}
+
+/// The line being edited.
+struct Buffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Buffer {
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// Where the word being typed at the cursor starts.
+    fn completion_start(&self) -> usize {
+        self.chars[..self.cursor].iter().rposition(|ch| ch.is_whitespace()).map_or(0, |idx| idx + 1)
+    }
+
+    fn word_start(&self) -> usize {
+        let mut idx = self.cursor;
+        while idx > 0 && self.chars[idx - 1].is_whitespace() {
+            idx -= 1;
+        }
+        while idx > 0 && !self.chars[idx - 1].is_whitespace() {
+            idx -= 1;
+        }
+        idx
+    }
+
+    fn word_end(&self) -> usize {
+        let mut idx = self.cursor;
+        while idx < self.chars.len() && self.chars[idx].is_whitespace() {
+            idx += 1;
+        }
+        while idx < self.chars.len() && !self.chars[idx].is_whitespace() {
+            idx += 1;
+        }
+        idx
+    }
+}
+
+impl LineEditor {
+    /// An editor with an empty history that is not saved.
+    pub fn new() -> Self {
+        Self { history: Vec::new(), history_file: None, prompt: String::new(), pending: VecDeque::new() }
+    }
+
+    /// An editor whose history is loaded from `path`, if it exists, and
+    /// appended to as lines are accepted.
+    pub fn with_history_file(path: &Path) -> io::Result<Self> {
+        let mut editor = Self::new();
+        match fs::read_to_string(path) {
+            Ok(text) => editor.history = text.lines().map(str::to_string).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+        let excess = editor.history.len().saturating_sub(MAX_HISTORY);
+        editor.history.drain(..excess);
+        editor.history_file = Some(path.to_path_buf());
+        Ok(editor)
+    }
+
+    /// Sets the prompt the caller writes before each line, so it can be
+    /// written again when the line has to start over on a new row.
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_string();
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Records `line` in the history, unless it is blank or repeats the last
+    /// entry.
+    pub fn add_history(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.history_file {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{line}")?;
+        }
+        Ok(())
+    }
+
+    /// Reads one line, without its newline, or `None` at the end of input.
+    /// The line starts wherever the cursor is, so write any prompt first.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.read_line_with(&mut |_| Vec::new())
+    }
+
+    /// Like [`read_line`](Self::read_line), completing with `complete` when
+    /// Tab is pressed. It is given the line up to the cursor and returns the
+    /// candidates for the word that ends there. A single candidate replaces
+    /// the word; several are filled in as far as they agree, and listed if Tab
+    /// is pressed again.
+    pub fn read_line_with(&mut self, complete: &mut dyn FnMut(&str) -> Vec<String>) -> io::Result<Option<String>> {
+        let _raw_mode = RawMode::enter()?;
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout().lock();
+        let mut buffer = Buffer { chars: Vec::new(), cursor: 0 };
+        // Where we are in the history, and the line that was being typed
+        // before walking into it.
+        let mut history_idx = self.history.len();
+        let mut draft = String::new();
+        // Where the terminal's cursor is, in characters from the line start.
+        let mut shown_cursor = 0;
+        let mut last_key = Key::Ignored;
+        loop {
+            let key = read_key(&mut stdin)?;
+            match key {
+                Key::Complete => {
+                    let start = buffer.completion_start();
+                    let before: String = buffer.chars[..buffer.cursor].iter().collect();
+                    let candidates = complete(&before);
+                    let word_len = buffer.cursor - start;
+                    let replacement = match candidates.as_slice() {
+                        [] => None,
+                        [only] if only.ends_with('/') => Some(only.clone()),
+                        [only] => Some(format!("{only} ")),
+                        _ => Some(common_prefix(&candidates)).filter(|prefix| prefix.chars().count() > word_len),
+                    };
+                    if let Some(replacement) = replacement {
+                        buffer.chars.splice(start..buffer.cursor, replacement.chars());
+                        buffer.cursor = start + replacement.chars().count();
+                    } else if candidates.len() > 1 && last_key == Key::Complete {
+                        redraw(&mut stdout, &Buffer { chars: buffer.chars.clone(), cursor: buffer.chars.len() }, shown_cursor)?;
+                        writeln!(stdout)?;
+                        writeln!(stdout, "{}", candidates.join("  "))?;
+                        write!(stdout, "{}", self.prompt)?;
+                        shown_cursor = 0;
+                    }
+                },
+                Key::Char(ch) => {
+                    buffer.chars.insert(buffer.cursor, ch);
+                    buffer.cursor += 1;
+                },
+                Key::Enter => {
+                    writeln!(stdout)?;
+                    stdout.flush()?;
+                    let line = buffer.text();
+                    self.add_history(&line)?;
+                    return Ok(Some(line));
+                },
+                Key::Backspace if buffer.cursor > 0 => {
+                    buffer.cursor -= 1;
+                    buffer.chars.remove(buffer.cursor);
+                },
+                Key::Delete if buffer.cursor < buffer.chars.len() => {
+                    buffer.chars.remove(buffer.cursor);
+                },
+                Key::Left => buffer.cursor = buffer.cursor.saturating_sub(1),
+                Key::Right => buffer.cursor = (buffer.cursor + 1).min(buffer.chars.len()),
+                Key::Home => buffer.cursor = 0,
+                Key::End => buffer.cursor = buffer.chars.len(),
+                Key::WordLeft => buffer.cursor = buffer.word_start(),
+                Key::WordRight => buffer.cursor = buffer.word_end(),
+                Key::KillToEnd => buffer.chars.truncate(buffer.cursor),
+                Key::KillToStart => {
+                    buffer.chars.drain(..buffer.cursor);
+                    buffer.cursor = 0;
+                },
+                Key::KillWord => {
+                    let start = buffer.word_start();
+                    buffer.chars.drain(start..buffer.cursor);
+                    buffer.cursor = start;
+                },
+                Key::Up if history_idx > 0 => {
+                    if history_idx == self.history.len() {
+                        draft = buffer.text();
+                    }
+                    history_idx -= 1;
+                    buffer.set(&self.history[history_idx]);
+                },
+                Key::Down if history_idx < self.history.len() => {
+                    history_idx += 1;
+                    buffer.set(self.history.get(history_idx).unwrap_or(&draft));
+                },
+                Key::Interrupt => {
+                    writeln!(stdout, "^C")?;
+                    write!(stdout, "{}", self.prompt)?;
+                    buffer.set("");
+                    history_idx = self.history.len();
+                    shown_cursor = 0;
+                },
+                Key::EndOfInput if buffer.chars.is_empty() => {
+                    writeln!(stdout)?;
+                    stdout.flush()?;
+                    return Ok(None);
+                },
+                Key::EndOfInput if buffer.cursor < buffer.chars.len() => {
+                    buffer.chars.remove(buffer.cursor);
+                },
+                _ => (),
+            }
+            redraw(&mut stdout, &buffer, shown_cursor)?;
+            shown_cursor = buffer.cursor;
+            last_key = key;
+        }
+    }
+}
+
+/// The longest prefix shared by all of `words`.
+fn common_prefix(words: &[String]) -> String {
+    let Some((first, rest)) = words.split_first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for word in rest {
+        len = first.char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(word.len()), |((idx, _), _)| idx.min(len));
+    }
+    first[..len].to_string()
+}
+
+/// Rewrites the line in place and leaves the terminal's cursor at the
+/// buffer's.
+fn redraw(out: &mut impl Write, buffer: &Buffer, shown_cursor: usize) -> io::Result<()> {
+    if shown_cursor > 0 {
+        write!(out, "\x1b[{shown_cursor}D")?;
+    }
+    write!(out, "{}\x1b[K", buffer.text())?;
+    let back = buffer.chars.len() - buffer.cursor;
+    if back > 0 {
+        write!(out, "\x1b[{back}D")?;
+    }
+    out.flush()
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(Key::EndOfInput);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Complete,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x02 => Key::Left,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfInput,
+        0x05 => Key::End,
+        0x06 => Key::Right,
+        0x0b => Key::KillToEnd,
+        0x0e => Key::Down,
+        0x10 => Key::Up,
+        0x15 => Key::KillToStart,
+        0x17 => Key::KillWord,
+        0x1b => read_escape(input)?,
+        byte if byte < 0x20 => Key::Ignored,
+        byte if byte < 0x80 => Key::Char(byte as char),
+        lead => {
+            let len = match lead {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Ok(Key::Ignored),
+            };
+            let mut bytes = vec![lead];
+            for _ in 1..len {
+                match read_byte(input)? {
+                    Some(byte) => bytes.push(byte),
+                    None => return Ok(Key::Ignored),
+                }
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()) {
+                Some(ch) => Key::Char(ch),
+                None => Key::Ignored,
+            }
+        },
+    };
+    Ok(key)
+}
+
+/// Decodes what follows an escape byte: a CSI or SS3 sequence, or Alt with a
+/// letter.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let key = match read_byte(input)? {
+        Some(b'[') | Some(b'O') => {
+            let mut params = Vec::new();
+            let last = loop {
+                match read_byte(input)? {
+                    Some(byte @ (b'0'..=b'9' | b';')) => params.push(byte),
+                    Some(byte) => break byte,
+                    None => return Ok(Key::Ignored),
+                }
+            };
+            match (last, params.as_slice()) {
+                (b'A', _) => Key::Up,
+                (b'B', _) => Key::Down,
+                (b'C', [.., b'5']) => Key::WordRight,
+                (b'D', [.., b'5']) => Key::WordLeft,
+                (b'C', _) => Key::Right,
+                (b'D', _) => Key::Left,
+                (b'H', _) | (b'~', b"1") | (b'~', b"7") => Key::Home,
+                (b'F', _) | (b'~', b"4") | (b'~', b"8") => Key::End,
+                (b'~', b"3") => Key::Delete,
+                _ => Key::Ignored,
+            }
+        },
+        Some(b'b') => Key::WordLeft,
+        Some(b'f') => Key::WordRight,
+        Some(0x7f) => Key::KillWord,
+        _ => Key::Ignored,
+    };
+    Ok(key)
+}
+
+impl Read for LineEditor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.read_line()? {
+                Some(line) => {
+                    self.pending.extend(line.bytes());
+                    self.pending.push_back(b'\n');
+                },
+                None => return Ok(0),
+            }
+        }
+        self.pending.read(buf)
+    }
+}
+
+/// Reads input a line at a time, for a terminal left in its own line mode or
+/// a pipe carrying keystrokes, and hands each line on as a [`Read`] once it
+/// is complete. Backspace and delete remove the character before them and
+/// Ctrl-U the whole line, so a line comes out as it was meant rather than
+/// with the keys that corrected it. Carriage returns end lines like newlines,
+/// and a line cut off by the end of input is still ended with one.
+///
+/// ```
+/// use std::io::Read;
+/// use oscon_2012_vm_challenge::lineedit::LineInput;
+///
+/// let mut input = LineInput::new(&b"lool\x7f\x7fok\r\ngo north"[..]);
+/// let mut text = String::new();
+/// input.read_to_string(&mut text).unwrap();
+/// assert_eq!(text, "look\ngo north\n");
+/// ```
+pub struct LineInput<R> {
+    inner: R,
+    echo: Option<Box<dyn Write>>,
+    pending: VecDeque<u8>,
+    // Whether the last line ended with a carriage return, so a newline
+    // straight after it ends nothing.
+    after_cr: bool,
+}
+
+impl<R: Read> LineInput<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, echo: None, pending: VecDeque::new(), after_cr: false }
+    }
+
+    /// Writes each line to `echo` as it is accepted, for input nothing else
+    /// shows, such as a pipe. Leave it off for a terminal, which has already
+    /// echoed what was typed.
+    pub fn set_echo(&mut self, echo: Option<Box<dyn Write>>) {
+        self.echo = echo;
+    }
+
+    /// Reads one line, with its backspaces applied, or `None` at the end of
+    /// input.
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        let mut any = false;
+        loop {
+            let Some(byte) = read_byte(&mut self.inner)? else {
+                return Ok(any.then_some(line));
+            };
+            let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => continue,
+                b'\n' | b'\r' => return Ok(Some(line)),
+                0x7f | 0x08 => {
+                    // Drop a whole UTF-8 character: its continuation bytes,
+                    // then its first.
+                    while line.pop().is_some_and(|byte| byte & 0xc0 == 0x80) {}
+                },
+                0x15 => line.clear(),
+                byte => line.push(byte),
+            }
+            any = true;
+        }
+    }
+}
+
+impl<R: Read> Read for LineInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let Some(line) = self.read_line()? else {
+                return Ok(0);
+            };
+            if let Some(echo) = &mut self.echo {
+                echo.write_all(&line)?;
+                echo.write_all(b"\n")?;
+                echo.flush()?;
+            }
+            self.pending.extend(line);
+            self.pending.push_back(b'\n');
+        }
+        self.pending.read(buf)
+    }
+}
@@ -0,0 +1,208 @@
+//! Structured diagnostics: leveled events with key-value fields, nested in
+//! spans, written to stderr as logfmt lines.
+//!
+//! ```text
+//! ts=1760520000.123 level=info target=serve msg=connected peer=127.0.0.1:51234
+//! ts=1760520000.456 level=debug target=vm spans=call@6027>call@1458 msg=output line="What do you do?"
+//! ```
+//!
+//! This follows the model of the `tracing` crate (events, spans, and a
+//! maximum level) without depending on it, so the output can be filtered and
+//! parsed by any logfmt tooling. [`VmLogger`] turns a VM's execution into
+//! spans and events.
+
+use std::cell::RefCell;
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::vm::{Hook, Operation, VM};
+
+/// How much to log, from least to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        })
+    }
+}
+
+/// Parses a level name, or `off` for `None`.
+pub fn parse_level(text: &str) -> Result<Option<Level>, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "off" => Ok(None),
+        "error" => Ok(Some(Level::Error)),
+        "warn" => Ok(Some(Level::Warn)),
+        "info" => Ok(Some(Level::Info)),
+        "debug" => Ok(Some(Level::Debug)),
+        "trace" => Ok(Some(Level::Trace)),
+        _ => Err(format!("unknown log level `{text}` (expected off, error, warn, info, debug, or trace)")),
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        parse_level(text)?.ok_or_else(|| "`off` is not a level".to_string())
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Logs events at `level` and below; `None` turns logging off.
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |level| level as u8), Ordering::Relaxed);
+}
+
+/// Whether events at `level` are logged.
+#[inline]
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    /// The names of the spans this thread is in, outermost first.
+    static SPANS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Logs `message` with `fields` if `level` is enabled.
+pub fn event(level: Level, target: &str, message: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    if !enabled(level) {
+        return;
+    }
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!("ts={}.{:03} level={level} target={target}", time.as_secs(), time.subsec_millis());
+    SPANS.with_borrow(|spans| {
+        if !spans.is_empty() {
+            line.push_str(" spans=");
+            quoted(&mut line, &spans.join(">"));
+        }
+    });
+    line.push_str(" msg=");
+    quoted(&mut line, message);
+    for (key, value) in fields {
+        let _ = write!(line, " {key}=");
+        quoted(&mut line, &value.to_string());
+    }
+    line.push('\n');
+    // Diagnostics must never take the program down with them.
+    let _ = std::io::stderr().lock().write_all(line.as_bytes());
+}
+
+/// Appends `value`, quoted and escaped if it has spaces or special characters.
+fn quoted(line: &mut String, value: &str) {
+    let bare = !value.is_empty() && value.chars().all(|c| c.is_ascii_graphic() && c != '"' && c != '=' && c != '\\');
+    if bare {
+        line.push_str(value);
+        return;
+    }
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(line, "\\u{{{:x}}}", c as u32);
+            },
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+/// Enters a span named `name`, logging the entry with `fields` at `level`.
+/// Events on this thread name it until [`exit`] leaves it.
+pub fn enter(level: Level, target: &str, name: String, fields: &[(&str, &dyn fmt::Display)]) {
+    event(level, target, "enter", &[&[("span", &name as &dyn fmt::Display)], fields].concat());
+    SPANS.with_borrow_mut(|spans| spans.push(name));
+}
+
+/// Leaves the innermost span, logging the exit at `level`.
+pub fn exit(level: Level, target: &str) {
+    if let Some(name) = SPANS.with_borrow_mut(Vec::pop) {
+        event(level, target, "exit", &[("span", &name)]);
+    }
+}
+
+/// How many spans this thread is in.
+pub fn depth() -> usize {
+    SPANS.with_borrow(Vec::len)
+}
+
+/// Logs the execution of the VM it is added to: a `call@ADDR` span for each
+/// frame of its shadow call stack at trace level, and each line the program
+/// prints or reads at debug level. Only add it when one of those levels is
+/// enabled; it costs a little on every instruction.
+#[derive(Default)]
+pub struct VmLogger {
+    output: Vec<u8>,
+    input: Vec<u8>,
+}
+
+impl VmLogger {
+    /// Leaves spans until as many are open as the VM has frames, which also
+    /// catches frames a program abandons without returning.
+    fn unwind_to(call_depth: i32) {
+        while depth() > call_depth.max(0) as usize {
+            exit(Level::Trace, "vm");
+        }
+    }
+
+    fn line(kind: &str, buffer: &mut Vec<u8>) {
+        let line = String::from_utf8_lossy(buffer).into_owned();
+        event(Level::Debug, "vm", kind, &[("line", &line)]);
+        buffer.clear();
+    }
+}
+
+/// The value of the operand `word`: a literal, or the register it names.
+fn value(vm: &VM, word: u16) -> u16 {
+    match word {
+        32_768..=32_775 => vm.registers()[(word - 32_768) as usize],
+        word => word,
+    }
+}
+
+impl Hook for VmLogger {
+    fn before_op(&mut self, vm: &VM, _address: u16, operation: &Operation) {
+        if let Operation::Out(operand) = *operation {
+            match value(vm, operand) as u8 {
+                b'\n' => Self::line("output", &mut self.output),
+                byte => self.output.push(byte),
+            }
+        }
+    }
+
+    fn after_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        match *operation {
+            Operation::Call(_) => {
+                Self::unwind_to(vm.call_depth() - 1);
+                let function = vm.instruction_ptr();
+                enter(Level::Trace, "vm", format!("call@{function}"), &[("from", &address), ("depth", &vm.call_depth())]);
+            },
+            Operation::Ret => Self::unwind_to(vm.call_depth()),
+            Operation::In(register) => match value(vm, register) as u8 {
+                b'\n' => Self::line("input", &mut self.input),
+                byte => self.input.push(byte),
+            },
+            _ => (),
+        }
+    }
+}
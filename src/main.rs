@@ -1,223 +1,1765 @@
-use std::io::Read;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{IsTerminal, Read, Write};
 use std::{fs, io};
-use std::collections::HashMap;
-
-#[derive(Debug, Clone, Copy)]
-enum Operation {
-    Halt,
-    Set(u16, u16),
-    Push(u16),
-    Pop(u16),
-    Eq(u16, u16, u16),
-    Gt(u16, u16, u16),
-    Jmp(u16),
-    Jt(u16, u16),
-    Jf(u16, u16),
-    Add(u16, u16, u16),
-    Mult(u16, u16, u16),
-    Mod(u16, u16, u16),
-    And(u16, u16, u16),
-    Or(u16, u16, u16),
-    Not(u16, u16),
-    Rmem(u16, u16),
-    Wmem(u16, u16),
-    Call(u16),
-    Ret,
-    Out(u16),
-    In(u16),
-    Noop,
-}
-
-impl Operation {
-    fn new(opcode: u16, args: Vec<u16>) -> Self {
-        match opcode {
-            0 => Operation::Halt,
-            1 => Operation::Set(args[0], args[1]),
-            2 => Operation::Push(args[0]),
-            3 => Operation::Pop(args[0]),
-            4 => Operation::Eq(args[0], args[1], args[2]),
-            5 => Operation::Gt(args[0], args[1], args[2]),
-            6 => Operation::Jmp(args[0]),
-            7 => Operation::Jt(args[0], args[1]),
-            8 => Operation::Jf(args[0], args[1]),
-            9 => Operation::Add(args[0], args[1], args[2]),
-            10 => Operation::Mult(args[0], args[1], args[2]),
-            11 => Operation::Mod(args[0], args[1], args[2]),
-            12 => Operation::And(args[0], args[1], args[2]),
-            13 => Operation::Or(args[0], args[1], args[2]),
-            14 => Operation::Not(args[0], args[1]),
-            15 => Operation::Rmem(args[0], args[1]),
-            16 => Operation::Wmem(args[0], args[1]),
-            17 => Operation::Call(args[0]),
-            18 => Operation::Ret,
-            19 => Operation::Out(args[0]),
-            20 => Operation::In(args[0]),
-            21 => Operation::Noop,
-            _ => panic!("Invalid opcode.")
-        }
-    }
-
-    fn num_arguments(opcode: u16) -> u16 {
-        match opcode {
-            0 | 18 | 21 => 0,
-            2 | 3 | 6 | 17 | 19 | 20 => 1,
-            1 | 7 | 8 | 14 | 15 | 16 => 2,
-            4 | 5 | 9 | 10 | 11 | 12 | 13 => 3,
-            _ => panic!("Invalid opcode."),
-        }
-    }    
-}
-
-#[derive(Debug, Default)]
-struct VM {
-    instruction_ptr: u16,
-    mem: HashMap<u16, u16>,
-    registers: [u16; 8],
-    stack: Vec<u16>,
-    halted: bool,
-}
-
-impl VM {
-    // TODO: should this return a value?
-    fn run_binary(&mut self, filename: &str) {
-        self.read_binary(filename);
-        while !self.halted {
-            self.execute_next_operation();
-        }
-    }
-
-    fn read_binary(&mut self, filename: &str) {
-        fs::read(filename).unwrap()
-            .chunks_mut(2)
-            .enumerate()
-            .for_each(|(idx, bytes)| {
-                self.mem.insert(
-                    idx.try_into().unwrap(),
-                    u16::from_le_bytes(bytes.try_into().unwrap()),
-                );
-            });
-    }
-
-    fn execute_next_operation(&mut self) {
-        let operation = self.parse_next_operation();
-        self.execute_operation(operation);
-    }
-
-    fn parse_next_operation(&self) -> Operation {
-        // TODO: assert?
-        let mut args = vec![];
-        let opcode = *self.mem.get(&self.instruction_ptr).unwrap();
-        for i in 0..Operation::num_arguments(opcode) {
-            args.push(*self.mem.get(&(self.instruction_ptr + 1 + i)).unwrap());
-        }
-        Operation::new(opcode, args)
-    }
-
-    fn execute_operation(&mut self, operation: Operation) {
-        match operation {
-            Operation::Halt => self.halted = true,
-            Operation::Set(register, value) => {
-                let register = Self::register_idx(register)
-                    .expect("First argument for set operation should be a register.");
-                self.registers[register] = self.get_value(value);
-            },
-            Operation::Push(value) => self.stack.push(self.get_value(value)),
-            Operation::Pop(address) => {
-                let val = self.stack.pop().expect("Stack should be non-empty for pop operation.");
-                self.set_register(address, val);
-            },
-            Operation::Eq(address, b, c) => {
-                self.set_register(address, if self.get_value(b) == self.get_value(c) { 1 } else { 0 });
-            },
-            Operation::Gt(address, b, c) => {
-                self.set_register(address, if self.get_value(b) > self.get_value(c) { 1 } else { 0 });
-            },
-            Operation::Jmp(address) => self.instruction_ptr = self.get_value(address),
-            Operation::Jt(value, address) => {
-                if self.get_value(value) != 0 { self.instruction_ptr = self.get_value(address); }
-                else { self.instruction_ptr += 3; }
-            },
-            Operation::Jf(value, address) => {
-                if self.get_value(value) == 0 { self.instruction_ptr = self.get_value(address); }
-                else { self.instruction_ptr += 3; }
-            },
-            Operation::Add(address, b, c) => {
-                self.set_register(address, (self.get_value(b) + self.get_value(c)) % 32_768);
-            },
-            Operation::Mult(address, b, c) => {
-                self.set_register(address, ((self.get_value(b) as u32 * self.get_value(c) as u32) % 32_768) as u16);
-            },
-            Operation::Mod(address, b, c) => {
-                self.set_register(address, self.get_value(b) % self.get_value(c));
-            },
-            Operation::And(address, b, c) => {
-                self.set_register(address, self.get_value(b) & self.get_value(c));
-            },
-            Operation::Or(address, b, c) => {
-                self.set_register(address, self.get_value(b) | self.get_value(c));
-            },
-            Operation::Not(address, b) => {
-                self.set_register(address, (self.get_value(b) ^ 0xffff) & 0x7fff);
-            },
-            Operation::Rmem(write_address, read_address) => {
-                self.set_register(write_address, *self.mem.get(&self.get_value(read_address)).unwrap());
-            },
-            Operation::Wmem(write_address, read_address) => {
-                self.mem.insert(self.get_value(write_address), self.get_value(read_address));
-            },
-            Operation::Call(address) => {
-                self.stack.push(self.instruction_ptr + 2);
-                self.instruction_ptr = self.get_value(address);
-            },
-            Operation::Ret => {
-                if let Some(next) = self.stack.pop() {
-                    self.instruction_ptr = next;
-                } else {
-                    self.halted = true;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::net::TcpListener;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use oscon_2012_vm_challenge::bintrace::{TraceReader, TraceWriter};
+use oscon_2012_vm_challenge::calltrace::CallTrace;
+use oscon_2012_vm_challenge::flamegraph::FoldedStacks;
+use oscon_2012_vm_challenge::dap::DapServer;
+use oscon_2012_vm_challenge::debugger::{self, Debugger};
+use oscon_2012_vm_challenge::expect::regex::Regex;
+use oscon_2012_vm_challenge::expect::{Expect, Script};
+use oscon_2012_vm_challenge::gdb::GdbStub;
+use oscon_2012_vm_challenge::transcript::Transcript;
+use oscon_2012_vm_challenge::tui::{self, Tui};
+use oscon_2012_vm_challenge::analysis::callgraph::CallGraph;
+use oscon_2012_vm_challenge::analysis::cfg::Cfg;
+use oscon_2012_vm_challenge::analysis::deadcode::DeadCode;
+use oscon_2012_vm_challenge::analysis::functions::Functions;
+use oscon_2012_vm_challenge::analysis::loops;
+use oscon_2012_vm_challenge::analysis::teleporter::{self, TeleporterCheck};
+use oscon_2012_vm_challenge::analysis::validate::Validation;
+use oscon_2012_vm_challenge::analysis::{decompile, strings};
+use oscon_2012_vm_challenge::hexdump::hexdump;
+use oscon_2012_vm_challenge::lineedit::{LineEditor, LineInput};
+use oscon_2012_vm_challenge::log::{self, Level, VmLogger};
+use oscon_2012_vm_challenge::replay::{Recorder, Recording, Replayer};
+use oscon_2012_vm_challenge::serve::{self, ServeConfig};
+use oscon_2012_vm_challenge::signals;
+use oscon_2012_vm_challenge::symbols::Symbols;
+use oscon_2012_vm_challenge::solve::coins::{self, Coin};
+use oscon_2012_vm_challenge::solve::playthrough;
+use oscon_2012_vm_challenge::solve::search::{self, SearchConfig};
+use oscon_2012_vm_challenge::solve::vault::{self, Vault};
+use oscon_2012_vm_challenge::{asm, disasm, recompile};
+use oscon_2012_vm_challenge::vm::{self, ArithmeticPolicy, CheckpointConfig, CoreDump, EofPolicy, Executed, HaltCause, HaltReason, ImageFormat, MetaConfig, OpcodePolicy, Operation, Patch, Snapshot, VmError, MEMORY_SIZE, VM};
+
+/// Exit codes for the ways a run can end other than `halt` (0). Any other
+/// error exits with 1, and a usage error with 2.
+const EXIT_EMPTY_STACK_RETURN: u8 = 3;
+const EXIT_USER_QUIT: u8 = 4;
+const EXIT_STEP_LIMIT: u8 = 5;
+const EXIT_INPUT_EXHAUSTED: u8 = 6;
+const EXIT_FAULT: u8 = 7;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  the program executed `halt`, or ran out of input with --on-eof halt
+  1  an error outside the program, such as a missing file
+  2  invalid arguments
+  3  the program executed `ret` with an empty stack
+  4  the user typed the `quit` meta-command
+  5  the step limit was reached
+  6  the program wanted input after the input ran out
+  7  the program faulted";
+
+/// How many instructions `run` executes between checks for a SIGUSR1
+/// snapshot request.
+const SNAPSHOT_POLL_STEPS: u64 = 100_000;
+
+/// The debugger commands `debug` runs first, if the file exists.
+const DEFAULT_INIT_FILE: &str = ".vmdbg";
+
+#[derive(Debug, Parser)]
+#[command(about = "Virtual machine for the Synacor OSCON 2012 challenge", after_help = EXIT_CODES_HELP)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Log diagnostics at this level and above to stderr: off, error, warn,
+    /// info, debug (adds the game's input and output lines), or trace (adds a
+    /// span for every function call).
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "info", value_parser = log::parse_level)]
+    // Spelled out so clap parses `off` to `None` rather than treating the
+    // option as optional.
+    log_level: std::option::Option<Level>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a binary until it halts.
+    Run(RunArgs),
+    /// Load a binary and drive it from an interactive debugger.
+    Debug(DebugArgs),
+    /// Load a binary into a full-screen debugger showing the code, registers,
+    /// stack, memory, and the game's console together.
+    Tui(TuiArgs),
+    /// Drive a binary with an expect script of `send` lines and `expect`
+    /// patterns.
+    Expect(ExpectArgs),
+    /// Load a binary and wait for GDB to attach with `target remote`.
+    Gdb(GdbArgs),
+    /// Act as a Debug Adapter Protocol server for an editor, on stdin and
+    /// stdout or on a TCP port.
+    Dap(DapArgs),
+    /// Print the disassembly of a binary.
+    Disasm(DisasmArgs),
+    /// Assemble a source file into a binary.
+    Asm(AsmArgs),
+    /// Export the control-flow graph of a binary in Graphviz DOT format.
+    Cfg(CfgArgs),
+    /// Export the call graph of a binary, optionally including calls observed
+    /// while running it.
+    Callgraph(CallgraphArgs),
+    /// Compare two saved states, listing changed registers, memory, and stack.
+    DiffState(DiffStateArgs),
+    /// Hex dump a range of memory, or write it out as a raw binary.
+    Dump(DumpArgs),
+    /// Print rough pseudo-code for the functions of a binary.
+    Decompile(DecompileArgs),
+    /// List the functions of a binary: where each starts, how it was found,
+    /// and the addresses it spans.
+    Functions(FunctionsArgs),
+    /// List candidate strings: runs of `out` instructions and character data.
+    Strings(StringsArgs),
+    /// Check that a binary is whole, holds only valid words, and starts with
+    /// code that decodes, and report its opcodes and suspected data.
+    Validate(ValidateArgs),
+    /// Translate a binary into a standalone Rust program that runs it natively.
+    Recompile(RecompileArgs),
+    /// Serve a binary over TCP, running a separate game for each telnet
+    /// connection.
+    Serve(ServeArgs),
+    /// Serve a terminal page that plays a binary in the browser, running a
+    /// separate game for each visitor.
+    #[cfg(feature = "web")]
+    Web(WebArgs),
+    /// Search breadth first for commands that make a binary print a line
+    /// matching a pattern.
+    Search(SearchArgs),
+    /// Read back a binary trace written by `run --trace-format binary`.
+    Trace {
+        #[command(subcommand)]
+        action: TraceAction,
+    },
+    /// Solve one of the challenge's puzzles and keep playing.
+    Solve {
+        #[command(subcommand)]
+        puzzle: Puzzle,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Puzzle {
+    /// Find the r7 value that passes the teleporter's confirmation check, set
+    /// it, and patch out the slow check before handing the game to you.
+    Teleporter(Box<RunArgs>),
+    /// Find the order to place the coins on the monument and print the
+    /// commands that do it.
+    Coins(CoinsArgs),
+    /// Find the shortest walk through the vault's orb rooms that reaches the
+    /// door with the right weight, and print the commands that walk it.
+    Vault(VaultArgs),
+    /// Play the whole game automatically, solving every puzzle, and list the
+    /// codes it prints.
+    All(AllArgs),
+}
+
+#[derive(Debug, Args)]
+struct DebugArgs {
+    #[command(flatten)]
+    run: RunArgs,
+    /// Keep the history of debugger commands in this file.
+    #[arg(long, value_name = "FILE", default_value = ".vmdbg_history")]
+    command_history: PathBuf,
+    /// Run the debugger commands in this file before reading any from the
+    /// terminal, e.g. to set breakpoints and load symbols. Defaults to
+    /// `.vmdbg` in the current directory, if there is one.
+    #[arg(long, value_name = "FILE")]
+    init: Option<PathBuf>,
+    /// Don't run an init file.
+    #[arg(long, conflicts_with = "init")]
+    no_init: bool,
+    /// Inspect a core dump written by `--core-dump` instead of starting the
+    /// binary.
+    #[arg(long, value_name = "FILE", conflicts_with = "load_state")]
+    core: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct TuiArgs {
+    /// Path to the binary to load.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Queue this file as the game's first input.
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Resume from a saved state instead of starting the binary from scratch.
+    #[arg(long)]
+    load_state: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ExpectArgs {
+    /// Path to the script.
+    script: PathBuf,
+    /// Path to the binary to run.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Resume from a saved state instead of starting the binary from scratch.
+    #[arg(long)]
+    load_state: Option<PathBuf>,
+    /// Don't echo the session; only report whether the script passed.
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Debug, Args)]
+struct GdbArgs {
+    #[command(flatten)]
+    run: RunArgs,
+    /// The address to listen on for GDB.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:1234")]
+    listen: String,
+}
+
+#[derive(Debug, Args)]
+struct DapArgs {
+    /// Wait for the editor to connect to this address instead of talking over
+    /// stdin and stdout.
+    #[arg(long, value_name = "ADDR")]
+    listen: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Path to the binary to serve.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// The address to listen on.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:2323")]
+    listen: String,
+    /// Turn clients away while this many games are running.
+    #[arg(long, value_name = "N")]
+    max_connections: Option<usize>,
+    /// Close a connection that has sent nothing for this many seconds.
+    #[arg(long, value_name = "SECS")]
+    idle_timeout: Option<u64>,
+}
+
+#[cfg(feature = "web")]
+#[derive(Debug, Args)]
+struct WebArgs {
+    /// Path to the binary to serve.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// The address to listen on.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: String,
+}
+
+#[derive(Debug, Args)]
+struct SearchArgs {
+    /// Path to the binary to play.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Start from a saved state instead.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Stop at the first response matching this pattern.
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    until: Regex,
+    /// A command to try at every prompt; repeat for more.
+    #[arg(short, long = "command", value_name = "COMMAND")]
+    commands: Vec<String>,
+    /// Also try `go` in each exit the game listed in its last response.
+    #[arg(long)]
+    exits: bool,
+    /// The most commands to try in a row.
+    #[arg(long, value_name = "N", default_value_t = SearchConfig::default().max_depth)]
+    depth: usize,
+    /// The most instructions one command may run.
+    #[arg(long, value_name = "N", default_value_t = SearchConfig::default().max_steps)]
+    max_steps: u64,
+    /// Worker threads (default: one per core).
+    #[arg(long, value_name = "N", default_value_t = 0, hide_default_value = true)]
+    threads: usize,
+    /// Ignore this address, or range of addresses, when deciding whether two
+    /// states are the same; repeat for more.
+    #[arg(long, value_name = "ADDR[-ADDR]", value_parser = parse_address_range)]
+    volatile: Vec<RangeInclusive<u16>>,
+}
+
+/// Parses `--on-eof`.
+fn parse_eof_policy(text: &str) -> Result<EofPolicy, String> {
+    match text {
+        "wait" => Ok(EofPolicy::NeedsInput),
+        "halt" => Ok(EofPolicy::Halt),
+        value => match value.parse::<u16>() {
+            Ok(value) if value < 32_768 => Ok(EofPolicy::Value(value)),
+            _ => Err(format!("expected `wait`, `halt`, or a value below 32768, not `{value}`")),
+        },
+    }
+}
+
+/// Parses `--on-divide-by-zero`.
+fn parse_arithmetic_policy(text: &str) -> Result<ArithmeticPolicy, String> {
+    match text {
+        "fault" => Ok(ArithmeticPolicy::Fault),
+        "saturate" => Ok(ArithmeticPolicy::Saturate),
+        "wrap" => Ok(ArithmeticPolicy::Wrap),
+        _ => Err(format!("expected `fault`, `saturate`, or `wrap`, not `{text}`")),
+    }
+}
+
+/// Parses `--on-invalid-opcode`.
+fn parse_opcode_policy(text: &str) -> Result<OpcodePolicy, String> {
+    match text {
+        "fault" => Ok(OpcodePolicy::Fault),
+        "noop" => Ok(OpcodePolicy::Noop),
+        "break" => Ok(OpcodePolicy::Break),
+        _ => Err(format!("expected `fault`, `noop`, or `break`, not `{text}`")),
+    }
+}
+
+/// Parses `rN=VALUE`, with the value in decimal or `0x` hex.
+fn parse_register_setting(text: &str) -> Result<(usize, u16), String> {
+    let (register, value) = text.split_once('=').ok_or("expected `rN=VALUE`")?;
+    let register = match register.trim().strip_prefix('r').map(str::parse::<usize>) {
+        Some(Ok(register @ 0..=7)) => register,
+        _ => return Err(format!("no such register `{register}`; use r0 to r7")),
+    };
+    let value = debugger::parse_number::<u16>(value.trim())?;
+    match value < 32_768 {
+        true => Ok((register, value)),
+        false => Err(format!("{value} is not a 15-bit value")),
+    }
+}
+
+/// Parses `FILE@OFFSET`, with the offset in decimal or `0x` hex.
+fn parse_load_spec(text: &str) -> Result<(PathBuf, u16), String> {
+    let (path, offset) = text.rsplit_once('@').ok_or("expected `FILE@OFFSET`")?;
+    let offset = debugger::parse_number::<u16>(offset.trim())?;
+    match (offset as usize) < MEMORY_SIZE {
+        true => Ok((PathBuf::from(path), offset)),
+        false => Err(format!("offset {offset} is past the end of memory")),
+    }
+}
+
+/// Parses `ADDR` or `START-END`.
+fn parse_address_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse = |address: &str| address.trim().parse::<u16>().map_err(|err| format!("`{address}`: {err}"));
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(text)?, parse(text)?),
+    };
+    match start <= end {
+        true => Ok(start..=end),
+        false => Err(format!("{start} is after {end}")),
+    }
+}
+
+#[derive(Debug, Args)]
+struct AllArgs {
+    /// Path to the challenge binary.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Write the commands and everything the game printed to this file.
+    #[arg(long)]
+    transcript: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct VaultArgs {
+    /// The 16 rooms, north row first, e.g. "* 8 - 1 4 * 11 * + 4 - 18 22 - 9 *".
+    #[arg(long, default_value_t = Vault::default().to_string())]
+    grid: String,
+    /// The weight the door expects.
+    #[arg(long, default_value_t = vault::VAULT_TARGET)]
+    target: i64,
+}
+
+#[derive(Debug, Args)]
+struct CoinsArgs {
+    /// The five coins as `name=value`, e.g. `red=2 corroded=3 shiny=5
+    /// concave=7 blue=9`.
+    #[arg(num_args = 5, required = true, value_name = "NAME=VALUE")]
+    coins: Vec<Coin>,
+    /// The value the equation must reach.
+    #[arg(long, default_value_t = coins::MONUMENT_TOTAL, allow_negative_numbers = true)]
+    total: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputMode {
+    Edit,
+    Line,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TraceFormat {
+    Text,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SummaryFormat {
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// Path to the binary to load.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// How the binary is encoded: `binary`, `hex` (one word per token),
+    /// `ihex` (Intel HEX), `base64`, or `auto` to tell from its contents.
+    #[arg(long, value_name = "FORMAT", default_value = "auto")]
+    format: ImageFormat,
+    /// Feed this file to the program's input first, then continue reading from
+    /// stdin once it is exhausted.
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+    /// Write program output to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Append everything the program prints to this file as well.
+    #[arg(long)]
+    transcript: Option<PathBuf>,
+    /// Also record input lines in the transcript.
+    #[arg(long, requires = "transcript")]
+    transcript_input: bool,
+    /// Log every executed instruction to FILE, or to stderr if no file is given.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    trace: Option<PathBuf>,
+    /// How --trace writes: `text`, a line per instruction, or `binary`, a
+    /// compact encoding for long runs to read back with `trace dump` and
+    /// `trace query`.
+    #[arg(long, value_name = "FORMAT", default_value = "text", requires = "trace")]
+    trace_format: TraceFormat,
+    /// Write the program's function calls to FILE as Chrome trace events,
+    /// one microsecond per instruction, to view as a flame chart in Perfetto
+    /// or `chrome://tracing`.
+    #[arg(long, value_name = "FILE")]
+    chrome_trace: Option<PathBuf>,
+    /// Count instructions by call path, and write them to FILE (or stderr) on
+    /// exit as folded stacks, for inferno, `flamegraph.pl`, or speedscope.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    flamegraph: Option<PathBuf>,
+    /// Name addresses in the trace and the debugger with the `address = name`
+    /// lines in this symbol file.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+    /// Count executions per address and opcode, and write a report sorted by
+    /// hotness to FILE (or stderr) on exit.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    profile: Option<PathBuf>,
+    /// Record which addresses execute, and write a coverage summary with an
+    /// annotated disassembly to FILE (or stderr) on exit.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    coverage: Option<PathBuf>,
+    /// Record which addresses execute and which are read, and on exit write
+    /// the reachable code that never executed and the memory never executed
+    /// or read, with an annotated disassembly, to FILE (or stderr).
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    dead_code: Option<PathBuf>,
+    /// Record writes over instructions that have already executed, with each
+    /// instruction before and after, and where written code such as
+    /// decrypted code runs, and write them to FILE (or stderr) on exit. With
+    /// `--log-level debug`, each is also logged as it happens.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    self_modification: Option<PathBuf>,
+    /// Watch the output for challenge codes, and write a summary of those found
+    /// to FILE (or stderr) on exit.
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    codes: Option<PathBuf>,
+    /// Show the last code found as it reads in a mirror.
+    #[arg(long, requires = "codes")]
+    mirror_last_code: bool,
+    /// Stop after executing this many instructions.
+    #[arg(long, value_name = "N")]
+    max_steps: Option<u64>,
+    /// Take an automatic checkpoint every N instructions (see `!rollback`).
+    #[arg(long, value_name = "N")]
+    checkpoint_every: Option<u64>,
+    /// The number of automatic checkpoints to keep.
+    #[arg(long, value_name = "K", default_value_t = 10)]
+    checkpoint_keep: usize,
+    /// Resume from a saved state instead of starting the binary from scratch.
+    #[arg(long)]
+    load_state: Option<PathBuf>,
+    /// Overwrite memory after loading: `addr=value`, or `addr=v1,v2,...` for
+    /// consecutive words. May be repeated.
+    #[arg(long, value_name = "ADDR=VALUE")]
+    patch: Vec<Patch>,
+    /// Also load the binary in FILE at OFFSET, after the main binary or state
+    /// and before any patches. May be repeated.
+    #[arg(long, value_name = "FILE@OFFSET", value_parser = parse_load_spec)]
+    load: Vec<(PathBuf, u16)>,
+    /// Apply the patches listed in FILE, one `addr=value` per line, before any
+    /// given with --patch.
+    #[arg(long, value_name = "FILE")]
+    patch_file: Option<PathBuf>,
+    /// Once the program has decrypted itself, set r7 to R7 and replace the
+    /// teleporter's confirmation call with `set`/`noop` instructions.
+    #[arg(long, value_name = "R7")]
+    auto_patch_teleporter: Option<u16>,
+    /// Set a register after loading and patching: `r7=25734`. May be
+    /// repeated.
+    #[arg(long, value_name = "REG=VALUE", value_parser = parse_register_setting)]
+    set_register: Vec<(usize, u16)>,
+    /// Set the --set-register registers once the program first waits for
+    /// input instead, since its self-test fails if any register is nonzero.
+    #[arg(long, requires = "set_register")]
+    registers_after_self_test: bool,
+    /// Once everything else is set up, empty the stacks and start executing at
+    /// ADDR, to run one function by itself: its final `ret` then halts.
+    #[arg(long, value_name = "ADDR")]
+    start_addr: Option<u16>,
+    /// Stop before executing the instruction at ADDR, and print the registers
+    /// and stack.
+    #[arg(long, value_name = "ADDR")]
+    stop_addr: Option<u16>,
+    /// When the program faults, write its state and the last instructions it
+    /// executed to FILE, to inspect with `debug --core FILE`.
+    #[arg(long, value_name = "FILE")]
+    core_dump: Option<PathBuf>,
+    /// Keep the last N instructions executed, with their operands, to show
+    /// when the program faults and to include in a core dump. 0 keeps none.
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    recent_instructions: usize,
+    /// Pass input lines starting with `!` to the program instead of treating
+    /// them as meta-commands (`!save`, `!load`, `!regs`, `!quit`). Always the
+    /// case with `--record` or `--replay`, since a recording can't capture
+    /// what a meta-command does.
+    #[arg(long)]
+    no_meta_commands: bool,
+    /// Keep the history of lines typed at the game in this file.
+    #[arg(long, value_name = "FILE", default_value = ".vm_history")]
+    history: PathBuf,
+    /// Read typed input straight from the terminal, without line editing or
+    /// history. The same as `--input-mode raw`.
+    #[arg(long)]
+    no_line_editing: bool,
+    /// How typed input reaches the program: `edit` through the line editor,
+    /// with history; `line` a whole line at a time, applying backspaces
+    /// locally and leaving echo to the terminal; or `raw` byte by byte.
+    /// Without a terminal, `edit` reads raw.
+    #[arg(long, value_name = "MODE", default_value = "edit")]
+    input_mode: InputMode,
+    /// In `line` mode, don't write each line read back to the output. Lines
+    /// are only echoed when stdin is not a terminal, since a terminal echoes
+    /// them already.
+    #[arg(long)]
+    no_echo: bool,
+    /// Record every byte the program reads, and everything it prints, to this
+    /// file.
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Feed the program the input recorded in this file instead of reading
+    /// any, and fail if it reads or prints anything different.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "record"])]
+    replay: Option<PathBuf>,
+    /// Where to save the snapshots taken when the process receives SIGUSR1
+    /// while running.
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    snapshot_dir: PathBuf,
+    /// What `in` does when the input runs out: `wait` for more (failing
+    /// unless stdin can supply it), `halt`, or read a value such as `0` or
+    /// `32767` and carry on.
+    #[arg(long, value_name = "POLICY", default_value = "wait", value_parser = parse_eof_policy)]
+    on_eof: EofPolicy,
+    /// What `mod` does when dividing by zero: `fault`, `saturate` to 32767,
+    /// or `wrap`, leaving the dividend as if dividing by 32768.
+    #[arg(long, value_name = "POLICY", default_value = "fault", value_parser = parse_arithmetic_policy)]
+    on_divide_by_zero: ArithmeticPolicy,
+    /// What to do on reaching a word that is not an opcode: `fault`, run it
+    /// as `noop`, or `break` into the debugger (under `debug`; `run` treats
+    /// it as a fault).
+    #[arg(long, value_name = "POLICY", default_value = "fault", value_parser = parse_opcode_policy)]
+    on_invalid_opcode: OpcodePolicy,
+    /// Check the program against the spec as it runs. Operands are always
+    /// checked; this also faults when `rmem` reads a word above 32775.
+    #[arg(long)]
+    strict: bool,
+    /// On exit, write a summary of the run to stderr: the steps executed, why
+    /// it stopped, how long it took, the codes printed, and the bytes of
+    /// output.
+    #[arg(long, value_name = "FORMAT")]
+    summary: Option<SummaryFormat>,
+}
+
+#[derive(Debug, Args)]
+struct DisasmArgs {
+    /// Path to the binary to disassemble.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Disassemble the memory of a saved state instead, e.g. after the
+    /// program has decrypted its code.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Name addresses with the `address = name` lines in this symbol file.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+    /// Mark the headers and back edges of the loops in the code reachable
+    /// from address 0 (and from a saved state's instruction pointer).
+    #[arg(long)]
+    loops: bool,
+}
+
+#[derive(Debug, Args)]
+struct AsmArgs {
+    /// Path to the assembly source.
+    source: PathBuf,
+    /// Where to write the assembled binary.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct CfgArgs {
+    /// Path to the binary to analyze.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Only include the function starting at this address or symbol.
+    #[arg(long, value_name = "ADDR")]
+    function: Option<String>,
+    /// Name addresses with the `address = name` lines in this symbol file.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+    /// Write the graph to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct CallgraphArgs {
+    /// Path to the binary to analyze.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+    /// Label functions in the DOT output with the names in this symbol file.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+    /// Also run the binary, recording the calls it makes, until it halts or
+    /// exhausts its input.
+    #[arg(long)]
+    dynamic: bool,
+    /// Input to feed the program when running with --dynamic.
+    #[arg(short, long, requires = "dynamic")]
+    input: Option<PathBuf>,
+    /// Stop the --dynamic run after this many instructions.
+    #[arg(long, value_name = "N", requires = "dynamic")]
+    max_steps: Option<u64>,
+    /// Write the graph to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct DiffStateArgs {
+    /// The earlier state.
+    old: PathBuf,
+    /// The later state.
+    new: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct DumpArgs {
+    /// Path to the binary to dump.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Dump the memory of a saved state instead.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// The first address to dump.
+    #[arg(long, value_name = "ADDR", default_value_t = 0)]
+    start: u16,
+    /// The number of words to dump (default: through the end of memory).
+    #[arg(long, value_name = "N")]
+    len: Option<usize>,
+    /// Write the words to this file as a raw little-endian binary instead of
+    /// printing a hex dump.
+    #[arg(long, value_name = "FILE")]
+    raw: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct DecompileArgs {
+    /// Path to the binary to analyze.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Analyze the memory of a saved state instead, e.g. after the program has
+    /// decrypted its code.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Only decompile the function starting at this address.
+    #[arg(long, value_name = "ADDR")]
+    function: Option<u16>,
+    /// Write the pseudo-code to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct FunctionsArgs {
+    /// Path to the binary to analyze.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Analyze the memory of a saved state instead, e.g. after the program has
+    /// decrypted its code.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Show the names in this symbol file.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+    /// Write a symbol file naming each function without a name as `fn_ADDR`,
+    /// keeping the names from --symbols.
+    #[arg(long, value_name = "FILE")]
+    write_symbols: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct RecompileArgs {
+    /// Path to the binary to translate.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Translate a saved state instead, starting where it left off. Save one
+    /// after the program has decrypted its code to keep it all native.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Write the Rust source to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct StringsArgs {
+    /// Path to the binary to search.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// Search the memory of a saved state instead, e.g. after the program has
+    /// decrypted its data.
+    #[arg(long, conflicts_with = "binary")]
+    state: Option<PathBuf>,
+    /// Only report strings with at least this many characters.
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    min_len: usize,
+}
+
+#[derive(Debug, Subcommand)]
+enum TraceAction {
+    /// Print the instructions in a range of steps as text.
+    Dump(TraceDumpArgs),
+    /// Print or count the instructions at given addresses or with a given
+    /// opcode.
+    Query(TraceQueryArgs),
+}
+
+#[derive(Debug, Args)]
+struct TraceDumpArgs {
+    /// The binary trace to read.
+    trace: PathBuf,
+    /// Start at this step.
+    #[arg(long, value_name = "STEP", default_value_t = 0)]
+    from: u64,
+    /// Stop after this step.
+    #[arg(long, value_name = "STEP", default_value_t = u64::MAX)]
+    to: u64,
+}
+
+#[derive(Debug, Args)]
+struct TraceQueryArgs {
+    #[command(flatten)]
+    range: TraceDumpArgs,
+    /// Only instructions at ADDR, or between START and END inclusive.
+    #[arg(long, value_name = "ADDR|START-END", value_parser = parse_address_range)]
+    address: Option<RangeInclusive<u16>>,
+    /// Only instructions with this mnemonic.
+    #[arg(long, value_name = "MNEMONIC", value_parser = parse_mnemonic)]
+    opcode: Option<u16>,
+    /// Print how many instructions match instead of listing them.
+    #[arg(long)]
+    count: bool,
+}
+
+/// Parses an instruction mnemonic into its opcode.
+fn parse_mnemonic(text: &str) -> Result<u16, String> {
+    (0..).map_while(Operation::mnemonic_for)
+        .position(|mnemonic| mnemonic == text)
+        .map(|opcode| opcode as u16)
+        .ok_or_else(|| format!("unknown mnemonic `{text}`"))
+}
+
+#[derive(Debug, Args)]
+struct ValidateArgs {
+    /// Path to the binary to check.
+    #[arg(default_value = "input/challenge.bin")]
+    binary: PathBuf,
+    /// How the binary is encoded; see `run --format`.
+    #[arg(long, value_name = "FORMAT", default_value = "auto")]
+    format: ImageFormat,
+}
+
+/// Whether typed input should go through the line editor.
+fn line_editing(args: &RunArgs) -> bool {
+    !args.no_line_editing
+        && args.input_mode == InputMode::Edit
+        && io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+}
+
+/// Typed input, a line at a time, for `--input-mode line`.
+fn line_input(args: &RunArgs) -> LineInput<io::Stdin> {
+    let mut input = LineInput::new(io::stdin());
+    if !args.no_echo && !io::stdin().is_terminal() {
+        input.set_echo(Some(Box::new(io::stdout())));
+    }
+    input
+}
+
+/// A run that ended some way other than `halt`, with the exit code that
+/// reports it and, unless the program simply ended, a message.
+#[derive(Debug)]
+struct Stopped {
+    code: u8,
+    message: Option<String>,
+}
+
+impl std::fmt::Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message.as_deref().unwrap_or("the program stopped"))
+    }
+}
+
+impl Error for Stopped {}
+
+/// The exit code for a command that failed with `err`.
+fn exit_code(err: &(dyn Error + 'static)) -> u8 {
+    if let Some(stopped) = err.downcast_ref::<Stopped>() {
+        return stopped.code;
+    }
+    match err.downcast_ref::<VmError>() {
+        Some(VmError::InputExhausted { .. }) => EXIT_INPUT_EXHAUSTED,
+        Some(VmError::Io(_) | VmError::InvalidImage { .. } | VmError::InvalidEncoding(_) | VmError::InvalidSnapshot(_))
+        | None => 1,
+        Some(_) => EXIT_FAULT,
+    }
+}
+
+/// The recording or replay requested with `--record` or `--replay`, and the
+/// count of output bytes for `--summary`.
+#[derive(Default)]
+struct Session {
+    recorder: Option<(Recorder, PathBuf)>,
+    replayer: Option<Replayer>,
+    trace: Option<TraceWriter>,
+    call_trace: Option<CallTrace>,
+    flamegraph: Option<FoldedStacks>,
+    output_bytes: Rc<Cell<u64>>,
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter {
+    inner: Box<dyn Write>,
+    count: Rc<Cell<u64>>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.set(self.count.get() + written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Session {
+    /// Saves the recording, or checks the replay, once the VM has stopped
+    /// with `result`. A replay is expected to stop by running out of input.
+    fn finish(self, result: Result<HaltReason, VmError>) -> Result<HaltReason, Box<dyn Error>> {
+        if let Some(trace) = &self.trace {
+            trace.finish()?;
+        }
+        if let Some(trace) = &self.call_trace {
+            trace.finish()?;
+        }
+        if let Some((recorder, path)) = self.recorder {
+            recorder.recording().save(&path)?;
+        }
+        match self.replayer {
+            Some(replayer) => {
+                replayer.finish()?;
+                match result {
+                    Err(VmError::InputExhausted { .. }) => Ok(HaltReason::Halted(HaltCause::Instruction)),
+                    result => Ok(result?),
                 }
             },
-            Operation::Out(value) => {
-                let value: u8 = self.get_value(value).try_into().unwrap();
-                print!("{}", value as char);
-            },
-            Operation::In(address) => {
-                let mut buffer = [0u8; 1];
-                io::stdin().read_exact(&mut buffer).unwrap();
-                self.set_register(address, buffer[0] as u16);
-            },
-            Operation::Noop => (),
+            None => Ok(result?),
+        }
+    }
+}
+
+fn load_vm(args: &RunArgs) -> Result<(VM, Session), Box<dyn Error>> {
+    let mut session = Session::default();
+    let recording = args.replay.as_deref().map(Recording::load).transpose()?;
+    let replay_input = recording.as_ref().map(Recording::input_bytes);
+    let stdin: Box<dyn Read> = if recording.is_some() {
+        Box::new(io::empty())
+    } else if line_editing(args) {
+        Box::new(LineEditor::with_history_file(&args.history)?)
+    } else if args.input_mode == InputMode::Line && !args.no_line_editing {
+        Box::new(line_input(args))
+    } else {
+        Box::new(io::stdin())
+    };
+    let mut input: Box<dyn Read> = match &args.input {
+        Some(path) => Box::new(io::BufReader::new(fs::File::open(path)?).chain(stdin)),
+        None => stdin,
+    };
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    if let Some(path) = &args.transcript {
+        let transcript = Transcript::append(path)?;
+        if args.transcript_input {
+            input = transcript.tee_input(input);
+        }
+        output = transcript.tee_output(output);
+    }
+    if let Some(path) = &args.record {
+        let recorder = Recorder::new();
+        output = recorder.tee_output(output);
+        session.recorder = Some((recorder, path.clone()));
+    }
+    if let Some(recording) = recording {
+        let replayer = Replayer::new(recording);
+        output = replayer.tee_output(output);
+        session.replayer = Some(replayer);
+    }
+    if args.summary.is_some() {
+        output = Box::new(CountingWriter { inner: output, count: session.output_bytes.clone() });
+    }
+    let mut vm = VM::new(input, output);
+    if let Some((recorder, _)) = &session.recorder {
+        vm.add_hook(recorder.clone());
+    }
+    if let Some(replayer) = &session.replayer {
+        vm.add_hook(replayer.clone());
+    }
+    if log::enabled(Level::Debug) {
+        vm.add_hook(VmLogger::default());
+    }
+    if let Some(bytes) = &replay_input {
+        vm.provide_input(bytes);
+    }
+    match (&args.trace, args.trace_format) {
+        (Some(path), TraceFormat::Text) => {
+            vm.set_trace(Some(open_report(path)?));
+            vm.set_trace_symbols(load_symbols(args.symbols.as_deref())?);
+        },
+        (Some(path), TraceFormat::Binary) if path.as_os_str() == "-" => {
+            return Err("a binary trace needs a file: --trace FILE".into());
+        },
+        (Some(path), TraceFormat::Binary) => {
+            let trace = TraceWriter::new(Box::new(io::BufWriter::new(fs::File::create(path)?)))?;
+            vm.add_hook(trace.clone());
+            session.trace = Some(trace);
+        },
+        (None, _) => (),
+    }
+    if let Some(path) = &args.chrome_trace {
+        let out = Box::new(io::BufWriter::new(fs::File::create(path)?));
+        let trace = CallTrace::new(out, load_symbols(args.symbols.as_deref())?);
+        vm.add_hook(trace.clone());
+        session.call_trace = Some(trace);
+    }
+    if args.flamegraph.is_some() {
+        let stacks = FoldedStacks::new();
+        vm.add_hook(stacks.clone());
+        session.flamegraph = Some(stacks);
+    }
+    vm.set_profiling(args.profile.is_some());
+    vm.set_coverage(args.coverage.is_some() || args.dead_code.is_some());
+    vm.set_self_modification_tracking(args.self_modification.is_some());
+    vm.set_code_scanning(args.codes.is_some() || args.summary.is_some());
+    vm.set_eof_policy(args.on_eof);
+    vm.set_arithmetic_policy(args.on_divide_by_zero);
+    vm.set_opcode_policy(args.on_invalid_opcode);
+    vm.set_strict(args.strict);
+    vm.set_checkpoints(args.checkpoint_every.map(|interval| CheckpointConfig {
+        interval,
+        capacity: args.checkpoint_keep,
+    }));
+    if !args.no_meta_commands && args.record.is_none() && args.replay.is_none() {
+        vm.set_meta_commands(Some(MetaConfig::default()));
+    }
+    if args.recent_instructions > 0 {
+        vm.set_recent_instructions(Some(args.recent_instructions));
+    }
+    match &args.load_state {
+        Some(path) => vm.load_state(path)?,
+        None => vm.load(&vm::read_image(&args.binary, args.format)?)?,
+    }
+    for (path, offset) in &args.load {
+        let image = vm::read_image(path, ImageFormat::Auto).map_err(|err| format!("{}: {err}", path.display()))?;
+        vm.load_at(&image, *offset).map_err(|err| format!("{}@{offset}: {err}", path.display()))?;
+    }
+    if let Some(path) = &args.patch_file {
+        let manifest = Patch::parse_manifest(&fs::read_to_string(path)?)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        for patch in &manifest {
+            vm.apply_patch(patch);
+        }
+    }
+    for patch in &args.patch {
+        vm.apply_patch(patch);
+    }
+    if !args.registers_after_self_test {
+        set_registers(&mut vm, &args.set_register);
+    }
+    if let Some(r7) = args.auto_patch_teleporter {
+        let check = find_teleporter_check(&mut vm)?;
+        let patch = check.call_site_patch();
+        eprintln!("setting r7 = {r7} and applying --patch {patch}");
+        vm.registers_mut()[7] = r7;
+        vm.apply_patch(&patch);
+    }
+    if args.registers_after_self_test {
+        let waiting = |vm: &VM| matches!(vm.next_operation(), Ok(Operation::In(_)));
+        if !waiting(&vm) {
+            vm.run_until(waiting)?;
+        }
+        set_registers(&mut vm, &args.set_register);
+    }
+    if let Some(address) = args.start_addr {
+        vm.reset_call_state();
+        vm.set_instruction_ptr(address);
+    }
+    if let Some(address) = args.stop_addr {
+        vm.set_breakpoint(address);
+    }
+    Ok((vm, session))
+}
+
+fn set_registers(vm: &mut VM, settings: &[(usize, u16)]) {
+    for &(register, value) in settings {
+        vm.registers_mut()[register] = value;
+    }
+}
+
+/// Runs `vm` until the program has decrypted itself and finds the teleporter's
+/// confirmation check.
+fn find_teleporter_check(vm: &mut VM) -> Result<TeleporterCheck, Box<dyn Error>> {
+    // The program decrypts itself before it first asks for input.
+    vm.run_until(|vm| matches!(vm.next_operation(), Ok(Operation::In(_))))?;
+    let check = teleporter::find_check(&vm.memory_image())
+        .ok_or("could not find the teleporter confirmation routine")?;
+    eprintln!(
+        "confirmation routine at {} is called from {} with r0={} r1={} and must return {}",
+        check.routine, check.call_site, check.args.0, check.args.1, check.expected,
+    );
+    Ok(check)
+}
+
+/// Opens a diagnostics file, where `-` means stderr.
+fn open_report(path: &Path) -> io::Result<Box<dyn Write>> {
+    if path.as_os_str() == "-" {
+        Ok(Box::new(io::BufWriter::new(io::stderr())))
+    } else {
+        Ok(Box::new(io::BufWriter::new(fs::File::create(path)?)))
+    }
+}
+
+/// Reads a symbol file, or returns no symbols if no path is given.
+fn load_symbols(path: Option<&Path>) -> Result<Symbols, Box<dyn Error>> {
+    let Some(path) = path else {
+        return Ok(Symbols::default());
+    };
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Ok(Symbols::parse(&text).map_err(|err| format!("{}: {err}", path.display()))?)
+}
+
+/// Opens an output file, or stdout if no path is given.
+fn open_output(path: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    match path {
+        Some(path) => Ok(Box::new(io::BufWriter::new(fs::File::create(path)?))),
+        None => Ok(Box::new(io::BufWriter::new(io::stdout()))),
+    }
+}
+
+fn run(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let (vm, session) = load_vm(&args)?;
+    run_loaded(vm, session, &args)
+}
+
+/// Saves `vm` to a new file in `dir` named for the time and step count.
+fn save_snapshot(vm: &VM, dir: &Path) {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("snapshot-{time}-{}.state", vm.steps()));
+    match vm.save_state(&path) {
+        Ok(()) => eprintln!("saved {}", path.display()),
+        Err(err) => eprintln!("could not save {}: {err}", path.display()),
+    }
+}
+
+/// Runs `vm` until it halts, writing any reports requested in `args`.
+fn run_loaded(mut vm: VM, session: Session, args: &RunArgs) -> Result<(), Box<dyn Error>> {
+    signals::install_interrupt_handler()?;
+    vm.set_interrupt(Some(signals::interrupt_flag()));
+    signals::install_snapshot_handler()?;
+    let snapshot = signals::snapshot_flag();
+    let limit = args.max_steps.map_or(u64::MAX, |steps| vm.steps().saturating_add(steps));
+    let (started, start_steps) = (Instant::now(), vm.steps());
+    let result = loop {
+        // Run in slices so a SIGUSR1 snapshot is taken promptly.
+        let result = vm.run_for((limit - vm.steps()).min(SNAPSHOT_POLL_STEPS));
+        if snapshot.swap(false, Ordering::SeqCst) {
+            save_snapshot(&vm, &args.snapshot_dir);
+        }
+        match result {
+            Ok(HaltReason::StepLimit) if vm.steps() < limit => continue,
+            Ok(HaltReason::Interrupted) => (),
+            result => break result,
+        }
+        // Without a debugger to drop into, show where the program was and let
+        // it carry on; the next Ctrl+C exits.
+        eprintln!();
+        describe_stop(&vm, "interrupted");
+        eprintln!("press Ctrl+C again to exit");
+        vm.set_interrupt(None);
+        signals::exit_on_next_interrupt();
+    };
+    if let Err(err) = &result {
+        log::event(Level::Debug, "vm", "fault", &[("address", &vm.instruction_ptr()), ("steps", &vm.steps()), ("error", err)]);
+    }
+    let fault = match &result {
+        Err(VmError::InputExhausted { .. }) => None,
+        Err(err) => Some(err.to_string()),
+        Ok(HaltReason::InvalidOpcode { address, opcode }) => Some(format!("invalid opcode {opcode} at address {address}")),
+        Ok(_) => None,
+    };
+    if let Some(fault) = &fault {
+        if let Some(recent) = vm.recent_instructions().filter(|recent| !recent.is_empty()) {
+            eprintln!("the last {} instructions before the fault:", recent.len());
+            eprint!("{recent}");
         }
-        match operation {
-            Operation::Halt | Operation::Jmp(_) | Operation::Jt(_, _) | Operation::Jf(_, _) | Operation::Call(_) | Operation::Ret => (),
-            Operation::Noop => self.instruction_ptr += 1,
-            Operation::Push(_) | Operation::Pop(_) | Operation::Out(_) | Operation::In(_) => self.instruction_ptr += 2,
-            Operation::Set(_, _) | Operation::Not(_, _) | Operation::Rmem(_, _) | Operation::Wmem(_, _) => self.instruction_ptr += 3,
-            Operation::Eq(_, _, _) | Operation::Gt(_, _, _) | Operation::Add(_, _, _) | Operation::Mult(_, _, _) | Operation::Mod(_, _, _) | Operation::And(_, _, _) | Operation::Or(_, _, _) => self.instruction_ptr += 4,
+        if let Some(path) = &args.core_dump {
+            CoreDump::capture(&vm, fault).save(path)?;
+            eprintln!("wrote a core dump to {}", path.display());
         }
     }
+    if let (Some(path), Some(profile)) = (&args.profile, vm.profile()) {
+        let mut report = open_report(path)?;
+        profile.report(&vm, 50, &mut report)?;
+        report.flush()?;
+    }
+    if let (Some(path), Some(stacks)) = (&args.flamegraph, &session.flamegraph) {
+        let mut report = open_report(path)?;
+        stacks.write(&load_symbols(args.symbols.as_deref())?, &mut report)?;
+        report.flush()?;
+    }
+    if let (Some(path), Some(coverage)) = (&args.coverage, vm.coverage()) {
+        let mut report = open_report(path)?;
+        coverage.report(&vm, &mut report)?;
+        report.flush()?;
+    }
+    if let (Some(path), Some(coverage)) = (&args.dead_code, vm.coverage()) {
+        let words: Vec<u16> = (0..MEMORY_SIZE as u16).map_while(|address| vm.memory(address)).collect();
+        let functions = Functions::detect_including(&words, &[0], vm.instruction_ptr());
+        let mut report = open_report(path)?;
+        DeadCode::analyze(&words, &functions, coverage).report(&mut report)?;
+        report.flush()?;
+    }
+    if let (Some(path), Some(tracker)) = (&args.self_modification, vm.self_modification()) {
+        let mut report = open_report(path)?;
+        tracker.report(&mut report)?;
+        report.flush()?;
+    }
+    if args.mirror_last_code {
+        if let Some(scanner) = vm.code_scanner_mut() {
+            scanner.mirror_last_code();
+        }
+    }
+    if let (Some(path), Some(scanner)) = (&args.codes, vm.code_scanner()) {
+        let mut report = open_report(path)?;
+        scanner.report(&mut report)?;
+        report.flush()?;
+    }
+    if let Some(SummaryFormat::Json) = args.summary {
+        let elapsed = started.elapsed().as_secs_f64();
+        let steps = vm.steps() - start_steps;
+        let codes: Vec<&str> = vm.code_scanner().map_or(vec![], |scanner| {
+            scanner.codes().iter().map(|found| found.code.as_str()).collect()
+        });
+        let (halt_reason, error) = match &result {
+            Ok(reason) => (halt_reason_name(reason), None),
+            Err(VmError::InputExhausted { .. }) => ("input_exhausted", None),
+            Err(err) => ("fault", Some(err.to_string())),
+        };
+        let summary = serde_json::json!({
+            "steps": steps,
+            "halt_reason": halt_reason,
+            "error": error,
+            "address": vm.instruction_ptr(),
+            "wall_time_secs": elapsed,
+            "instructions_per_sec": if elapsed > 0.0 { (steps as f64 / elapsed).round() } else { 0.0 },
+            "codes": codes,
+            "output_bytes": session.output_bytes.get(),
+        });
+        eprintln!("{summary}");
+    }
+    let (code, message) = match session.finish(result)? {
+        HaltReason::Halted(HaltCause::EmptyStackReturn) => {
+            if args.start_addr.is_some() {
+                describe_stop(&vm, "returned");
+            }
+            (EXIT_EMPTY_STACK_RETURN, None)
+        },
+        HaltReason::Breakpoint(address) if args.stop_addr == Some(address) => {
+            describe_stop(&vm, "stopped");
+            let stack: Vec<String> = vm.stack().iter().map(u16::to_string).collect();
+            eprintln!("stack [{}]", stack.join(", "));
+            return Ok(());
+        },
+        HaltReason::Halted(HaltCause::UserQuit) => (EXIT_USER_QUIT, None),
+        HaltReason::StepLimit => {
+            (EXIT_STEP_LIMIT, Some(format!("step limit reached at address {}", vm.instruction_ptr())))
+        },
+        // Without a debugger to break into, this is a fault.
+        HaltReason::InvalidOpcode { address, opcode } => {
+            (EXIT_FAULT, Some(format!("invalid opcode {opcode} at address {address}")))
+        },
+        _ => return Ok(()),
+    };
+    Err(Box::new(Stopped { code, message }))
+}
+
+/// Writes where `vm` is and its registers to stderr.
+fn describe_stop(vm: &VM, what: &str) {
+    eprintln!("{what} at {} after {} steps", vm.instruction_ptr(), vm.steps());
+    let registers: Vec<String> = vm.registers().iter().enumerate().map(|(idx, value)| format!("r{idx}={value}")).collect();
+    eprintln!("{} stack depth {}", registers.join(" "), vm.stack().len());
+}
+
+/// The exits listed after the game's "There are N exits:" line.
+fn listed_exits(output: &str) -> Vec<String> {
+    output.lines()
+        .skip_while(|line| !line.contains("exit"))
+        .skip(1)
+        .map_while(|line| line.strip_prefix("- "))
+        .map(|exit| format!("go {exit}"))
+        .collect()
+}
 
-    fn register_idx(value: u16) -> Option<usize> {
-        if value < 32_768 || value > 32_775 {
-            return None;
+fn search(args: SearchArgs) -> Result<(), Box<dyn Error>> {
+    if args.commands.is_empty() && !args.exits {
+        return Err("give at least one --command or --exits".into());
+    }
+    let start = match &args.state {
+        Some(path) => Snapshot::load(path)?,
+        None => {
+            let mut vm = VM::new(io::empty(), io::sink());
+            vm.load_file(&args.binary)?;
+            vm.snapshot()
+        },
+    };
+    let config = SearchConfig {
+        max_depth: args.depth,
+        max_steps: args.max_steps,
+        threads: args.threads,
+        volatile: args.volatile.clone(),
+        ..SearchConfig::default()
+    };
+    let candidates = |output: &str| {
+        let mut commands = args.commands.clone();
+        if args.exits {
+            commands.extend(listed_exits(output));
         }
-        Some((value % 32_768).try_into().unwrap())
+        commands
+    };
+    let outcome = search::search(&start, &config, candidates, |output| args.until.is_match(output.as_bytes()))?;
+    eprintln!("{} states explored, {} duplicates skipped", outcome.states, outcome.duplicates);
+    let found = outcome.found
+        .ok_or_else(|| format!("no sequence of up to {} commands prints /{}/", args.depth, args.until))?;
+    for command in &found.commands {
+        println!("{command}");
+    }
+    Ok(())
+}
+
+/// How `--summary` names a reason for stopping.
+fn halt_reason_name(reason: &HaltReason) -> &'static str {
+    match reason {
+        HaltReason::Halted(HaltCause::Instruction) => "halted",
+        HaltReason::Halted(HaltCause::EmptyStackReturn) => "empty_stack_return",
+        HaltReason::Halted(HaltCause::UserQuit) => "user_quit",
+        HaltReason::Halted(HaltCause::EndOfInput) => "end_of_input",
+        HaltReason::Breakpoint(_) => "breakpoint",
+        HaltReason::Watchpoint(_) => "watchpoint",
+        HaltReason::Condition => "condition",
+        HaltReason::StepLimit => "step_limit",
+        HaltReason::OutOfFuel => "out_of_fuel",
+        HaltReason::Interrupted => "interrupted",
+        HaltReason::InvalidOpcode { .. } => "invalid_opcode",
+    }
+}
+
+fn solve_teleporter(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let (mut vm, session) = load_vm(&args)?;
+    let check = find_teleporter_check(&mut vm)?;
+    let r7 = check.solve().ok_or("no value of r7 passes the confirmation check")?;
+    let patch = check.patch();
+    eprintln!("setting r7 = {r7} and applying --patch {patch}");
+    vm.registers_mut()[7] = r7;
+    vm.apply_patch(&patch);
+    run_loaded(vm, session, &args)
+}
+
+fn solve_coins(args: CoinsArgs) -> Result<(), Box<dyn Error>> {
+    let coins: &[Coin; 5] = args.coins.as_slice().try_into().expect("clap should require five coins");
+    let order = coins::solve(coins, args.total)
+        .ok_or_else(|| format!("no order of the coins makes the equation equal {}", args.total))?;
+    let values = order.map(|coin| coin.value);
+    eprintln!(
+        "{} + {} * {}^2 + {}^3 - {} = {}",
+        values[0], values[1], values[2], values[3], values[4], coins::evaluate(values),
+    );
+    for command in coins::commands(&order) {
+        println!("{command}");
     }
+    Ok(())
+}
 
-    fn set_register(&mut self, address: u16, value: u16) {
-        let register_idx = Self::register_idx(address).unwrap();
-        self.registers[register_idx] = value;
+fn solve_vault(args: VaultArgs) -> Result<(), Box<dyn Error>> {
+    let grid: Vault = args.grid.parse()?;
+    let route = grid.shortest_route(args.target)
+        .ok_or_else(|| format!("no route reaches the vault with weight {}", args.target))?;
+    eprintln!("{route} = {}", args.target);
+    for command in route.commands() {
+        println!("{command}");
+    }
+    Ok(())
+}
+
+fn solve_all(args: AllArgs) -> Result<(), Box<dyn Error>> {
+    let playthrough = playthrough::play(&vm::read_image(&args.binary, ImageFormat::Auto)?)?;
+    if let Some(path) = &args.transcript {
+        fs::write(path, &playthrough.transcript)?;
+    }
+    eprintln!("{} commands sent", playthrough.commands.len());
+    for (idx, found) in playthrough.codes.iter().enumerate() {
+        println!("code {}: {found}", idx + 1);
     }
+    Ok(())
+}
+
+fn expect(args: ExpectArgs) -> Result<(), Box<dyn Error>> {
+    let script = Script::parse(&fs::read_to_string(&args.script)?)
+        .map_err(|err| format!("{}: {err}", args.script.display()))?;
+    let mut expect = Expect::new();
+    match &args.load_state {
+        Some(path) => expect.vm_mut().load_state(path)?,
+        None => expect.vm_mut().load_file(&args.binary)?,
+    }
+    let mut echo: Box<dyn Write> = match args.quiet {
+        true => Box::new(io::sink()),
+        false => Box::new(io::stdout()),
+    };
+    expect.run(&script, &mut echo)?;
+    Ok(())
+}
 
-    fn get_value(&self, value: u16) -> u16 {
-        if let Some(val_register) = Self::register_idx(value) {
-            self.registers[val_register]
-        } else {
-            value
+fn gdb(args: GdbArgs) -> Result<(), Box<dyn Error>> {
+    let (mut vm, session) = load_vm(&args.run)?;
+    let listener = TcpListener::bind(&args.listen)?;
+    eprintln!("waiting for gdb on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    eprintln!("gdb attached from {peer}");
+    GdbStub::new(&mut vm, stream)?.serve()?;
+    session.finish(Ok(HaltReason::Halted(HaltCause::Instruction)))?;
+    Ok(())
+}
+
+fn dap(args: DapArgs) -> Result<(), Box<dyn Error>> {
+    let mut server = match &args.listen {
+        Some(address) => {
+            let listener = TcpListener::bind(address)?;
+            eprintln!("waiting for the editor on {}", listener.local_addr()?);
+            let (stream, _) = listener.accept()?;
+            DapServer::new(stream.try_clone()?, Box::new(stream))
+        },
+        None => DapServer::new(io::stdin(), Box::new(io::stdout())),
+    };
+    server.serve()?;
+    Ok(())
+}
+
+fn serve(args: ServeArgs) -> Result<(), Box<dyn Error>> {
+    let image: Arc<[u8]> = vm::read_image(&args.binary, ImageFormat::Auto)?.into();
+    vm::decode_image(&image)?;
+    let listener = TcpListener::bind(&args.listen)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    let config = ServeConfig {
+        max_connections: args.max_connections,
+        idle_timeout: args.idle_timeout.map(Duration::from_secs),
+    };
+    serve::serve(listener, image, config)?;
+    Ok(())
+}
+
+#[cfg(feature = "web")]
+fn web(args: WebArgs) -> Result<(), Box<dyn Error>> {
+    let image: Arc<[u8]> = vm::read_image(&args.binary, ImageFormat::Auto)?.into();
+    vm::decode_image(&image)?;
+    let listener = TcpListener::bind(&args.listen)?;
+    eprintln!("serving http://{}/", listener.local_addr()?);
+    oscon_2012_vm_challenge::web::serve(listener, image)?;
+    Ok(())
+}
+
+fn debug(args: DebugArgs) -> Result<(), Box<dyn Error>> {
+    let (mut vm, session) = load_vm(&args.run)?;
+    if let Some(path) = &args.core {
+        let dump = CoreDump::load(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        println!("core dump: {}", dump.fault);
+        if let Some(recent) = &dump.recent {
+            println!("last {} instructions:", recent.len());
+            print!("{recent}");
+        }
+        vm.restore(dump.snapshot);
+    }
+    signals::install_interrupt_handler()?;
+    vm.set_interrupt(Some(signals::interrupt_flag()));
+    let mut debugger = Debugger::new(vm, Box::new(io::stdout()));
+    debugger.set_symbols(load_symbols(args.run.symbols.as_deref())?, args.run.symbols.clone());
+    let init = match &args.init {
+        Some(path) => Some(path.clone()),
+        None => Some(PathBuf::from(DEFAULT_INIT_FILE)).filter(|path| !args.no_init && path.is_file()),
+    };
+    if let Some(path) = init {
+        let resume = debugger.source(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+        if !resume {
+            session.finish(Ok(HaltReason::Halted(HaltCause::Instruction)))?;
+            return Ok(());
         }
     }
+    if line_editing(&args.run) {
+        debugger.repl_with_editor(&mut LineEditor::with_history_file(&args.command_history)?)?;
+    } else {
+        debugger.repl(|line| io::stdin().read_line(line))?;
+    }
+    session.finish(Ok(HaltReason::Halted(HaltCause::Instruction)))?;
+    Ok(())
+}
+
+fn tui(args: TuiArgs) -> Result<(), Box<dyn Error>> {
+    let output = Tui::output();
+    let mut vm = VM::new(io::empty(), output.clone());
+    match &args.load_state {
+        Some(path) => vm.load_state(path)?,
+        None => vm.load_file(&args.binary)?,
+    }
+    if let Some(path) = &args.input {
+        vm.provide_input(&fs::read(path)?);
+    }
+    let mut tui = Tui::new(vm, output);
+    tui.run(|line| io::stdin().read_line(line), &mut io::stdout(), tui::terminal_size)?;
+    Ok(())
 }
 
-fn main() {
-    let mut vm = VM::default();
-    vm.run_binary("input/challenge.bin");
+fn disasm(args: DisasmArgs) -> Result<(), Box<dyn Error>> {
+    let symbols = load_symbols(args.symbols.as_deref())?;
+    let (words, start) = match &args.state {
+        Some(path) => {
+            let snapshot = Snapshot::load(path)?;
+            (snapshot.memory_image(), snapshot.instruction_ptr())
+        }
+        None => (vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?, 0),
+    };
+    let mut notes: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    if args.loops {
+        for found in loops::detect(&Functions::detect_including(&words, &[0], start)) {
+            notes.entry(found.header).or_default().push(found.to_string());
+            for &address in &found.back_edges {
+                notes.entry(address).or_default().push(format!("back edge to {}", found.header));
+            }
+        }
+    }
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    disasm::write_listing(&disasm::disassemble(&words), &symbols, &notes, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn assemble(args: AsmArgs) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(&args.source)?;
+    let words = asm::assemble(&source)
+        .map_err(|err| format!("{}: {err}", args.source.display()))?;
+    fs::write(&args.output, vm::encode_image(&words))?;
+    Ok(())
+}
+
+fn cfg(args: CfgArgs) -> Result<(), Box<dyn Error>> {
+    let symbols = load_symbols(args.symbols.as_deref())?;
+    let words = vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?;
+    let mut cfg = Cfg::build(&words, &[0]);
+    if let Some(function) = &args.function {
+        let entry = debugger::parse_address(function, &symbols)?;
+        if !cfg.blocks.contains_key(&entry) {
+            cfg = Cfg::build(&words, &[entry]);
+        }
+        cfg = cfg.function(entry);
+    }
+    let mut out = open_output(args.output.as_deref())?;
+    cfg.write_dot(&symbols, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn callgraph(args: CallgraphArgs) -> Result<(), Box<dyn Error>> {
+    let image = vm::read_image(&args.binary, ImageFormat::Auto)?;
+    let words = vm::decode_image(&image)?;
+    let mut graph = CallGraph::from_cfg(&Cfg::build(&words, &[0]));
+    if args.dynamic {
+        let input: Box<dyn Read> = match &args.input {
+            Some(path) => Box::new(io::BufReader::new(fs::File::open(path)?)),
+            None => Box::new(io::empty()),
+        };
+        let mut vm = VM::new(input, io::sink());
+        vm.load(&image)?;
+        vm.set_call_logging(true);
+        match vm.run_for(args.max_steps.unwrap_or(u64::MAX)) {
+            Ok(_) | Err(VmError::InputExhausted { .. }) => (),
+            Err(err) => return Err(err.into()),
+        }
+        graph.add_observed(vm.call_log().expect("Call logging should be enabled."));
+    }
+    let mut out = open_output(args.output.as_deref())?;
+    match args.format {
+        GraphFormat::Dot => graph.write_dot(&load_symbols(args.symbols.as_deref())?, &mut out)?,
+        GraphFormat::Json => graph.write_json(&mut out)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn diff_state(args: DiffStateArgs) -> Result<(), VmError> {
+    let diff = Snapshot::load(&args.old)?.diff(&Snapshot::load(&args.new)?);
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    write!(stdout, "{diff}")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn dump(args: DumpArgs) -> Result<(), VmError> {
+    let words: Vec<Option<u16>> = match &args.state {
+        Some(path) => {
+            let snapshot = Snapshot::load(path)?;
+            (0..MEMORY_SIZE as u16).map(|address| snapshot.memory(address)).collect()
+        }
+        None => vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?.into_iter().map(Some).collect(),
+    };
+    let start = (args.start as usize).min(words.len());
+    let end = args.len.map_or(words.len(), |len| (start + len).min(words.len()));
+    let words = &words[start..end];
+    match &args.raw {
+        Some(path) => {
+            let words: Vec<u16> = words.iter().map(|word| word.unwrap_or(0)).collect();
+            fs::write(path, vm::encode_image(&words))?;
+        }
+        None => {
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            hexdump(args.start, words, &mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn recompile(args: RecompileArgs) -> Result<(), Box<dyn Error>> {
+    let (snapshot, source) = match &args.state {
+        Some(path) => (Snapshot::load(path)?, path.display().to_string()),
+        None => {
+            let mut vm = VM::new(io::empty(), io::sink());
+            vm.load_file(&args.binary)?;
+            (vm.snapshot(), args.binary.display().to_string())
+        },
+    };
+    let mut out = open_output(args.output.as_deref())?;
+    recompile::recompile(&snapshot, &source, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn decompile(args: DecompileArgs) -> Result<(), Box<dyn Error>> {
+    let (words, start) = match &args.state {
+        Some(path) => {
+            let snapshot = Snapshot::load(path)?;
+            (snapshot.memory_image(), snapshot.instruction_ptr())
+        }
+        None => (vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?, 0),
+    };
+    let entries: Vec<u16> = [0].into_iter().chain(args.function).collect();
+    let functions = Functions::detect_including(&words, &entries, start);
+    if let Some(entry) = args.function {
+        if functions.cfg.blocks[&entry].instructions.is_empty() {
+            return Err(format!("no instruction decodes at address {entry}").into());
+        }
+    }
+    let entries: Vec<u16> = match args.function {
+        Some(entry) => vec![entry],
+        None => functions.iter().map(|function| function.entry).collect(),
+    };
+    let mut out = open_output(args.output.as_deref())?;
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        decompile::decompile(&functions.cfg, entry, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn functions(args: FunctionsArgs) -> Result<(), Box<dyn Error>> {
+    let symbols = load_symbols(args.symbols.as_deref())?;
+    let (words, start) = match &args.state {
+        Some(path) => {
+            let snapshot = Snapshot::load(path)?;
+            (snapshot.memory_image(), snapshot.instruction_ptr())
+        }
+        None => (vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?, 0),
+    };
+    let functions = Functions::detect_including(&words, &[0], start);
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for function in functions.iter() {
+        match symbols.name(function.entry) {
+            Some(name) => writeln!(stdout, "{function}  {name}")?,
+            None => writeln!(stdout, "{function}")?,
+        }
+    }
+    stdout.flush()?;
+    if let Some(path) = &args.write_symbols {
+        fs::write(path, functions.name_in(&symbols).to_string())?;
+    }
+    Ok(())
+}
+
+fn strings(args: StringsArgs) -> Result<(), VmError> {
+    let words = match &args.state {
+        Some(path) => Snapshot::load(path)?.memory_image(),
+        None => vm::decode_image(&vm::read_image(&args.binary, ImageFormat::Auto)?)?,
+    };
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for string in strings::find_strings(&words, args.min_len) {
+        writeln!(stdout, "{string}")?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// The records of the trace in `args` between its steps.
+fn read_trace(args: &TraceDumpArgs) -> Result<impl Iterator<Item = io::Result<Executed>>, Box<dyn Error>> {
+    let file = fs::File::open(&args.trace).map_err(|err| format!("{}: {err}", args.trace.display()))?;
+    let (from, to) = (args.from, args.to);
+    Ok(TraceReader::new(io::BufReader::new(file))?
+        .filter(move |record| record.as_ref().map_or(true, |executed| executed.step >= from))
+        .take_while(move |record| record.as_ref().map_or(true, |executed| executed.step <= to)))
+}
+
+fn trace_dump(args: TraceDumpArgs) -> Result<(), Box<dyn Error>> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for executed in read_trace(&args)? {
+        writeln!(stdout, "{}", executed?)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn trace_query(args: TraceQueryArgs) -> Result<(), Box<dyn Error>> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    let mut count = 0u64;
+    for executed in read_trace(&args.range)? {
+        let executed = executed?;
+        if args.address.as_ref().is_some_and(|range| !range.contains(&executed.address))
+            || args.opcode.is_some_and(|opcode| executed.operation.opcode() != opcode)
+        {
+            continue;
+        }
+        count += 1;
+        if !args.count {
+            writeln!(stdout, "{executed}")?;
+        }
+    }
+    if args.count {
+        writeln!(stdout, "{count}")?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn validate(args: ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let validation = Validation::check(&vm::read_image(&args.binary, args.format)?);
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    validation.report(&mut stdout)?;
+    stdout.flush()?;
+    match validation.is_ok() {
+        true => Ok(()),
+        false => Err(Box::new(Stopped { code: 1, message: None })),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    log::set_max_level(cli.log_level);
+    let result: Result<(), Box<dyn Error>> = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Debug(args) => debug(args),
+        Command::Tui(args) => tui(args),
+        Command::Expect(args) => expect(args),
+        Command::Gdb(args) => gdb(args),
+        Command::Dap(args) => dap(args),
+        Command::Disasm(args) => disasm(args),
+        Command::Asm(args) => assemble(args),
+        Command::Cfg(args) => cfg(args),
+        Command::Callgraph(args) => callgraph(args),
+        Command::DiffState(args) => diff_state(args).map_err(Into::into),
+        Command::Dump(args) => dump(args).map_err(Into::into),
+        Command::Decompile(args) => decompile(args),
+        Command::Functions(args) => functions(args),
+        Command::Strings(args) => strings(args).map_err(Into::into),
+        Command::Validate(args) => validate(args),
+        Command::Trace { action: TraceAction::Dump(args) } => trace_dump(args),
+        Command::Trace { action: TraceAction::Query(args) } => trace_query(args),
+        Command::Recompile(args) => recompile(args),
+        Command::Serve(args) => serve(args),
+        Command::Search(args) => search(args),
+        #[cfg(feature = "web")]
+        Command::Web(args) => web(args),
+        Command::Solve { puzzle: Puzzle::Teleporter(args) } => solve_teleporter(*args),
+        Command::Solve { puzzle: Puzzle::Coins(args) } => solve_coins(args),
+        Command::Solve { puzzle: Puzzle::Vault(args) } => solve_vault(args),
+        Command::Solve { puzzle: Puzzle::All(args) } => solve_all(args),
+    };
+    if let Err(err) = result {
+        if !matches!(err.downcast_ref::<Stopped>(), Some(Stopped { message: None, .. })) {
+            eprintln!("error: {err}");
+        }
+        return ExitCode::from(exit_code(err.as_ref()));
+    }
+    ExitCode::SUCCESS
 }
\ No newline at end of file
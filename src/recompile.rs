@@ -0,0 +1,354 @@
+//! Ahead-of-time translation of a program into a standalone Rust source file.
+//!
+//! Each function becomes a Rust function whose basic blocks are arms of a
+//! `match` on the program counter. Functions are found by [`Cfg::build`] from
+//! the start address and from every literal `call` target in a linear sweep
+//! of memory, since programs like the challenge reach most of their code
+//! through calls from code that is itself only reached indirectly. The
+//! generated program
+//! keeps the machine's own stack of return addresses, so whenever translated
+//! code meets something it cannot follow natively, such as an indirect jump
+//! out of the function or a return to an unexpected address, it hands the
+//! current address to an interpreter included in the output and carries on
+//! there. The interpreter calls back into translated code at every `call` to
+//! a translated function.
+//!
+//! A `wmem` to any word of a translated function turns that function off for
+//! the rest of the run, so self-modifying programs stay correct, if slower.
+//! Translate a state saved after the program has finished modifying its code
+//! (for the challenge, after its self-test) to keep the speedup.
+//!
+//! Unlike the VM, the generated program reads never-written memory as zero.
+
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::analysis::cfg::{BasicBlock, Cfg};
+use crate::disasm::disassemble;
+use crate::vm::{Operation, Snapshot};
+
+/// The interpreter and machine state shared by every generated program.
+const RUNTIME: &str = r#"
+struct Machine {
+    mem: Vec<u16>,
+    reg: [u16; 8],
+    stack: Vec<u16>,
+    // Whether each translated function may still run; cleared for good once
+    // the program writes to one of its words.
+    enabled: Vec<bool>,
+    input: io::Bytes<io::StdinLock<'static>>,
+    output: io::BufWriter<io::Stdout>,
+}
+
+enum Exit {
+    /// `ret` popped this address.
+    Ret(u16),
+    /// Continue at this address in the interpreter.
+    Goto(u16),
+    Halt,
+}
+
+impl Machine {
+    fn fault(&mut self, address: u16, message: &str) -> ! {
+        let _ = self.output.flush();
+        eprintln!("error: {message} at address {address}");
+        process::exit(1)
+    }
+
+    fn value(&self, word: u16) -> u16 {
+        match word {
+            32_768..=32_775 => self.reg[(word - 32_768) as usize],
+            _ => word,
+        }
+    }
+
+    fn set(&mut self, address: u16, operand: u16, value: u16) {
+        match operand {
+            32_768..=32_775 => self.reg[(operand - 32_768) as usize] = value,
+            _ => self.fault(address, &format!("operand {operand} is not a register")),
+        }
+    }
+
+    fn pop(&mut self, address: u16) -> u16 {
+        match self.stack.pop() {
+            Some(value) => value,
+            None => self.fault(address, "pop from empty stack"),
+        }
+    }
+
+    fn modulo(&mut self, address: u16, a: u16, b: u16) -> u16 {
+        if b == 0 {
+            self.fault(address, "division by zero");
+        }
+        a % b
+    }
+
+    fn rmem(&mut self, address: u16, target: u16) -> u16 {
+        match self.mem.get(target as usize) {
+            Some(&value) => value,
+            None => self.fault(address, &format!("read of invalid address {target}")),
+        }
+    }
+
+    fn wmem(&mut self, address: u16, target: u16, value: u16) {
+        match self.mem.get_mut(target as usize) {
+            Some(word) => *word = value,
+            None => self.fault(address, &format!("write to invalid address {target}")),
+        }
+        for &function in owners(target) {
+            self.enabled[function] = false;
+        }
+    }
+
+    fn out(&mut self, address: u16, value: u16) {
+        match u8::try_from(value) {
+            Ok(byte) => {
+                let _ = self.output.write_all(&[byte]);
+            },
+            Err(_) => self.fault(address, &format!("cannot output value {value} as a character")),
+        }
+    }
+
+    fn input(&mut self, address: u16) -> u16 {
+        let _ = self.output.flush();
+        match self.input.next() {
+            Some(Ok(byte)) => byte as u16,
+            Some(Err(err)) => self.fault(address, &format!("cannot read input: {err}")),
+            None => {
+                eprintln!("input exhausted at address {address}");
+                process::exit(0)
+            },
+        }
+    }
+
+    /// Runs the function at `target`, whose return address has been pushed.
+    fn call(&mut self, target: u16) -> Exit {
+        match native(target) {
+            Some((index, function)) if self.enabled[index] => function(self),
+            _ => Exit::Goto(target),
+        }
+    }
+
+    /// Interprets from `ip` until the program halts.
+    fn run(&mut self, mut ip: u16) {
+        loop {
+            let word = |offset: u16| self.mem.get(ip as usize + offset as usize).copied().unwrap_or(0);
+            let (op, a, b, c) = (word(0), word(1), word(2), word(3));
+            let address = ip;
+            ip = match op {
+                0 => return,
+                1 => { let v = self.value(b); self.set(address, a, v); ip + 3 },
+                2 => { let v = self.value(a); self.stack.push(v); ip + 2 },
+                3 => { let v = self.pop(address); self.set(address, a, v); ip + 2 },
+                4 => { let v = (self.value(b) == self.value(c)) as u16; self.set(address, a, v); ip + 4 },
+                5 => { let v = (self.value(b) > self.value(c)) as u16; self.set(address, a, v); ip + 4 },
+                6 => self.value(a),
+                7 => if self.value(a) != 0 { self.value(b) } else { ip + 3 },
+                8 => if self.value(a) == 0 { self.value(b) } else { ip + 3 },
+                9 => { let v = ((self.value(b) as u32 + self.value(c) as u32) % 32_768) as u16; self.set(address, a, v); ip + 4 },
+                10 => { let v = ((self.value(b) as u32 * self.value(c) as u32) % 32_768) as u16; self.set(address, a, v); ip + 4 },
+                11 => { let (x, y) = (self.value(b), self.value(c)); let v = self.modulo(address, x, y); self.set(address, a, v); ip + 4 },
+                12 => { let v = self.value(b) & self.value(c); self.set(address, a, v); ip + 4 },
+                13 => { let v = self.value(b) | self.value(c); self.set(address, a, v); ip + 4 },
+                14 => { let v = !self.value(b) & 0x7fff; self.set(address, a, v); ip + 3 },
+                15 => { let t = self.value(b); let v = self.rmem(address, t); self.set(address, a, v); ip + 3 },
+                16 => { let (t, v) = (self.value(a), self.value(b)); self.wmem(address, t, v); ip + 3 },
+                17 => {
+                    let target = self.value(a);
+                    self.stack.push(ip + 2);
+                    match self.call(target) {
+                        Exit::Ret(next) | Exit::Goto(next) => next,
+                        Exit::Halt => return,
+                    }
+                },
+                18 => match self.stack.pop() {
+                    Some(next) => next,
+                    None => return,
+                },
+                19 => { let v = self.value(a); self.out(address, v); ip + 2 },
+                20 => { let v = self.input(address); self.set(address, a, v); ip + 2 },
+                21 => ip + 1,
+                _ => self.fault(address, &format!("invalid opcode {op}")),
+            };
+        }
+    }
+}
+
+fn main() {
+    let mut machine = Machine {
+        mem: IMAGE.to_vec(),
+        reg: REGISTERS,
+        stack: STACK.to_vec(),
+        enabled: vec![true; FUNCTIONS],
+        input: io::stdin().lock().bytes(),
+        output: io::BufWriter::new(io::stdout()),
+    };
+    machine.run(START);
+    let _ = machine.output.flush();
+}
+"#;
+
+/// An operand as a Rust expression on the machine `m`.
+fn value(word: u16) -> String {
+    match word {
+        32_768..=32_775 => format!("m.reg[{}]", word - 32_768),
+        _ => word.to_string(),
+    }
+}
+
+/// A statement storing `expression` in the register `operand`.
+fn store(address: u16, operand: u16, expression: &str) -> String {
+    match operand {
+        32_768..=32_775 => format!("m.reg[{}] = {expression};", operand - 32_768),
+        _ => format!("m.fault({address}, \"operand {operand} is not a register\");"),
+    }
+}
+
+/// The Rust statements for the instruction at `address` in the function with
+/// index `index`.
+fn statement(address: u16, operation: Operation, index: usize, functions: &BTreeSet<u16>) -> String {
+    let next = address.wrapping_add(operation.size());
+    match operation {
+        Operation::Halt => "return Exit::Halt;".to_string(),
+        Operation::Set(a, b) => store(address, a, &value(b)),
+        Operation::Push(a) => format!("m.stack.push({});", value(a)),
+        Operation::Pop(a) => format!("let v = m.pop({address}); {}", store(address, a, "v")),
+        Operation::Eq(a, b, c) => store(address, a, &format!("({} == {}) as u16", value(b), value(c))),
+        Operation::Gt(a, b, c) => store(address, a, &format!("({} > {}) as u16", value(b), value(c))),
+        Operation::Jmp(a) => format!("pc = {}; continue;", value(a)),
+        Operation::Jt(a, b) => format!("if {} != 0 {{ pc = {}; continue; }}", value(a), value(b)),
+        Operation::Jf(a, b) => format!("if {} == 0 {{ pc = {}; continue; }}", value(a), value(b)),
+        Operation::Add(a, b, c) => {
+            store(address, a, &format!("((({} as u32) + ({} as u32)) % 32_768) as u16", value(b), value(c)))
+        },
+        Operation::Mult(a, b, c) => {
+            store(address, a, &format!("((({} as u32) * ({} as u32)) % 32_768) as u16", value(b), value(c)))
+        },
+        Operation::Mod(a, b, c) => {
+            format!("let v = m.modulo({address}, {}, {}); {}", value(b), value(c), store(address, a, "v"))
+        },
+        Operation::And(a, b, c) => store(address, a, &format!("{} & {}", value(b), value(c))),
+        Operation::Or(a, b, c) => store(address, a, &format!("{} | {}", value(b), value(c))),
+        Operation::Not(a, b) => store(address, a, &format!("!{} & 0x7fff", value(b))),
+        Operation::Rmem(a, b) => format!("let v = m.rmem({address}, {}); {}", value(b), store(address, a, "v")),
+        Operation::Wmem(a, b) => {
+            format!("m.wmem({address}, {}, {}); if !m.enabled[{index}] {{ return Exit::Goto({next}); }}", value(a), value(b))
+        },
+        Operation::Call(a) => {
+            let call = match functions.contains(&a) {
+                true => format!("f{a}(m)"),
+                false => format!("m.call({})", value(a)),
+            };
+            // The callee may have written to this function.
+            format!(
+                "m.stack.push({next}); match {call} {{ Exit::Ret({next}) if m.enabled[{index}] => (), \
+                 Exit::Ret(r) | Exit::Goto(r) => return Exit::Goto(r), Exit::Halt => return Exit::Halt }}"
+            )
+        },
+        Operation::Ret => "return match m.stack.pop() { Some(r) => Exit::Ret(r), None => Exit::Halt };".to_string(),
+        Operation::Out(a) => format!("m.out({address}, {});", value(a)),
+        Operation::In(a) => format!("let v = m.input({address}); {}", store(address, a, "v")),
+        Operation::Noop => String::new(),
+    }
+}
+
+fn write_block(out: &mut dyn Write, block: &BasicBlock, index: usize, functions: &BTreeSet<u16>) -> io::Result<()> {
+    writeln!(out, "            {} => {{", block.start)?;
+    for &(address, operation) in &block.instructions {
+        let statement = statement(address, operation, index, functions);
+        if !statement.is_empty() {
+            writeln!(out, "                {statement} // {address}: {operation}")?;
+        }
+    }
+    writeln!(out, "                pc = {};", block.end)?;
+    writeln!(out, "            }},")
+}
+
+/// Writes a Rust program that runs `snapshot` from its instruction pointer,
+/// reading input from stdin and writing output to stdout.
+pub fn recompile(snapshot: &Snapshot, source: &str, out: &mut dyn Write) -> io::Result<()> {
+    let words = snapshot.memory_image();
+    let start = snapshot.instruction_ptr();
+    let mut entries = vec![0, start];
+    entries.extend(disassemble(&words).into_iter().filter_map(|line| match line.operation {
+        Some(Operation::Call(target)) if (target as usize) < words.len() => Some(target),
+        _ => None,
+    }));
+    let cfg = Cfg::build(&words, &entries);
+    let functions = &cfg.entries;
+
+    // The functions covering each word, as runs of words with the same owners.
+    let mut owners: Vec<Vec<usize>> = vec![Vec::new(); words.len()];
+    for (index, &entry) in functions.iter().enumerate() {
+        for block in cfg.function(entry).blocks.values() {
+            for address in block.start..block.end {
+                owners[address as usize].push(index);
+            }
+        }
+    }
+
+    writeln!(out, "// Generated by `oscon_2012_vm_challenge recompile` from {source}.")?;
+    writeln!(out, "#![allow(unreachable_code, unused_assignments, unused_mut, unused_variables, clippy::all)]")?;
+    writeln!(out)?;
+    writeln!(out, "use std::io::{{self, Read, Write}};")?;
+    writeln!(out, "use std::process;")?;
+    writeln!(out)?;
+    writeln!(out, "const START: u16 = {start};")?;
+    writeln!(out, "const REGISTERS: [u16; 8] = {:?};", snapshot.registers())?;
+    writeln!(out, "const STACK: &[u16] = &{:?};", snapshot.stack())?;
+    writeln!(out, "const FUNCTIONS: usize = {};", functions.len())?;
+    writeln!(out, "static IMAGE: [u16; {}] = [", words.len())?;
+    for chunk in words.chunks(16) {
+        let line: Vec<String> = chunk.iter().map(u16::to_string).collect();
+        writeln!(out, "    {},", line.join(", "))?;
+    }
+    writeln!(out, "];")?;
+    out.write_all(RUNTIME.as_bytes())?;
+
+    writeln!(out)?;
+    writeln!(out, "/// The indices of the translated functions that include `address`.")?;
+    writeln!(out, "fn owners(address: u16) -> &'static [usize] {{")?;
+    writeln!(out, "    match address {{")?;
+    let mut run_start = 0;
+    for address in 1..=owners.len() {
+        if address < owners.len() && owners[address] == owners[run_start] {
+            continue;
+        }
+        if !owners[run_start].is_empty() {
+            writeln!(out, "        {run_start}..={} => &{:?},", address - 1, owners[run_start])?;
+        }
+        run_start = address;
+    }
+    writeln!(out, "        _ => &[],")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    writeln!(out)?;
+    writeln!(out, "fn native(target: u16) -> Option<(usize, fn(&mut Machine) -> Exit)> {{")?;
+    writeln!(out, "    Some(match target {{")?;
+    for (index, entry) in functions.iter().enumerate() {
+        writeln!(out, "        {entry} => ({index}, f{entry}),")?;
+    }
+    writeln!(out, "        _ => return None,")?;
+    writeln!(out, "    }})")?;
+    writeln!(out, "}}")?;
+
+    for (index, &entry) in functions.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "fn f{entry}(m: &mut Machine) -> Exit {{")?;
+        writeln!(out, "    let mut pc: u16 = {entry};")?;
+        writeln!(out, "    loop {{")?;
+        writeln!(out, "        if !m.enabled[{index}] {{")?;
+        writeln!(out, "            return Exit::Goto(pc);")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "        match pc {{")?;
+        for block in cfg.function(entry).blocks.values() {
+            write_block(out, block, index, functions)?;
+        }
+        writeln!(out, "            _ => return Exit::Goto(pc),")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+    Ok(())
+}
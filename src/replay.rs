@@ -0,0 +1,275 @@
+//! Recording a session's input and output, and replaying it to check that the
+//! program still behaves the same.
+//!
+//! A [`Recorder`] notes every byte `in` consumes, with the step count at which
+//! it was read, and everything the program prints. A [`Replayer`] feeds the
+//! same bytes back and checks each `in` and each byte of output against the
+//! recording as the program runs. Output is kept byte for byte: as a string
+//! in the JSON when it is valid UTF-8, and otherwise as an array of bytes.
+//!
+//! A meta-command changes the VM's state and prints to its output outside the
+//! program, so a session with one in it cannot be replayed. The `run` command
+//! turns meta-commands off while recording or replaying.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::{error, fmt, fs};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::vm::{Hook, Operation, VM};
+
+/// A byte consumed by `in`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputEvent {
+    /// The number of instructions executed, counting the `in`.
+    pub step: u64,
+    pub byte: u8,
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at step {}", self.byte as char, self.step)
+    }
+}
+
+/// The input and output of one session, saved as JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub inputs: Vec<InputEvent>,
+    #[serde(with = "text_or_bytes")]
+    pub output: Vec<u8>,
+}
+
+/// Bytes as a string if they are UTF-8, so recordings stay readable, and as
+/// an array of numbers otherwise.
+mod text_or_bytes {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => serializer.serialize_str(text),
+            Err(_) => serializer.collect_seq(bytes),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Ok(match Encoded::deserialize(deserializer)? {
+            Encoded::Text(text) => text.into_bytes(),
+            Encoded::Bytes(bytes) => bytes,
+        })
+    }
+}
+
+impl Recording {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// The recorded input bytes, in order.
+    pub fn input_bytes(&self) -> Vec<u8> {
+        self.inputs.iter().map(|event| event.byte).collect()
+    }
+}
+
+/// How a replayed run differed from its recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The `index`th input was read at a different step, or was a different
+    /// byte; `actual` is `None` if the run read more input than was recorded.
+    InputMismatch { index: usize, expected: Option<InputEvent>, actual: InputEvent },
+    /// The output differed from the recording at byte `offset`.
+    OutputMismatch { offset: usize, expected: Option<u8>, actual: u8 },
+    /// The run stopped before reading all of the recorded input.
+    InputUnused { consumed: usize, recorded: usize },
+    /// The run stopped before printing all of the recorded output.
+    OutputMissing { printed: usize, recorded: usize },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::InputMismatch { index, expected: Some(expected), actual } => {
+                write!(f, "input {index} was {actual}, but the recording has {expected}")
+            },
+            ReplayError::InputMismatch { index, expected: None, actual } => {
+                write!(f, "input {index} was {actual}, past the end of the recording")
+            },
+            ReplayError::OutputMismatch { offset, expected: Some(expected), actual } => write!(
+                f, "output byte {offset} was {:?}, but the recording has {:?}", *actual as char, *expected as char,
+            ),
+            ReplayError::OutputMismatch { offset, expected: None, actual } => {
+                write!(f, "output byte {offset} was {:?}, past the end of the recording", *actual as char)
+            },
+            ReplayError::InputUnused { consumed, recorded } => {
+                write!(f, "the run read {consumed} of {recorded} recorded input bytes")
+            },
+            ReplayError::OutputMissing { printed, recorded } => {
+                write!(f, "the run printed {printed} of {recorded} recorded output bytes")
+            },
+        }
+    }
+}
+
+impl error::Error for ReplayError {}
+
+/// The byte an `in` at `address` just stored, if it consumed one; a
+/// meta-command leaves the instruction pointer on the `in`.
+fn consumed_byte(vm: &VM, address: u16, operation: &Operation) -> Option<u8> {
+    let Operation::In(register) = *operation else {
+        return None;
+    };
+    if vm.instruction_ptr() == address {
+        return None;
+    }
+    vm.registers().get(register.wrapping_sub(32_768) as usize).map(|&value| value as u8)
+}
+
+/// Builds a [`Recording`]: register it as a hook on the VM and wrap the VM's
+/// output with [`tee_output`](Self::tee_output).
+#[derive(Clone, Default)]
+pub struct Recorder(Rc<RefCell<Recording>>);
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `inner` so everything written to it is also recorded.
+    pub fn tee_output(&self, inner: Box<dyn Write>) -> Box<dyn Write> {
+        Box::new(RecordingWriter { inner, recorder: self.clone() })
+    }
+
+    /// The session so far.
+    pub fn recording(&self) -> Recording {
+        self.0.borrow().clone()
+    }
+}
+
+impl Hook for Recorder {
+    fn after_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        if let Some(byte) = consumed_byte(vm, address, operation) {
+            self.0.borrow_mut().inputs.push(InputEvent { step: vm.steps(), byte });
+        }
+    }
+}
+
+struct RecordingWriter {
+    inner: Box<dyn Write>,
+    recorder: Recorder,
+}
+
+impl Write for RecordingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.recorder.0.borrow_mut().output.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ReplayState {
+    recording: Recording,
+    inputs_seen: usize,
+    output_seen: usize,
+    error: Option<ReplayError>,
+}
+
+impl ReplayState {
+    fn fail(&mut self, error: ReplayError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+/// Checks a run against a [`Recording`]: give the VM the recording's
+/// [`input_bytes`](Recording::input_bytes), register the replayer as a hook,
+/// wrap the output with [`tee_output`](Self::tee_output), and call
+/// [`finish`](Self::finish) when the run stops.
+#[derive(Clone)]
+pub struct Replayer(Rc<RefCell<ReplayState>>);
+
+impl Replayer {
+    pub fn new(recording: Recording) -> Self {
+        Self(Rc::new(RefCell::new(ReplayState { recording, inputs_seen: 0, output_seen: 0, error: None })))
+    }
+
+    /// Wraps `inner` so everything written to it is also checked.
+    pub fn tee_output(&self, inner: Box<dyn Write>) -> Box<dyn Write> {
+        Box::new(ReplayWriter { inner, replayer: self.clone() })
+    }
+
+    /// The first difference from the recording, including any input or
+    /// output left over once the run has stopped.
+    pub fn finish(&self) -> Result<(), ReplayError> {
+        let state = self.0.borrow();
+        if let Some(error) = &state.error {
+            return Err(error.clone());
+        }
+        let recorded = state.recording.inputs.len();
+        if state.inputs_seen < recorded {
+            return Err(ReplayError::InputUnused { consumed: state.inputs_seen, recorded });
+        }
+        let recorded = state.recording.output.len();
+        if state.output_seen < recorded {
+            return Err(ReplayError::OutputMissing { printed: state.output_seen, recorded });
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Replayer {
+    fn after_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        let Some(byte) = consumed_byte(vm, address, operation) else {
+            return;
+        };
+        let actual = InputEvent { step: vm.steps(), byte };
+        let mut state = self.0.borrow_mut();
+        let index = state.inputs_seen;
+        let expected = state.recording.inputs.get(index).copied();
+        if expected != Some(actual) {
+            state.fail(ReplayError::InputMismatch { index, expected, actual });
+        }
+        state.inputs_seen += 1;
+    }
+}
+
+struct ReplayWriter {
+    inner: Box<dyn Write>,
+    replayer: Replayer,
+}
+
+impl Write for ReplayWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let mut state = self.replayer.0.borrow_mut();
+        for &actual in &buf[..written] {
+            let offset = state.output_seen;
+            let expected = state.recording.output.get(offset).copied();
+            if expected != Some(actual) {
+                state.fail(ReplayError::OutputMismatch { offset, expected, actual });
+            }
+            state.output_seen += 1;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
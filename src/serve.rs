@@ -0,0 +1,166 @@
+//! Serving the VM over TCP, one game per connection.
+//!
+//! Each connection gets its own VM loaded from the same image, running on its
+//! own thread. Input has carriage returns and telnet commands stripped, and
+//! output newlines become CRLF, so the game can be played with `telnet` or
+//! `nc`. A session ends when the client disconnects, stays idle too long, or
+//! the program halts.
+
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::log::{self, Level};
+use crate::vm::{InputSource, VmError, VM};
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+#[derive(Clone, Copy)]
+enum TelnetState {
+    Data,
+    Command,
+    Option,
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+/// Input from a telnet client, with option negotiation, carriage returns,
+/// and NULs removed.
+pub struct TelnetInput<R> {
+    inner: R,
+    state: TelnetState,
+}
+
+impl<R: Read> TelnetInput<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, state: TelnetState::Data }
+    }
+}
+
+impl<R: Read> InputSource for TelnetInput<R> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let Some(byte) = self.inner.read_byte()? else {
+                return Ok(None);
+            };
+            self.state = match (self.state, byte) {
+                (TelnetState::Data, IAC) => TelnetState::Command,
+                (TelnetState::Data, b'\r' | 0) => TelnetState::Data,
+                (TelnetState::Data, byte) => return Ok(Some(byte)),
+                // An escaped 255 is not a character the game understands.
+                (TelnetState::Command, IAC) => TelnetState::Data,
+                (TelnetState::Command, SB) => TelnetState::Subnegotiation,
+                // WILL, WONT, DO, and DONT are followed by an option byte.
+                (TelnetState::Command, 251..=254) => TelnetState::Option,
+                (TelnetState::Command, _) | (TelnetState::Option, _) => TelnetState::Data,
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationCommand,
+                (TelnetState::Subnegotiation, _) => TelnetState::Subnegotiation,
+                (TelnetState::SubnegotiationCommand, SE) => TelnetState::Data,
+                (TelnetState::SubnegotiationCommand, _) => TelnetState::Subnegotiation,
+            };
+        }
+    }
+}
+
+/// Output to a telnet client, with each `\n` sent as `\r\n`.
+pub struct TelnetOutput<W> {
+    inner: W,
+}
+
+impl<W: Write> TelnetOutput<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W: Write> Write for TelnetOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.inner.write_all(line)?;
+                    self.inner.write_all(b"\r\n")?;
+                },
+                None => self.inner.write_all(line)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Limits on the connections [`serve`] accepts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServeConfig {
+    /// The most sessions to run at once; later clients are turned away.
+    pub max_connections: Option<usize>,
+    /// How long a session may wait for input before it is closed.
+    pub idle_timeout: Option<Duration>,
+}
+
+/// Accepts connections on `listener` forever, running `image` for each one.
+/// Sessions are logged with [`log`](crate::log).
+pub fn serve(listener: TcpListener, image: Arc<[u8]>, config: ServeConfig) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::event(Level::Warn, "serve", "accept failed", &[("error", &err)]);
+                continue;
+            },
+        };
+        let peer = stream.peer_addr().map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+        if config.max_connections.is_some_and(|max| active.load(Ordering::SeqCst) >= max) {
+            log::event(Level::Info, "serve", "refused", &[("peer", &peer), ("reason", &"server full")]);
+            // The client is being turned away; failing to tell it is fine.
+            let _ = stream.write_all(b"Server full, try again later.\r\n");
+            continue;
+        }
+        active.fetch_add(1, Ordering::SeqCst);
+        let (image, active) = (Arc::clone(&image), Arc::clone(&active));
+        thread::spawn(move || {
+            log::event(Level::Info, "serve", "connected", &[("peer", &peer)]);
+            match session(stream, &image, config.idle_timeout) {
+                Ok(reason) => log::event(Level::Info, "serve", "disconnected", &[("peer", &peer), ("reason", &reason)]),
+                Err(err) => log::event(Level::Warn, "serve", "session failed", &[("peer", &peer), ("error", &err)]),
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Plays one game over `stream` until the client leaves or the program halts,
+/// returning why the session ended.
+fn session(stream: TcpStream, image: &[u8], idle_timeout: Option<Duration>) -> Result<&'static str, VmError> {
+    stream.set_read_timeout(idle_timeout)?;
+    let input = TelnetInput::new(stream.try_clone()?);
+    // The VM flushes its output before every `in`, so prompts still arrive.
+    let output = BufWriter::new(TelnetOutput::new(stream.try_clone()?));
+    let mut vm = VM::new(input, output);
+    vm.load(image)?;
+    let result = match vm.run() {
+        Ok(_) => Ok("program halted"),
+        Err(VmError::InputExhausted { .. }) => Ok("disconnected"),
+        Err(VmError::Io(err)) => match err.kind() {
+            io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => {
+                Ok("disconnected")
+            },
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok("idle timeout"),
+            _ => Err(VmError::Io(err)),
+        },
+        Err(err) => Err(err),
+    };
+    // The client may already be gone.
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
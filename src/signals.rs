@@ -0,0 +1,100 @@
+//! Turning Unix signals into requests the VM handles between instructions.
+//!
+//! [`install_interrupt_handler`] replaces the default SIGINT action, which
+//! would kill the process and the game with it, with one that sets a flag the
+//! VM checks between instructions (see [`VM::set_interrupt`]). A second Ctrl+C
+//! before the VM has noticed the first one exits, so a program stuck outside
+//! the VM can still be killed.
+//!
+//! [`install_snapshot_handler`] makes SIGUSR1 set a flag asking whoever is
+//! running the VM to save its state and carry on, for grabbing the state of a
+//! long unattended run with `kill -USR1`.
+//!
+//! [`VM::set_interrupt`]: crate::vm::VM::set_interrupt
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+static SNAPSHOT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// The flag set by Ctrl+C once the handler is installed.
+pub fn interrupt_flag() -> Arc<AtomicBool> {
+    INTERRUPT.get_or_init(Arc::default).clone()
+}
+
+/// Makes the next Ctrl+C exit the process, as if the previous one were still
+/// waiting to be handled.
+pub fn exit_on_next_interrupt() {
+    interrupt_flag().store(true, Ordering::SeqCst);
+}
+
+/// The flag set by SIGUSR1 once the handler is installed.
+pub fn snapshot_flag() -> Arc<AtomicBool> {
+    SNAPSHOT.get_or_init(Arc::default).clone()
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    pub const SIGINT: c_int = 2;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const SIGUSR1: c_int = 10;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const SIGUSR1: c_int = 30;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    /// Installs `handler` for `signum`. The handler must only do
+    /// async-signal-safe work: atomics on already initialized flags, and
+    /// `_exit`.
+    pub fn install(signum: c_int, handler: extern "C" fn(c_int)) -> io::Result<()> {
+        // SAFETY: every handler in this module keeps to the rule above.
+        let previous = unsafe { signal(signum, handler) };
+        if previous == SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub extern "C" fn on_interrupt(_signum: c_int) {
+        // Only async-signal-safe work here: an atomic swap, and `_exit`.
+        if let Some(flag) = super::INTERRUPT.get() {
+            if flag.swap(true, Ordering::SeqCst) {
+                unsafe { _exit(130) }
+            }
+        }
+    }
+
+    pub extern "C" fn on_snapshot(_signum: c_int) {
+        if let Some(flag) = super::SNAPSHOT.get() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Installs the Ctrl+C handler. Does nothing on platforms without Unix
+/// signals, where Ctrl+C keeps its default behavior.
+pub fn install_interrupt_handler() -> io::Result<()> {
+    interrupt_flag();
+    #[cfg(unix)]
+    sys::install(sys::SIGINT, sys::on_interrupt)?;
+    Ok(())
+}
+
+/// Installs the SIGUSR1 handler. Does nothing on platforms without Unix
+/// signals.
+pub fn install_snapshot_handler() -> io::Result<()> {
+    snapshot_flag();
+    #[cfg(unix)]
+    sys::install(sys::SIGUSR1, sys::on_snapshot)?;
+    Ok(())
+}
@@ -0,0 +1,6 @@
+//! Solvers for the challenge's puzzles.
+
+pub mod coins;
+pub mod playthrough;
+pub mod search;
+pub mod vault;
@@ -0,0 +1,89 @@
+//! The coin puzzle: five coins must be placed on the monument so that
+//! `_ + _ * _^2 + _^3 - _` equals 399.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The value the monument's equation must reach.
+pub const MONUMENT_TOTAL: i64 = 399;
+
+/// A coin as it is named in the game (`red` for the red coin) and the value
+/// its description gives it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    pub name: String,
+    pub value: i64,
+}
+
+impl FromStr for Coin {
+    type Err = String;
+
+    /// Parses `name=value`, e.g. `red=2`.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (name, value) = text.split_once('=')
+            .ok_or_else(|| format!("expected `name=value`, got `{text}`"))?;
+        let value = value.trim().parse().map_err(|_| format!("invalid coin value `{}`", value.trim()))?;
+        Ok(Coin { name: name.trim().to_string(), value })
+    }
+}
+
+impl fmt::Display for Coin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.value)
+    }
+}
+
+/// Reads a coin's value from its description, which ends "It has <mark> on
+/// one side." where the mark is a number of dots or a polygon.
+pub fn value_from_description(description: &str) -> Option<i64> {
+    const NUMBERS: [&str; 11] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    const POLYGONS: [(&str, i64); 6] = [
+        ("triangle", 3), ("square", 4), ("pentagon", 5), ("hexagon", 6), ("heptagon", 7), ("octagon", 8),
+    ];
+    let (_, mark) = description.split_once("It has ")?;
+    let (mark, _) = mark.split_once(" on one side")?;
+    let mark = mark.trim_start_matches("a ").trim_start_matches("an ");
+    if let Some(&(_, value)) = POLYGONS.iter().find(|(name, _)| *name == mark) {
+        return Some(value);
+    }
+    let count = mark.strip_suffix(" dots").or_else(|| mark.strip_suffix(" dot"))?;
+    NUMBERS.iter().position(|name| *name == count).map(|value| value as i64)
+}
+
+/// Evaluates the monument's equation with the values in slot order.
+pub fn evaluate(values: [i64; 5]) -> i64 {
+    let [a, b, c, d, e] = values;
+    a + b * c.pow(2) + d.pow(3) - e
+}
+
+/// Finds the order in which to place `coins` so the equation equals `total`.
+pub fn solve(coins: &[Coin; 5], total: i64) -> Option<[&Coin; 5]> {
+    let mut order = [0, 1, 2, 3, 4];
+    permutations(&mut order, 0, &mut |order| {
+        evaluate(order.map(|idx| coins[idx].value)) == total
+    })
+    .then(|| order.map(|idx| &coins[idx]))
+}
+
+/// Visits the permutations of `order[k..]` in place until `found` accepts one,
+/// leaving that permutation in `order`.
+fn permutations(order: &mut [usize; 5], k: usize, found: &mut impl FnMut(&[usize; 5]) -> bool) -> bool {
+    if k == order.len() {
+        return found(order);
+    }
+    for idx in k..order.len() {
+        order.swap(k, idx);
+        if permutations(order, k + 1, found) {
+            return true;
+        }
+        order.swap(k, idx);
+    }
+    false
+}
+
+/// The game commands that place `order` on the monument.
+pub fn commands(order: &[&Coin; 5]) -> Vec<String> {
+    order.iter().map(|coin| format!("use {} coin", coin.name)).collect()
+}
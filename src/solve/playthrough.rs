@@ -0,0 +1,188 @@
+//! An automatic playthrough of the whole challenge.
+//!
+//! The game is fed one command at a time. Puzzles are solved along the way:
+//! the coin values are read from the coins' descriptions, the teleporter's
+//! confirmation check is solved and patched out before the second jump, and
+//! the vault route is found by search. Every code printed is collected by the
+//! VM's [`CodeScanner`](crate::codes::CodeScanner).
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::{error, fmt};
+
+use super::coins::{self, Coin};
+use super::vault::{Vault, VAULT_TARGET};
+use crate::analysis::teleporter;
+use crate::codes::FoundCode;
+use crate::vm::{HaltReason, VmError, VM};
+
+/// From the foothills to the room with the coins' monument.
+const TO_MONUMENT: &[&str] = &[
+    "take tablet", "use tablet", "doorway", "north", "north", "bridge", "continue", "down",
+    "east", "take empty lantern", "west", "west", "passage", "ladder", "west", "south", "north",
+    "take can", "west", "ladder", "use can", "use lantern", "darkness", "continue", "west",
+    "west", "west", "west", "north", "take red coin", "north", "east", "take concave coin",
+    "down", "take corroded coin", "up", "west", "west", "take blue coin", "up",
+    "take shiny coin", "down", "east",
+];
+
+const COINS: [&str; 5] = ["red", "corroded", "shiny", "concave", "blue"];
+
+/// From the monument through the first jump to Synacor Headquarters.
+const TO_HEADQUARTERS: &[&str] = &[
+    "north", "take teleporter", "use teleporter", "take business card", "take strange book",
+];
+
+/// From the beach to the orb's antechamber, holding the orb.
+const TO_ORB: &[&str] = &[
+    "north", "north", "north", "north", "north", "north", "north", "east", "take journal",
+    "west", "north", "north", "take orb",
+];
+
+/// From the vault door to the end.
+const TO_MIRROR: &[&str] = &["vault", "take mirror", "use mirror"];
+
+/// Why a playthrough stopped early.
+#[derive(Debug)]
+pub enum PlaythroughError {
+    Vm(VmError),
+    /// The game did not respond the way the playthrough expects.
+    Stuck(String),
+}
+
+impl fmt::Display for PlaythroughError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaythroughError::Vm(err) => write!(f, "{err}"),
+            PlaythroughError::Stuck(message) => write!(f, "playthrough stuck: {message}"),
+        }
+    }
+}
+
+impl error::Error for PlaythroughError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PlaythroughError::Vm(err) => Some(err),
+            PlaythroughError::Stuck(_) => None,
+        }
+    }
+}
+
+impl From<VmError> for PlaythroughError {
+    fn from(err: VmError) -> Self {
+        PlaythroughError::Vm(err)
+    }
+}
+
+/// The outcome of [`play`]: every command sent, the full output, and the
+/// codes found in it.
+#[derive(Debug, Clone, Default)]
+pub struct Playthrough {
+    pub commands: Vec<String>,
+    pub transcript: String,
+    pub codes: Vec<FoundCode>,
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Game {
+    vm: VM,
+    output: SharedBuffer,
+    playthrough: Playthrough,
+}
+
+impl Game {
+    /// Runs until the game asks for more input or halts, and records what it
+    /// printed.
+    fn resume(&mut self) -> Result<String, PlaythroughError> {
+        match self.vm.run() {
+            Ok(HaltReason::Halted(_)) | Err(VmError::InputExhausted { .. }) => (),
+            Ok(reason) => return Err(PlaythroughError::Stuck(format!("stopped unexpectedly: {reason:?}"))),
+            Err(err) => return Err(err.into()),
+        }
+        let output: Vec<u8> = self.output.0.borrow_mut().drain(..).collect();
+        let output = String::from_utf8_lossy(&output).into_owned();
+        self.playthrough.transcript.push_str(&output);
+        Ok(output)
+    }
+
+    fn send(&mut self, command: &str) -> Result<String, PlaythroughError> {
+        if self.vm.is_halted() {
+            return Err(PlaythroughError::Stuck(format!("the game halted before `{command}`")));
+        }
+        self.vm.provide_input(command.as_bytes());
+        self.vm.provide_input(b"\n");
+        self.playthrough.commands.push(command.to_string());
+        self.playthrough.transcript.push_str(command);
+        self.playthrough.transcript.push('\n');
+        self.resume()
+    }
+
+    fn send_all(&mut self, commands: &[&str]) -> Result<(), PlaythroughError> {
+        for command in commands {
+            self.send(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays the challenge in `image` from start to finish.
+pub fn play(image: &[u8]) -> Result<Playthrough, PlaythroughError> {
+    let output = SharedBuffer::default();
+    let mut vm = VM::new(io::empty(), output.clone());
+    vm.load(image)?;
+    let mut game = Game { vm, output, playthrough: Playthrough::default() };
+    game.vm.set_code_scanning(true);
+    game.resume()?;
+    game.send_all(TO_MONUMENT)?;
+
+    let mut coins = Vec::new();
+    for name in COINS {
+        let description = game.send(&format!("look {name} coin"))?;
+        let value = coins::value_from_description(&description)
+            .ok_or_else(|| PlaythroughError::Stuck(format!("could not read the value of the {name} coin")))?;
+        coins.push(Coin { name: name.to_string(), value });
+    }
+    let coins: [Coin; 5] = coins.try_into().expect("there should be five coins");
+    let order = coins::solve(&coins, coins::MONUMENT_TOTAL)
+        .ok_or_else(|| PlaythroughError::Stuck("no order of the coins solves the monument".to_string()))?;
+    for command in coins::commands(&order) {
+        game.send(&command)?;
+    }
+    game.send_all(TO_HEADQUARTERS)?;
+
+    let check = teleporter::find_check(&game.vm.memory_image())
+        .ok_or_else(|| PlaythroughError::Stuck("could not find the teleporter check".to_string()))?;
+    let r7 = check.solve()
+        .ok_or_else(|| PlaythroughError::Stuck("no value of r7 passes the teleporter check".to_string()))?;
+    game.vm.registers_mut()[7] = r7;
+    game.vm.apply_patch(&check.patch());
+    game.send("use teleporter")?;
+    game.send_all(TO_ORB)?;
+
+    let route = Vault::default().shortest_route(VAULT_TARGET)
+        .ok_or_else(|| PlaythroughError::Stuck("no route through the vault".to_string()))?;
+    for command in route.commands() {
+        game.send(&command)?;
+    }
+    game.send_all(TO_MIRROR)?;
+    // The last code is seen in the mirror.
+    if let Some(scanner) = game.vm.code_scanner_mut() {
+        scanner.mirror_last_code();
+    }
+    game.playthrough.codes = game.vm.code_scanner().map_or(vec![], |scanner| scanner.codes().to_vec());
+    Ok(game.playthrough)
+}
@@ -0,0 +1,191 @@
+//! Breadth-first search over the game's inputs: find the commands that make
+//! it print something.
+//!
+//! Each state is a snapshot taken while the game waits for input. A state is
+//! expanded by restoring it into a fresh VM once per candidate command and
+//! running until the game asks again, halts, or exceeds its step budget. The
+//! output printed in response decides whether the goal is reached and which
+//! commands to try next. States are deduplicated by their
+//! [`state_hash`](Snapshot::state_hash), so commands that change nothing, or
+//! orders of commands that end up in the same place, are explored once.
+//!
+//! Each depth is expanded in parallel on scoped threads (a VM is not `Send`,
+//! but snapshots are, and share their memory pages), and the result does not
+//! depend on the number of threads: the first match in breadth-first order
+//! wins.
+
+use std::collections::HashSet;
+use std::io;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::vm::{Snapshot, VmError, VmEvent, VM};
+
+/// Limits on a search.
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    /// The most commands in a solution.
+    pub max_depth: usize,
+    /// The most instructions a command may run before its branch is dropped.
+    pub max_steps: u64,
+    /// The most distinct states kept at any depth; the rest are dropped.
+    pub max_states: usize,
+    /// Worker threads; zero uses every available core.
+    pub threads: usize,
+    /// Memory left out when deciding whether two states are the same, such
+    /// as a move counter.
+    pub volatile: Vec<RangeInclusive<u16>>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self { max_depth: 8, max_steps: 10_000_000, max_states: 100_000, threads: 0, volatile: Vec::new() }
+    }
+}
+
+/// The commands that reach the goal, what the last of them printed, and the
+/// state they leave the game in.
+#[derive(Clone)]
+pub struct Found {
+    pub commands: Vec<String>,
+    pub output: String,
+    pub snapshot: Snapshot,
+}
+
+/// What a search found, with how much it explored.
+pub struct Outcome {
+    pub found: Option<Found>,
+    /// Distinct states reached.
+    pub states: usize,
+    /// Commands that led back to an already reached state.
+    pub duplicates: usize,
+}
+
+/// A state waiting for input, and the commands that reached it.
+struct Node {
+    snapshot: Snapshot,
+    commands: Vec<String>,
+    output: String,
+}
+
+/// Where a command took the game.
+struct Branch {
+    snapshot: Snapshot,
+    output: String,
+    /// Whether the game is waiting for more input rather than halted.
+    waiting: bool,
+}
+
+/// Searches from `start` for commands after which the game's response
+/// satisfies `goal`. At each prompt, `candidates` is given the output since
+/// the previous command and returns the commands to try. Fails only if the
+/// game faults before its first prompt, and finds nothing if it does not
+/// reach one within `max_steps`.
+pub fn search(
+    start: &Snapshot,
+    config: &SearchConfig,
+    candidates: impl Fn(&str) -> Vec<String> + Sync,
+    goal: impl Fn(&str) -> bool + Sync,
+) -> Result<Outcome, VmError> {
+    let mut outcome = Outcome { found: None, states: 0, duplicates: 0 };
+    let Some(first) = advance(start, None, config.max_steps)? else {
+        return Ok(outcome);
+    };
+    let mut seen = HashSet::from([first.snapshot.state_hash_masking(&config.volatile)]);
+    outcome.states = 1;
+    if goal(&first.output) {
+        outcome.found = Some(Found { commands: Vec::new(), output: first.output, snapshot: first.snapshot });
+        return Ok(outcome);
+    }
+    let mut frontier = match first.waiting {
+        true => vec![Node { snapshot: first.snapshot, commands: Vec::new(), output: first.output }],
+        false => Vec::new(),
+    };
+    for _ in 0..config.max_depth {
+        let jobs: Vec<(usize, String)> = frontier.iter()
+            .enumerate()
+            .flat_map(|(idx, node)| candidates(&node.output).into_iter().map(move |command| (idx, command)))
+            .collect();
+        let branches = expand(&frontier, &jobs, config);
+        let mut next = Vec::new();
+        for ((parent, command), branch) in jobs.into_iter().zip(branches) {
+            let Some(branch) = branch else {
+                continue;
+            };
+            if !seen.insert(branch.snapshot.state_hash_masking(&config.volatile)) {
+                outcome.duplicates += 1;
+                continue;
+            }
+            outcome.states += 1;
+            let mut commands = frontier[parent].commands.clone();
+            commands.push(command);
+            if goal(&branch.output) {
+                outcome.found = Some(Found { commands, output: branch.output, snapshot: branch.snapshot });
+                return Ok(outcome);
+            }
+            if branch.waiting && next.len() < config.max_states {
+                next.push(Node { snapshot: branch.snapshot, commands, output: branch.output });
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    Ok(outcome)
+}
+
+/// Runs every `(node, command)` job, returning their branches in job order.
+/// Commands that fault or run too long give `None`.
+fn expand(frontier: &[Node], jobs: &[(usize, String)], config: &SearchConfig) -> Vec<Option<Branch>> {
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    let next_job = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Branch>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..threads.min(jobs.len()) {
+            scope.spawn(|| loop {
+                let idx = next_job.fetch_add(1, Ordering::Relaxed);
+                let Some((parent, command)) = jobs.get(idx) else {
+                    break;
+                };
+                let branch = advance(&frontier[*parent].snapshot, Some(command), config.max_steps).ok().flatten();
+                *results[idx].lock().expect("no worker should panic holding a result") = branch;
+            });
+        }
+    });
+    results.into_iter()
+        .map(|result| result.into_inner().expect("no worker should panic holding a result"))
+        .collect()
+}
+
+/// Restores `snapshot`, types `command` if there is one, and runs until the
+/// game waits for input or halts. Returns `None` if that takes more than
+/// `max_steps` instructions.
+fn advance(snapshot: &Snapshot, command: Option<&str>, max_steps: u64) -> Result<Option<Branch>, VmError> {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.restore(snapshot.clone());
+    if let Some(command) = command {
+        vm.provide_input(command.as_bytes());
+        vm.provide_input(b"\n");
+    }
+    let mut output = Vec::new();
+    for _ in 0..max_steps {
+        let waiting = match vm.step()? {
+            VmEvent::Continued => continue,
+            VmEvent::Output(byte) => {
+                output.push(byte);
+                continue;
+            },
+            VmEvent::NeedsInput => true,
+            VmEvent::Halted => false,
+        };
+        let output = String::from_utf8_lossy(&output).into_owned();
+        return Ok(Some(Branch { snapshot: vm.snapshot(), output, waiting }));
+    }
+    Ok(None)
+}
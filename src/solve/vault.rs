@@ -0,0 +1,216 @@
+//! The vault puzzle: carry the orb from the antechamber in the south-west
+//! corner of a 4x4 grid of rooms to the vault door in the north-east corner,
+//! arriving with the weight the door demands. Rooms alternate between
+//! numbers and operators, and each operator is applied to the orb's weight
+//! with the number of the next room entered.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+/// The weight the vault door expects.
+pub const VAULT_TARGET: i64 = 30;
+
+/// Weights outside this range are not explored.
+const MAX_WEIGHT: i64 = 32_767;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl Op {
+    fn apply(self, weight: i64, value: i64) -> i64 {
+        match self {
+            Op::Add => weight + value,
+            Op::Sub => weight - value,
+            Op::Mul => weight * value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Number(i64),
+    Op(Op),
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Number(value) => write!(f, "{value}"),
+            Cell::Op(Op::Add) => f.write_str("+"),
+            Cell::Op(Op::Sub) => f.write_str("-"),
+            Cell::Op(Op::Mul) => f.write_str("*"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+
+    /// The room one step in this direction from `(row, col)`, if it exists.
+    fn step(self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        let (row, col) = match self {
+            Direction::North => (row.checked_sub(1)?, col),
+            Direction::South => (row + 1, col),
+            Direction::East => (row, col + 1),
+            Direction::West => (row, col.checked_sub(1)?),
+        };
+        (row < 4 && col < 4).then_some((row, col))
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::North => "north",
+            Direction::South => "south",
+            Direction::East => "east",
+            Direction::West => "west",
+        })
+    }
+}
+
+/// The grid of rooms, north row first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vault {
+    pub grid: [[Cell; 4]; 4],
+}
+
+impl Default for Vault {
+    /// The layout in the challenge.
+    fn default() -> Self {
+        "* 8 - 1  4 * 11 *  + 4 - 18  22 - 9 *".parse().expect("the default grid should parse")
+    }
+}
+
+impl FromStr for Vault {
+    type Err = String;
+
+    /// Parses 16 whitespace-separated numbers and operators (`+`, `-`, `*`),
+    /// row by row from the north.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let cells = text.split_whitespace()
+            .map(|token| match token {
+                "+" => Ok(Cell::Op(Op::Add)),
+                "-" => Ok(Cell::Op(Op::Sub)),
+                "*" => Ok(Cell::Op(Op::Mul)),
+                _ => token.parse().map(Cell::Number).map_err(|_| format!("invalid room `{token}`")),
+            })
+            .collect::<Result<Vec<Cell>, String>>()?;
+        if cells.len() != 16 {
+            return Err(format!("expected 16 rooms, got {}", cells.len()));
+        }
+        let mut grid = [[Cell::Number(0); 4]; 4];
+        for (idx, cell) in cells.into_iter().enumerate() {
+            grid[idx / 4][idx % 4] = cell;
+        }
+        if !matches!(grid[3][0], Cell::Number(_)) || !matches!(grid[0][3], Cell::Number(_)) {
+            return Err("the corner rooms must hold numbers".to_string());
+        }
+        Ok(Vault { grid })
+    }
+}
+
+impl fmt::Display for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells: Vec<String> = self.grid.iter().flatten().map(Cell::to_string).collect();
+        f.write_str(&cells.join(" "))
+    }
+}
+
+/// A route through the vault: the directions walked and the rooms entered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub directions: Vec<Direction>,
+    /// Every room on the route, starting with the antechamber.
+    pub cells: Vec<Cell>,
+}
+
+impl Route {
+    /// The game commands that walk the route.
+    pub fn commands(&self) -> Vec<String> {
+        self.directions.iter().map(Direction::to_string).collect()
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells: Vec<String> = self.cells.iter().map(Cell::to_string).collect();
+        f.write_str(&cells.join(" "))
+    }
+}
+
+const START: (usize, usize) = (3, 0);
+const GOAL: (usize, usize) = (0, 3);
+
+impl Vault {
+    fn cell(&self, (row, col): (usize, usize)) -> Cell {
+        self.grid[row][col]
+    }
+
+    /// Finds the shortest route reaching the vault door with weight `target`,
+    /// by breadth-first search over rooms and weights. Returning to the
+    /// antechamber resets the orb and reaching the door ends the walk, so
+    /// neither is passed through.
+    pub fn shortest_route(&self, target: i64) -> Option<Route> {
+        let Cell::Number(initial) = self.cell(START) else {
+            return None;
+        };
+        // The pending operator is implied by the room, so (room, weight) is
+        // the whole state.
+        type State = ((usize, usize), i64);
+        let mut seen: HashSet<State> = HashSet::from([(START, initial)]);
+        let mut queue: VecDeque<(State, Vec<Direction>)> = VecDeque::from([((START, initial), vec![])]);
+        while let Some(((room, weight), directions)) = queue.pop_front() {
+            for direction in Direction::ALL {
+                let Some(next) = direction.step(room) else {
+                    continue;
+                };
+                if next == START {
+                    continue;
+                }
+                let weight = match (self.cell(room), self.cell(next)) {
+                    (Cell::Op(op), Cell::Number(value)) => op.apply(weight, value),
+                    _ => weight,
+                };
+                if !(0..=MAX_WEIGHT).contains(&weight) {
+                    continue;
+                }
+                let mut directions = directions.clone();
+                directions.push(direction);
+                if next == GOAL {
+                    if weight == target {
+                        return Some(self.route(directions));
+                    }
+                    continue;
+                }
+                if seen.insert((next, weight)) {
+                    queue.push_back(((next, weight), directions));
+                }
+            }
+        }
+        None
+    }
+
+    fn route(&self, directions: Vec<Direction>) -> Route {
+        let mut room = START;
+        let mut cells = vec![self.cell(room)];
+        for direction in &directions {
+            room = direction.step(room).expect("the route should stay in the grid");
+            cells.push(self.cell(room));
+        }
+        Route { directions, cells }
+    }
+}
@@ -0,0 +1,162 @@
+//! Names for addresses, shared by the disassembler, the debugger, the tracer,
+//! and the graph exports.
+//!
+//! A symbol file holds one `address = name` pair per line; `#` starts a
+//! comment. Names follow the assembler's rules for labels.
+//!
+//! ```text
+//! # entry points
+//! 0x0000 = start
+//! 1458   = print
+//! 6027   = main_loop
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::{error, fmt};
+
+use crate::asm::is_identifier;
+use crate::vm::{Operation, MEMORY_SIZE};
+
+/// An error at a (1-based) line of a symbol file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for SymbolError {}
+
+/// A set of names, at most one per address and one address per name.
+/// Displays in the symbol file format, in address order.
+///
+/// ```
+/// use oscon_2012_vm_challenge::symbols::Symbols;
+///
+/// let mut symbols = Symbols::parse("1458 = print  # writes a string\n").unwrap();
+/// symbols.insert(0x178b, "main_loop").unwrap();
+/// assert_eq!(symbols.address("main_loop"), Some(6027));
+/// assert_eq!(symbols.to_string(), "1458 = print\n6027 = main_loop\n");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    /// Parses a symbol file. Addresses may be decimal or `0x`-prefixed hex.
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::default();
+        for (idx, text) in text.lines().enumerate() {
+            let error = |message: String| SymbolError { line: idx + 1, message };
+            let text = text.split_once('#').map_or(text, |(text, _)| text).trim();
+            if text.is_empty() {
+                continue;
+            }
+            let Some((address, name)) = text.split_once('=') else {
+                return Err(error(format!("expected `address = name`, found `{text}`")));
+            };
+            let (address, name) = (address.trim(), name.trim());
+            let address = parse_address(address).ok_or_else(|| error(format!("invalid address `{address}`")))?;
+            if symbols.address(name).is_some() {
+                return Err(error(format!("duplicate name `{name}`")));
+            }
+            if symbols.name(address).is_some() {
+                return Err(error(format!("address {address} is already named")));
+            }
+            symbols.insert(address, name).map_err(error)?;
+        }
+        Ok(symbols)
+    }
+
+    /// Names `address`, replacing its old name and moving `name` from any
+    /// other address. Fails if `name` is not a valid label or is a register.
+    pub fn insert(&mut self, address: u16, name: &str) -> Result<(), String> {
+        if !is_identifier(name) || is_register(name) {
+            return Err(format!("invalid name `{name}`"));
+        }
+        self.remove(address);
+        if let Some(old) = self.addresses.remove(name) {
+            self.names.remove(&old);
+        }
+        self.names.insert(address, name.to_string());
+        self.addresses.insert(name.to_string(), address);
+        Ok(())
+    }
+
+    /// Removes the name of `address`, returning it.
+    pub fn remove(&mut self, address: u16) -> Option<String> {
+        let name = self.names.remove(&address)?;
+        self.addresses.remove(&name);
+        Some(name)
+    }
+
+    /// The name of `address`.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// The address named `name`.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// The name of the literal address `operation` jumps or calls to.
+    pub fn target(&self, operation: &Operation) -> Option<&str> {
+        match *operation {
+            Operation::Jmp(target) | Operation::Jt(_, target) | Operation::Jf(_, target) | Operation::Call(target) => {
+                self.name(target)
+            },
+            _ => None,
+        }
+    }
+
+    /// `address`, followed by its name in angle brackets if it has one.
+    pub fn describe(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => format!("{address} <{name}>"),
+            None => address.to_string(),
+        }
+    }
+
+    /// The named addresses and their names, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.names.iter().map(|(&address, name)| (address, name.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, name) in self.iter() {
+            writeln!(f, "{address} = {name}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let address = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    };
+    address.filter(|&address| (address as usize) < MEMORY_SIZE)
+}
+
+/// Whether `name` is `r0`..`r7`, which commands read as registers.
+fn is_register(name: &str) -> bool {
+    name.strip_prefix('r').is_some_and(|idx| matches!(idx, "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7"))
+}
@@ -0,0 +1,62 @@
+//! Copying VM I/O to a log file.
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/// A log file shared by the tees wrapped around a VM's input and output.
+#[derive(Clone)]
+pub struct Transcript {
+    file: Rc<RefCell<BufWriter<File>>>,
+}
+
+impl Transcript {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn append(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Rc::new(RefCell::new(BufWriter::new(file))) })
+    }
+
+    /// Wraps `inner` so everything written to it is also logged.
+    pub fn tee_output(&self, inner: Box<dyn Write>) -> Box<dyn Write> {
+        Box::new(TeeWriter { inner, transcript: self.clone() })
+    }
+
+    /// Wraps `inner` so everything read from it is also logged.
+    pub fn tee_input(&self, inner: Box<dyn Read>) -> Box<dyn Read> {
+        Box::new(TeeReader { inner, transcript: self.clone() })
+    }
+}
+
+struct TeeWriter {
+    inner: Box<dyn Write>,
+    transcript: Transcript,
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.transcript.file.borrow_mut().write_all(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.transcript.file.borrow_mut().flush()
+    }
+}
+
+struct TeeReader {
+    inner: Box<dyn Read>,
+    transcript: Transcript,
+}
+
+impl Read for TeeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.transcript.file.borrow_mut().write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
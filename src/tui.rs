@@ -0,0 +1,267 @@
+//! A full-screen debugger view, redrawn with ANSI escapes after every command.
+//!
+//! The screen shows the disassembly around the instruction pointer, the
+//! registers with the stack and active calls, a memory view, and a console
+//! holding the game's output interleaved with the debugger's messages. The
+//! prompt at the bottom takes the same commands as the plain debugger, plus
+//! `> text` to send a line to the game and `view <addr>` to move the memory
+//! view. Input is read a line at a time, so the terminal stays in its normal
+//! mode and nothing needs restoring if the process dies.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::debugger::{self, Command, Debugger};
+use crate::disasm;
+use crate::hexdump::hexdump;
+use crate::vm::{MEMORY_SIZE, VM};
+
+const MEMORY_ROWS: usize = 6;
+/// Console lines kept for scrollback; only the last screenful is shown.
+const CONSOLE_HISTORY: usize = 1000;
+
+#[derive(Clone, Default)]
+struct Console(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The debugger and the state of the view around it.
+pub struct Tui {
+    debugger: Debugger,
+    console: Console,
+    lines: Vec<String>,
+    memory_start: u16,
+}
+
+/// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+/// Splits `text` into pieces of at most `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width.max(1)).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// A pane's title bar, e.g. `-- registers ------`.
+fn title(name: &str, width: usize) -> String {
+    let title = format!("-- {name} ");
+    format!("\x1b[1m{}\x1b[0m", fit(&format!("{title}{}", "-".repeat(width)), width))
+}
+
+impl Tui {
+    /// Wraps `vm`, which should have been created with the writer returned by
+    /// [`Tui::output`] as its output so the game's text lands in the console.
+    pub fn new(vm: VM, console: TuiOutput) -> Self {
+        let mut debugger = Debugger::new(vm, Box::new(console.0.clone()));
+        debugger.set_trace_output(Box::new(console.0.clone()));
+        Self { debugger, console: console.0, lines: Vec::new(), memory_start: 0 }
+    }
+
+    /// A writer for the VM's output that feeds the console pane.
+    pub fn output() -> TuiOutput {
+        TuiOutput(Console::default())
+    }
+
+    pub fn vm(&self) -> &VM {
+        self.debugger.vm()
+    }
+
+    /// Runs the interface until `quit` or end of input.
+    pub fn run(
+        &mut self,
+        mut read_line: impl FnMut(&mut String) -> io::Result<usize>,
+        out: &mut dyn Write,
+        size: impl Fn() -> (usize, usize),
+    ) -> io::Result<()> {
+        loop {
+            let (width, height) = size();
+            write!(out, "\x1b[H\x1b[2J{}", self.render(width, height))?;
+            write!(out, "\x1b[1m(vmtui)\x1b[0m ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if read_line(&mut line)? == 0 {
+                break;
+            }
+            if !self.execute(&line)? {
+                break;
+            }
+        }
+        writeln!(out)
+    }
+
+    /// Runs one line typed at the prompt. Returns `false` on `quit`.
+    pub fn execute(&mut self, line: &str) -> io::Result<bool> {
+        let mut console = self.console.clone();
+        if let Some(input) = line.trim_end_matches(['\r', '\n']).strip_prefix('>') {
+            let input = input.strip_prefix(' ').unwrap_or(input);
+            self.debugger.vm_mut().provide_input(format!("{input}\n").as_bytes());
+            writeln!(console, "> {input}")?;
+            return Ok(true);
+        }
+        let mut words = line.split_whitespace();
+        if words.next() == Some("view") {
+            match words.next().map(debugger::parse_number::<u16>) {
+                Some(Ok(address)) if (address as usize) < MEMORY_SIZE => self.memory_start = address,
+                _ => writeln!(console, "usage: view <addr>")?,
+            }
+            return Ok(true);
+        }
+        match Command::parse(line) {
+            Ok(Some(Command::Quit)) => return Ok(false),
+            Ok(Some(Command::Help)) => {
+                self.debugger.execute(Command::Help)?;
+                writeln!(console, "  > <text>                  send a line of input to the game")?;
+                writeln!(console, "  view <addr>               show memory from addr in the memory pane")?;
+            },
+            Ok(Some(command)) => self.debugger.execute(command)?,
+            Ok(None) => (),
+            Err(message) => writeln!(console, "{message}")?,
+        }
+        Ok(true)
+    }
+
+    /// Moves new console output into the line history.
+    fn collect_console(&mut self) {
+        let bytes = std::mem::take(&mut *self.console.0.borrow_mut());
+        if bytes.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let mut pieces = text.split('\n');
+        if let (Some(last), Some(first)) = (self.lines.last_mut(), pieces.next()) {
+            last.push_str(first);
+        }
+        self.lines.extend(pieces.map(str::to_string));
+        if self.lines.len() > CONSOLE_HISTORY {
+            self.lines.drain(..self.lines.len() - CONSOLE_HISTORY);
+        }
+    }
+
+    /// Draws the whole screen, leaving the last line for the prompt.
+    pub fn render(&mut self, width: usize, height: usize) -> String {
+        self.collect_console();
+        let (width, height) = (width.max(40), height.max(20));
+        let right_width = (width / 3).max(24);
+        let left_width = width - right_width - 1;
+        let console_height = (height - MEMORY_ROWS - 3) / 2;
+        let top_height = height - console_height - MEMORY_ROWS - 4;
+
+        let mut screen = Vec::new();
+        let (disassembly, ip_row) = self.disassembly_pane(top_height);
+        let side = self.side_pane(top_height);
+        screen.push(format!("{} {}", title("disassembly", left_width), title("registers", right_width)));
+        for row in 0..top_height {
+            let mut left = fit(disassembly.get(row).map_or("", String::as_str), left_width);
+            if ip_row == Some(row) {
+                left = format!("\x1b[7m{left}\x1b[0m");
+            }
+            let right = side.get(row).map_or("", String::as_str);
+            screen.push(format!("{left} {}", fit(right, right_width)));
+        }
+        screen.push(title(&format!("memory from {}", self.memory_start), width));
+        screen.extend(self.memory_pane().iter().map(|line| fit(line, width)));
+        screen.push(title("console", width));
+        let console: Vec<String> = self.lines.iter().flat_map(|line| wrap(line, width)).collect();
+        let shown = &console[console.len().saturating_sub(console_height)..];
+        for row in 0..console_height {
+            screen.push(fit(shown.get(row).map_or("", String::as_str), width));
+        }
+        screen.iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    /// The disassembly centered on the instruction pointer, decoded afresh
+    /// from memory so code the program rewrites shows as it now is, and the
+    /// row of the next instruction, to highlight.
+    fn disassembly_pane(&self, rows: usize) -> (Vec<String>, Option<usize>) {
+        let vm = self.debugger.vm();
+        let ip = vm.instruction_ptr();
+        let lines = disasm::around(&vm.memory_image(), ip, rows / 2, rows);
+        let breakpoints: Vec<u16> = vm.breakpoints().collect();
+        let ip_row = lines.iter().position(|line| line.address == ip);
+        let lines = lines.iter()
+            .map(|line| {
+                let marker = match (line.address == ip, breakpoints.contains(&line.address)) {
+                    (true, _) => "=>",
+                    (false, true) => " *",
+                    (false, false) => "  ",
+                };
+                format!("{marker}{line}")
+            })
+            .collect();
+        (lines, ip_row)
+    }
+
+    fn side_pane(&self, rows: usize) -> Vec<String> {
+        let vm = self.debugger.vm();
+        let mut lines = vec![format!("ip {:5}   steps {}", vm.instruction_ptr(), vm.steps())];
+        for (idx, values) in vm.registers().chunks(2).enumerate() {
+            lines.push(format!("r{} {:5}   r{} {:5}", 2 * idx, values[0], 2 * idx + 1, values[1]));
+        }
+        let calls = vm.call_stack();
+        lines.push(format!("-- calls ({}) --", calls.len()));
+        lines.extend(calls.iter().rev().take(3).map(|frame| format!("{} from {}", frame.target, frame.call_site)));
+        lines.push(format!("-- stack ({}) --", vm.stack().len()));
+        let stack_rows = rows.saturating_sub(lines.len());
+        lines.extend(vm.stack().iter().rev().take(stack_rows).enumerate().map(|(depth, value)| format!("#{depth:<3} {value}")));
+        lines
+    }
+
+    fn memory_pane(&self) -> Vec<String> {
+        let vm = self.debugger.vm();
+        let start = self.memory_start as usize;
+        let end = (start + 8 * MEMORY_ROWS).min(MEMORY_SIZE);
+        let words: Vec<Option<u16>> = (start..end).map(|address| vm.memory(address as u16)).collect();
+        let mut dump = Vec::new();
+        // Writing to a Vec cannot fail.
+        let _ = hexdump(self.memory_start, &words, &mut dump);
+        String::from_utf8_lossy(&dump).lines().map(str::to_string).collect()
+    }
+}
+
+/// The terminal's size as (columns, rows), asked of `stty` or taken from
+/// `COLUMNS` and `LINES`, defaulting to 80x24.
+pub fn terminal_size() -> (usize, usize) {
+    let from_stty = std::fs::File::open("/dev/tty").ok().and_then(|tty| {
+        let output = std::process::Command::new("stty").arg("size").stdin(tty).output().ok()?;
+        let text = String::from_utf8(output.stdout).ok()?;
+        let mut fields = text.split_whitespace().map(str::parse::<usize>);
+        let (rows, columns) = (fields.next()?.ok()?, fields.next()?.ok()?);
+        Some((columns, rows))
+    });
+    from_stty.unwrap_or_else(|| {
+        let var = |name: &str, default| std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+        (var("COLUMNS", 80), var("LINES", 24))
+    })
+}
+
+/// The VM output for a [`Tui`], from [`Tui::output`].
+#[derive(Clone)]
+pub struct TuiOutput(Console);
+
+impl Write for TuiOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,1317 @@
+//! The virtual machine described by the architecture spec.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::CodeScanner;
+use crate::log::{self, Level};
+use crate::symbols::Symbols;
+
+mod checkpoint;
+mod coredump;
+mod coverage;
+mod device;
+mod error;
+mod fuel;
+mod history;
+mod hooks;
+mod image;
+mod memory;
+mod meta;
+mod operation;
+mod patch;
+mod profile;
+mod recent;
+mod selfmod;
+mod snapshot;
+mod state_hash;
+mod streams;
+mod syscall;
+mod watch;
+
+pub use checkpoint::CheckpointConfig;
+pub use coredump::CoreDump;
+use checkpoint::Checkpoints;
+use fuel::Fuel;
+pub use coverage::Coverage;
+pub use device::Device;
+use device::MemoryMap;
+pub use error::VmError;
+pub use memory::MEMORY_SIZE;
+use history::History;
+pub use hooks::{Hook, HookId};
+pub use image::{read_image, ImageFormat};
+use hooks::Hooks;
+use memory::Memory;
+pub use meta::MetaConfig;
+pub use operation::{format_operand, DecodeError, Operation};
+pub use profile::Profile;
+pub use recent::{Executed, RecentInstructions};
+pub use selfmod::{CodeWrite, SelfModification, WrittenCodeRun};
+pub use patch::Patch;
+pub use snapshot::{Snapshot, SnapshotDiff};
+pub use streams::{EofPolicy, InputSource, OutputSink, PollingInput};
+pub use syscall::{Syscall, FIRST_SYSCALL_OPCODE};
+use syscall::Syscalls;
+pub use watch::{WatchHit, WatchTarget, Watchpoint};
+
+/// Splits a little-endian image into words, checking that it fits in memory.
+pub fn decode_image(image: &[u8]) -> Result<Vec<u16>, VmError> {
+    if !image.len().is_multiple_of(2) || image.len() > 2 * MEMORY_SIZE {
+        return Err(VmError::InvalidImage { len: image.len() });
+    }
+    Ok(image
+        .chunks(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect())
+}
+
+/// Encodes words as a little-endian image.
+pub fn encode_image(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// What happened during one [`VM::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmEvent {
+    /// An instruction executed with nothing for the embedder to act on.
+    Continued,
+    /// `out` wrote this byte (to the output sink as well).
+    Output(u8),
+    /// `in` found no input; nothing executed. Supply some with
+    /// [`VM::provide_input`] and step again.
+    NeedsInput,
+    /// The machine has halted.
+    Halted,
+}
+
+/// What stopped a halted machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltCause {
+    /// It executed `halt`. Machines restored halted from a snapshot report
+    /// this too.
+    #[default]
+    Instruction,
+    /// It executed `ret` with nothing on the stack.
+    EmptyStackReturn,
+    /// The user typed the `quit` meta-command.
+    UserQuit,
+    /// `in` found no more input under [`EofPolicy::Halt`].
+    EndOfInput,
+}
+
+/// What `mod` does when the divisor is zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticPolicy {
+    /// Fault with [`VmError::DivideByZero`].
+    #[default]
+    Fault,
+    /// Yield 32767, the largest value.
+    Saturate,
+    /// Yield the dividend, treating the divisor as 32768, which 0 is
+    /// congruent to in the VM's 15-bit arithmetic.
+    Wrap,
+}
+
+/// What the VM does on reaching a word that is not a known opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpcodePolicy {
+    /// Fault with [`VmError::InvalidOpcode`].
+    #[default]
+    Fault,
+    /// Execute the word as `noop`, moving on to the next word.
+    Noop,
+    /// Stop [`VM::run`] with [`HaltReason::InvalidOpcode`], as at a
+    /// breakpoint, so a debugger can patch memory or move the instruction
+    /// pointer. Resuming without doing either faults. [`VM::step`] faults
+    /// either way.
+    Break,
+}
+
+/// Why [`VM::run`] returned control to the caller. Faults are reported as
+/// errors instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    /// The machine halted.
+    Halted(HaltCause),
+    /// The instruction pointer reached a breakpoint; the instruction there has
+    /// not been executed yet.
+    Breakpoint(u16),
+    /// A watched location was written; the writing instruction has executed.
+    Watchpoint(WatchHit),
+    /// The stop condition passed to [`VM::run_until`] was met.
+    Condition,
+    /// [`VM::run_for`] executed its allotted number of instructions.
+    StepLimit,
+    /// [`VM::run_with_fuel`] used up its fuel.
+    OutOfFuel,
+    /// The interrupt flag (see [`VM::set_interrupt`]) was raised; the
+    /// instruction pointer is at the next instruction to execute.
+    Interrupted,
+    /// The instruction pointer reached a word that is not an opcode, under
+    /// [`OpcodePolicy::Break`].
+    InvalidOpcode { address: u16, opcode: u16 },
+}
+
+/// A `call` that has not yet returned, as recorded on the shadow call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    /// The address of the `call` instruction.
+    pub call_site: u16,
+    /// The address that was called.
+    pub target: u16,
+    /// The return address pushed on the stack.
+    pub return_address: u16,
+}
+
+/// A `call` observed at runtime while call logging is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObservedCall {
+    /// The function making the call: the target of the innermost active frame,
+    /// or 0 at the top level.
+    pub caller: u16,
+    pub call_site: u16,
+    pub target: u16,
+}
+
+/// The machine state: memory, registers, stack, and the I/O used by `in`/`out`.
+pub struct VM {
+    instruction_ptr: u16,
+    mem: Memory,
+    registers: [u16; 8],
+    stack: Vec<u16>,
+    halted: bool,
+    halt_cause: HaltCause,
+    input: Box<dyn InputSource>,
+    eof_policy: EofPolicy,
+    arithmetic_policy: ArithmeticPolicy,
+    opcode_policy: OpcodePolicy,
+    // Called while the input source has nothing ready; see `set_idle_handler`.
+    idle: Option<Box<dyn FnMut() -> ControlFlow<()>>>,
+    // The rest of the current input line, fed to `in` one byte at a time.
+    pending_input: VecDeque<u8>,
+    // Input supplied by the embedder, read before `input`.
+    provided_input: VecDeque<u8>,
+    output: Box<dyn OutputSink>,
+    meta: Option<MetaConfig>,
+    trace: Option<Box<dyn Write>>,
+    trace_symbols: Symbols,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
+    recent: Option<RecentInstructions>,
+    self_modification: Option<SelfModification>,
+    code_scanner: Option<CodeScanner>,
+    hooks: Hooks,
+    syscalls: Syscalls,
+    devices: MemoryMap,
+    call_log: Option<BTreeMap<ObservedCall, u64>>,
+    breakpoints: BTreeSet<u16>,
+    // The breakpoint `run` last stopped at, so the next `run` resumes past it.
+    suspended_at: Option<u16>,
+    watchpoints: Vec<Watchpoint>,
+    watch_hit: Option<WatchHit>,
+    call_depth: i32,
+    frames: Vec<Frame>,
+    steps: u64,
+    history: Option<History>,
+    checkpoints: Option<Checkpoints>,
+    interrupt: Option<Arc<AtomicBool>>,
+    decode_cache: bool,
+    strict: bool,
+    fuel: Fuel,
+}
+
+impl Default for VM {
+    /// A VM wired to the process's stdin and stdout.
+    fn default() -> Self {
+        Self::new(io::stdin(), io::stdout())
+    }
+}
+
+/// Forks the machine with [`fork`](VM::fork), giving the copy no input beyond
+/// what has already been provided and discarding its output. Drive a clone
+/// with [`step`](VM::step), reading output from the events it returns.
+impl Clone for VM {
+    fn clone(&self) -> Self {
+        self.fork(io::empty(), io::sink())
+    }
+}
+
+impl VM {
+    /// Creates an empty VM that reads `in` bytes from `input` and writes `out`
+    /// bytes to `output`.
+    pub fn new(input: impl InputSource + 'static, output: impl OutputSink + 'static) -> Self {
+        Self {
+            instruction_ptr: 0,
+            mem: Memory::default(),
+            registers: [0; 8],
+            stack: Vec::new(),
+            halted: false,
+            halt_cause: HaltCause::Instruction,
+            input: Box::new(input),
+            eof_policy: EofPolicy::NeedsInput,
+            arithmetic_policy: ArithmeticPolicy::Fault,
+            opcode_policy: OpcodePolicy::Fault,
+            idle: None,
+            pending_input: VecDeque::new(),
+            provided_input: VecDeque::new(),
+            output: Box::new(output),
+            meta: None,
+            trace: None,
+            trace_symbols: Symbols::default(),
+            profile: None,
+            coverage: None,
+            recent: None,
+            self_modification: None,
+            code_scanner: None,
+            hooks: Hooks::default(),
+            syscalls: Syscalls::new(),
+            devices: MemoryMap::default(),
+            call_log: None,
+            breakpoints: BTreeSet::new(),
+            suspended_at: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            call_depth: 0,
+            frames: Vec::new(),
+            steps: 0,
+            history: None,
+            checkpoints: None,
+            interrupt: None,
+            decode_cache: true,
+            strict: false,
+            fuel: Fuel::default(),
+        }
+    }
+
+    /// Loads the binary at `filename` and runs it until it halts or hits a
+    /// breakpoint.
+    pub fn run_binary(&mut self, filename: &Path) -> Result<HaltReason, VmError> {
+        self.load_file(filename)?;
+        self.run()
+    }
+
+    /// Reads a binary from disk, in any format [`ImageFormat::detect`]
+    /// recognizes, and loads it at address 0.
+    pub fn load_file(&mut self, filename: &Path) -> Result<(), VmError> {
+        self.load(&read_image(filename, ImageFormat::Auto)?)
+    }
+
+    /// Loads a little-endian image (low byte, high byte per word) at address 0.
+    pub fn load(&mut self, image: &[u8]) -> Result<(), VmError> {
+        self.load_at(image, 0)
+    }
+
+    /// Loads a little-endian image starting at `offset`, leaving the rest of
+    /// memory as it is. The image must fit below the end of memory.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.load(&[21, 0, 0, 0]).unwrap();
+    /// vm.load_at(&[19, 0, 65, 0], 1).unwrap();
+    /// assert_eq!((0..3).map(|address| vm.memory(address)).collect::<Vec<_>>(), [Some(21), Some(19), Some(65)]);
+    /// assert!(vm.load_at(&[0, 0, 0, 0], 32_767).is_err());
+    /// ```
+    pub fn load_at(&mut self, image: &[u8], offset: u16) -> Result<(), VmError> {
+        let words = decode_image(image)?;
+        if offset as usize + words.len() > MEMORY_SIZE {
+            return Err(VmError::InvalidImage { len: image.len() });
+        }
+        for (address, word) in (offset..).zip(words) {
+            self.mem.set(address, word);
+        }
+        Ok(())
+    }
+
+    /// Enables (or with `None`, disables) meta-commands: input lines starting
+    /// with `config.sigil` are executed by the host instead of being fed to the
+    /// program. See [`MetaConfig`].
+    pub fn set_meta_commands(&mut self, config: Option<MetaConfig>) {
+        self.meta = config;
+    }
+
+    /// Makes `run` and its variants stop with [`HaltReason::Interrupted`] when
+    /// `flag` is set, clearing it. The flag is checked before each
+    /// instruction, so another thread or a signal handler can suspend a long
+    /// run. `None` stops checking.
+    pub fn set_interrupt(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.interrupt = flag;
+    }
+
+    /// Sets what `in` does once the input has run out. The default,
+    /// [`EofPolicy::NeedsInput`], waits for more.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{EofPolicy, HaltCause, HaltReason, VM};
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// // in r0; halt
+    /// vm.load(&[20, 0, 0, 128, 0, 0]).unwrap();
+    /// vm.set_eof_policy(EofPolicy::Halt);
+    /// assert_eq!(vm.run().unwrap(), HaltReason::Halted(HaltCause::EndOfInput));
+    /// ```
+    pub fn set_eof_policy(&mut self, policy: EofPolicy) {
+        self.eof_policy = policy;
+    }
+
+    /// Sets what `mod` does when dividing by zero. The default,
+    /// [`ArithmeticPolicy::Fault`], stops the program.
+    pub fn set_arithmetic_policy(&mut self, policy: ArithmeticPolicy) {
+        self.arithmetic_policy = policy;
+    }
+
+    /// Sets what happens on reaching a word that is not an opcode, such as
+    /// when running into data or a fuzzed image.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{encode_image, HaltCause, HaltReason, OpcodePolicy, VM};
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.load(&encode_image(&[30_000, 0])).unwrap();
+    /// vm.set_opcode_policy(OpcodePolicy::Break);
+    /// assert_eq!(vm.run().unwrap(), HaltReason::InvalidOpcode { address: 0, opcode: 30_000 });
+    /// vm.set_opcode_policy(OpcodePolicy::Noop);
+    /// assert_eq!(vm.run().unwrap(), HaltReason::Halted(HaltCause::Instruction));
+    /// ```
+    pub fn set_opcode_policy(&mut self, policy: OpcodePolicy) {
+        self.opcode_policy = policy;
+    }
+
+    /// Sets what `in` does while a non-blocking input source, such as a
+    /// [`PollingInput`], has nothing ready. With no handler (the default), or
+    /// when `idle` breaks, the `in` is left unexecuted and reported as
+    /// [`VmEvent::NeedsInput`], whatever the EOF policy, so the caller's
+    /// thread is never blocked. While `idle` continues, the read is retried
+    /// after each call, so the handler can sleep, pump an event loop, or
+    /// give up after a while.
+    pub fn set_idle_handler(&mut self, idle: Option<Box<dyn FnMut() -> ControlFlow<()>>>) {
+        self.idle = idle;
+    }
+
+    /// Turns the cache of decoded instructions on (the default) or off. With it
+    /// on, each address is decoded once and decoded again only after a write
+    /// to one of its words; turning it off decodes every instruction as it
+    /// executes.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+    }
+
+    /// Turns strict spec conformance on or off (the default). Every
+    /// instruction's operands are checked as it is decoded, and reads of
+    /// never-written memory fault, either way; strict mode also faults with
+    /// [`VmError::InvalidNumber`] when `rmem` reads a word above 32775, which
+    /// the spec makes invalid as a value.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{encode_image, VmError, VM};
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// // rmem r0 4; halt; data 40000
+    /// vm.load(&encode_image(&[15, 32_768, 4, 0, 40_000])).unwrap();
+    /// vm.set_strict(true);
+    /// assert!(matches!(vm.run(), Err(VmError::InvalidNumber { address: 0, value: 40_000 })));
+    /// ```
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+    }
+
+    /// Captures the execution state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            instruction_ptr: self.instruction_ptr,
+            registers: self.registers,
+            mem: self.mem.clone(),
+            stack: self.stack.clone(),
+            halted: self.halted,
+            call_depth: self.call_depth,
+            frames: self.frames.clone(),
+            steps: self.steps,
+        }
+    }
+
+    /// Replaces the execution state with `snapshot`.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.instruction_ptr = snapshot.instruction_ptr;
+        self.registers = snapshot.registers;
+        self.mem = snapshot.mem;
+        self.stack = snapshot.stack;
+        self.halted = snapshot.halted;
+        self.halt_cause = HaltCause::Instruction;
+        self.call_depth = snapshot.call_depth;
+        self.frames = snapshot.frames;
+        self.steps = snapshot.steps;
+        self.suspended_at = None;
+        self.watch_hit = None;
+        // Undo records only make sense relative to the state they came from.
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// A copy of the machine that reads `in` bytes from `input` and writes `out`
+    /// bytes to `output`.
+    ///
+    /// Memory pages are shared until either machine writes to them, so a fork
+    /// is cheap enough to take at every branch of a search. The copy keeps the
+    /// execution state, unread input, breakpoints, watchpoints, meta-command
+    /// settings, instrumentation, and fuel costs. Hooks, syscalls, mapped
+    /// devices, and the trace writer belong to their owner and are not
+    /// copied.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.load(&[21, 0, 0, 0]).unwrap();
+    /// let mut fork = vm.clone();
+    /// fork.set_memory(1, 42);
+    /// assert_eq!((vm.memory(1), fork.memory(1)), (Some(0), Some(42)));
+    /// ```
+    pub fn fork(&self, input: impl InputSource + 'static, output: impl OutputSink + 'static) -> VM {
+        VM {
+            instruction_ptr: self.instruction_ptr,
+            mem: self.mem.clone(),
+            registers: self.registers,
+            stack: self.stack.clone(),
+            halted: self.halted,
+            halt_cause: self.halt_cause,
+            eof_policy: self.eof_policy,
+            arithmetic_policy: self.arithmetic_policy,
+            opcode_policy: self.opcode_policy,
+            pending_input: self.pending_input.clone(),
+            provided_input: self.provided_input.clone(),
+            meta: self.meta.clone(),
+            profile: self.profile.clone(),
+            coverage: self.coverage.clone(),
+            recent: self.recent.clone(),
+            self_modification: self.self_modification.clone(),
+            code_scanner: self.code_scanner.clone(),
+            call_log: self.call_log.clone(),
+            breakpoints: self.breakpoints.clone(),
+            suspended_at: self.suspended_at,
+            watchpoints: self.watchpoints.clone(),
+            watch_hit: self.watch_hit,
+            call_depth: self.call_depth,
+            frames: self.frames.clone(),
+            steps: self.steps,
+            history: self.history.clone(),
+            checkpoints: self.checkpoints.clone(),
+            interrupt: self.interrupt.clone(),
+            decode_cache: self.decode_cache,
+            strict: self.strict,
+            fuel: self.fuel.clone(),
+            ..VM::new(input, output)
+        }
+    }
+
+    /// Writes the execution state to `path`.
+    pub fn save_state(&self, path: &Path) -> Result<(), VmError> {
+        self.snapshot().save(path)
+    }
+
+    /// Replaces the execution state with one written by
+    /// [`save_state`](Self::save_state).
+    pub fn load_state(&mut self, path: &Path) -> Result<(), VmError> {
+        self.restore(Snapshot::load(path)?);
+        Ok(())
+    }
+
+    /// Executes instructions until the machine halts, reaches a breakpoint, or
+    /// triggers a watchpoint.
+    /// Calling `run` again after a breakpoint resumes past it. Output is flushed
+    /// even if execution faults.
+    pub fn run(&mut self) -> Result<HaltReason, VmError> {
+        self.run_until(|_| false)
+    }
+
+    /// Like [`run`](Self::run), but also stops with [`HaltReason::Condition`]
+    /// once `stop` returns `true`. `stop` is checked after each instruction.
+    pub fn run_until(&mut self, stop: impl FnMut(&VM) -> bool) -> Result<HaltReason, VmError> {
+        self.run_limited(u64::MAX, stop)
+    }
+
+    /// Like [`run`](Self::run), but stops with [`HaltReason::StepLimit`] after
+    /// executing `steps` instructions.
+    pub fn run_for(&mut self, steps: u64) -> Result<HaltReason, VmError> {
+        self.run_limited(steps, |_| false)
+    }
+
+    fn run_limited(&mut self, max_steps: u64, stop: impl FnMut(&VM) -> bool) -> Result<HaltReason, VmError> {
+        let result = self.run_until_stopped(max_steps, stop);
+        self.output.flush()?;
+        if let Some(trace) = &mut self.trace {
+            Write::flush(trace)?;
+        }
+        result
+    }
+
+    fn run_until_stopped(&mut self, max_steps: u64, mut stop: impl FnMut(&VM) -> bool) -> Result<HaltReason, VmError> {
+        let limit = self.steps.saturating_add(max_steps);
+        loop {
+            if self.halted {
+                return Ok(HaltReason::Halted(self.halt_cause));
+            }
+            if self.steps >= limit {
+                return Ok(HaltReason::StepLimit);
+            }
+            if self.fuel.is_exhausted() {
+                return Ok(HaltReason::OutOfFuel);
+            }
+            if self.interrupt.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed) && flag.swap(false, Ordering::SeqCst)) {
+                return Ok(HaltReason::Interrupted);
+            }
+            let ip = self.instruction_ptr;
+            if self.breakpoints.contains(&ip) && self.suspended_at != Some(ip) {
+                self.suspended_at = Some(ip);
+                return Ok(HaltReason::Breakpoint(ip));
+            }
+            let resumed_here = self.suspended_at == Some(ip);
+            match self.step() {
+                Ok(VmEvent::NeedsInput) => return Err(VmError::InputExhausted { address: ip }),
+                Err(VmError::InvalidOpcode { address, opcode })
+                    if self.opcode_policy == OpcodePolicy::Break && !resumed_here =>
+                {
+                    self.suspended_at = Some(ip);
+                    return Ok(HaltReason::InvalidOpcode { address, opcode });
+                },
+                result => {
+                    result?;
+                },
+            }
+            if let Some(hit) = self.watch_hit.take() {
+                return Ok(HaltReason::Watchpoint(hit));
+            }
+            if stop(self) {
+                return Ok(HaltReason::Condition);
+            }
+        }
+    }
+
+    /// Executes the single instruction at the instruction pointer, ignoring
+    /// breakpoints, and reports what the embedder needs to know about it. A
+    /// triggered watchpoint is available from
+    /// [`take_watch_hit`](Self::take_watch_hit) afterwards. An `in` that
+    /// finds no input reports [`VmEvent::NeedsInput`] and leaves no mark in
+    /// the trace, profile, coverage, or recent instructions; only hooks see
+    /// it again when it is retried.
+    pub fn step(&mut self) -> Result<VmEvent, VmError> {
+        if self.halted {
+            return Ok(VmEvent::Halted);
+        }
+        self.suspended_at = None;
+        self.watch_hit = None;
+        if !self.syscalls.is_empty() && self.try_syscall()? {
+            return Ok(if self.halted { VmEvent::Halted } else { VmEvent::Continued });
+        }
+        let operation = match self.fetch_operation() {
+            Err(VmError::InvalidOpcode { .. }) if self.opcode_policy == OpcodePolicy::Noop => Operation::Noop,
+            result => result?,
+        };
+        let address = self.instruction_ptr;
+        let registers = self.registers;
+        // An `in` that finds no input runs again once there is some, so it is
+        // only instrumented once it has read.
+        let reads_input = matches!(operation, Operation::In(_));
+        if !reads_input {
+            self.instrument(address, operation, registers)?;
+        }
+        if !self.hooks.is_empty() {
+            self.run_hooks(address, &operation, false);
+        }
+        let undo = self.history.is_some().then(|| self.undo_record(&operation));
+        let event = match operation {
+            Operation::Out(value) => VmEvent::Output(self.get_value(value) as u8),
+            _ => VmEvent::Continued,
+        };
+        match self.execute_operation(operation) {
+            Ok(()) => (),
+            Err(VmError::InputExhausted { .. }) => {
+                // Nothing executed, so resuming should not stop at a
+                // breakpoint here again.
+                self.suspended_at = Some(address);
+                return Ok(VmEvent::NeedsInput);
+            },
+            Err(err) => return Err(err),
+        }
+        if reads_input {
+            self.instrument(address, operation, registers)?;
+        }
+        self.steps += 1;
+        if let (Some(profile), Operation::Jt(..) | Operation::Jf(..)) = (&mut self.profile, operation) {
+            if self.instruction_ptr != address.wrapping_add(3) {
+                profile.record_taken(address);
+            }
+        }
+        if self.fuel.is_metering() {
+            self.burn_fuel(operation.opcode());
+        }
+        if !self.hooks.is_empty() {
+            self.run_hooks(address, &operation, true);
+        }
+        if let Some(undo) = undo {
+            self.push_undo_record(undo, &operation);
+        }
+        if self.checkpoints.is_some() {
+            self.maybe_checkpoint();
+        }
+        Ok(if self.halted { VmEvent::Halted } else { event })
+    }
+
+    /// Queues `bytes` for `in`, ahead of anything from the input source.
+    pub fn provide_input(&mut self, bytes: &[u8]) {
+        self.provided_input.extend(bytes);
+    }
+
+    /// Starts counting executions per address and per opcode (discarding any
+    /// previous counts), or stops with `false`.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = enabled.then(Profile::default);
+    }
+
+    /// The counts gathered since profiling was enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Starts recording which addresses execute and which `rmem` reads
+    /// (discarding anything recorded before), or stops with `false`.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(Coverage::default);
+    }
+
+    /// Starts keeping the last `capacity` instructions executed (discarding
+    /// any kept before), or stops with `None`.
+    pub fn set_recent_instructions(&mut self, capacity: Option<usize>) {
+        self.recent = capacity.map(RecentInstructions::new);
+    }
+
+    /// The last instructions executed, oldest first, if they are being kept.
+    pub fn recent_instructions(&self) -> Option<&RecentInstructions> {
+        self.recent.as_ref()
+    }
+
+    /// Starts recording which words execute as code and every later write
+    /// to one, and the first run of each instruction whose words were
+    /// written (discarding any previous record), or stops with `false`. Each
+    /// is also logged at debug level, writes with the instruction before and
+    /// after them.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{encode_image, VM};
+    ///
+    /// let mut vm = VM::new(std::io::empty(), Vec::new());
+    /// // 0: out 'a'; wmem 1 'b'; jt r0 13; set r0 1; jmp 0; 13: halt
+    /// vm.load(&encode_image(&[19, 97, 16, 1, 98, 7, 32_768, 13, 1, 32_768, 1, 6, 0, 0])).unwrap();
+    /// vm.set_self_modification_tracking(true);
+    /// vm.run().unwrap();
+    /// let tracker = vm.self_modification().unwrap();
+    /// let write = tracker.writes()[0];
+    /// assert_eq!((write.writer, write.target, write.instruction), (2, 1, 0));
+    /// assert_eq!((write.before.unwrap().to_string(), write.after.unwrap().to_string()), ("out 97".into(), "out 98".into()));
+    /// assert_eq!(tracker.runs()[0].address, 0);
+    /// ```
+    pub fn set_self_modification_tracking(&mut self, enabled: bool) {
+        self.self_modification = enabled.then(SelfModification::default);
+    }
+
+    /// The writes over executed code since tracking was enabled.
+    pub fn self_modification(&self) -> Option<&SelfModification> {
+        self.self_modification.as_ref()
+    }
+
+    /// The addresses executed and read since coverage tracking was enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts looking for challenge codes in the output (discarding any found
+    /// before), or stops with `false`.
+    pub fn set_code_scanning(&mut self, enabled: bool) {
+        self.code_scanner = enabled.then(CodeScanner::default);
+    }
+
+    /// The codes seen since scanning was enabled.
+    pub fn code_scanner(&self) -> Option<&CodeScanner> {
+        self.code_scanner.as_ref()
+    }
+
+    pub fn code_scanner_mut(&mut self) -> Option<&mut CodeScanner> {
+        self.code_scanner.as_mut()
+    }
+
+    /// Starts counting executed calls by caller, call site, and target
+    /// (discarding any previous counts), or stops with `false`.
+    pub fn set_call_logging(&mut self, enabled: bool) {
+        self.call_log = enabled.then(BTreeMap::new);
+    }
+
+    /// The calls counted since call logging was enabled.
+    pub fn call_log(&self) -> Option<&BTreeMap<ObservedCall, u64>> {
+        self.call_log.as_ref()
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Logs every executed instruction to `trace`, or stops logging with `None`.
+    /// Each line holds the address, the decoded instruction, and the values of
+    /// the registers it reads.
+    pub fn set_trace(&mut self, trace: Option<Box<dyn Write>>) {
+        self.trace = trace;
+    }
+
+    /// Names addresses in the trace: a `name:` line before each instruction
+    /// at a named address, and the names of jump and call targets.
+    pub fn set_trace_symbols(&mut self, symbols: Symbols) {
+        self.trace_symbols = symbols;
+    }
+
+    /// Records the instruction at `address`, about to run (or, for an `in`,
+    /// just run) with `registers`, in the trace and whichever of the profile,
+    /// coverage, recent instructions, and self-modification tracker are on.
+    #[inline]
+    fn instrument(&mut self, address: u16, operation: Operation, registers: [u16; 8]) -> io::Result<()> {
+        if self.trace.is_some() {
+            self.trace_operation(address, operation)?;
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record(address, &operation);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address);
+        }
+        if let Some(recent) = &mut self.recent {
+            recent.record(Executed { step: self.steps, address, operation, registers });
+        }
+        if let Some(tracker) = &mut self.self_modification {
+            if let Some(run) = tracker.executed(self.steps, address, &operation) {
+                let operation = run.operation.to_string();
+                log::event(Level::Debug, "vm", "written code run", &[("address", &run.address), ("operation", &operation), ("writer", &run.writer)]);
+            }
+        }
+        Ok(())
+    }
+
+    fn trace_operation(&mut self, address: u16, operation: Operation) -> io::Result<()> {
+        let mut line = format!("{address:5}: {operation}");
+        let comments: Vec<String> = self.trace_symbols.target(&operation).map(str::to_string).into_iter()
+            .chain(operation.sources().into_iter()
+                .filter(|&operand| Self::register_idx(operand).is_some())
+                .map(|operand| format!("{}={}", format_operand(operand), self.get_value(operand))))
+            .collect();
+        if !comments.is_empty() {
+            line = format!("{line:<32} ; {}", comments.join(" "));
+        }
+        if let Some(name) = self.trace_symbols.name(address) {
+            line = format!("{name}:\n{line}");
+        }
+        let trace = self.trace.as_mut().expect("Tracing should be enabled.");
+        trace.write_all(format!("{line}\n").as_bytes())
+    }
+
+    /// Decodes the instruction at the instruction pointer without executing it.
+    pub fn next_operation(&self) -> Result<Operation, VmError> {
+        self.parse_next_operation()
+    }
+
+    /// Decodes the instruction at `address`, or `None` if the words there do
+    /// not form a valid instruction.
+    pub fn operation_at(&self, address: u16) -> Option<Operation> {
+        let words: Vec<u16> = (0..4)
+            .map_while(|offset| self.mem.get(address.wrapping_add(offset)))
+            .collect();
+        Operation::decode(&words)
+    }
+
+    /// Flushes any buffered `out` bytes.
+    pub fn flush_output(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Stops `run` before the instruction at `address` executes. Returns `false`
+    /// if a breakpoint was already set there.
+    pub fn set_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Removes the breakpoint at `address`, returning whether one was set.
+    pub fn clear_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// The breakpoint addresses, in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Suspends `run` after writes to the watched location. Returns `false` if
+    /// the watchpoint already exists.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Removes a watchpoint, returning whether it existed.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|existing| *existing != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    /// The watchpoints, in the order they were added.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The watchpoint triggered by the last [`step`](Self::step), if any.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// The number of `call`s executed minus the number of `ret`s that returned.
+    /// Goes negative if the program returns from the frame it started in.
+    pub fn call_depth(&self) -> i32 {
+        self.call_depth
+    }
+
+    /// The shadow call stack of `call`s that have not returned, outermost first.
+    /// It is kept separately from the data stack, so it survives programs that
+    /// push and pop around their calls; each `ret` pops one frame.
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// The address of the next instruction to execute.
+    pub fn instruction_ptr(&self) -> u16 {
+        self.instruction_ptr
+    }
+
+    /// Empties the stack and the shadow call stack and zeroes the call depth,
+    /// so code started with [`set_instruction_ptr`](Self::set_instruction_ptr)
+    /// runs as if called from the top level: its `ret` halts with
+    /// [`HaltCause::EmptyStackReturn`]. Undo history is discarded, as it is
+    /// relative to the old stacks.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// // call 4; halt; halt; ret
+    /// vm.load(&[17, 0, 4, 0, 0, 0, 0, 0, 18, 0]).unwrap();
+    /// vm.step().unwrap();
+    /// assert_eq!((vm.call_depth(), vm.call_stack().len()), (1, 1));
+    /// vm.reset_call_state();
+    /// assert_eq!((vm.call_depth(), vm.call_stack().len(), vm.stack().len()), (0, 0, 0));
+    /// ```
+    pub fn reset_call_state(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.call_depth = 0;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /// Moves execution to `address`.
+    pub fn set_instruction_ptr(&mut self, address: u16) {
+        self.instruction_ptr = address;
+    }
+
+    /// Registers `r0..=r7`.
+    pub fn registers(&self) -> &[u16; 8] {
+        &self.registers
+    }
+
+    /// Mutable access to registers `r0..=r7`.
+    pub fn registers_mut(&mut self) -> &mut [u16; 8] {
+        &mut self.registers
+    }
+
+    /// The word at `address`, or `None` if it has never been written.
+    pub fn memory(&self, address: u16) -> Option<u16> {
+        self.mem.get(address)
+    }
+
+    /// All of memory as a flat image, with never-written words as zero.
+    pub fn memory_image(&self) -> Vec<u16> {
+        (0..MEMORY_SIZE as u16)
+            .map(|address| self.mem.get(address).unwrap_or(0))
+            .collect()
+    }
+
+    /// Writes `value` to `address`. Returns `false` if `address` is outside the
+    /// 15-bit address space.
+    pub fn set_memory(&mut self, address: u16, value: u16) -> bool {
+        self.mem.set(address, value)
+    }
+
+    /// The stack, bottom first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Mutable access to the stack, bottom first.
+    pub fn stack_mut(&mut self) -> &mut Vec<u16> {
+        &mut self.stack
+    }
+
+    /// Whether a `halt` (or `ret` on an empty stack) has been executed.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// What halted the machine, if it has halted.
+    pub fn halt_cause(&self) -> Option<HaltCause> {
+        self.halted.then_some(self.halt_cause)
+    }
+
+    fn halt(&mut self, cause: HaltCause) {
+        self.halted = true;
+        self.halt_cause = cause;
+    }
+
+    /// The instruction at the instruction pointer, from the decode cache when
+    /// it is enabled.
+    #[inline]
+    fn fetch_operation(&mut self) -> Result<Operation, VmError> {
+        if !self.decode_cache {
+            return self.parse_next_operation();
+        }
+        let address = self.instruction_ptr;
+        if let Some(operation) = self.mem.decoded(address) {
+            return Ok(operation);
+        }
+        let operation = self.parse_next_operation()?;
+        self.mem.cache_decoded(address, operation);
+        Ok(operation)
+    }
+
+    fn parse_next_operation(&self) -> Result<Operation, VmError> {
+        let address = self.instruction_ptr;
+        let mut args = [0; 3];
+        let opcode = self.read_memory(address)?;
+        let invalid_opcode = VmError::InvalidOpcode { address, opcode };
+        let num_arguments = Operation::num_arguments(opcode).ok_or(invalid_opcode)?;
+        for i in 0..num_arguments {
+            args[i as usize] = self.read_memory(address.wrapping_add(1 + i))?;
+        }
+        Operation::new(opcode, &args[..num_arguments as usize]).map_err(|err| VmError::from_decode(address, err))
+    }
+
+    fn read_memory(&self, target: u16) -> Result<u16, VmError> {
+        self.mem.get(target)
+            .ok_or(VmError::UninitializedMemory { address: self.instruction_ptr, target })
+    }
+
+    /// Executes `operation`, the instruction at the instruction pointer. Each
+    /// handler reads its operands once and yields the address to continue
+    /// at, so dispatch is a single `match`.
+    fn execute_operation(&mut self, operation: Operation) -> Result<(), VmError> {
+        let ip = self.instruction_ptr;
+        let next = match operation {
+            Operation::Halt => {
+                self.halt(HaltCause::Instruction);
+                ip
+            },
+            Operation::Set(register, a) => {
+                self.set_register(register, self.get_value(a))?;
+                ip + 3
+            },
+            Operation::Push(a) => {
+                self.stack.push(self.get_value(a));
+                ip + 2
+            },
+            Operation::Pop(register) => {
+                let value = self.stack.pop().ok_or(VmError::EmptyStack { address: ip })?;
+                self.set_register(register, value)?;
+                ip + 2
+            },
+            Operation::Eq(register, a, b) => {
+                self.set_register(register, (self.get_value(a) == self.get_value(b)) as u16)?;
+                ip + 4
+            },
+            Operation::Gt(register, a, b) => {
+                self.set_register(register, (self.get_value(a) > self.get_value(b)) as u16)?;
+                ip + 4
+            },
+            Operation::Jmp(target) => self.get_value(target),
+            Operation::Jt(a, target) => match self.get_value(a) {
+                0 => ip + 3,
+                _ => self.get_value(target),
+            },
+            Operation::Jf(a, target) => match self.get_value(a) {
+                0 => self.get_value(target),
+                _ => ip + 3,
+            },
+            Operation::Add(register, a, b) => {
+                let sum = self.get_value(a) as u32 + self.get_value(b) as u32;
+                self.set_register(register, (sum % 32_768) as u16)?;
+                ip + 4
+            },
+            Operation::Mult(register, a, b) => {
+                let product = self.get_value(a) as u32 * self.get_value(b) as u32;
+                self.set_register(register, (product % 32_768) as u16)?;
+                ip + 4
+            },
+            Operation::Mod(register, a, b) => {
+                let dividend = self.get_value(a);
+                let remainder = match (self.get_value(b), self.arithmetic_policy) {
+                    (0, ArithmeticPolicy::Fault) => return Err(VmError::DivideByZero { address: ip }),
+                    (0, ArithmeticPolicy::Saturate) => 32_767,
+                    (0, ArithmeticPolicy::Wrap) => dividend,
+                    (divisor, _) => dividend % divisor,
+                };
+                self.set_register(register, remainder)?;
+                ip + 4
+            },
+            Operation::And(register, a, b) => {
+                self.set_register(register, self.get_value(a) & self.get_value(b))?;
+                ip + 4
+            },
+            Operation::Or(register, a, b) => {
+                self.set_register(register, self.get_value(a) | self.get_value(b))?;
+                ip + 4
+            },
+            Operation::Not(register, a) => {
+                self.set_register(register, !self.get_value(a) & 0x7fff)?;
+                ip + 3
+            },
+            Operation::Rmem(register, read_address) => {
+                let target = self.get_value(read_address);
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record_read(target);
+                }
+                let value = match self.devices.device_at(target) {
+                    Some((device, offset)) => device.read(offset)?,
+                    None => self.read_memory(target)?,
+                };
+                if self.strict && value > 32_775 {
+                    return Err(VmError::InvalidNumber { address: ip, value });
+                }
+                self.set_register(register, value)?;
+                ip + 3
+            },
+            Operation::Wmem(write_address, a) => {
+                self.write_memory(self.get_value(write_address), self.get_value(a))?;
+                ip + 3
+            },
+            Operation::Call(address) => {
+                let target = self.get_value(address);
+                if let Some(log) = &mut self.call_log {
+                    let caller = self.frames.last().map_or(0, |frame| frame.target);
+                    *log.entry(ObservedCall { caller, call_site: ip, target }).or_default() += 1;
+                }
+                self.stack.push(ip + 2);
+                self.frames.push(Frame { call_site: ip, target, return_address: ip + 2 });
+                self.call_depth += 1;
+                target
+            },
+            Operation::Ret => match self.stack.pop() {
+                Some(next) => {
+                    self.call_depth -= 1;
+                    self.frames.pop();
+                    next
+                },
+                None => {
+                    self.halt(HaltCause::EmptyStackReturn);
+                    ip
+                },
+            },
+            Operation::Out(a) => {
+                let value = self.get_value(a);
+                let value: u8 = value.try_into()
+                    .map_err(|_| VmError::InvalidCharacter { address: ip, value })?;
+                self.output.write_bytes(&[value])?;
+                if let Some(scanner) = &mut self.code_scanner {
+                    scanner.push(value, ip, self.steps);
+                }
+                ip + 2
+            },
+            Operation::In(register) => {
+                self.output.flush()?;
+                let byte = loop {
+                    break match (self.read_input_byte(), self.eof_policy) {
+                        (Err(VmError::Io(err)), _) if err.kind() == io::ErrorKind::WouldBlock => {
+                            match self.idle.as_mut().map(|idle| idle()) {
+                                Some(ControlFlow::Continue(())) => continue,
+                                _ => return Err(VmError::InputExhausted { address: ip }),
+                            }
+                        },
+                        (Err(VmError::InputExhausted { .. }), EofPolicy::Halt) => {
+                            self.halt(HaltCause::EndOfInput);
+                            return Ok(());
+                        },
+                        (Err(VmError::InputExhausted { .. }), EofPolicy::Value(value)) => Some(value),
+                        (result, _) => result?.map(u16::from),
+                    };
+                };
+                match byte {
+                    Some(value) => {
+                        self.set_register(register, value)?;
+                        ip + 2
+                    },
+                    // A meta-command consumed the line. Stay on the `in` so it
+                    // (or whatever a restored state holds there) executes again.
+                    None => self.instruction_ptr,
+                }
+            },
+            Operation::Noop => ip + 1,
+        };
+        self.instruction_ptr = next;
+        Ok(())
+    }
+
+    /// `wmem`: writes `value` to `target`, or to the device mapped there, and
+    /// notes a watchpoint hit.
+    fn write_memory(&mut self, target: u16, value: u16) -> Result<(), VmError> {
+        let ip = self.instruction_ptr;
+        let old = if let Some((device, offset)) = self.devices.device_at(target) {
+            device.write(offset, value)?;
+            None
+        } else {
+            let old = self.mem.get(target);
+            if !self.mem.set(target, value) {
+                return Err(VmError::InvalidAddress { address: ip, target });
+            }
+            if let Some(tracker) = &mut self.self_modification {
+                tracker.written(ip, target);
+                if let Some(instruction) = tracker.owner(target) {
+                    self.record_code_write(instruction, target, old, value);
+                }
+            }
+            old
+        };
+        if self.watchpoints.iter().any(|watchpoint| watchpoint.covers_memory(target)) {
+            self.watch_hit = Some(WatchHit { address: ip, target: WatchTarget::Memory(target), old, new: value });
+        }
+        Ok(())
+    }
+
+    /// Records and logs a write of `new` over `old` at `target`, part of the
+    /// executed instruction at `instruction`.
+    fn record_code_write(&mut self, instruction: u16, target: u16, old: Option<u16>, new: u16) {
+        let words: Vec<u16> = (0..4)
+            .map(|offset| instruction.wrapping_add(offset))
+            .map_while(|address| if address == target { old } else { self.mem.get(address) })
+            .collect();
+        let write = CodeWrite {
+            step: self.steps,
+            writer: self.instruction_ptr,
+            target,
+            instruction,
+            old,
+            new,
+            before: Operation::decode(&words),
+            after: self.operation_at(instruction),
+        };
+        let show = |operation: Option<Operation>| operation.map_or("<invalid>".to_string(), |operation| operation.to_string());
+        log::event(
+            Level::Debug,
+            "vm",
+            "code modified",
+            &[
+                ("writer", &write.writer),
+                ("target", &target),
+                ("instruction", &instruction),
+                ("before", &show(write.before)),
+                ("after", &show(write.after)),
+            ],
+        );
+        if let Some(tracker) = &mut self.self_modification {
+            tracker.record(write);
+        }
+    }
+
+    /// The next input byte, or `None` if the line read was a meta-command.
+    fn read_input_byte(&mut self) -> Result<Option<u8>, VmError> {
+        if self.pending_input.is_empty() {
+            self.read_input_line()?;
+            if let Some(config) = &self.meta {
+                if self.pending_input.front() == Some(&config.sigil) {
+                    let line: Vec<u8> = self.pending_input.drain(..).skip(1).collect();
+                    self.run_meta_command(&String::from_utf8_lossy(&line))?;
+                    return Ok(None);
+                }
+            }
+            if let Some(scanner) = &mut self.code_scanner {
+                let line: Vec<u8> = self.pending_input.iter().copied().collect();
+                scanner.input(&String::from_utf8_lossy(&line), self.instruction_ptr, self.steps);
+            }
+        }
+        Ok(self.pending_input.pop_front())
+    }
+
+    /// Reads up to and including the next newline into `pending_input`. If
+    /// the source would block partway through a line, the part read so far
+    /// is put back for the next attempt.
+    fn read_input_line(&mut self) -> Result<(), VmError> {
+        loop {
+            let byte = match self.provided_input.pop_front() {
+                Some(byte) => byte,
+                None => match self.input.read_byte() {
+                    Ok(Some(byte)) => byte,
+                    Ok(None) => break,
+                    Err(err) => {
+                        for byte in self.pending_input.drain(..).rev() {
+                            self.provided_input.push_front(byte);
+                        }
+                        return Err(err.into());
+                    },
+                },
+            };
+            self.pending_input.push_back(byte);
+            if byte == b'\n' {
+                break;
+            }
+        }
+        if self.pending_input.is_empty() {
+            return Err(VmError::InputExhausted { address: self.instruction_ptr });
+        }
+        Ok(())
+    }
+
+    fn register_idx(value: u16) -> Option<usize> {
+        if !(32_768..=32_775).contains(&value) {
+            return None;
+        }
+        Some((value % 32_768).into())
+    }
+
+    fn set_register(&mut self, operand: u16, value: u16) -> Result<(), VmError> {
+        let register_idx = Self::register_idx(operand)
+            .ok_or(VmError::InvalidRegister { address: self.instruction_ptr, operand })?;
+        let old = self.registers[register_idx];
+        self.registers[register_idx] = value;
+        let register = register_idx as u8;
+        if old != value && self.watchpoints.contains(&Watchpoint::Register(register)) {
+            self.watch_hit = Some(WatchHit {
+                address: self.instruction_ptr,
+                target: WatchTarget::Register(register),
+                old: Some(old),
+                new: value,
+            });
+        }
+        Ok(())
+    }
+
+    fn get_value(&self, value: u16) -> u16 {
+        if let Some(val_register) = Self::register_idx(value) {
+            self.registers[val_register]
+        } else {
+            value
+        }
+    }
+}
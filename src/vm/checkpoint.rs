@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+use super::{Snapshot, VM};
+
+/// How often [`VM`] takes automatic checkpoints and how many it keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointConfig {
+    /// Take a checkpoint whenever the step count is a multiple of this.
+    pub interval: u64,
+    /// The number of most recent checkpoints to keep.
+    pub capacity: usize,
+}
+
+#[derive(Clone)]
+pub(super) struct Checkpoints {
+    config: CheckpointConfig,
+    ring: VecDeque<Snapshot>,
+}
+
+impl VM {
+    /// Starts taking automatic checkpoints, or stops and discards them with
+    /// `None`.
+    pub fn set_checkpoints(&mut self, config: Option<CheckpointConfig>) {
+        self.checkpoints = config
+            .filter(|config| config.interval > 0 && config.capacity > 0)
+            .map(|config| Checkpoints { config, ring: VecDeque::new() });
+    }
+
+    /// The step counts at which the kept checkpoints were taken, most recent
+    /// first.
+    pub fn checkpoint_steps(&self) -> Vec<u64> {
+        self.checkpoints.as_ref()
+            .map(|checkpoints| checkpoints.ring.iter().rev().map(Snapshot::steps).collect())
+            .unwrap_or_default()
+    }
+
+    /// Restores the `k`th most recent checkpoint (1 is the newest) and discards
+    /// any newer ones. Input that was consumed since then is not replayed.
+    /// Returns `false` if there are fewer than `k` checkpoints.
+    pub fn rollback(&mut self, k: usize) -> bool {
+        let Some(checkpoints) = &mut self.checkpoints else {
+            return false;
+        };
+        if k == 0 || k > checkpoints.ring.len() {
+            return false;
+        }
+        checkpoints.ring.truncate(checkpoints.ring.len() - k + 1);
+        let snapshot = checkpoints.ring.back().cloned().expect("Checkpoint should exist.");
+        self.restore(snapshot);
+        true
+    }
+
+    #[inline]
+    pub(super) fn maybe_checkpoint(&mut self) {
+        let due = matches!(&self.checkpoints, Some(checkpoints) if self.steps.is_multiple_of(checkpoints.config.interval));
+        if due {
+            let snapshot = self.snapshot();
+            let checkpoints = self.checkpoints.as_mut().expect("Checkpoints should be enabled.");
+            if checkpoints.ring.len() == checkpoints.config.capacity {
+                checkpoints.ring.pop_front();
+            }
+            checkpoints.ring.push_back(snapshot);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Core dumps: the state of a machine that faulted, with the instructions
+//! that led up to the fault, saved to be inspected later.
+
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{RecentInstructions, Snapshot, VmError, VM};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CoreDump {
+    /// The state when the fault was raised. The instruction pointer is at
+    /// the instruction that faulted.
+    pub snapshot: Snapshot,
+    /// The last instructions executed, if they were being kept.
+    pub recent: Option<RecentInstructions>,
+    /// What went wrong.
+    pub fault: String,
+}
+
+impl CoreDump {
+    /// Captures `vm` as it stands after faulting with `fault`.
+    pub fn capture(vm: &VM, fault: &str) -> Self {
+        CoreDump {
+            snapshot: vm.snapshot(),
+            recent: vm.recent_instructions().cloned(),
+            fault: fault.to_string(),
+        }
+    }
+
+    /// Writes the dump to `path` in bincode format.
+    pub fn save(&self, path: &Path) -> Result<(), VmError> {
+        let writer = BufWriter::new(fs::File::create(path)?);
+        bincode::serialize_into(writer, self).map_err(VmError::from)
+    }
+
+    /// Reads a dump written by [`save`](Self::save).
+    pub fn load(path: &Path) -> Result<Self, VmError> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        bincode::deserialize_from(reader).map_err(VmError::from)
+    }
+}
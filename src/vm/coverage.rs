@@ -0,0 +1,102 @@
+use std::io::{self, Write};
+
+use super::{MEMORY_SIZE, VM};
+use crate::disasm::{self, Line};
+
+/// The set of addresses an instruction has been executed from, and the set
+/// of addresses `rmem` has read, while coverage tracking is enabled.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    executed: Box<[u64]>,
+    read: Box<[u64]>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            executed: vec![0; MEMORY_SIZE / 64].into_boxed_slice(),
+            read: vec![0; MEMORY_SIZE / 64].into_boxed_slice(),
+        }
+    }
+}
+
+fn set(bits: &mut [u64], address: u16) {
+    let idx = address as usize;
+    if idx < MEMORY_SIZE {
+        bits[idx / 64] |= 1 << (idx % 64);
+    }
+}
+
+fn get(bits: &[u64], address: u16) -> bool {
+    let idx = address as usize;
+    idx < MEMORY_SIZE && bits[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+impl Coverage {
+    #[inline]
+    pub(super) fn record(&mut self, address: u16) {
+        set(&mut self.executed, address);
+    }
+
+    #[inline]
+    pub(super) fn record_read(&mut self, address: u16) {
+        set(&mut self.read, address);
+    }
+
+    /// Whether an instruction starting at `address` has executed.
+    pub fn is_executed(&self, address: u16) -> bool {
+        get(&self.executed, address)
+    }
+
+    /// Whether `rmem` has read `address`.
+    pub fn is_read(&self, address: u16) -> bool {
+        get(&self.read, address)
+    }
+
+    /// The executed instruction addresses in ascending order.
+    pub fn addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..MEMORY_SIZE as u16).filter(|&address| self.is_executed(address))
+    }
+
+    /// Writes a summary, the gaps between executed instructions, and the
+    /// loaded part of `vm`'s memory disassembled with each line marked `+` if
+    /// it executed and `-` if not.
+    pub fn report(&self, vm: &VM, out: &mut dyn Write) -> io::Result<()> {
+        let words: Vec<u16> = (0..MEMORY_SIZE as u16).map_while(|address| vm.memory(address)).collect();
+        let mut instructions = 0;
+        let mut covered = 0;
+        let mut gaps = Vec::new();
+        let mut end: Option<u16> = None;
+        for address in self.addresses() {
+            if let Some(end) = end.filter(|&end| end < address) {
+                gaps.push((end, address - 1));
+            }
+            let size = vm.operation_at(address).map_or(1, |operation| operation.size());
+            instructions += 1;
+            covered += size as usize;
+            let next = address.saturating_add(size);
+            end = Some(end.map_or(next, |end| end.max(next)));
+        }
+        writeln!(out, "instructions executed: {instructions} distinct addresses, {covered} words")?;
+        writeln!(out, "memory loaded: {} words", words.len())?;
+        writeln!(out)?;
+        writeln!(out, "unexecuted gaps between executed code:")?;
+        for (start, last) in &gaps {
+            writeln!(out, "  {start:5}..={last:<5} {:>6} words", last - start + 1)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "annotated disassembly (+ executed, - not executed):")?;
+        let mut address = 0;
+        while let Some(mut line) = disasm::disassemble_at(&words, address) {
+            // Keep an unexecuted decode from swallowing the start of an
+            // executed instruction, so the sweep stays aligned with real code.
+            let executed = self.is_executed(address);
+            if !executed && (1..line.words.len() as u16).any(|k| self.is_executed(address + k)) {
+                line = Line { address, words: vec![line.words[0]], operation: None };
+            }
+            writeln!(out, "{} {line}", if executed { '+' } else { '-' })?;
+            address += line.words.len() as u16;
+        }
+        Ok(())
+    }
+}
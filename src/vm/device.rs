@@ -0,0 +1,94 @@
+use super::{VmError, MEMORY_SIZE, VM};
+
+/// A peripheral that `rmem` and `wmem` reach through a range of addresses
+/// mapped with [`VM::map_device`].
+///
+/// ```
+/// use oscon_2012_vm_challenge::vm::{Device, VmError, VM};
+///
+/// /// Counts up by one on every read.
+/// struct Timer(u16);
+///
+/// impl Device for Timer {
+///     fn read(&mut self, _offset: u16) -> Result<u16, VmError> {
+///         self.0 = (self.0 + 1) % 32_768;
+///         Ok(self.0)
+///     }
+///
+///     fn write(&mut self, _offset: u16, value: u16) -> Result<(), VmError> {
+///         self.0 = value;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // rmem r0 30000; rmem r0 30000; halt
+/// vm.load(&[15, 0, 0, 128, 48, 117, 15, 0, 0, 128, 48, 117, 0, 0]).unwrap();
+/// vm.map_device(30_000, 30_000, Timer(0));
+/// vm.run().unwrap();
+/// assert_eq!(vm.registers()[0], 2);
+/// ```
+pub trait Device {
+    /// The word at `offset` from the start of the mapped range.
+    fn read(&mut self, offset: u16) -> Result<u16, VmError>;
+
+    /// Stores `value` at `offset` from the start of the mapped range.
+    fn write(&mut self, offset: u16, value: u16) -> Result<(), VmError>;
+}
+
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: Box<dyn Device>,
+}
+
+/// The devices mapped into the address space, by range.
+#[derive(Default)]
+pub(super) struct MemoryMap {
+    mappings: Vec<Mapping>,
+}
+
+impl MemoryMap {
+    /// The device mapped at `address` and the offset of `address` within its
+    /// range.
+    pub(super) fn device_at(&mut self, address: u16) -> Option<(&mut (dyn Device + 'static), u16)> {
+        self.mappings.iter_mut()
+            .find(|mapping| (mapping.start..=mapping.end).contains(&address))
+            .map(|mapping| (mapping.device.as_mut(), address - mapping.start))
+    }
+
+    /// Whether a device is mapped at `address`.
+    pub(super) fn is_mapped(&self, address: u16) -> bool {
+        self.mappings.iter().any(|mapping| (mapping.start..=mapping.end).contains(&address))
+    }
+}
+
+impl VM {
+    /// Routes `rmem` and `wmem` on `start..=end` to `device` instead of
+    /// memory. Instructions are still fetched from memory, and device state is
+    /// not part of snapshots or undo history: stepping back over a `wmem` to
+    /// a device leaves both the device and the memory behind it as they are.
+    /// Returns `false`, mapping nothing,
+    /// if the range is empty, runs past the end of memory, or overlaps a
+    /// device already mapped.
+    pub fn map_device(&mut self, start: u16, end: u16, device: impl Device + 'static) -> bool {
+        let overlaps = self.devices.mappings.iter()
+            .any(|mapping| start <= mapping.end && mapping.start <= end);
+        if start > end || end as usize >= MEMORY_SIZE || overlaps {
+            return false;
+        }
+        self.devices.mappings.push(Mapping { start, end, device: Box::new(device) });
+        true
+    }
+
+    /// Unmaps the device whose range starts at `start`, returning it.
+    pub fn unmap_device(&mut self, start: u16) -> Option<Box<dyn Device>> {
+        let idx = self.devices.mappings.iter().position(|mapping| mapping.start == start)?;
+        Some(self.devices.mappings.remove(idx).device)
+    }
+
+    /// The mapped address ranges, in the order they were mapped.
+    pub fn device_ranges(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.devices.mappings.iter().map(|mapping| (mapping.start, mapping.end))
+    }
+}
@@ -0,0 +1,112 @@
+use std::{error, fmt, io};
+
+use super::DecodeError;
+
+/// A fault raised while loading or executing a program.
+#[derive(Debug)]
+pub enum VmError {
+    /// The word at `address` is not a known opcode.
+    InvalidOpcode { address: u16, opcode: u16 },
+    /// An operand that must name a register held something else.
+    InvalidRegister { address: u16, operand: u16 },
+    /// An operand was above 32775, so neither a number nor a register, or in
+    /// strict mode `rmem` read such a word.
+    InvalidNumber { address: u16, value: u16 },
+    /// `pop` was executed with nothing on the stack.
+    EmptyStack { address: u16 },
+    /// The instruction at `address` read a memory word that was never written.
+    UninitializedMemory { address: u16, target: u16 },
+    /// The instruction at `address` wrote outside the 15-bit address space.
+    InvalidAddress { address: u16, target: u16 },
+    /// `mod` was asked to divide by zero.
+    DivideByZero { address: u16 },
+    /// `out` was given a value that is not a byte.
+    InvalidCharacter { address: u16, value: u16 },
+    /// The image does not fit in the 15-bit address space or has a dangling byte.
+    InvalidImage { len: usize },
+    /// A text image could not be decoded.
+    InvalidEncoding(String),
+    /// `in` was executed after the input reached end of file.
+    InputExhausted { address: u16 },
+    /// A saved state could not be decoded.
+    InvalidSnapshot(String),
+    /// Reading the binary or performing program I/O failed.
+    Io(io::Error),
+}
+
+impl VmError {
+    /// The fault for the instruction at `address` failing to decode.
+    pub(super) fn from_decode(address: u16, err: DecodeError) -> Self {
+        match err {
+            DecodeError::InvalidOpcode { opcode } => VmError::InvalidOpcode { address, opcode },
+            DecodeError::InvalidOperand { word, .. } => VmError::InvalidNumber { address, value: word },
+            DecodeError::NotARegister { word, .. } => VmError::InvalidRegister { address, operand: word },
+        }
+    }
+
+    /// The address of the faulting instruction, if the error came from execution.
+    pub fn address(&self) -> Option<u16> {
+        match self {
+            VmError::InvalidOpcode { address, .. }
+            | VmError::InvalidRegister { address, .. }
+            | VmError::InvalidNumber { address, .. }
+            | VmError::EmptyStack { address }
+            | VmError::UninitializedMemory { address, .. }
+            | VmError::InvalidAddress { address, .. }
+            | VmError::DivideByZero { address }
+            | VmError::InvalidCharacter { address, .. }
+            | VmError::InputExhausted { address } => Some(*address),
+            VmError::InvalidImage { .. }
+            | VmError::InvalidEncoding(_)
+            | VmError::InvalidSnapshot(_)
+            | VmError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode { address, opcode } => {
+                write!(f, "invalid opcode {opcode} at address {address}")
+            },
+            VmError::InvalidRegister { address, operand } => {
+                write!(f, "operand {operand} at address {address} is not a register")
+            },
+            VmError::InvalidNumber { address, value } => {
+                write!(f, "value {value} at address {address} is neither a number nor a register")
+            },
+            VmError::EmptyStack { address } => write!(f, "pop from empty stack at address {address}"),
+            VmError::UninitializedMemory { address, target } => {
+                write!(f, "read of uninitialized memory {target} at address {address}")
+            },
+            VmError::InvalidAddress { address, target } => {
+                write!(f, "write to invalid address {target} at address {address}")
+            },
+            VmError::DivideByZero { address } => write!(f, "division by zero at address {address}"),
+            VmError::InvalidCharacter { address, value } => {
+                write!(f, "cannot output value {value} as a character at address {address}")
+            },
+            VmError::InvalidImage { len } => write!(f, "invalid image of {len} bytes"),
+            VmError::InvalidEncoding(message) => write!(f, "invalid image: {message}"),
+            VmError::InputExhausted { address } => write!(f, "input exhausted at address {address}"),
+            VmError::InvalidSnapshot(message) => write!(f, "invalid saved state: {message}"),
+            VmError::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl error::Error for VmError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            VmError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(err: io::Error) -> Self {
+        VmError::Io(err)
+    }
+}
@@ -0,0 +1,78 @@
+use super::{HaltReason, VmError, VM};
+
+/// Per-opcode costs and the budget of the current [`VM::run_with_fuel`].
+#[derive(Clone)]
+pub(super) struct Fuel {
+    costs: [u64; 22],
+    // `None` outside `run_with_fuel`.
+    remaining: Option<u64>,
+    consumed: u64,
+}
+
+impl Default for Fuel {
+    fn default() -> Self {
+        Self { costs: [1; 22], remaining: None, consumed: 0 }
+    }
+}
+
+impl Fuel {
+    #[inline]
+    pub(super) fn is_metering(&self) -> bool {
+        self.remaining.is_some()
+    }
+
+    #[inline]
+    pub(super) fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+impl VM {
+    /// Sets the fuel the instruction with `opcode` costs under
+    /// [`run_with_fuel`](Self::run_with_fuel); every instruction costs 1 by
+    /// default, and a syscall always costs 1. Returns `false` if `opcode` is
+    /// not a built-in opcode.
+    pub fn set_fuel_cost(&mut self, opcode: u16, cost: u64) -> bool {
+        match self.fuel.costs.get_mut(opcode as usize) {
+            Some(entry) => {
+                *entry = cost;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Like [`run`](Self::run), but charges each instruction its fuel cost and
+    /// stops with [`HaltReason::OutOfFuel`] once `fuel` is used up. Returns
+    /// why the run stopped and how much fuel it consumed. An instruction runs
+    /// as long as any fuel is left, so the last one may take the total past
+    /// `fuel`.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{HaltReason, VM};
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// // loop: jmp loop
+    /// vm.load(&[6, 0, 0, 0]).unwrap();
+    /// vm.set_fuel_cost(6, 3);
+    /// assert_eq!(vm.run_with_fuel(10).unwrap(), (HaltReason::OutOfFuel, 12));
+    /// assert_eq!(vm.steps(), 4);
+    /// ```
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Result<(HaltReason, u64), VmError> {
+        self.fuel.remaining = Some(fuel);
+        self.fuel.consumed = 0;
+        let result = self.run();
+        self.fuel.remaining = None;
+        Ok((result?, self.fuel.consumed))
+    }
+
+    /// Charges for an executed instruction during `run_with_fuel`. Syscalls
+    /// cost 1.
+    pub(super) fn burn_fuel(&mut self, opcode: u16) {
+        if let Some(remaining) = &mut self.fuel.remaining {
+            let cost = self.fuel.costs.get(opcode as usize).copied().unwrap_or(1);
+            *remaining = remaining.saturating_sub(cost);
+            self.fuel.consumed += cost;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+use super::{Frame, Operation, Snapshot, VM};
+
+/// What it takes to undo one instruction. Registers are small enough to copy
+/// whole; everything else records only the single value an instruction can
+/// change. A syscall can change anything, so its record keeps the whole
+/// state from before it instead.
+#[derive(Clone)]
+pub(super) struct UndoRecord {
+    instruction_ptr: u16,
+    registers: [u16; 8],
+    halted: bool,
+    call_depth: i32,
+    stack_len: usize,
+    stack_top: Option<u16>,
+    frames_len: usize,
+    frame_top: Option<Frame>,
+    // The written address and its previous value, for a `wmem` to memory
+    // rather than a device.
+    memory: Option<(u16, Option<u16>)>,
+    // The byte consumed by `in`, returned to the input on undo.
+    input: Option<u8>,
+    // The state before a syscall.
+    state: Option<Box<Snapshot>>,
+}
+
+/// A bounded log of undo records, oldest first.
+#[derive(Clone)]
+pub(super) struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize,
+}
+
+impl History {
+    pub(super) fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl VM {
+    /// Starts recording undo information for up to `capacity` instructions so
+    /// they can be reversed with [`step_back`](Self::step_back), or stops and
+    /// discards the recording with `None`. Output that was already written is
+    /// not taken back.
+    pub fn set_recording(&mut self, capacity: Option<usize>) {
+        self.history = capacity.map(|capacity| History { records: VecDeque::new(), capacity });
+    }
+
+    /// Whether undo information is being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// The number of instructions that can currently be reversed.
+    pub fn recorded_steps(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.records.len())
+    }
+
+    /// Reverses the most recently executed instruction. Returns `false` if
+    /// there is nothing recorded to reverse.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.as_mut().and_then(|history| history.records.pop_back()) else {
+            return false;
+        };
+        self.suspended_at = None;
+        self.watch_hit = None;
+        if let Some(state) = record.state {
+            self.instruction_ptr = state.instruction_ptr;
+            self.registers = state.registers;
+            self.mem = state.mem;
+            self.stack = state.stack;
+            self.halted = state.halted;
+            self.call_depth = state.call_depth;
+            self.frames = state.frames;
+            self.steps = state.steps;
+            return true;
+        }
+        self.instruction_ptr = record.instruction_ptr;
+        self.registers = record.registers;
+        self.halted = record.halted;
+        self.call_depth = record.call_depth;
+        self.stack.truncate(record.stack_len);
+        if self.stack.len() < record.stack_len {
+            self.stack.extend(record.stack_top);
+        }
+        self.frames.truncate(record.frames_len);
+        if self.frames.len() < record.frames_len {
+            self.frames.extend(record.frame_top);
+        }
+        match record.memory {
+            Some((address, Some(old))) => { self.mem.set(address, old); },
+            Some((address, None)) => self.mem.clear(address),
+            None => (),
+        }
+        if let Some(byte) = record.input {
+            self.pending_input.push_front(byte);
+        }
+        self.steps -= 1;
+        true
+    }
+
+    /// Steps backwards until the instruction pointer reaches a breakpoint or
+    /// the recording is exhausted. Returns the breakpoint address, if one was
+    /// reached.
+    pub fn run_back(&mut self) -> Option<u16> {
+        while self.step_back() {
+            if self.breakpoints.contains(&self.instruction_ptr) {
+                return Some(self.instruction_ptr);
+            }
+        }
+        None
+    }
+
+    pub(super) fn undo_record(&self, operation: &Operation) -> UndoRecord {
+        let memory = match *operation {
+            Operation::Wmem(address, _) => {
+                let address = self.get_value(address);
+                (!self.devices.is_mapped(address)).then(|| (address, self.mem.get(address)))
+            },
+            _ => None,
+        };
+        UndoRecord {
+            instruction_ptr: self.instruction_ptr,
+            registers: self.registers,
+            halted: self.halted,
+            call_depth: self.call_depth,
+            stack_len: self.stack.len(),
+            stack_top: self.stack.last().copied(),
+            frames_len: self.frames.len(),
+            frame_top: self.frames.last().copied(),
+            memory,
+            input: None,
+            state: None,
+        }
+    }
+
+    /// The record for the syscall about to run at the instruction pointer.
+    pub(super) fn syscall_undo_record(&self) -> UndoRecord {
+        UndoRecord { state: Some(Box::new(self.snapshot())), ..self.undo_record(&Operation::Noop) }
+    }
+
+    pub(super) fn push_undo_record(&mut self, mut record: UndoRecord, operation: &Operation) {
+        if let Operation::In(register) = *operation {
+            // An `in` that ran a meta-command consumed no input and changed no
+            // program state, so there is nothing to undo.
+            if self.instruction_ptr != record.instruction_ptr.wrapping_add(2) {
+                return;
+            }
+            record.input = Some(self.get_value(register) as u8);
+        }
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        if history.records.len() == history.capacity {
+            history.records.pop_front();
+        }
+        if history.capacity > 0 {
+            history.records.push_back(record);
+        }
+    }
+}
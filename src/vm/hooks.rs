@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Operation, VM};
+
+/// An observer of every instruction the VM executes, registered with
+/// [`VM::add_hook`]. Both methods default to doing nothing. Syscalls are not
+/// instructions, and hooks are not called for them; see
+/// [`VM::register_syscall`].
+///
+/// ```
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use oscon_2012_vm_challenge::vm::{Hook, Operation, VM};
+///
+/// #[derive(Default)]
+/// struct CallCounter(u64);
+///
+/// impl Hook for CallCounter {
+///     fn before_op(&mut self, _vm: &VM, _address: u16, operation: &Operation) {
+///         if let Operation::Call(_) = operation {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+///
+/// let counter = Rc::new(RefCell::new(CallCounter::default()));
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // call 4; halt; halt; ret
+/// vm.load(&[17, 0, 4, 0, 0, 0, 0, 0, 18, 0]).unwrap();
+/// vm.add_hook(counter.clone());
+/// vm.run().unwrap();
+/// assert_eq!(counter.borrow().0, 1);
+/// ```
+pub trait Hook {
+    /// Called with the instruction at `address` just before it executes.
+    fn before_op(&mut self, _vm: &VM, _address: u16, _operation: &Operation) {}
+
+    /// Called after the instruction at `address` has executed, with the VM in
+    /// its new state. Not called for an `in` that found no input, which runs
+    /// (and calls `before_op`) again once input is provided.
+    fn after_op(&mut self, _vm: &VM, _address: u16, _operation: &Operation) {}
+}
+
+/// Lets the caller keep a handle on a hook's state while the VM runs it.
+impl<H: Hook + ?Sized> Hook for Rc<RefCell<H>> {
+    fn before_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        self.borrow_mut().before_op(vm, address, operation);
+    }
+
+    fn after_op(&mut self, vm: &VM, address: u16, operation: &Operation) {
+        self.borrow_mut().after_op(vm, address, operation);
+    }
+}
+
+/// Identifies a registered hook, for [`VM::remove_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+#[derive(Default)]
+pub(super) struct Hooks {
+    hooks: Vec<(HookId, Box<dyn Hook>)>,
+    next_id: u64,
+}
+
+impl Hooks {
+    pub(super) fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl VM {
+    /// Registers `hook` to observe every instruction from now on. Hooks run in
+    /// the order they were added.
+    pub fn add_hook(&mut self, hook: impl Hook + 'static) -> HookId {
+        let id = HookId(self.hooks.next_id);
+        self.hooks.next_id += 1;
+        self.hooks.hooks.push((id, Box::new(hook)));
+        id
+    }
+
+    /// Unregisters a hook, returning it if it was registered.
+    pub fn remove_hook(&mut self, id: HookId) -> Option<Box<dyn Hook>> {
+        let idx = self.hooks.hooks.iter().position(|(existing, _)| *existing == id)?;
+        Some(self.hooks.hooks.remove(idx).1)
+    }
+
+    pub(super) fn run_hooks(&mut self, address: u16, operation: &Operation, after: bool) {
+        // Hooks see the VM immutably, so they are moved out while they run.
+        let mut hooks = std::mem::take(&mut self.hooks.hooks);
+        for (_, hook) in &mut hooks {
+            if after {
+                hook.after_op(self, address, operation);
+            } else {
+                hook.before_op(self, address, operation);
+            }
+        }
+        self.hooks.hooks = hooks;
+    }
+}
@@ -0,0 +1,181 @@
+//! The encodings a program image can be read in. However it is written, an
+//! image decodes to the raw little-endian words the VM loads.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{VmError, MEMORY_SIZE};
+use crate::base64;
+
+/// How a program image is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    /// Whichever of the others [`detect`](Self::detect) recognizes.
+    #[default]
+    Auto,
+    /// Raw little-endian words, as the challenge binary is distributed.
+    Binary,
+    /// One hexadecimal word per whitespace-separated token, optionally
+    /// `0x`-prefixed, with `#` comments.
+    Hex,
+    /// Intel HEX records, addressed in bytes.
+    IntelHex,
+    /// The raw binary in standard base64.
+    Base64,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "auto" => Ok(ImageFormat::Auto),
+            "binary" | "bin" => Ok(ImageFormat::Binary),
+            "hex" => Ok(ImageFormat::Hex),
+            "ihex" => Ok(ImageFormat::IntelHex),
+            "base64" => Ok(ImageFormat::Base64),
+            _ => Err(format!("expected `auto`, `binary`, `hex`, `ihex`, or `base64`, not `{text}`")),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImageFormat::Auto => "auto",
+            ImageFormat::Binary => "binary",
+            ImageFormat::Hex => "hex",
+            ImageFormat::IntelHex => "ihex",
+            ImageFormat::Base64 => "base64",
+        })
+    }
+}
+
+impl ImageFormat {
+    /// Guesses the format of `data`. Anything that is not printable text is
+    /// binary; text starting with `:` is Intel HEX; text that parses as hex
+    /// words is hex, and otherwise base64 if it decodes as such.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::ImageFormat;
+    ///
+    /// assert_eq!(ImageFormat::detect(&[21, 0, 0, 0]), ImageFormat::Binary);
+    /// assert_eq!(ImageFormat::detect(b"0013 0041\n0000\n"), ImageFormat::Hex);
+    /// assert_eq!(ImageFormat::detect(b":0400000013004100A8\n:00000001FF\n"), ImageFormat::IntelHex);
+    /// assert_eq!(ImageFormat::detect(b"EwBBAAAA\n"), ImageFormat::Base64);
+    /// ```
+    pub fn detect(data: &[u8]) -> ImageFormat {
+        let Ok(text) = std::str::from_utf8(data) else {
+            return ImageFormat::Binary;
+        };
+        if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace()) {
+            ImageFormat::Binary
+        } else if text.trim_start().starts_with(':') {
+            ImageFormat::IntelHex
+        } else if decode_hex(text).is_ok() {
+            ImageFormat::Hex
+        } else if base64::decode(text).is_some() {
+            ImageFormat::Base64
+        } else {
+            ImageFormat::Binary
+        }
+    }
+
+    /// Decodes `data` into a little-endian binary image.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::ImageFormat;
+    ///
+    /// let binary = vec![19, 0, 65, 0, 0, 0];
+    /// assert_eq!(ImageFormat::Hex.decode(b"0x13 41 # out 'A'\n0\n").unwrap(), binary);
+    /// assert_eq!(ImageFormat::IntelHex.decode(b":06000000130041000000A6\n:00000001FF\n").unwrap(), binary);
+    /// assert_eq!(ImageFormat::Base64.decode(b"EwBBAAAA").unwrap(), binary);
+    /// assert_eq!(ImageFormat::Auto.decode(&binary).unwrap(), binary);
+    /// assert!(ImageFormat::IntelHex.decode(b":06000000130041000000A7\n").is_err());
+    /// ```
+    pub fn decode(self, data: &[u8]) -> Result<Vec<u8>, VmError> {
+        let format = match self {
+            ImageFormat::Auto => ImageFormat::detect(data),
+            format => format,
+        };
+        if format == ImageFormat::Binary {
+            return Ok(data.to_vec());
+        }
+        let text = std::str::from_utf8(data).map_err(|_| VmError::InvalidEncoding(format!("{format} image is not text")))?;
+        let decoded = match format {
+            ImageFormat::Hex => decode_hex(text),
+            ImageFormat::IntelHex => decode_intel_hex(text),
+            _ => base64::decode(text).ok_or_else(|| "invalid base64".to_string()),
+        };
+        decoded.map_err(|message| VmError::InvalidEncoding(format!("{format}: {message}")))
+    }
+}
+
+/// Reads the image at `path` in `format` and decodes it to a little-endian
+/// binary image.
+pub fn read_image(path: &Path, format: ImageFormat) -> Result<Vec<u8>, VmError> {
+    format.decode(&fs::read(path)?)
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut image = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        for token in line.split('#').next().unwrap_or_default().split_whitespace() {
+            let digits = token.strip_prefix("0x").unwrap_or(token);
+            let word = match digits.len() {
+                1..=4 => u16::from_str_radix(digits, 16).ok(),
+                _ => None,
+            };
+            let word = word.ok_or_else(|| format!("line {}: `{token}` is not a hex word", idx + 1))?;
+            image.extend(word.to_le_bytes());
+        }
+    }
+    Ok(image)
+}
+
+fn decode_intel_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut image = Vec::new();
+    let mut base = 0;
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |message: &str| format!("line {}: {message}", idx + 1);
+        let record = line.strip_prefix(':').ok_or_else(|| err("expected a record starting with `:`"))?;
+        let bytes = (0..record.len())
+            .step_by(2)
+            .map(|idx| record.get(idx..idx + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| err("invalid hex digits"))?;
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(err("wrong record length"));
+        }
+        if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(err("bad checksum"));
+        }
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        match (bytes[3], data) {
+            (0x00, _) => {
+                let start = base + address;
+                if start + data.len() > 2 * MEMORY_SIZE {
+                    return Err(err("data past the end of memory"));
+                }
+                if image.len() < start + data.len() {
+                    image.resize(start + data.len(), 0);
+                }
+                image[start..start + data.len()].copy_from_slice(data);
+            },
+            (0x01, _) => break,
+            (0x02, &[high, low]) => base = (u16::from_be_bytes([high, low]) as usize) << 4,
+            (0x04, &[high, low]) => base = (u16::from_be_bytes([high, low]) as usize) << 16,
+            (0x02 | 0x04, _) => return Err(err("an extended address record needs 2 bytes")),
+            // Start addresses mean nothing here: execution starts at 0.
+            _ => (),
+        }
+    }
+    Ok(image)
+}
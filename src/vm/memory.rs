@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::Operation;
+
+/// The number of addressable words in the 15-bit address space.
+pub const MEMORY_SIZE: usize = 32_768;
+
+/// The most words an instruction occupies.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+/// The number of words in a page, the unit copied when a shared page is
+/// first written.
+const PAGE_SIZE: usize = 1_024;
+
+const PAGE_COUNT: usize = MEMORY_SIZE / PAGE_SIZE;
+
+#[derive(Clone)]
+struct Page {
+    words: [u16; PAGE_SIZE],
+    written: [u64; PAGE_SIZE / 64],
+}
+
+const EMPTY_PAGE: Page = Page { words: [0; PAGE_SIZE], written: [0; PAGE_SIZE / 64] };
+
+/// Word-addressed memory. Tracks which addresses have been written so reads
+/// of never-written words can still be reported, and caches the instructions
+/// decoded from it.
+///
+/// Memory is split into pages shared between copies: cloning copies only the
+/// page pointers, and a page is copied the first time one of its owners
+/// writes to it. Forking a VM for a search therefore costs a few hundred
+/// bytes plus the pages the fork actually changes.
+#[derive(Serialize, Deserialize)]
+#[serde(from = "FlatMemory", into = "FlatMemory")]
+pub struct Memory {
+    pages: Box<[Arc<Page>; PAGE_COUNT]>,
+    // The instruction decoded at each address, grown to cover the highest
+    // address decoded so far. A write clears every entry whose instruction
+    // could cover the word.
+    decoded: Vec<Option<Operation>>,
+}
+
+/// The serialized form of [`Memory`], unchanged since memory was flat so
+/// older state files still load.
+#[derive(Serialize, Deserialize)]
+struct FlatMemory {
+    words: Box<[u16]>,
+    written: Box<[u64]>,
+}
+
+impl From<Memory> for FlatMemory {
+    fn from(memory: Memory) -> Self {
+        Self {
+            words: memory.pages.iter().flat_map(|page| page.words).collect(),
+            written: memory.pages.iter().flat_map(|page| page.written).collect(),
+        }
+    }
+}
+
+impl From<FlatMemory> for Memory {
+    fn from(flat: FlatMemory) -> Self {
+        let mut memory = Memory::default();
+        let (words, written) = (flat.words.chunks(PAGE_SIZE), flat.written.chunks(PAGE_SIZE / 64));
+        for ((page, words), written) in memory.pages.iter_mut().zip(words).zip(written) {
+            let page = Arc::make_mut(page);
+            page.words[..words.len()].copy_from_slice(words);
+            page.written[..written.len()].copy_from_slice(written);
+        }
+        memory
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        let empty = Arc::new(EMPTY_PAGE);
+        Self { pages: Box::new(std::array::from_fn(|_| empty.clone())), decoded: Vec::new() }
+    }
+}
+
+/// Shares every page; the copy decodes afresh. Snapshots clone memory often
+/// and rarely execute from it.
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        Self { pages: self.pages.clone(), decoded: Vec::new() }
+    }
+}
+
+impl Memory {
+    /// The word at `address`, or `None` if it is out of range or was never written.
+    #[inline]
+    pub fn get(&self, address: u16) -> Option<u16> {
+        let idx = address as usize;
+        if idx >= MEMORY_SIZE {
+            return None;
+        }
+        let (page, offset) = (&self.pages[idx / PAGE_SIZE], idx % PAGE_SIZE);
+        (page.written[offset / 64] & (1 << (offset % 64)) != 0).then(|| page.words[offset])
+    }
+
+    /// Writes `value` to `address`. Returns `false` if `address` is out of range.
+    #[inline]
+    pub fn set(&mut self, address: u16, value: u16) -> bool {
+        let idx = address as usize;
+        if idx >= MEMORY_SIZE {
+            return false;
+        }
+        let (page, offset) = (Arc::make_mut(&mut self.pages[idx / PAGE_SIZE]), idx % PAGE_SIZE);
+        page.words[offset] = value;
+        page.written[offset / 64] |= 1 << (offset % 64);
+        self.invalidate(idx);
+        true
+    }
+
+    /// Marks `address` as never written.
+    pub fn clear(&mut self, address: u16) {
+        let idx = address as usize;
+        if idx < MEMORY_SIZE {
+            let (page, offset) = (Arc::make_mut(&mut self.pages[idx / PAGE_SIZE]), idx % PAGE_SIZE);
+            page.words[offset] = 0;
+            page.written[offset / 64] &= !(1 << (offset % 64));
+            self.invalidate(idx);
+        }
+    }
+
+    /// The cached decoding of the instruction at `address`, if any.
+    #[inline]
+    pub fn decoded(&self, address: u16) -> Option<Operation> {
+        self.decoded.get(address as usize).copied().flatten()
+    }
+
+    /// Caches `operation` as the decoding of the words at `address`.
+    pub fn cache_decoded(&mut self, address: u16, operation: Operation) {
+        let idx = address as usize;
+        if idx >= MEMORY_SIZE {
+            return;
+        }
+        if idx >= self.decoded.len() {
+            self.decoded.resize(idx + 1, None);
+        }
+        self.decoded[idx] = Some(operation);
+    }
+
+    /// Forgets the decodings of any instruction that could include `idx`.
+    #[inline]
+    fn invalidate(&mut self, idx: usize) {
+        let first = idx.saturating_sub(MAX_INSTRUCTION_SIZE - 1);
+        let end = (idx + 1).min(self.decoded.len());
+        if first < end {
+            self.decoded[first..end].fill(None);
+        }
+    }
+}
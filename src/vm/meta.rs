@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use super::{HaltCause, VmError, VM};
+
+const HELP: &str = "\
+meta-commands:
+  {sigil}save <slot>   save the game to <slot>.state
+  {sigil}load <slot>   restore the game from <slot>.state
+  {sigil}rollback [k]  restore the kth most recent checkpoint (default 1)
+  {sigil}regs          show the instruction pointer and registers
+  {sigil}quit          stop the VM
+  {sigil}help          show this message
+";
+
+/// Settings for host commands typed at the game's input prompt.
+#[derive(Debug, Clone)]
+pub struct MetaConfig {
+    /// Input lines starting with this byte are meta-commands.
+    pub sigil: u8,
+    /// Where save slots are stored.
+    pub save_dir: PathBuf,
+}
+
+impl Default for MetaConfig {
+    fn default() -> Self {
+        Self { sigil: b'!', save_dir: PathBuf::from(".") }
+    }
+}
+
+impl VM {
+    /// Executes a meta-command line (without its sigil), reporting the result
+    /// on the VM's output.
+    pub(super) fn run_meta_command(&mut self, line: &str) -> Result<(), VmError> {
+        let config = self.meta.clone().expect("Meta-commands should be enabled.");
+        let slot_path = |slot: &str| config.save_dir.join(format!("{slot}.state"));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["save", slot] => {
+                let path = slot_path(slot);
+                match self.save_state(&path) {
+                    Ok(()) => writeln!(self.output, "Saved to {}.", path.display())?,
+                    Err(err) => writeln!(self.output, "Could not save to {}: {err}", path.display())?,
+                }
+            },
+            ["load", slot] => {
+                let path = slot_path(slot);
+                match self.load_state(&path) {
+                    Ok(()) => writeln!(self.output, "Loaded {}.", path.display())?,
+                    Err(err) => writeln!(self.output, "Could not load {}: {err}", path.display())?,
+                }
+            },
+            ["rollback"] => self.meta_rollback(1)?,
+            ["rollback", k] => match k.parse() {
+                Ok(k) => self.meta_rollback(k)?,
+                Err(_) => writeln!(self.output, "Invalid checkpoint number {k}.")?,
+            },
+            ["regs"] => {
+                write!(self.output, "ip={}", self.instruction_ptr)?;
+                for (idx, value) in self.registers.iter().enumerate() {
+                    write!(self.output, " r{idx}={value}")?;
+                }
+                writeln!(self.output)?;
+            },
+            ["quit"] => self.halt(HaltCause::UserQuit),
+            ["help"] => write!(self.output, "{}", HELP.replace("{sigil}", &(config.sigil as char).to_string()))?,
+            _ => writeln!(self.output, "Unknown meta-command; try {}help.", config.sigil as char)?,
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn meta_rollback(&mut self, k: usize) -> Result<(), VmError> {
+        if self.rollback(k) {
+            writeln!(self.output, "Rolled back to step {}.", self.steps)?;
+        } else {
+            writeln!(self.output, "No checkpoint {k}; {} available.", self.checkpoint_steps().len())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use std::{error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// A single decoded instruction. Arguments are raw words: literals `0..=32767`
+/// or registers `32768..=32775`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    Halt,
+    Set(u16, u16),
+    Push(u16),
+    Pop(u16),
+    Eq(u16, u16, u16),
+    Gt(u16, u16, u16),
+    Jmp(u16),
+    Jt(u16, u16),
+    Jf(u16, u16),
+    Add(u16, u16, u16),
+    Mult(u16, u16, u16),
+    Mod(u16, u16, u16),
+    And(u16, u16, u16),
+    Or(u16, u16, u16),
+    Not(u16, u16),
+    Rmem(u16, u16),
+    Wmem(u16, u16),
+    Call(u16),
+    Ret,
+    Out(u16),
+    In(u16),
+    Noop,
+}
+
+/// Why words do not form a valid instruction. Operands are numbered from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The first word is not a known opcode.
+    InvalidOpcode { opcode: u16 },
+    /// An operand is above 32775, so neither a number nor a register.
+    InvalidOperand { idx: usize, word: u16 },
+    /// An operand the instruction writes to is not a register.
+    NotARegister { idx: usize, word: u16 },
+}
+
+impl DecodeError {
+    /// The offending word.
+    pub fn word(&self) -> u16 {
+        match *self {
+            DecodeError::InvalidOpcode { opcode } => opcode,
+            DecodeError::InvalidOperand { word, .. } | DecodeError::NotARegister { word, .. } => word,
+        }
+    }
+
+    /// How far the offending word is from the opcode, in words.
+    pub fn offset(&self) -> u16 {
+        match *self {
+            DecodeError::InvalidOpcode { .. } => 0,
+            DecodeError::InvalidOperand { idx, .. } | DecodeError::NotARegister { idx, .. } => 1 + idx as u16,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidOpcode { opcode } => write!(f, "invalid opcode {opcode}"),
+            DecodeError::InvalidOperand { idx, word } => {
+                write!(f, "operand {idx} is {word}, which is neither a number nor a register")
+            },
+            DecodeError::NotARegister { idx, word } => {
+                write!(f, "operand {idx} is written to but is {word}, not a register")
+            },
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl Operation {
+    /// Builds the operation for `opcode` from its argument words, checking
+    /// that each is a number or a register, and a register wherever the
+    /// operation writes. `args` must hold at least
+    /// [`num_arguments`](Self::num_arguments) words; any more are ignored.
+    pub fn new(opcode: u16, args: &[u16]) -> Result<Self, DecodeError> {
+        let operation = match opcode {
+            0 => Operation::Halt,
+            1 => Operation::Set(args[0], args[1]),
+            2 => Operation::Push(args[0]),
+            3 => Operation::Pop(args[0]),
+            4 => Operation::Eq(args[0], args[1], args[2]),
+            5 => Operation::Gt(args[0], args[1], args[2]),
+            6 => Operation::Jmp(args[0]),
+            7 => Operation::Jt(args[0], args[1]),
+            8 => Operation::Jf(args[0], args[1]),
+            9 => Operation::Add(args[0], args[1], args[2]),
+            10 => Operation::Mult(args[0], args[1], args[2]),
+            11 => Operation::Mod(args[0], args[1], args[2]),
+            12 => Operation::And(args[0], args[1], args[2]),
+            13 => Operation::Or(args[0], args[1], args[2]),
+            14 => Operation::Not(args[0], args[1]),
+            15 => Operation::Rmem(args[0], args[1]),
+            16 => Operation::Wmem(args[0], args[1]),
+            17 => Operation::Call(args[0]),
+            18 => Operation::Ret,
+            19 => Operation::Out(args[0]),
+            20 => Operation::In(args[0]),
+            21 => Operation::Noop,
+            _ => return Err(DecodeError::InvalidOpcode { opcode }),
+        };
+        let count = Self::num_arguments(opcode).unwrap_or(0) as usize;
+        if let Some((idx, &word)) = args[..count].iter().enumerate().find(|(_, &word)| word > 32_775) {
+            return Err(DecodeError::InvalidOperand { idx, word });
+        }
+        match operation.destination() {
+            Some(word) if word < 32_768 => Err(DecodeError::NotARegister { idx: 0, word }),
+            _ => Ok(operation),
+        }
+    }
+
+    /// The number of argument words that follow `opcode` in memory.
+    pub fn num_arguments(opcode: u16) -> Option<u16> {
+        match opcode {
+            0 | 18 | 21 => Some(0),
+            2 | 3 | 6 | 17 | 19 | 20 => Some(1),
+            1 | 7 | 8 | 14 | 15 | 16 => Some(2),
+            4 | 5 | 9 | 10 | 11 | 12 | 13 => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Decodes the operation at the start of `words`, or `None` if they do not
+    /// form a valid instruction (see [`new`](Self::new)) or its arguments are
+    /// cut off.
+    pub fn decode(words: &[u16]) -> Option<Self> {
+        let (&opcode, rest) = words.split_first()?;
+        let args = rest.get(..Self::num_arguments(opcode)? as usize)?;
+        Self::new(opcode, args).ok()
+    }
+
+    /// The numeric opcode.
+    pub fn opcode(&self) -> u16 {
+        match self {
+            Operation::Halt => 0,
+            Operation::Set(..) => 1,
+            Operation::Push(..) => 2,
+            Operation::Pop(..) => 3,
+            Operation::Eq(..) => 4,
+            Operation::Gt(..) => 5,
+            Operation::Jmp(..) => 6,
+            Operation::Jt(..) => 7,
+            Operation::Jf(..) => 8,
+            Operation::Add(..) => 9,
+            Operation::Mult(..) => 10,
+            Operation::Mod(..) => 11,
+            Operation::And(..) => 12,
+            Operation::Or(..) => 13,
+            Operation::Not(..) => 14,
+            Operation::Rmem(..) => 15,
+            Operation::Wmem(..) => 16,
+            Operation::Call(..) => 17,
+            Operation::Ret => 18,
+            Operation::Out(..) => 19,
+            Operation::In(..) => 20,
+            Operation::Noop => 21,
+        }
+    }
+
+    /// The assembly mnemonic, as used by the disassembler.
+    pub fn mnemonic(&self) -> &'static str {
+        Self::mnemonic_for(self.opcode()).expect("Every operation should have a mnemonic.")
+    }
+
+    /// The assembly mnemonic for `opcode`, or `None` if it is not a known opcode.
+    pub fn mnemonic_for(opcode: u16) -> Option<&'static str> {
+        MNEMONICS.get(opcode as usize).copied()
+    }
+
+    /// The argument words, in encoding order.
+    pub fn args(&self) -> Vec<u16> {
+        match *self {
+            Operation::Halt | Operation::Ret | Operation::Noop => vec![],
+            Operation::Push(a) | Operation::Pop(a) | Operation::Jmp(a) | Operation::Call(a)
+            | Operation::Out(a) | Operation::In(a) => vec![a],
+            Operation::Set(a, b) | Operation::Jt(a, b) | Operation::Jf(a, b) | Operation::Not(a, b)
+            | Operation::Rmem(a, b) | Operation::Wmem(a, b) => vec![a, b],
+            Operation::Eq(a, b, c) | Operation::Gt(a, b, c) | Operation::Add(a, b, c)
+            | Operation::Mult(a, b, c) | Operation::Mod(a, b, c) | Operation::And(a, b, c)
+            | Operation::Or(a, b, c) => vec![a, b, c],
+        }
+    }
+
+    /// The register operand the operation writes, if any.
+    pub fn destination(&self) -> Option<u16> {
+        match *self {
+            Operation::Set(a, _) | Operation::Pop(a) | Operation::Eq(a, _, _) | Operation::Gt(a, _, _)
+            | Operation::Add(a, _, _) | Operation::Mult(a, _, _) | Operation::Mod(a, _, _)
+            | Operation::And(a, _, _) | Operation::Or(a, _, _) | Operation::Not(a, _)
+            | Operation::Rmem(a, _) | Operation::In(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// The operands the operation reads as values, in encoding order.
+    pub fn sources(&self) -> Vec<u16> {
+        let mut args = self.args();
+        if self.destination().is_some() {
+            args.remove(0);
+        }
+        args
+    }
+
+    /// The number of words the operation occupies, including the opcode.
+    pub fn size(&self) -> u16 {
+        1 + self.args().len() as u16
+    }
+
+    /// The encoded words: the opcode followed by the arguments.
+    pub fn encode(&self) -> Vec<u16> {
+        let mut words = vec![self.opcode()];
+        words.extend(self.args());
+        words
+    }
+}
+
+const MNEMONICS: [&str; 22] = [
+    "halt", "set", "push", "pop", "eq", "gt", "jmp", "jt", "jf", "add", "mult",
+    "mod", "and", "or", "not", "rmem", "wmem", "call", "ret", "out", "in", "noop",
+];
+
+/// Formats an argument word: `r0`..`r7` for registers, decimal otherwise.
+pub fn format_operand(word: u16) -> String {
+    match word {
+        32_768..=32_775 => format!("r{}", word - 32_768),
+        _ => word.to_string(),
+    }
+}
+
+impl fmt::Display for Operation {
+    /// Formats as assembly, e.g. `set r0 4`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        for arg in self.args() {
+            write!(f, " {}", format_operand(arg))?;
+        }
+        Ok(())
+    }
+}
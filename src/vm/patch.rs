@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::{MEMORY_SIZE, VM};
+
+/// Words to overwrite starting at `address`, applied after a binary is loaded
+/// and before it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub address: u16,
+    pub words: Vec<u16>,
+}
+
+fn parse_word(text: &str) -> Option<u16> {
+    let text = text.trim();
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl FromStr for Patch {
+    type Err = String;
+
+    /// Parses `addr=value`, or `addr=v1,v2,...` for consecutive words. Numbers
+    /// may be decimal or `0x`-prefixed hex.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (address, values) = text.split_once('=')
+            .ok_or_else(|| format!("expected `addr=value`, got `{text}`"))?;
+        let address = parse_word(address)
+            .filter(|&address| (address as usize) < MEMORY_SIZE)
+            .ok_or_else(|| format!("invalid address `{}`", address.trim()))?;
+        let words = values.split(',')
+            .map(|value| parse_word(value).ok_or_else(|| format!("invalid value `{}`", value.trim())))
+            .collect::<Result<Vec<u16>, String>>()?;
+        if address as usize + words.len() > MEMORY_SIZE {
+            return Err(format!("patch at {address} runs past the end of memory"));
+        }
+        Ok(Patch { address, words })
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(u16::to_string).collect();
+        write!(f, "{}={}", self.address, words.join(","))
+    }
+}
+
+impl Patch {
+    /// Parses a patch manifest: one patch per line, with blank lines and
+    /// `#` comments ignored.
+    pub fn parse_manifest(text: &str) -> Result<Vec<Patch>, String> {
+        text.lines()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                (!line.is_empty()).then(|| line.parse().map_err(|err| format!("line {}: {err}", idx + 1)))
+            })
+            .collect()
+    }
+}
+
+impl VM {
+    /// Overwrites memory with `patch`.
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        for (address, &word) in (patch.address..).zip(&patch.words) {
+            self.mem.set(address, word);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::{Operation, MEMORY_SIZE, VM};
+use crate::analysis::functions::Functions;
+use crate::analysis::loops::{self, Loop, TripCount};
+
+/// Execution counts gathered while profiling is enabled.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    by_address: Vec<u64>,
+    by_opcode: [u64; 22],
+    // How many times the `jt` or `jf` at each address jumped.
+    taken: BTreeMap<u16, u64>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self { by_address: vec![0; MEMORY_SIZE], by_opcode: [0; 22], taken: BTreeMap::new() }
+    }
+}
+
+impl Profile {
+    #[inline]
+    pub(super) fn record(&mut self, address: u16, operation: &Operation) {
+        self.by_address[address as usize] += 1;
+        self.by_opcode[operation.opcode() as usize] += 1;
+    }
+
+    pub(super) fn record_taken(&mut self, address: u16) {
+        *self.taken.entry(address).or_default() += 1;
+    }
+
+    /// The total number of instructions counted.
+    pub fn total(&self) -> u64 {
+        self.by_opcode.iter().sum()
+    }
+
+    /// How many times the instruction at `address` executed.
+    pub fn count_at(&self, address: u16) -> u64 {
+        self.by_address.get(address as usize).copied().unwrap_or(0)
+    }
+
+    /// How many times the `jt` or `jf` at `address` jumped.
+    pub fn taken_at(&self, address: u16) -> u64 {
+        self.taken.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Executed addresses with their counts, hottest first.
+    pub fn hottest_addresses(&self) -> Vec<(u16, u64)> {
+        let mut counts: Vec<(u16, u64)> = self.by_address.iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(address, &count)| (address as u16, count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Executed opcodes with their counts, hottest first.
+    pub fn hottest_opcodes(&self) -> Vec<(u16, u64)> {
+        let mut counts: Vec<(u16, u64)> = self.by_opcode.iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(opcode, &count)| (opcode as u16, count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Instructions executed in each of `functions`, not counting the
+    /// functions they call, hottest first. Executed addresses outside every
+    /// function are counted under `None`.
+    pub fn by_function(&self, functions: &Functions) -> Vec<(Option<u16>, u64)> {
+        let mut counts = BTreeMap::new();
+        for (address, count) in self.hottest_addresses() {
+            let entry = functions.containing(address).map(|function| function.entry);
+            *counts.entry(entry).or_insert(0) += count;
+        }
+        let mut counts: Vec<(Option<u16>, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Writes per-opcode counts, then the `top` hottest functions and loops,
+    /// found in `vm`'s current memory, and the `top` hottest addresses,
+    /// disassembled against it.
+    pub fn report(&self, vm: &VM, top: usize, out: &mut dyn Write) -> io::Result<()> {
+        let total = self.total();
+        let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+        writeln!(out, "instructions executed: {total}")?;
+        writeln!(out)?;
+        writeln!(out, "by opcode:")?;
+        for (opcode, count) in self.hottest_opcodes() {
+            let mnemonic = Operation::mnemonic_for(opcode).unwrap_or("?");
+            writeln!(out, "  {mnemonic:<6} {count:>14} {:6.2}%", percent(count))?;
+        }
+        writeln!(out)?;
+        writeln!(out, "hottest functions:")?;
+        let entries: Vec<u16> = [0].into_iter().chain(vm.call_stack().iter().map(|frame| frame.target)).collect();
+        let functions = Functions::detect_including(&vm.memory_image(), &entries, vm.instruction_ptr());
+        for (entry, count) in self.by_function(&functions).into_iter().take(top) {
+            let entry = entry.map_or_else(|| "elsewhere".to_string(), |entry| format!("fn_{entry}"));
+            writeln!(out, "  {entry:<31} {count:>14} {:6.2}%", percent(count))?;
+        }
+        writeln!(out)?;
+        writeln!(out, "hottest loops (iterations, entries, average trips):")?;
+        let mut loops: Vec<(Loop, TripCount)> = loops::detect(&functions).into_iter()
+            .map(|found| {
+                let trips = found.trip_count(&functions.cfg, self);
+                (found, trips)
+            })
+            .filter(|(_, trips)| trips.iterations > 0)
+            .collect();
+        loops.sort_by(|a, b| b.1.iterations.cmp(&a.1.iterations).then(a.0.header.cmp(&b.0.header)));
+        for (found, trips) in loops.into_iter().take(top) {
+            writeln!(
+                out,
+                "  {:5} in fn_{:<5} depth {} {:>14} {:>10} {:>10.1}",
+                found.header, found.function, found.depth, trips.iterations, trips.entries, trips.average(),
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "hottest addresses:")?;
+        for (address, count) in self.hottest_addresses().into_iter().take(top) {
+            let operation = vm.operation_at(address)
+                .map_or_else(|| "<invalid>".to_string(), |operation| operation.to_string());
+            writeln!(out, "  {address:5}: {operation:<24} {count:>14} {:6.2}%", percent(count))?;
+        }
+        Ok(())
+    }
+}
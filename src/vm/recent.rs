@@ -0,0 +1,99 @@
+//! The last instructions executed, kept for looking back after a fault.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{format_operand, Operation};
+
+/// An instruction about to execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Executed {
+    /// The number of instructions executed before it.
+    pub step: u64,
+    pub address: u16,
+    pub operation: Operation,
+    /// The registers before the instruction ran, so the values of its
+    /// register operands can be shown.
+    pub registers: [u16; 8],
+}
+
+impl fmt::Display for Executed {
+    /// Writes the instruction followed by the registers it names and what
+    /// they held, e.g. `add r1 r0 1   r1=0 r0=7`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut shown = Vec::new();
+        let mut registers = String::new();
+        for operand in self.operation.args() {
+            if (32_768..=32_775).contains(&operand) && !shown.contains(&operand) {
+                let value = self.registers[(operand - 32_768) as usize];
+                registers.push_str(&format!(" {}={value}", format_operand(operand)));
+                shown.push(operand);
+            }
+        }
+        match registers.is_empty() {
+            true => write!(f, "{:>10} {:5}: {}", self.step, self.address, self.operation),
+            false => write!(f, "{:>10} {:5}: {:<20}{registers}", self.step, self.address, self.operation.to_string()),
+        }
+    }
+}
+
+/// A ring of the last instructions executed, oldest first.
+///
+/// ```
+/// use oscon_2012_vm_challenge::vm::{encode_image, VM};
+///
+/// let mut vm = VM::new(std::io::empty(), std::io::sink());
+/// // noop; set r0 4; add r0 r0 1; halt
+/// vm.load(&encode_image(&[21, 1, 32_768, 4, 9, 32_768, 32_768, 1, 0])).unwrap();
+/// vm.set_recent_instructions(Some(2));
+/// vm.run().unwrap();
+/// let recent: Vec<u16> = vm.recent_instructions().unwrap().iter().map(|executed| executed.address).collect();
+/// assert_eq!(recent, [4, 8]);
+/// let add = vm.recent_instructions().unwrap().iter().next().unwrap();
+/// assert_eq!(add.to_string().split_whitespace().collect::<Vec<_>>(), ["2", "4:", "add", "r0", "r0", "1", "r0=4"]);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentInstructions {
+    entries: Vec<Executed>,
+    capacity: usize,
+    // Where the next entry goes once the ring is full: the oldest.
+    next: usize,
+}
+
+impl RecentInstructions {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: Vec::with_capacity(capacity), capacity, next: 0 }
+    }
+
+    #[inline]
+    pub(super) fn record(&mut self, executed: Executed) {
+        if self.entries.len() < self.capacity {
+            self.entries.push(executed);
+        } else if self.capacity > 0 {
+            self.entries[self.next] = executed;
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Executed> {
+        self.entries[self.next..].iter().chain(&self.entries[..self.next])
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for RecentInstructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for executed in self.iter() {
+            writeln!(f, "{executed}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use super::{Operation, MEMORY_SIZE};
+
+/// Marks a word that no executed instruction covers, or that nothing has
+/// written.
+const NONE: u16 = u16::MAX;
+
+/// A write over a word that had already executed as part of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeWrite {
+    /// The step count when the write executed.
+    pub step: u64,
+    /// The address of the writing instruction.
+    pub writer: u16,
+    /// The word written.
+    pub target: u16,
+    /// Where the executed instruction covering `target` starts.
+    pub instruction: u16,
+    pub old: Option<u16>,
+    pub new: u16,
+    /// That instruction as it executed, and as it decodes after the write;
+    /// `None` where the words are not a valid instruction.
+    pub before: Option<Operation>,
+    pub after: Option<Operation>,
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |operation: Option<Operation>| operation.map_or("<invalid>".to_string(), |operation| operation.to_string());
+        let old = self.old.map_or("nothing".to_string(), |old| old.to_string());
+        write!(
+            f,
+            "step {}: {} wrote {} over {old} at {} (instruction at {}): {} -> {}",
+            self.step,
+            self.writer,
+            self.new,
+            self.target,
+            self.instruction,
+            show(self.before),
+            show(self.after),
+        )
+    }
+}
+
+/// The first execution of an instruction with words written since tracking
+/// started, such as code the program has just decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrittenCodeRun {
+    /// The step count when it executed.
+    pub step: u64,
+    pub address: u16,
+    pub operation: Operation,
+    /// The address of the instruction that last wrote one of its words.
+    pub writer: u16,
+}
+
+/// Records which words have executed as code and every later write to one,
+/// and the first run of each instruction built from written words, while
+/// self-modification tracking is enabled.
+#[derive(Debug, Clone)]
+pub struct SelfModification {
+    // For each word, the start of the last executed instruction covering it.
+    owner: Box<[u16]>,
+    // For each word, the last instruction to write it, until it executes.
+    writer: Box<[u16]>,
+    writes: Vec<CodeWrite>,
+    runs: Vec<WrittenCodeRun>,
+}
+
+impl Default for SelfModification {
+    fn default() -> Self {
+        Self {
+            owner: vec![NONE; MEMORY_SIZE].into_boxed_slice(),
+            writer: vec![NONE; MEMORY_SIZE].into_boxed_slice(),
+            writes: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+}
+
+impl SelfModification {
+    /// Notes that `operation` is executing at `address`, returning the
+    /// record of its first run since one of its words was written, if this
+    /// is that run.
+    pub(super) fn executed(&mut self, step: u64, address: u16, operation: &Operation) -> Option<WrittenCodeRun> {
+        let mut writer = None;
+        for offset in 0..operation.size() {
+            let idx = address.wrapping_add(offset) as usize;
+            if idx >= MEMORY_SIZE {
+                break;
+            }
+            self.owner[idx] = address;
+            if self.writer[idx] != NONE {
+                writer = Some(std::mem::replace(&mut self.writer[idx], NONE));
+            }
+        }
+        let run = WrittenCodeRun { step, address, operation: *operation, writer: writer? };
+        self.runs.push(run);
+        Some(run)
+    }
+
+    /// The start of the executed instruction covering `target`, if any.
+    pub(super) fn owner(&self, target: u16) -> Option<u16> {
+        self.owner.get(target as usize).copied().filter(|&owner| owner != NONE)
+    }
+
+    /// Notes that the instruction at `writer` wrote `target`.
+    pub(super) fn written(&mut self, writer: u16, target: u16) {
+        if let Some(slot) = self.writer.get_mut(target as usize) {
+            *slot = writer;
+        }
+    }
+
+    pub(super) fn record(&mut self, write: CodeWrite) {
+        self.writes.push(write);
+    }
+
+    /// Whether a word has executed as part of an instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.owner(address).is_some()
+    }
+
+    /// The writes over executed code, in the order they happened.
+    pub fn writes(&self) -> &[CodeWrite] {
+        &self.writes
+    }
+
+    /// The first runs of written instructions, in the order they happened.
+    pub fn runs(&self) -> &[WrittenCodeRun] {
+        &self.runs
+    }
+
+    /// Writes each write over executed code, then the written code that ran,
+    /// as ranges of adjacent instructions.
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut instructions: Vec<u16> = self.writes.iter().map(|write| write.instruction).collect();
+        instructions.sort_unstable();
+        instructions.dedup();
+        writeln!(out, "writes over executed code: {} to {} instructions", self.writes.len(), instructions.len())?;
+        for write in &self.writes {
+            writeln!(out, "  {write}")?;
+        }
+        writeln!(out)?;
+        let mut runs = self.runs.clone();
+        runs.sort_by_key(|run| run.address);
+        let mut ranges: Vec<Vec<WrittenCodeRun>> = Vec::new();
+        for run in runs {
+            match ranges.last_mut() {
+                Some(range) if range.last().is_some_and(|last| last.address + last.operation.size() >= run.address) => {
+                    range.push(run);
+                },
+                _ => ranges.push(vec![run]),
+            }
+        }
+        writeln!(out, "written instructions run: {} in {} ranges", self.runs.len(), ranges.len())?;
+        for range in ranges {
+            let (first, last) = (range[0], range[range.len() - 1]);
+            let step = range.iter().map(|run| run.step).min().unwrap_or(first.step);
+            let mut writers: Vec<u16> = range.iter().map(|run| run.writer).collect();
+            writers.sort_unstable();
+            writers.dedup();
+            let writers: Vec<String> = writers.iter().map(u16::to_string).collect();
+            writeln!(
+                out,
+                "  {:5}-{:<5} {} instructions, first run at step {step}, written by {}",
+                first.address,
+                last.address + last.operation.size() - 1,
+                range.len(),
+                writers.join(" "),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use std::{fmt, fs};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::memory::{Memory, MEMORY_SIZE};
+use super::{Frame, VmError};
+
+/// The bytes a saved state starts with, before its format version.
+const MAGIC: &[u8; 7] = b"VMSTATE";
+
+/// The version of the saved-state layout, bumped whenever a field is added to
+/// or removed from [`Snapshot`].
+const FORMAT_VERSION: u8 = 1;
+
+/// Everything needed to resume execution: the instruction pointer, registers,
+/// memory, stack, halted flag, call depth, shadow call stack, and step count.
+/// Breakpoints, watchpoints, and I/O are not part of a snapshot.
+///
+/// A saved snapshot starts with a format version, and only states saved in
+/// the current format load. States saved before the version was recorded,
+/// which lack the call depth, shadow call stack, or step count, cannot be
+/// loaded at all.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub(super) instruction_ptr: u16,
+    pub(super) registers: [u16; 8],
+    pub(super) mem: Memory,
+    pub(super) stack: Vec<u16>,
+    pub(super) halted: bool,
+    pub(super) call_depth: i32,
+    pub(super) frames: Vec<Frame>,
+    pub(super) steps: u64,
+}
+
+impl Snapshot {
+    /// The number of instructions executed when the snapshot was taken.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The instruction pointer when the snapshot was taken.
+    pub fn instruction_ptr(&self) -> u16 {
+        self.instruction_ptr
+    }
+
+    pub fn registers(&self) -> &[u16; 8] {
+        &self.registers
+    }
+
+    /// The word at `address`, or `None` if it was never written.
+    pub fn memory(&self, address: u16) -> Option<u16> {
+        self.mem.get(address)
+    }
+
+    /// All of memory as a flat image, with never-written words as zero.
+    pub fn memory_image(&self) -> Vec<u16> {
+        (0..MEMORY_SIZE as u16)
+            .map(|address| self.mem.get(address).unwrap_or(0))
+            .collect()
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// What changed between `self` and the later snapshot `other`.
+    pub fn diff(&self, other: &Snapshot) -> SnapshotDiff {
+        let common = self.stack.iter()
+            .zip(&other.stack)
+            .take_while(|(old, new)| old == new)
+            .count();
+        SnapshotDiff {
+            steps: (self.steps, other.steps),
+            instruction_ptr: (self.instruction_ptr != other.instruction_ptr)
+                .then_some((self.instruction_ptr, other.instruction_ptr)),
+            registers: (0..8)
+                .filter(|&idx| self.registers[idx] != other.registers[idx])
+                .map(|idx| (idx as u8, self.registers[idx], other.registers[idx]))
+                .collect(),
+            memory: (0..MEMORY_SIZE as u16)
+                .filter_map(|address| {
+                    let (old, new) = (self.mem.get(address), other.mem.get(address));
+                    (old != new).then_some((address, old, new))
+                })
+                .collect(),
+            stack_common: common,
+            stack_removed: self.stack[common..].to_vec(),
+            stack_added: other.stack[common..].to_vec(),
+        }
+    }
+
+    /// Writes the snapshot to `path`: a format header, then the snapshot in
+    /// bincode format.
+    pub fn save(&self, path: &Path) -> Result<(), VmError> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        bincode::serialize_into(writer, self).map_err(VmError::from)
+    }
+
+    /// Reads a snapshot written by [`save`](Self::save) in the current
+    /// format.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::{Snapshot, VmError, VM};
+    ///
+    /// let path = std::env::temp_dir().join(format!("snapshot-doctest-{}.state", std::process::id()));
+    /// let vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.save_state(&path).unwrap();
+    /// assert_eq!(Snapshot::load(&path).unwrap().steps(), 0);
+    ///
+    /// // A state saved without a format header, as before there was one.
+    /// std::fs::write(&path, [0; 64]).unwrap();
+    /// let Err(VmError::InvalidSnapshot(message)) = Snapshot::load(&path) else { panic!() };
+    /// assert!(message.starts_with("not a saved state"), "{message}");
+    /// std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, VmError> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut header = [0; MAGIC.len() + 1];
+        let complete = match reader.read_exact(&mut header) {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(err) => return Err(err.into()),
+        };
+        if !complete || &header[..MAGIC.len()] != MAGIC {
+            return Err(VmError::InvalidSnapshot(
+                "not a saved state, or one saved by an older version without the call stack and step count".to_string(),
+            ));
+        }
+        let version = header[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(VmError::InvalidSnapshot(format!(
+                "saved in format version {version}, but this version reads only format {FORMAT_VERSION}",
+            )));
+        }
+        bincode::deserialize_from(reader).map_err(VmError::from)
+    }
+}
+
+impl From<bincode::Error> for VmError {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(err) if err.kind() != io::ErrorKind::UnexpectedEof => VmError::Io(err),
+            err => VmError::InvalidSnapshot(err.to_string()),
+        }
+    }
+}
+
+/// The differences between two snapshots, from [`Snapshot::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub steps: (u64, u64),
+    pub instruction_ptr: Option<(u16, u16)>,
+    /// Changed registers as (index, old, new).
+    pub registers: Vec<(u8, u16, u16)>,
+    /// Changed words as (address, old, new), where `None` is never written.
+    pub memory: Vec<(u16, Option<u16>, Option<u16>)>,
+    /// The number of entries at the bottom of both stacks that are equal.
+    pub stack_common: usize,
+    /// Entries above the common part in the old stack, bottom first.
+    pub stack_removed: Vec<u16>,
+    /// Entries above the common part in the new stack, bottom first.
+    pub stack_added: Vec<u16>,
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let word = |value: Option<u16>| value.map_or_else(|| "-".to_string(), |value| value.to_string());
+        let words = |values: &[u16]| values.iter().map(u16::to_string).collect::<Vec<_>>().join(" ");
+        writeln!(f, "steps: {} -> {}", self.steps.0, self.steps.1)?;
+        if let Some((old, new)) = self.instruction_ptr {
+            writeln!(f, "ip: {old} -> {new}")?;
+        }
+        for (idx, old, new) in &self.registers {
+            writeln!(f, "r{idx}: {old} -> {new}")?;
+        }
+        writeln!(f, "memory: {} words changed", self.memory.len())?;
+        for (address, old, new) in &self.memory {
+            writeln!(f, "  {address:5}: {:>5} -> {}", word(*old), word(*new))?;
+        }
+        if self.stack_removed.is_empty() && self.stack_added.is_empty() {
+            writeln!(f, "stack: unchanged ({} entries)", self.stack_common)
+        } else {
+            writeln!(
+                f, "stack: above {} common entries, [{}] -> [{}]",
+                self.stack_common, words(&self.stack_removed), words(&self.stack_added),
+            )
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::ops::RangeInclusive;
+
+use super::memory::{Memory, MEMORY_SIZE};
+use super::{Snapshot, VM};
+
+/// 64-bit FNV-1a, chosen over `DefaultHasher` because its output is fixed:
+/// the same state hashes the same on every platform and Rust version, so
+/// hashes can be stored and compared across runs and implementations.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn word(&mut self, word: u16) {
+        for byte in word.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Hashes the instruction pointer, registers, stack, and every word of
+/// memory outside `volatile`. Never-written words hash differently from
+/// words written with zero. The step count is left out, so the same state
+/// reached by different routes hashes the same.
+fn hash_state(
+    instruction_ptr: u16,
+    registers: &[u16; 8],
+    stack: &[u16],
+    mem: &Memory,
+    volatile: &[RangeInclusive<u16>],
+) -> u64 {
+    let mut hash = Fnv::new();
+    hash.word(instruction_ptr);
+    registers.iter().for_each(|&register| hash.word(register));
+    hash.word(stack.len() as u16);
+    stack.iter().for_each(|&value| hash.word(value));
+    for address in 0..MEMORY_SIZE as u16 {
+        if volatile.iter().any(|range| range.contains(&address)) {
+            continue;
+        }
+        match mem.get(address) {
+            Some(word) => {
+                hash.word(1);
+                hash.word(word);
+            },
+            None => hash.word(0),
+        }
+    }
+    hash.0
+}
+
+impl VM {
+    /// A stable hash of the execution state, for deduplicating states in a
+    /// search or comparing machines without diffing them.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// vm.load(&[21, 0, 21, 0]).unwrap();
+    /// let (before, masked) = (vm.state_hash(), vm.state_hash_masking(&[100..=100]));
+    /// vm.set_memory(100, 7);
+    /// assert_ne!(vm.state_hash(), before);
+    /// assert_eq!(vm.state_hash_masking(&[100..=100]), masked);
+    /// ```
+    pub fn state_hash(&self) -> u64 {
+        self.state_hash_masking(&[])
+    }
+
+    /// Like [`state_hash`](Self::state_hash), but ignores memory in the
+    /// `volatile` ranges, such as counters that differ between otherwise
+    /// equivalent states.
+    pub fn state_hash_masking(&self, volatile: &[RangeInclusive<u16>]) -> u64 {
+        hash_state(self.instruction_ptr, &self.registers, &self.stack, &self.mem, volatile)
+    }
+}
+
+impl Snapshot {
+    /// The [`VM::state_hash`] of the state the snapshot holds.
+    pub fn state_hash(&self) -> u64 {
+        self.state_hash_masking(&[])
+    }
+
+    /// The [`VM::state_hash_masking`] of the state the snapshot holds.
+    pub fn state_hash_masking(&self, volatile: &[RangeInclusive<u16>]) -> u64 {
+        hash_state(self.instruction_ptr, &self.registers, &self.stack, &self.mem, volatile)
+    }
+}
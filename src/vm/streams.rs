@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Where `in` gets its bytes. Implemented for every [`Read`], so files,
+/// stdin, and byte slices work directly; implement it by hand to feed input
+/// from anything else.
+pub trait InputSource {
+    /// The next byte, or `None` at the end of input.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+}
+
+impl<R: Read + ?Sized> InputSource for R {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buffer = [0u8; 1];
+        loop {
+            match self.read(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buffer[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Input that never blocks: a background thread reads `reader`, such as
+/// stdin, and [`read_byte`](InputSource::read_byte) fails with
+/// [`io::ErrorKind::WouldBlock`] until the thread has something, so the VM
+/// reports [`VmEvent::NeedsInput`](super::VmEvent::NeedsInput) instead of
+/// stalling the caller (see [`VM::set_idle_handler`](super::VM::set_idle_handler)).
+/// A reader that itself fails with `WouldBlock`, like a non-blocking socket,
+/// works the same way without one.
+pub struct PollingInput {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    buffered: VecDeque<u8>,
+}
+
+impl PollingInput {
+    pub fn new(mut reader: impl Read + Send + 'static) -> Self {
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                let chunk = match reader.read(&mut buffer) {
+                    // Dropping the sender marks the end of input.
+                    Ok(0) => return,
+                    Ok(len) => Ok(buffer[..len].to_vec()),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = chunk.is_err();
+                if sender.send(chunk).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Self { chunks, buffered: VecDeque::new() }
+    }
+}
+
+impl InputSource for PollingInput {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.buffered.is_empty() {
+            match self.chunks.try_recv() {
+                Ok(chunk) => self.buffered.extend(chunk?),
+                Err(TryRecvError::Empty) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(TryRecvError::Disconnected) => return Ok(None),
+            }
+        }
+        Ok(self.buffered.pop_front())
+    }
+}
+
+/// Where `out` sends its bytes. Implemented for every [`Write`]; implement it
+/// by hand to collect output somewhere else.
+pub trait OutputSink {
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Called before the VM blocks on input and when a run stops.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Lets `write!` and `writeln!` target a sink.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        self.write_bytes(args.to_string().as_bytes())
+    }
+}
+
+impl<W: Write + ?Sized> OutputSink for W {
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+}
+
+/// What `in` does when the input has run out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EofPolicy {
+    /// Leave the `in` unexecuted and report [`VmEvent::NeedsInput`] from
+    /// [`VM::step`], or [`VmError::InputExhausted`] from [`VM::run`], so more
+    /// input can be provided and the run resumed.
+    ///
+    /// [`VmEvent::NeedsInput`]: super::VmEvent::NeedsInput
+    /// [`VM::step`]: super::VM::step
+    /// [`VmError::InputExhausted`]: super::VmError::InputExhausted
+    /// [`VM::run`]: super::VM::run
+    #[default]
+    NeedsInput,
+    /// Halt the machine, as [`HaltCause::EndOfInput`](super::HaltCause::EndOfInput).
+    Halt,
+    /// Read this value, such as 0 or 32767 (-1 in 15 bits), and carry on.
+    Value(u16),
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use super::{Operation, VmError, VM};
+
+/// A host function run in place of an extension opcode.
+pub type Syscall = Box<dyn FnMut(&mut VM) -> Result<(), VmError>>;
+
+/// The opcodes above the spec's 0..=21 that can be bound to host functions.
+pub const FIRST_SYSCALL_OPCODE: u16 = 22;
+
+pub(super) type Syscalls = BTreeMap<u16, Syscall>;
+
+impl VM {
+    /// Binds `opcode` to a host function, replacing any previous binding.
+    ///
+    /// An extension instruction is the single word `opcode`. The instruction
+    /// pointer is moved past it before `handler` runs, so a handler can pass
+    /// arguments and results in registers or memory and may also jump.
+    ///
+    /// A syscall counts as a step, is charged fuel and marked as executed in
+    /// coverage, can be undone with [`step_back`](Self::step_back), and
+    /// appears in the text trace. It is not an [`Operation`], so hooks are not
+    /// called for it, and it is left out of the profile and the recent
+    /// instructions: recordings, binary traces, call traces, and folded
+    /// stacks, which are all built on hooks, skip it.
+    ///
+    /// Returns `false`, binding nothing, if `opcode` belongs to a built-in
+    /// instruction.
+    ///
+    /// ```
+    /// use oscon_2012_vm_challenge::vm::VM;
+    ///
+    /// let mut vm = VM::new(std::io::empty(), std::io::sink());
+    /// // syscall 22; halt
+    /// vm.load(&[22, 0, 0, 0]).unwrap();
+    /// vm.register_syscall(22, |vm| {
+    ///     vm.registers_mut()[0] = 42;
+    ///     Ok(())
+    /// });
+    /// vm.run().unwrap();
+    /// assert_eq!(vm.registers()[0], 42);
+    /// ```
+    pub fn register_syscall(
+        &mut self,
+        opcode: u16,
+        handler: impl FnMut(&mut VM) -> Result<(), VmError> + 'static,
+    ) -> bool {
+        if opcode < FIRST_SYSCALL_OPCODE {
+            return false;
+        }
+        self.syscalls.insert(opcode, Box::new(handler));
+        true
+    }
+
+    /// Removes the binding for `opcode`, returning whether there was one.
+    pub fn unregister_syscall(&mut self, opcode: u16) -> bool {
+        self.syscalls.remove(&opcode).is_some()
+    }
+
+    /// Runs the host function bound to the opcode at the instruction pointer,
+    /// returning `false` if there is none.
+    pub(super) fn try_syscall(&mut self) -> Result<bool, VmError> {
+        let ip = self.instruction_ptr;
+        let Some(opcode) = self.mem.get(ip) else {
+            return Ok(false);
+        };
+        // The handler gets the whole VM, so it is taken out while it runs.
+        let Some(mut handler) = self.syscalls.remove(&opcode) else {
+            return Ok(false);
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.write_all(format!("{ip:5}: syscall {opcode}\n").as_bytes())?;
+        }
+        let undo = self.history.is_some().then(|| self.syscall_undo_record());
+        self.instruction_ptr = ip.wrapping_add(1);
+        let result = handler(self);
+        // Keep a binding the handler made for its own opcode.
+        self.syscalls.entry(opcode).or_insert(handler);
+        result?;
+        self.steps += 1;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(ip);
+        }
+        if self.fuel.is_metering() {
+            self.burn_fuel(opcode);
+        }
+        if let Some(undo) = undo {
+            self.push_undo_record(undo, &Operation::Noop);
+        }
+        if self.checkpoints.is_some() {
+            self.maybe_checkpoint();
+        }
+        Ok(true)
+    }
+}
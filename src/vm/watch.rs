@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// Something whose writes suspend [`VM::run`](super::VM::run).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// Any `wmem` to an address in `start..=end`.
+    Memory { start: u16, end: u16 },
+    /// Any change to the value of a register.
+    Register(u8),
+}
+
+impl Watchpoint {
+    pub(super) fn covers_memory(&self, address: u16) -> bool {
+        matches!(*self, Watchpoint::Memory { start, end } if (start..=end).contains(&address))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watchpoint::Memory { start, end } if start == end => write!(f, "mem[{start}]"),
+            Watchpoint::Memory { start, end } => write!(f, "mem[{start}..={end}]"),
+            Watchpoint::Register(register) => write!(f, "r{register}"),
+        }
+    }
+}
+
+/// The location a triggered watchpoint saw written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    Memory(u16),
+    Register(u8),
+}
+
+impl fmt::Display for WatchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchTarget::Memory(address) => write!(f, "mem[{address}]"),
+            WatchTarget::Register(register) => write!(f, "r{register}"),
+        }
+    }
+}
+
+/// A write that triggered a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// The address of the instruction that performed the write.
+    pub address: u16,
+    pub target: WatchTarget,
+    /// The previous value, or `None` for never-written memory.
+    pub old: Option<u16>,
+    pub new: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.old {
+            Some(old) => write!(f, "{} changed {} -> {} at {}", self.target, old, self.new, self.address),
+            None => write!(f, "{} set to {} at {}", self.target, self.new, self.address),
+        }
+    }
+}
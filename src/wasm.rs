@@ -0,0 +1,118 @@
+//! A VM wrapper for JavaScript hosts.
+//!
+//! The browser has no blocking stdin, so [`WasmVm`] never reads from one: the
+//! host steps the machine, feeds it lines with
+//! [`provide_input`](WasmVm::provide_input) when it asks, and collects what it
+//! printed with [`take_output`](WasmVm::take_output). Every argument and
+//! result is a number, string, byte slice, or fieldless enum, so the methods
+//! can be exported through wasm-bindgen as they are.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::vm::{VmEvent, VM};
+
+/// What happened during [`WasmVm::step`] or [`WasmVm::run`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// The machine can keep running.
+    Continued = 0,
+    /// The machine wrote a byte; collect it with [`WasmVm::take_output`].
+    Output = 1,
+    /// The machine is waiting for [`WasmVm::provide_input`].
+    NeedsInput = 2,
+    Halted = 3,
+}
+
+#[derive(Clone, Default)]
+struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A VM whose input and output are exchanged with the host in memory.
+///
+/// ```
+/// use oscon_2012_vm_challenge::wasm::{StepResult, WasmVm};
+///
+/// let mut vm = WasmVm::new();
+/// // out 'h'; in r0; halt
+/// vm.load(&[19, 0, 104, 0, 20, 0, 0, 128, 0, 0]).unwrap();
+/// assert_eq!(vm.run(100), Ok(StepResult::NeedsInput));
+/// assert_eq!(vm.take_output(), "h");
+/// vm.provide_input("x\n");
+/// assert_eq!(vm.run(100), Ok(StepResult::Halted));
+/// ```
+pub struct WasmVm {
+    vm: VM,
+    output: OutputBuffer,
+}
+
+impl Default for WasmVm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmVm {
+    pub fn new() -> Self {
+        let output = OutputBuffer::default();
+        Self { vm: VM::new(io::empty(), output.clone()), output }
+    }
+
+    /// Loads a little-endian image at address 0.
+    pub fn load(&mut self, image: &[u8]) -> Result<(), String> {
+        self.vm.load(image).map_err(|err| err.to_string())
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Result<StepResult, String> {
+        let event = self.vm.step().map_err(|err| err.to_string())?;
+        Ok(match event {
+            VmEvent::Continued => StepResult::Continued,
+            VmEvent::Output(_) => StepResult::Output,
+            VmEvent::NeedsInput => StepResult::NeedsInput,
+            VmEvent::Halted => StepResult::Halted,
+        })
+    }
+
+    /// Executes up to `max_steps` instructions, stopping early when the
+    /// machine needs input or halts, so the host can yield to the page
+    /// between calls. Returns [`StepResult::Continued`] if the budget ran out.
+    pub fn run(&mut self, max_steps: u32) -> Result<StepResult, String> {
+        for _ in 0..max_steps {
+            match self.step()? {
+                StepResult::Continued | StepResult::Output => (),
+                result => return Ok(result),
+            }
+        }
+        Ok(StepResult::Continued)
+    }
+
+    /// Queues `text` for the program's `in` instructions.
+    pub fn provide_input(&mut self, text: &str) {
+        self.vm.provide_input(text.as_bytes());
+    }
+
+    /// Everything printed since the last call.
+    pub fn take_output(&mut self) -> String {
+        let bytes = std::mem::take(&mut *self.output.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> f64 {
+        // JavaScript numbers are doubles; `u64` would cross as a BigInt.
+        self.vm.steps() as f64
+    }
+}
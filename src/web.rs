@@ -0,0 +1,121 @@
+//! A browser front end: a terminal page served over HTTP that plays the game
+//! over a WebSocket, with one VM per connection.
+//!
+//! Built with the `web` feature. Like [`serve`](crate::serve), each connection
+//! gets its own thread and sessions are logged with [`log`](crate::log).
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use crate::log::{self, Level};
+use crate::vm::{VmError, VM};
+
+pub mod websocket;
+
+use websocket::{WebSocketInput, WebSocketOutput};
+
+const TERMINAL_PAGE: &str = include_str!("web/terminal.html");
+
+/// The parts of an HTTP request the server looks at.
+struct Request {
+    path: String,
+    websocket_key: Option<String>,
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let path = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => path.to_string(),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported request `{}`", line.trim()))),
+    };
+    let mut websocket_key = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+    Ok(Request { path, websocket_key })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    )
+}
+
+/// Accepts connections on `listener` forever. `/` serves the terminal page and
+/// `/ws` runs `image` for the page's WebSocket.
+pub fn serve(listener: TcpListener, image: Arc<[u8]>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::event(Level::Warn, "web", "accept failed", &[("error", &err)]);
+                continue;
+            },
+        };
+        let image = Arc::clone(&image);
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+            if let Err(err) = handle(stream, &image, &peer) {
+                log::event(Level::Warn, "web", "request failed", &[("peer", &peer), ("error", &err)]);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, image: &[u8], peer: &str) -> Result<(), VmError> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = read_request(&mut reader)?;
+    match (request.path.as_str(), request.websocket_key) {
+        ("/ws", Some(key)) => {
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                websocket::accept_key(&key),
+            )?;
+            log::event(Level::Info, "web", "session started", &[("peer", &peer)]);
+            let reason = session(reader, &stream, image)?;
+            log::event(Level::Info, "web", "session ended", &[("peer", &peer), ("reason", &reason)]);
+        },
+        ("/" | "/index.html", _) => respond(&mut stream, "200 OK", "text/html; charset=utf-8", TERMINAL_PAGE)?,
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "not found\n")?,
+    }
+    Ok(())
+}
+
+/// Plays one game over an upgraded connection, returning why it ended.
+fn session(reader: BufReader<TcpStream>, mut stream: &TcpStream, image: &[u8]) -> Result<&'static str, VmError> {
+    let input = WebSocketInput::new(reader, stream.try_clone()?);
+    let output = WebSocketOutput::new(stream.try_clone()?);
+    let mut vm = VM::new(input, output);
+    vm.load(image)?;
+    let reason = match vm.run() {
+        Ok(_) => {
+            // The client may already be gone.
+            let _ = websocket::close(&mut stream);
+            "program halted"
+        },
+        Err(VmError::InputExhausted { .. }) => "disconnected",
+        Err(VmError::Io(err)) if matches!(
+            err.kind(),
+            io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted
+        ) => "disconnected",
+        Err(err) => return Err(err),
+    };
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(reason)
+}
@@ -0,0 +1,195 @@
+//! Just enough of RFC 6455 to exchange text with a browser: the opening
+//! handshake and unfragmented text, close, ping, and pong frames.
+
+use std::io::{self, Read, Write};
+
+use crate::base64;
+use crate::vm::InputSource;
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Messages larger than this are refused; the game only needs short lines.
+const MAX_PAYLOAD: u64 = 64 * 1024;
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (idx, word) in chunk.chunks(4).enumerate() {
+            w[idx] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for idx in 16..80 {
+            w[idx] = (w[idx - 3] ^ w[idx - 8] ^ w[idx - 14] ^ w[idx - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (idx, &word) in w.iter().enumerate() {
+            let (f, k) = match idx {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// The `Sec-WebSocket-Accept` value answering a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    base64::encode(&sha1(format!("{}{GUID}", key.trim()).as_bytes()))
+}
+
+/// Writes one unmasked frame, as servers send them.
+fn write_frame(out: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut header = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => header.push(len as u8),
+        len @ 126..=0xffff => {
+            header.push(126);
+            header.extend((len as u16).to_be_bytes());
+        },
+        len => {
+            header.push(127);
+            header.extend((len as u64).to_be_bytes());
+        },
+    }
+    out.write_all(&header)?;
+    out.write_all(payload)?;
+    out.flush()
+}
+
+/// Tells the client the server is closing the connection.
+pub fn close(out: &mut impl Write) -> io::Result<()> {
+    write_frame(out, OP_CLOSE, &[])
+}
+
+/// Reads one frame, returning its opcode and unmasked payload.
+fn read_frame(input: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    input.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0u8; 2];
+            input.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        },
+        127 => {
+            let mut len = [0u8; 8];
+            input.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        },
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {len} bytes is too large")));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        input.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    input.read_exact(&mut payload)?;
+    for (idx, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[idx % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// The program's input, from the data of text and binary messages. Pings are
+/// answered on `replies`; a close message or a dropped connection ends input.
+pub struct WebSocketInput<R, W> {
+    frames: R,
+    replies: W,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read, W: Write> WebSocketInput<R, W> {
+    pub fn new(frames: R, replies: W) -> Self {
+        Self { frames, replies, data: Vec::new(), pos: 0 }
+    }
+}
+
+impl<R: Read, W: Write> InputSource for WebSocketInput<R, W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        while self.pos == self.data.len() {
+            let (opcode, payload) = match read_frame(&mut self.frames) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            match opcode {
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    // Browsers do not send carriage returns, but be forgiving.
+                    self.data = payload.into_iter().filter(|&byte| byte != b'\r').collect();
+                    self.pos = 0;
+                },
+                OP_PING => write_frame(&mut self.replies, OP_PONG, &payload)?,
+                OP_CLOSE => {
+                    // Echo the close; the client may not wait for it.
+                    let _ = write_frame(&mut self.replies, OP_CLOSE, &payload);
+                    return Ok(None);
+                },
+                _ => (),
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.data[self.pos - 1]))
+    }
+}
+
+/// The program's output, buffered and sent as one text message per flush.
+pub struct WebSocketOutput<W> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> WebSocketOutput<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, buffer: Vec::new() }
+    }
+}
+
+impl<W: Write> Write for WebSocketOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.buffer).into_owned();
+        self.buffer.clear();
+        write_frame(&mut self.inner, OP_TEXT, text.as_bytes())
+    }
+}
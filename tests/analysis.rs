@@ -0,0 +1,142 @@
+//! Static analyses over small assembled programs.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use oscon_2012_vm_challenge::analysis::deadcode::{DeadCode, Region, Usage};
+use oscon_2012_vm_challenge::analysis::functions::{Functions, Origin};
+use oscon_2012_vm_challenge::analysis::loops::{self, TripCount};
+use oscon_2012_vm_challenge::analysis::validate::{Problem, Validation};
+use oscon_2012_vm_challenge::analysis::xrefs::{XrefKind, XrefLog, XrefSource, Xrefs};
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{encode_image, DecodeError, VM};
+
+/// `main` calls `double` directly and `triple` through a register; `triple`
+/// is only found as the code after `double`'s `ret`. The string after it
+/// does not decode into anything that returns.
+const PROGRAM: &str = "
+        main:   set r0 1
+                call double
+                set r1 triple
+                call r1
+                halt
+        double: add r0 r0 r0
+                ret
+        triple: mult r0 r0 3
+                jt r0 done
+                add r0 r0 1
+        done:   ret
+        text:   data 'h' 'i' 0
+";
+
+#[test]
+fn functions_are_found_from_calls_and_after_returns() {
+    let words = assemble(PROGRAM).unwrap();
+    let functions = Functions::detect(&words, &[0]);
+    let found: Vec<(u16, Origin)> = functions.iter().map(|function| (function.entry, function.origin)).collect();
+    assert_eq!(found, [(0, Origin::Entry), (11, Origin::Call), (16, Origin::AfterReturn)]);
+
+    let triple = functions.get(16).unwrap();
+    assert_eq!((triple.start, triple.end), (16, 28));
+    assert_eq!(triple.blocks.len(), 3);
+    assert!(triple.returns);
+    assert!(!functions.get(0).unwrap().returns);
+    assert_eq!(functions.get(0).unwrap().calls.iter().copied().collect::<Vec<_>>(), [11]);
+
+    assert_eq!(functions.containing(25).map(|function| function.entry), Some(16));
+    assert_eq!(functions.containing(28).map(|function| function.entry), None);
+}
+
+#[test]
+fn xrefs_combine_literal_operands_with_observed_accesses() {
+    let words = assemble(PROGRAM).unwrap();
+    let functions = Functions::detect(&words, &[0]);
+    let mut xrefs = Xrefs::from_cfg(&functions.cfg);
+    assert_eq!(xrefs.to(16), []);
+
+    let log = Rc::new(RefCell::new(XrefLog::default()));
+    let mut vm = VM::new(std::io::empty(), std::io::sink());
+    vm.load(&encode_image(&words)).unwrap();
+    vm.add_hook(log.clone());
+    vm.run().unwrap();
+    xrefs.add_observed(&log.borrow());
+
+    let source = |is_static, count| XrefSource { is_static, count };
+    let to = |address| xrefs.to(address).into_iter().map(|(xref, source)| (xref.from, xref.kind, source)).collect::<Vec<_>>();
+    assert_eq!(to(11), [(3, XrefKind::Call, source(true, 1))]);
+    assert_eq!(to(16), [(8, XrefKind::Call, source(false, 1))]);
+    // `jt` at 20 is taken, since r0 is 6 by then.
+    assert_eq!(to(27), [(20, XrefKind::Jump, source(true, 1))]);
+}
+
+#[test]
+fn dead_code_separates_unexecuted_code_from_unused_data() {
+    let words = assemble(PROGRAM).unwrap();
+    let mut vm = VM::new(std::io::empty(), std::io::sink());
+    vm.load(&encode_image(&words)).unwrap();
+    vm.set_coverage(true);
+    vm.run().unwrap();
+    let dead = DeadCode::analyze(&words, &Functions::detect(&words, &[0]), vm.coverage().unwrap());
+
+    assert_eq!(dead.usage(20), Some(Usage::Executed));
+    let regions: Vec<Region> = dead.regions().into_iter().filter(|region| region.usage != Usage::Executed).collect();
+    assert_eq!(regions, [
+        Region { start: 23, end: 26, usage: Usage::Unexecuted },
+        Region { start: 28, end: 30, usage: Usage::Unused },
+    ]);
+}
+
+#[test]
+fn nested_loops_are_found_with_their_trip_counts() {
+    let words = assemble("
+                set r0 0
+        outer:  set r1 0
+        inner:  add r1 r1 1
+                eq r2 r1 3
+                jf r2 inner
+                add r0 r0 1
+                eq r2 r0 2
+                jf r2 outer
+                halt
+    ").unwrap();
+    let functions = Functions::detect(&words, &[0]);
+    let found = loops::detect(&functions);
+    let summary: Vec<(u16, Vec<u16>, Vec<u16>, usize)> = found.iter()
+        .map(|l| (l.header, l.back_edges.clone(), l.blocks.iter().copied().collect(), l.depth))
+        .collect();
+    assert_eq!(summary, [(3, vec![25], vec![3, 6, 17], 0), (6, vec![14], vec![6], 1)]);
+
+    let mut vm = VM::new(std::io::empty(), std::io::sink());
+    vm.load(&encode_image(&words)).unwrap();
+    vm.set_profiling(true);
+    vm.run().unwrap();
+    let profile = vm.profile().unwrap();
+    assert_eq!(found[0].trip_count(&functions.cfg, profile), TripCount { iterations: 2, entries: 1 });
+    let inner = found[1].trip_count(&functions.cfg, profile);
+    assert_eq!(inner, TripCount { iterations: 6, entries: 2 });
+    assert_eq!(inner.average(), 3.0);
+}
+
+#[test]
+fn validation_finds_bad_words_and_the_data_after_the_code() {
+    let mut words = assemble("
+                out 'h'
+                jmp done
+        table:  data 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17
+        done:   halt
+    ").unwrap();
+    let validation = Validation::check(&encode_image(&words));
+    assert!(validation.is_ok());
+    assert_eq!(validation.entry_instructions, 2);
+    assert_eq!(validation.opcodes.iter().map(|(&opcode, &count)| (opcode, count)).collect::<Vec<_>>(), [(0, 1), (6, 1), (19, 1)]);
+    assert_eq!(validation.data_regions, [(4, 20)]);
+
+    words[1] = 40_000;
+    words[10] = 32_776;
+    let validation = Validation::check(&encode_image(&words));
+    let error = DecodeError::InvalidOperand { idx: 0, word: 40_000 };
+    assert_eq!(validation.problems, [
+        Problem::OutOfRange { addresses: vec![1, 10] },
+        Problem::BadEntry { address: 0, error },
+    ]);
+}
@@ -0,0 +1,82 @@
+//! The assembler, on its own rather than through other tests' fixtures.
+
+use oscon_2012_vm_challenge::asm::{assemble, AsmError};
+
+fn error(source: &str) -> AsmError {
+    assemble(source).unwrap_err()
+}
+
+#[test]
+fn labels_resolve_forwards_and_backwards() {
+    let words = assemble(
+        "
+        start:  jmp end
+        loop:   noop
+                jt r0 loop
+        end:    call start
+        ",
+    )
+    .unwrap();
+    assert_eq!(words, [6, 6, 21, 7, 32_768, 2, 17, 0]);
+}
+
+#[test]
+fn several_labels_can_name_one_address() {
+    assert_eq!(assemble("a: b: noop\njmp b\njmp a").unwrap(), [21, 6, 0, 6, 0]);
+}
+
+#[test]
+fn operands_can_be_registers_numbers_and_characters() {
+    assert_eq!(assemble("set r7 0x2a").unwrap(), [1, 32_775, 42]);
+    assert_eq!(assemble("out 'a'").unwrap(), [19, 97]);
+    assert_eq!(
+        assemble(r"data '\n' '\t' '\\' '\'' ';' ','").unwrap(),
+        [10, 9, 92, 39, 59, 44],
+    );
+    assert_eq!(assemble("out ' ' ; a space").unwrap(), [19, 32]);
+}
+
+#[test]
+fn data_emits_its_operands_and_moves_later_labels() {
+    let words = assemble(
+        "
+                jmp after
+        table:  data 1 2 0x3 r0 'x' table
+        after:  halt
+        ",
+    )
+    .unwrap();
+    assert_eq!(words, [6, 8, 1, 2, 3, 32_768, 120, 2, 0]);
+    assert!(assemble("data").unwrap().is_empty());
+}
+
+#[test]
+fn labels_must_be_defined_once() {
+    assert_eq!(error("noop\njmp nowhere"), AsmError { line: 2, message: "undefined label `nowhere`".into() });
+    assert_eq!(error("here: noop\nhere: halt"), AsmError { line: 2, message: "duplicate label `here`".into() });
+    assert_eq!(error("2go: noop").message, "invalid label `2go`");
+}
+
+#[test]
+fn operands_out_of_range_are_rejected() {
+    assert_eq!(error("set r0 32768").message, "invalid number `32768`");
+    assert_eq!(error("set r0 0x8000").message, "invalid number `0x8000`");
+    assert_eq!(error("set r0 65536").message, "invalid number `65536`");
+    assert_eq!(error("set r8 1").message, "no such register `r8`");
+    assert_eq!(error("out '€'").message, "character literal '€' is not a byte");
+    assert_eq!(error("out 'ab'").message, "invalid character literal 'ab'");
+    assert_eq!(error("out 'a").message, "unterminated character literal in `'a`");
+}
+
+#[test]
+fn instructions_need_their_operands() {
+    assert_eq!(error("noop\nadd r0 r1").message, "`add` takes 3 operands, found 2");
+    assert_eq!(error("noop\nadd r0 r1").line, 2);
+    assert_eq!(error("frobnicate").message, "unknown mnemonic `frobnicate`");
+}
+
+#[test]
+fn programs_must_fit_in_memory() {
+    let source = "data 0 0 0 0\n".repeat(8_192) + "halt\n";
+    assert_eq!(error(&source), AsmError { line: 8_193, message: "program of 32769 words does not fit in memory".into() });
+}
@@ -0,0 +1,119 @@
+//! Running the VM under a minimal executor with input that is not always
+//! ready.
+
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::async_vm::{AsyncInput, AsyncOutput, AsyncVm};
+use oscon_2012_vm_challenge::vm::{encode_image, VmError};
+
+/// Counts wake-ups so the tests can check nothing spins without being woken.
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Polls `future` to completion on this thread, returning its output and the
+/// number of times it was pending.
+fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let counter = Arc::new(Counter::default());
+    let waker = Waker::from(counter.clone());
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    let mut pending = 0;
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return (output, pending);
+        }
+        pending += 1;
+        assert!(counter.0.swap(0, Ordering::Relaxed) > 0, "pending without a wake-up");
+    }
+}
+
+/// Lines that each arrive one poll after they are asked for.
+struct Lines(Vec<&'static [u8]>);
+
+impl AsyncInput for Lines {
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut ready = false;
+        poll_fn(|cx| match ready {
+            true => Poll::Ready(()),
+            false => {
+                ready = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            },
+        })
+        .await;
+        Ok((!self.0.is_empty()).then(|| self.0.remove(0).to_vec()))
+    }
+}
+
+#[derive(Default)]
+struct Collected(Vec<Vec<u8>>);
+
+impl AsyncOutput for Collected {
+    async fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if !bytes.is_empty() {
+            self.0.push(bytes.to_vec());
+        }
+        Ok(())
+    }
+}
+
+fn vm_with(source: &str) -> AsyncVm {
+    let mut vm = AsyncVm::new();
+    vm.vm_mut().load(&encode_image(&assemble(source).unwrap())).unwrap();
+    vm
+}
+
+#[test]
+fn output_is_written_before_waiting_for_input() {
+    let mut vm = vm_with(
+        "       out '>'
+         echo:  in r0
+                out r0
+                eq r1 r0 10
+                jf r1 echo
+                halt",
+    );
+    let mut output = Collected::default();
+    let (result, pending) = block_on(vm.run(&mut Lines(vec![b"hi\n"]), &mut output));
+    result.unwrap();
+    assert_eq!(output.0, [b">".to_vec(), b"hi\n".to_vec()]);
+    assert_eq!(pending, 1);
+}
+
+#[test]
+fn long_computations_yield_between_slices() {
+    let mut vm = vm_with(
+        "loop:  add r0 r0 1
+                jt r0 loop
+                out 'x'
+                halt",
+    );
+    vm.set_slice(1_000);
+    let mut output = Collected::default();
+    let (result, pending) = block_on(vm.run(&mut Lines(Vec::new()), &mut output));
+    result.unwrap();
+    assert_eq!(output.0, [b"x".to_vec()]);
+    assert_eq!(pending, 65);
+}
+
+#[test]
+fn running_out_of_input_is_an_error_after_flushing_output() {
+    let mut vm = vm_with("out 'a'\nin r0\nhalt");
+    let mut output = Collected::default();
+    let (result, _) = block_on(vm.run(&mut Lines(Vec::new()), &mut output));
+    assert!(matches!(result, Err(VmError::InputExhausted { address: 2 })), "{result:?}");
+    assert_eq!(output.0, [b"a".to_vec()]);
+}
@@ -0,0 +1,45 @@
+//! Debugger scripts run with `source`.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use oscon_2012_vm_challenge::debugger::{Command, Debugger};
+use oscon_2012_vm_challenge::vm::VM;
+
+/// The debugger's output, for the test to read back.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn a_script_that_sources_itself_stops_nesting() {
+    let path = std::env::temp_dir().join(format!("oscon-vm-self-{}.gdb", std::process::id()));
+    fs::write(&path, format!("source {}\nregs\n", path.display())).unwrap();
+    let out = Shared::default();
+    let mut debugger = Debugger::new(VM::new(io::empty(), io::sink()), Box::new(out.clone()));
+
+    debugger.execute(Command::Source(path.clone())).unwrap();
+    fs::remove_file(&path).unwrap();
+    let output = String::from_utf8(out.0.borrow().clone()).unwrap();
+    assert_eq!(output.matches("source nested too deeply").count(), 1, "{output}");
+    // Every level still runs the rest of its script.
+    assert_eq!(output.matches("r0 = ").count(), 16, "{output}");
+
+    // The depth unwinds, so the script can be sourced again.
+    out.0.borrow_mut().clear();
+    fs::write(&path, "regs\n").unwrap();
+    assert!(debugger.source(&path).unwrap());
+    fs::remove_file(&path).unwrap();
+    assert!(!String::from_utf8_lossy(&out.0.borrow()).contains("nested"));
+}
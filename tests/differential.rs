@@ -0,0 +1,102 @@
+//! Lockstep comparisons between differently configured machines.
+
+use std::io::{self, Cursor};
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::differential::{run_lockstep, Agreement, Divergence};
+use oscon_2012_vm_challenge::vm::{encode_image, Hook, VM};
+
+const CHALLENGE: &[u8] = include_bytes!("../input/challenge.bin");
+
+struct Nothing;
+
+impl Hook for Nothing {}
+
+fn vm_with(image: &[u8], input: &'static [u8]) -> VM {
+    let mut vm = VM::new(Cursor::new(input), io::sink());
+    vm.load(image).unwrap();
+    vm
+}
+
+fn program(source: &str) -> Vec<u8> {
+    encode_image(&assemble(source).unwrap())
+}
+
+#[test]
+fn instrumentation_does_not_change_the_self_test() {
+    let mut plain = vm_with(CHALLENGE, b"");
+    let mut instrumented = vm_with(CHALLENGE, b"");
+    instrumented.set_recording(Some(1_000));
+    instrumented.set_profiling(true);
+    instrumented.set_coverage(true);
+    instrumented.set_call_logging(true);
+    instrumented.add_hook(Nothing);
+    let agreement = run_lockstep(&mut plain, &mut instrumented, 10_000, u64::MAX).unwrap();
+    assert!(matches!(agreement, Agreement::NeedsInput), "{agreement:?}");
+}
+
+#[test]
+fn the_decode_cache_follows_self_modifying_code() {
+    // The self-test decrypts the rest of the binary with `wmem` before it runs.
+    let mut cached = vm_with(CHALLENGE, b"");
+    let mut uncached = vm_with(CHALLENGE, b"");
+    uncached.set_decode_cache(false);
+    let agreement = run_lockstep(&mut cached, &mut uncached, 1_000, u64::MAX).unwrap();
+    assert!(matches!(agreement, Agreement::NeedsInput), "{agreement:?}");
+}
+
+#[test]
+fn the_decode_cache_sees_writes_to_arguments() {
+    // Rewrites the argument of the `out` at `print` on each pass.
+    let image = program(
+        "       set r0 'a'
+         print: out 0
+                wmem 4 r0
+                add r0 r0 1
+                eq r1 r0 'e'
+                jf r1 print
+                halt",
+    );
+    let mut cached = vm_with(&image, b"");
+    let mut uncached = vm_with(&image, b"");
+    uncached.set_decode_cache(false);
+    let agreement = run_lockstep(&mut cached, &mut uncached, 1, u64::MAX).unwrap();
+    assert!(matches!(agreement, Agreement::Halted), "{agreement:?}");
+}
+
+#[test]
+fn different_output_is_reported_at_the_instruction() {
+    let image = program("in r0\nout r0\nhalt");
+    let mut left = vm_with(&image, b"a\n");
+    let mut right = vm_with(&image, b"b\n");
+    match run_lockstep(&mut left, &mut right, 1_000, u64::MAX) {
+        Err(Divergence::Event { steps: 2, .. }) => (),
+        result => panic!("{result:?}"),
+    }
+}
+
+#[test]
+fn different_state_is_reported_with_the_window() {
+    let image = program("loop: add r0 r0 1\njmp loop");
+    let mut left = vm_with(&image, b"");
+    let mut right = vm_with(&image, b"");
+    left.run_for(25).unwrap();
+    right.run_for(25).unwrap();
+    right.registers_mut()[1] = 7;
+    match run_lockstep(&mut left, &mut right, 10, 100) {
+        Err(Divergence::State { agreed: 25, steps: 25, diff }) => assert_eq!(diff.registers, [(1, 0, 7)]),
+        result => panic!("{result:?}"),
+    }
+    right.registers_mut()[1] = 0;
+    assert!(matches!(run_lockstep(&mut left, &mut right, 10, 100), Ok(Agreement::StepLimit)));
+}
+
+#[test]
+fn a_fork_runs_like_the_original() {
+    // Fork partway through the self-test, while it is still rewriting memory.
+    let mut original = vm_with(CHALLENGE, b"");
+    original.run_for(100_000).unwrap();
+    let mut fork = original.clone();
+    let agreement = run_lockstep(&mut original, &mut fork, 10_000, u64::MAX).unwrap();
+    assert!(matches!(agreement, Agreement::NeedsInput), "{agreement:?}");
+}
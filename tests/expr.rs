@@ -0,0 +1,83 @@
+//! Parsing and evaluating the debugger's breakpoint-condition expressions.
+
+use std::io;
+
+use oscon_2012_vm_challenge::debugger::expr::Expr;
+use oscon_2012_vm_challenge::symbols::Symbols;
+use oscon_2012_vm_challenge::vm::VM;
+
+fn vm() -> VM {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.registers_mut()[0] = 6;
+    vm.registers_mut()[1] = 32_767;
+    vm.set_memory(100, 42);
+    vm
+}
+
+fn eval(text: &str) -> Result<i64, String> {
+    Expr::parse(text, &Symbols::parse("100 = answer").unwrap())?.eval(&vm())
+}
+
+#[test]
+fn operators_follow_c_precedence() {
+    assert_eq!(eval("1 + 2 * 3 == 7"), Ok(1));
+    assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+    assert_eq!(eval("1 | 2 ^ 3 & 1"), Ok(3));
+    assert_eq!(eval("1 < 2 == 2 > 1"), Ok(1));
+    assert_eq!(eval("0 || 1 && 0"), Ok(0));
+    assert_eq!(eval("r0 | 1 || 0"), Ok(1));
+    assert_eq!(eval("-r0 * 2"), Ok(-12));
+    assert_eq!(eval("!0 + ~0"), Ok(0));
+    assert_eq!(eval("r0 + r1 > 32767"), Ok(1));
+}
+
+#[test]
+fn binary_operators_associate_to_the_left() {
+    assert_eq!(eval("1 - 2 - 3"), Ok(-4));
+    assert_eq!(eval("100 / 10 / 5"), Ok(2));
+    assert_eq!(eval("17 % 10 % 4"), Ok(3));
+    assert_eq!(eval("3 > 2 > 1"), Ok(0));
+}
+
+#[test]
+fn dividing_by_zero_is_an_error() {
+    assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+    assert_eq!(eval("r0 % (r0 - 6)"), Err("division by zero".to_string()));
+    assert_eq!(eval("0 && 1 / 0"), Ok(0));
+}
+
+#[test]
+fn logical_operators_skip_the_right_side() {
+    assert_eq!(eval("mem[40000]"), Err("address 40000 is out of range".to_string()));
+    assert_eq!(eval("mem[-1]"), Err("address -1 is out of range".to_string()));
+    assert_eq!(eval("r0 != 6 && mem[40000]"), Ok(0));
+    assert_eq!(eval("r0 == 6 || mem[40000]"), Ok(1));
+    assert_eq!(eval("r0 == 6 && mem[40000]"), Err("address 40000 is out of range".to_string()));
+}
+
+#[test]
+fn names_are_registers_state_or_symbols() {
+    assert_eq!(eval("mem[answer] + answer"), Ok(142));
+    assert_eq!(eval("ip + sp + depth + steps"), Ok(0));
+    assert_eq!(eval("mem[0x64]"), Ok(42));
+    assert_eq!(eval("missing + 1"), Err("unknown name `missing` in expression".to_string()));
+    assert_eq!(eval("r8"), Err("unknown name `r8` in expression".to_string()));
+}
+
+#[test]
+fn malformed_expressions_are_rejected() {
+    assert_eq!(eval("r0 r1"), Err("unexpected `r1` in expression".to_string()));
+    assert_eq!(eval("1 + 2)"), Err("unexpected `)` in expression".to_string()));
+    assert_eq!(eval("(1 + 2"), Err("missing `)` in expression".to_string()));
+    assert_eq!(eval("mem[1"), Err("missing `]` in expression".to_string()));
+    assert_eq!(eval("mem 1"), Err("expected `[` after `mem`".to_string()));
+    assert_eq!(eval("1 +"), Err("unexpected end of expression".to_string()));
+    assert_eq!(eval("  "), Err("empty expression".to_string()));
+    assert_eq!(eval("1 === 1"), Err("unexpected `=` in expression".to_string()));
+}
+
+#[test]
+fn expressions_display_as_written() {
+    let expr = Expr::parse("  r0==6  ", &Symbols::default()).unwrap();
+    assert_eq!(expr.to_string(), "r0==6");
+}
@@ -0,0 +1,53 @@
+//! Folded stacks for runs whose call stack changes other than by `call` and
+//! `ret`.
+
+use std::io;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::flamegraph::FoldedStacks;
+use oscon_2012_vm_challenge::symbols::Symbols;
+use oscon_2012_vm_challenge::vm::{encode_image, VM};
+
+const PROGRAM: &str = "
+        main:   call a
+                halt
+        a:      call b
+                ret
+        b:      noop
+                ret
+";
+
+fn vm_with_stacks() -> (VM, FoldedStacks) {
+    let stacks = FoldedStacks::new();
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(&encode_image(&assemble(PROGRAM).unwrap())).unwrap();
+    vm.add_hook(stacks.clone());
+    (vm, stacks)
+}
+
+fn folded(stacks: &FoldedStacks) -> String {
+    let mut out = Vec::new();
+    stacks.write(&Symbols::parse("3 = a\n6 = b").unwrap(), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn stepping_back_out_of_calls_resyncs_the_path() {
+    let (mut vm, stacks) = vm_with_stacks();
+    vm.set_recording(Some(10));
+    vm.run_until(|vm| vm.instruction_ptr() == 6).unwrap();
+    assert!(vm.step_back() && vm.step_back());
+    vm.run().unwrap();
+    assert_eq!(folded(&stacks), "top 3\ntop;a 3\ntop;a;b 2\n");
+}
+
+#[test]
+fn restoring_a_deeper_state_resyncs_the_path() {
+    let (mut vm, stacks) = vm_with_stacks();
+    vm.run_until(|vm| vm.instruction_ptr() == 6).unwrap();
+    let inside = vm.snapshot();
+    vm.run().unwrap();
+    vm.restore(inside);
+    vm.run().unwrap();
+    assert_eq!(folded(&stacks), "top 3\ntop;a 3\ntop;a;b 4\n");
+}
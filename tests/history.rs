@@ -0,0 +1,57 @@
+//! Stepping backwards over instructions whose effects reach outside the
+//! registers and memory.
+
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{encode_image, Device, VmError, VM};
+
+/// A single word shared with the test.
+struct Latch(Rc<Cell<u16>>);
+
+impl Device for Latch {
+    fn read(&mut self, _offset: u16) -> Result<u16, VmError> {
+        Ok(self.0.get())
+    }
+
+    fn write(&mut self, _offset: u16, value: u16) -> Result<(), VmError> {
+        self.0.set(value);
+        Ok(())
+    }
+}
+
+fn vm_with(source: &str) -> VM {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(&encode_image(&assemble(source).unwrap())).unwrap();
+    vm.set_recording(Some(100));
+    vm
+}
+
+#[test]
+fn stepping_back_over_a_device_write_leaves_memory_alone() {
+    let mut vm = vm_with(
+        "
+                wmem mapped 5
+                wmem plain 6
+                halt
+        mapped: data 1
+        plain:  data 2
+        ",
+    );
+    let latch = Rc::new(Cell::new(0));
+    vm.map_device(7, 7, Latch(latch.clone()));
+    vm.step().unwrap();
+    // A debugger can still change the word behind the device.
+    vm.set_memory(7, 9);
+    vm.step().unwrap();
+    assert_eq!((latch.get(), vm.memory(7), vm.memory(8)), (5, Some(9), Some(6)));
+
+    assert!(vm.step_back());
+    assert_eq!((latch.get(), vm.memory(7), vm.memory(8)), (5, Some(9), Some(2)));
+    assert!(vm.step_back());
+    // The device keeps its state, and the `wmem` never wrote the word behind it.
+    assert_eq!((latch.get(), vm.memory(7), vm.memory(8)), (5, Some(9), Some(2)));
+    assert_eq!(vm.instruction_ptr(), 0);
+}
@@ -0,0 +1,92 @@
+//! Decoding and detecting the text encodings of program images.
+
+use oscon_2012_vm_challenge::vm::{ImageFormat, VmError};
+
+/// An Intel HEX record of `kind` at `address`, with its checksum.
+fn record(kind: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let sum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    bytes.push(sum.wrapping_neg());
+    let digits: String = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    format!(":{digits}\n")
+}
+
+const END: &str = ":00000001FF\n";
+
+fn decode(format: ImageFormat, text: &str) -> Result<Vec<u8>, String> {
+    format.decode(text.as_bytes()).map_err(|err| match err {
+        VmError::InvalidEncoding(message) => message,
+        err => panic!("unexpected error {err}"),
+    })
+}
+
+#[test]
+fn extended_address_records_move_the_data() {
+    // A segment of 1 puts offset 2 at byte 18.
+    let text = record(0x02, 0, &[0, 1]) + &record(0x00, 2, &[0x15, 0]) + END;
+    let mut expected = vec![0; 20];
+    expected[18] = 0x15;
+    assert_eq!(decode(ImageFormat::IntelHex, &text), Ok(expected));
+
+    // A linear base of 0 leaves addresses alone.
+    let text = record(0x04, 0, &[0, 0]) + &record(0x00, 0, &[0x13, 0, 0x41, 0]) + END;
+    assert_eq!(decode(ImageFormat::IntelHex, &text), Ok(vec![0x13, 0, 0x41, 0]));
+
+    let text = record(0x02, 0, &[1]) + END;
+    assert_eq!(decode(ImageFormat::IntelHex, &text), Err("ihex: line 1: an extended address record needs 2 bytes".into()));
+}
+
+#[test]
+fn data_past_the_end_of_memory_is_rejected() {
+    // Memory is 65536 bytes, so the last two fit and the next do not.
+    let text = record(0x00, 0xfffe, &[1, 0]) + END;
+    assert_eq!(decode(ImageFormat::IntelHex, &text).map(|image| image.len()), Ok(65_536));
+    let text = record(0x00, 0xffff, &[1, 0]) + END;
+    assert_eq!(decode(ImageFormat::IntelHex, &text), Err("ihex: line 1: data past the end of memory".into()));
+    let text = record(0x04, 0, &[0, 1]) + &record(0x00, 0, &[1, 0]) + END;
+    assert_eq!(decode(ImageFormat::IntelHex, &text), Err("ihex: line 2: data past the end of memory".into()));
+}
+
+#[test]
+fn malformed_intel_hex_records_are_rejected() {
+    let err = |text: &str| decode(ImageFormat::IntelHex, text).unwrap_err();
+    assert_eq!(err(":0400000013004100A\n"), "ihex: line 1: invalid hex digits");
+    assert_eq!(err(":0400000013004100\n"), "ihex: line 1: wrong record length");
+    assert_eq!(err(":0400000013004100A7\n"), "ihex: line 1: bad checksum");
+    assert_eq!(err(":04000000130041ZZA8\n"), "ihex: line 1: invalid hex digits");
+    assert_eq!(err("\n0400000013004100A8\n"), "ihex: line 2: expected a record starting with `:`");
+    // Nothing after the end-of-file record is read.
+    assert_eq!(decode(ImageFormat::IntelHex, &(END.to_string() + "junk")), Ok(vec![]));
+}
+
+#[test]
+fn malformed_hex_words_are_rejected() {
+    assert_eq!(decode(ImageFormat::Hex, "13 41\n0 12345"), Err("hex: line 2: `12345` is not a hex word".into()));
+    assert_eq!(decode(ImageFormat::Hex, "0x"), Err("hex: line 1: `0x` is not a hex word".into()));
+    assert_eq!(decode(ImageFormat::Hex, "# only a comment\n"), Ok(vec![]));
+}
+
+#[test]
+fn text_that_is_both_hex_and_base64_detects_as_hex() {
+    // `AAAA` is the base64 of three zero bytes, and also the hex word 0xaaaa.
+    assert_eq!(ImageFormat::detect(b"AAAA"), ImageFormat::Hex);
+    assert_eq!(decode(ImageFormat::Auto, "AAAA"), Ok(vec![0xaa, 0xaa]));
+    assert_eq!(decode(ImageFormat::Base64, "AAAA"), Ok(vec![0, 0, 0]));
+    // Any base64 digit outside hex, or a token too long for a word, settles it.
+    assert_eq!(ImageFormat::detect(b"AAAB"), ImageFormat::Hex);
+    assert_eq!(ImageFormat::detect(b"AAAx"), ImageFormat::Base64);
+    assert_eq!(ImageFormat::detect(b"AAAAAAAA"), ImageFormat::Base64);
+    assert_eq!(ImageFormat::detect(b"EwBBAAAA"), ImageFormat::Base64);
+}
+
+#[test]
+fn text_in_no_format_is_binary() {
+    assert_eq!(ImageFormat::detect(b""), ImageFormat::Binary);
+    assert_eq!(ImageFormat::detect(b"not an image!"), ImageFormat::Binary);
+    assert_eq!(ImageFormat::detect(&[0x13, 0, 0x41, 0]), ImageFormat::Binary);
+    assert!(decode(ImageFormat::Base64, "not base64!").is_err());
+    assert!(ImageFormat::Hex.decode(&[0xff, 0xfe]).is_err());
+}
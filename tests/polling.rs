@@ -0,0 +1,128 @@
+//! Reading input that arrives while the VM is running, without blocking.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{encode_image, HaltCause, HaltReason, PollingInput, VmEvent, VM};
+
+/// Echoes one line, then halts.
+const ECHO: &str = "
+        echo:   in r0
+                out r0
+                eq r1 r0 10
+                jf r1 echo
+                halt
+";
+
+fn vm_reading(input: PollingInput) -> VM {
+    let mut vm = VM::new(input, Vec::new());
+    vm.load(&encode_image(&assemble(ECHO).unwrap())).unwrap();
+    vm
+}
+
+/// Steps until the VM halts, collecting its output, and counts the steps
+/// that found no input.
+fn step_to_halt(vm: &mut VM) -> (Vec<u8>, usize) {
+    let (mut output, mut waits) = (Vec::new(), 0);
+    loop {
+        match vm.step().unwrap() {
+            VmEvent::Continued => (),
+            VmEvent::Output(byte) => output.push(byte),
+            VmEvent::NeedsInput => {
+                waits += 1;
+                thread::sleep(Duration::from_millis(1));
+            },
+            VmEvent::Halted => return (output, waits),
+        }
+    }
+}
+
+#[test]
+fn stepping_reports_needs_input_until_a_line_arrives() {
+    let (mut writer, reader) = UnixStream::pair().unwrap();
+    let mut vm = vm_reading(PollingInput::new(reader));
+    vm.set_profiling(true);
+    assert_eq!(vm.step().unwrap(), VmEvent::NeedsInput);
+    assert_eq!(vm.instruction_ptr(), 0);
+    let typist = thread::spawn(move || {
+        writer.write_all(b"h").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        writer.write_all(b"i\n").unwrap();
+    });
+    let (output, waits) = step_to_halt(&mut vm);
+    typist.join().unwrap();
+    assert_eq!(output, b"hi\n");
+    assert!(waits > 0);
+    // The `in` is counted once per byte read, however often it polled.
+    assert_eq!(vm.profile().unwrap().count_at(0), 3);
+    assert_eq!(vm.profile().unwrap().total(), vm.steps());
+}
+
+/// A trace the test can read back.
+#[derive(Clone, Default)]
+struct Trace(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Trace {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn an_in_waiting_for_input_is_instrumented_once() {
+    let mut vm = VM::new(io::empty(), Vec::new());
+    vm.load(&encode_image(&assemble(ECHO).unwrap())).unwrap();
+    let trace = Trace::default();
+    vm.set_trace(Some(Box::new(trace.clone())));
+    vm.set_profiling(true);
+    vm.set_coverage(true);
+    vm.set_recent_instructions(Some(8));
+    for _ in 0..5 {
+        assert_eq!(vm.step().unwrap(), VmEvent::NeedsInput);
+    }
+    assert_eq!(vm.profile().unwrap().count_at(0), 0);
+    assert!(!vm.coverage().unwrap().is_executed(0));
+    assert!(vm.recent_instructions().unwrap().is_empty());
+    assert!(trace.0.borrow().is_empty());
+
+    vm.provide_input(b"a");
+    assert_eq!(vm.step().unwrap(), VmEvent::Continued);
+    assert_eq!(vm.registers()[0], u16::from(b'a'));
+    assert_eq!(vm.profile().unwrap().count_at(0), 1);
+    assert_eq!(vm.recent_instructions().unwrap().len(), 1);
+    assert_eq!(String::from_utf8(trace.0.borrow().clone()).unwrap(), "    0: in r0\n");
+}
+
+#[test]
+fn an_idle_handler_keeps_run_waiting() {
+    let (mut writer, reader) = UnixStream::pair().unwrap();
+    let mut vm = vm_reading(PollingInput::new(reader));
+    let mut idle_calls = 0;
+    vm.set_idle_handler(Some(Box::new(move || {
+        idle_calls += 1;
+        if idle_calls == 3 {
+            writer.write_all(b"ok\n").unwrap();
+        }
+        thread::sleep(Duration::from_millis(1));
+        ControlFlow::Continue(())
+    })));
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted(HaltCause::Instruction));
+}
+
+#[test]
+fn an_idle_handler_can_give_up() {
+    let (_writer, reader) = UnixStream::pair().unwrap();
+    let mut vm = vm_reading(PollingInput::new(reader));
+    vm.set_idle_handler(Some(Box::new(|| ControlFlow::Break(()))));
+    assert_eq!(vm.step().unwrap(), VmEvent::NeedsInput);
+}
@@ -0,0 +1,324 @@
+//! Property checks for instruction encoding and the arithmetic and stack
+//! instructions.
+//!
+//! Cases come from a small seeded generator rather than a property-testing
+//! crate, so the suite builds offline. A failing property reports the seed of
+//! the case that broke it; set `PROPTEST_SEED` to rerun from that seed.
+
+use std::env;
+use std::io;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{decode_image, encode_image, ArithmeticPolicy, DecodeError, Operation, VmError, VmEvent, VM};
+
+/// Cases tried per property.
+const CASES: u64 = 2_000;
+
+/// A SplitMix64 generator: fast, seedable, and good enough to pick test cases.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `0..bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    fn u16(&mut self) -> u16 {
+        self.next_u64() as u16
+    }
+
+    /// A literal, biased towards the edges of the 15-bit range.
+    fn literal(&mut self) -> u16 {
+        match self.below(4) {
+            0 => [0, 1, 32_766, 32_767][self.below(4) as usize],
+            _ => self.below(32_768) as u16,
+        }
+    }
+
+    fn register(&mut self) -> u16 {
+        32_768 + self.below(8) as u16
+    }
+
+    /// A well-formed operand: a literal or a register.
+    fn operand(&mut self) -> u16 {
+        match self.below(3) {
+            0 => self.register(),
+            _ => self.literal(),
+        }
+    }
+}
+
+/// Types the generator can produce.
+trait Arbitrary: Sized {
+    fn arbitrary(rng: &mut Rng) -> Self;
+}
+
+impl Arbitrary for Operation {
+    /// A well-formed instruction: registers where the operation writes, and
+    /// literals or registers everywhere else.
+    fn arbitrary(rng: &mut Rng) -> Self {
+        let opcode = rng.below(22) as u16;
+        let count = Operation::num_arguments(opcode).expect("0..22 should all be opcodes");
+        let mut args: Vec<u16> = (0..count).map(|_| rng.operand()).collect();
+        // Any destination is the first operand.
+        let writes = Operation::new(opcode, &[32_768; 3]).expect("0..22 should all be opcodes").destination().is_some();
+        if writes {
+            args[0] = rng.register();
+        }
+        Operation::new(opcode, &args).expect("registers and literals should decode")
+    }
+}
+
+/// Checks `property` on `CASES` generated cases, panicking with the seed of
+/// the first failure.
+fn check(name: &str, mut property: impl FnMut(&mut Rng) -> Result<(), String>) {
+    let base = env::var("PROPTEST_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or(0x5eed);
+    for case in 0..CASES {
+        let seed = base + case;
+        if let Err(message) = property(&mut Rng(seed)) {
+            panic!("property `{name}` failed with PROPTEST_SEED={seed}: {message}");
+        }
+    }
+}
+
+/// A VM with `words` loaded at address 0 and no input.
+fn vm_with(words: &[u16]) -> VM {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(&encode_image(words)).expect("test programs should fit in memory");
+    vm
+}
+
+#[test]
+fn operations_round_trip_through_their_encoding() {
+    check("decode(encode(op)) == op", |rng| {
+        let operation = Operation::arbitrary(rng);
+        let words = operation.encode();
+        if words.len() != operation.size() as usize {
+            return Err(format!("{operation} encodes to {} words but has size {}", words.len(), operation.size()));
+        }
+        match Operation::decode(&words) {
+            Some(decoded) if decoded == operation => Ok(()),
+            decoded => Err(format!("{operation} decoded as {decoded:?}")),
+        }
+    });
+}
+
+#[test]
+fn decoding_ignores_trailing_words_and_rejects_truncation() {
+    check("decode reads exactly size() words", |rng| {
+        let operation = Operation::arbitrary(rng);
+        let mut words = operation.encode();
+        words.push(rng.u16());
+        if Operation::decode(&words) != Some(operation) {
+            return Err(format!("{operation} followed by {} did not decode", words[words.len() - 1]));
+        }
+        words.truncate(operation.size() as usize - 1);
+        match Operation::decode(&words) {
+            None => Ok(()),
+            Some(decoded) => Err(format!("{operation} cut to {words:?} decoded as {decoded}")),
+        }
+    });
+}
+
+#[test]
+fn decoding_rejects_operands_of_the_wrong_class() {
+    check("new() rejects invalid numbers and literal destinations", |rng| {
+        let operation = Operation::arbitrary(rng);
+        let mut args = operation.args();
+        if args.is_empty() {
+            return Ok(());
+        }
+        let idx = rng.below(args.len() as u64) as usize;
+        let word = 32_776 + rng.below(32_760) as u16;
+        args[idx] = word;
+        let expected = DecodeError::InvalidOperand { idx, word };
+        match Operation::new(operation.opcode(), &args) {
+            Err(err) if err == expected => (),
+            result => return Err(format!("{operation} with operand {idx} set to {word} gave {result:?}")),
+        }
+        if operation.destination().is_none() {
+            return Ok(());
+        }
+        let mut args = operation.args();
+        args[0] = rng.literal();
+        match Operation::new(operation.opcode(), &args) {
+            Err(DecodeError::NotARegister { idx: 0, word }) if word == args[0] => Ok(()),
+            result => Err(format!("{operation} writing to literal {} gave {result:?}", args[0])),
+        }
+    });
+}
+
+#[test]
+fn operations_round_trip_through_the_assembler() {
+    check("assemble(op.to_string()) == encode(op)", |rng| {
+        let operation = Operation::arbitrary(rng);
+        match assemble(&operation.to_string()) {
+            Ok(words) if words == operation.encode() => Ok(()),
+            result => Err(format!("`{operation}` assembled to {result:?}")),
+        }
+    });
+}
+
+#[test]
+fn images_round_trip_through_bytes() {
+    check("decode_image(encode_image(words)) == words", |rng| {
+        let words: Vec<u16> = (0..rng.below(64)).map(|_| rng.u16()).collect();
+        match decode_image(&encode_image(&words)) {
+            Ok(decoded) if decoded == words => Ok(()),
+            result => Err(format!("{words:?} came back as {result:?}")),
+        }
+    });
+}
+
+/// Runs `opcode r0 b c` (or `opcode r0 b` for `not`) on literals and returns
+/// r0.
+fn arithmetic(opcode: u16, b: u16, c: u16) -> Result<u16, String> {
+    let mut words = vec![opcode, 32_768, b];
+    if opcode != 14 {
+        words.push(c);
+    }
+    let mut vm = vm_with(&words);
+    vm.step().map_err(|err| format!("opcode {opcode} on {b} and {c}: {err}"))?;
+    Ok(vm.registers()[0])
+}
+
+#[test]
+fn arithmetic_is_modulo_32768() {
+    check("add, mult, mod, and, or, not stay in range", |rng| {
+        let (b, c) = (rng.literal(), rng.literal());
+        let (wide_b, wide_c) = (b as u32, c as u32);
+        let expected = [
+            (9, "add", (wide_b + wide_c) % 32_768),
+            (10, "mult", (wide_b * wide_c) % 32_768),
+            (12, "and", wide_b & wide_c),
+            (13, "or", wide_b | wide_c),
+            (14, "not", !wide_b & 0x7fff),
+        ];
+        for (opcode, name, expected) in expected {
+            let actual = arithmetic(opcode, b, c)?;
+            if actual as u32 != expected {
+                return Err(format!("{name} {b} {c} gave {actual}, expected {expected}"));
+            }
+        }
+        if c != 0 {
+            let actual = arithmetic(11, b, c)?;
+            if actual != b % c {
+                return Err(format!("mod {b} {c} gave {actual}, expected {}", b % c));
+            }
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn mod_by_zero_follows_the_arithmetic_policy() {
+    check("mod b 0 faults, saturates, or wraps", |rng| {
+        let b = rng.literal();
+        let divisor = if rng.below(2) == 0 { 0 } else { 32_769 };
+        for (policy, expected) in [(ArithmeticPolicy::Saturate, 32_767), (ArithmeticPolicy::Wrap, b)] {
+            let mut vm = vm_with(&[11, 32_768, b, divisor]);
+            vm.set_arithmetic_policy(policy);
+            vm.step().map_err(|err| format!("mod {b} 0 under {policy:?}: {err}"))?;
+            if vm.registers()[0] != expected {
+                return Err(format!("mod {b} 0 under {policy:?} gave {}, expected {expected}", vm.registers()[0]));
+            }
+        }
+        match vm_with(&[11, 32_768, b, divisor]).step() {
+            Err(VmError::DivideByZero { address: 0 }) => Ok(()),
+            result => Err(format!("mod {b} 0 gave {result:?} instead of faulting")),
+        }
+    });
+}
+
+#[test]
+fn arithmetic_reads_registers_like_literals() {
+    check("add r0 r1 r2 == add r0 b c", |rng| {
+        let (b, c) = (rng.literal(), rng.literal());
+        let mut vm = vm_with(&[9, 32_768, 32_769, 32_770]);
+        vm.registers_mut()[1] = b;
+        vm.registers_mut()[2] = c;
+        vm.step().map_err(|err| err.to_string())?;
+        let expected = arithmetic(9, b, c)?;
+        match vm.registers()[0] {
+            actual if actual == expected => Ok(()),
+            actual => Err(format!("add r0 r1 r2 with r1={b} r2={c} gave {actual}, expected {expected}")),
+        }
+    });
+}
+
+#[test]
+fn add_rejects_out_of_range_operands_without_overflowing() {
+    // Words above 32775 are invalid operands, but a program can still contain
+    // them; they must fault at decode rather than panic or leak into a result.
+    check("add of any two words is in range or faults", |rng| {
+        let (b, c) = (rng.u16(), rng.u16());
+        match arithmetic(9, b, c) {
+            Ok(actual) if b > 32_775 || c > 32_775 => Err(format!("add {b} {c} gave {actual} instead of faulting")),
+            Ok(actual) if actual >= 32_768 => Err(format!("add {b} {c} gave {actual}, out of range")),
+            Ok(_) => Ok(()),
+            Err(_) if b > 32_775 || c > 32_775 => Ok(()),
+            Err(err) => Err(err),
+        }
+    });
+}
+
+#[test]
+fn push_then_pop_restores_the_value() {
+    check("push v; pop r0 leaves r0 == v and the stack as it was", |rng| {
+        let value = rng.literal();
+        let depth = rng.below(8) as usize;
+        let mut vm = vm_with(&[2, value, 3, 32_768]);
+        let below: Vec<u16> = (0..depth).map(|_| rng.literal()).collect();
+        vm.stack_mut().extend(&below);
+        vm.step().map_err(|err| err.to_string())?;
+        if vm.stack().last() != Some(&value) || vm.stack().len() != depth + 1 {
+            return Err(format!("push {value} left the stack as {:?}", vm.stack()));
+        }
+        vm.step().map_err(|err| err.to_string())?;
+        if vm.registers()[0] != value || vm.stack() != below.as_slice() {
+            return Err(format!("pop gave r0={} and stack {:?}", vm.registers()[0], vm.stack()));
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn call_then_ret_returns_past_the_call() {
+    check("call f; f: ret resumes at the next instruction", |rng| {
+        // A call somewhere in the first half of memory to a ret elsewhere.
+        let site = rng.below(16_000) as u16;
+        let target = loop {
+            let target = rng.below(32_000) as u16;
+            if target.abs_diff(site) > 2 {
+                break target;
+            }
+        };
+        let mut vm = vm_with(&[]);
+        vm.set_memory(site, 17);
+        vm.set_memory(site + 1, target);
+        vm.set_memory(target, 18);
+        vm.set_instruction_ptr(site);
+        vm.step().map_err(|err| err.to_string())?;
+        if vm.instruction_ptr() != target || vm.stack() != [site + 2] || vm.call_depth() != 1 {
+            return Err(format!("call {target} at {site} left ip={} stack={:?}", vm.instruction_ptr(), vm.stack()));
+        }
+        match vm.step().map_err(|err| err.to_string())? {
+            VmEvent::Continued if vm.instruction_ptr() == site + 2 && vm.stack().is_empty() => Ok(()),
+            event => Err(format!("ret from {target} gave {event:?} at ip={}", vm.instruction_ptr())),
+        }
+    });
+}
+
+#[test]
+fn ret_on_an_empty_stack_halts() {
+    let mut vm = vm_with(&[18]);
+    assert_eq!(vm.step().unwrap(), VmEvent::Halted);
+}
@@ -0,0 +1,116 @@
+//! Recording sessions and replaying them, through the library and through
+//! `run --record` and `run --replay`.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::replay::{Recorder, Recording, Replayer};
+use oscon_2012_vm_challenge::vm::{encode_image, VmError, VM};
+
+/// Echoes its input until the input runs out.
+const ECHO: &str = "loop: in r0\n out r0\n jmp loop";
+
+/// Prints a byte above 127 for every byte read.
+const ACCENTS: &str = "loop: in r0\n out 233\n jmp loop";
+
+/// A writer whose bytes the test can read afterwards.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn record(source: &str, input: &'static [u8]) -> Recording {
+    let recorder = Recorder::new();
+    let mut vm = VM::new(Cursor::new(input), recorder.tee_output(Box::new(io::sink())));
+    vm.load(&encode_image(&assemble(source).unwrap())).unwrap();
+    vm.add_hook(recorder.clone());
+    assert!(matches!(vm.run(), Err(VmError::InputExhausted { .. })));
+    recorder.recording()
+}
+
+fn replay(source: &str, recording: Recording) -> Result<Vec<u8>, String> {
+    let output = Shared::default();
+    let replayer = Replayer::new(recording.clone());
+    let mut vm = VM::new(io::empty(), replayer.tee_output(Box::new(output.clone())));
+    vm.load(&encode_image(&assemble(source).unwrap())).unwrap();
+    vm.provide_input(&recording.input_bytes());
+    vm.add_hook(replayer.clone());
+    assert!(matches!(vm.run(), Err(VmError::InputExhausted { .. })));
+    replayer.finish().map_err(|err| err.to_string())?;
+    let output = output.0.borrow().clone();
+    Ok(output)
+}
+
+#[test]
+fn output_that_is_not_utf8_replays_byte_for_byte() {
+    let recording = record(ACCENTS, b"ab");
+    assert_eq!(recording.output, [233, 233]);
+
+    let json = serde_json::to_string(&recording).unwrap();
+    assert!(json.contains(r#""output":[233,233]"#), "{json}");
+    let recording: Recording = serde_json::from_str(&json).unwrap();
+    assert_eq!(replay(ACCENTS, recording.clone()), Ok(vec![233, 233]));
+    assert_eq!(
+        replay(ECHO, recording),
+        Err("output byte 0 was 'a', but the recording has 'é'".to_string()),
+    );
+}
+
+#[test]
+fn text_output_is_saved_as_a_string() {
+    let recording = record(ECHO, "hé\n".as_bytes());
+    let json = serde_json::to_string(&recording).unwrap();
+    assert!(json.contains(r#""output":"hé\n""#), "{json}");
+    let recording: Recording = serde_json::from_str(&json).unwrap();
+    assert_eq!(replay(ECHO, recording), Ok("hé\n".as_bytes().to_vec()));
+}
+
+/// A fresh directory for one test's files.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oscon-vm-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn a_session_with_a_meta_command_line_replays() {
+    let dir = scratch_dir("replay");
+    let binary = dir.join("echo.bin");
+    let recording = dir.join("session.json");
+    fs::write(&binary, encode_image(&assemble(ECHO).unwrap())).unwrap();
+    let run = |extra: &[&std::ffi::OsStr], input: &[u8]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_oscon_2012_vm_challenge"))
+            .arg("run")
+            .arg(&binary)
+            .args(extra)
+            .current_dir(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    // While recording, the `!` line goes to the program like any other.
+    let recorded = run(&["--record".as_ref(), recording.as_os_str()], b"ab\n!regs\ncd\n");
+    assert_eq!(String::from_utf8_lossy(&recorded.stdout), "ab\n!regs\ncd\n");
+    let replayed = run(&["--replay".as_ref(), recording.as_os_str()], b"");
+    assert!(replayed.status.success(), "{}", String::from_utf8_lossy(&replayed.stderr));
+    assert_eq!(replayed.stdout, recorded.stdout);
+    fs::remove_dir_all(&dir).unwrap();
+}
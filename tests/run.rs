@@ -0,0 +1,64 @@
+//! Running one function by itself with `run --start-addr`.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::encode_image;
+
+/// `main` waits for input inside `wait`, so setting registers after the
+/// self-test leaves a frame on the call stack; `double` is at 6.
+const PROGRAM: &str = "
+        main:   call wait
+                halt
+        wait:   in r0
+                ret
+        double: add r0 r0 r0
+                ret
+";
+
+/// Runs the assembled `PROGRAM` with `args` in a fresh directory, returning
+/// the output and the directory.
+fn run(name: &str, args: &[&str], input: &[u8]) -> (Output, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("oscon-vm-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("program.bin"), encode_image(&assemble(PROGRAM).unwrap())).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_oscon_2012_vm_challenge"))
+        .args(["run", "program.bin"])
+        .args(args)
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The program may finish without reading its input, closing the pipe.
+    let _ = child.stdin.take().unwrap().write_all(input);
+    (child.wait_with_output().unwrap(), dir)
+}
+
+#[test]
+fn a_function_started_by_itself_returns_from_the_top_level() {
+    let args = [
+        "--set-register", "r0=5", "--registers-after-self-test",
+        "--start-addr", "6", "--stop-addr", "2",
+        "--chrome-trace", "calls.json", "--flamegraph", "stacks.txt",
+    ];
+    let (output, dir) = run("start-addr", &args, b"x\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{stderr}");
+    assert!(stderr.starts_with("returned at 10 after 3 steps\nr0=10 "), "{stderr}");
+
+    // `wait` was left behind when the stacks were emptied, not returned from
+    // by `double`'s `ret`.
+    let trace: serde_json::Value = serde_json::from_slice(&fs::read(dir.join("calls.json")).unwrap()).unwrap();
+    let events: Vec<(&str, &str, u64)> = trace["traceEvents"].as_array().unwrap().iter()
+        .map(|event| (event["name"].as_str().unwrap(), event["ph"].as_str().unwrap(), event["ts"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(events, [("fn_3", "B", 1), ("fn_3", "E", 1)]);
+    // The `call` before the registers were set, then `double`'s `add` and `ret`.
+    assert_eq!(fs::read_to_string(dir.join("stacks.txt")).unwrap(), "top 3\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
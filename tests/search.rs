@@ -0,0 +1,69 @@
+//! Searching a combination lock for its code.
+
+use std::io;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::solve::search::{search, SearchConfig};
+use oscon_2012_vm_challenge::vm::{encode_image, Snapshot, VM};
+
+/// Reads one letter per line. The right letter advances the lock, a wrong
+/// one resets it, and opening it prints `open`.
+const LOCK: &str = "
+        loop:   out '>'
+                in r0
+                in r1
+                add r3 r2 code
+                rmem r3 r3
+                eq r4 r0 r3
+                jf r4 reset
+                add r2 r2 1
+                eq r4 r2 3
+                jf r4 loop
+                out 'o'
+                out 'p'
+                out 'e'
+                out 'n'
+                halt
+        reset:  set r2 0
+                jmp loop
+        code:   data 'c' 'a' 'b'
+";
+
+fn start() -> Snapshot {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(&encode_image(&assemble(LOCK).unwrap())).unwrap();
+    vm.snapshot()
+}
+
+fn letters(_: &str) -> Vec<String> {
+    ["a", "b", "c"].map(String::from).to_vec()
+}
+
+#[test]
+fn finds_the_shortest_code_whatever_the_thread_count() {
+    for threads in [1, 4] {
+        let config = SearchConfig { threads, ..Default::default() };
+        let outcome = search(&start(), &config, letters, |output| output.contains("open")).unwrap();
+        let found = outcome.found.expect("the lock should open");
+        assert_eq!(found.commands, ["c", "a", "b"]);
+        assert_eq!(found.output, "open");
+    }
+}
+
+#[test]
+fn equivalent_states_are_explored_once() {
+    // A wrong letter resets the lock, so after `a`, `a` again or `b` then
+    // `a` leave it just as `a` did.
+    let config = SearchConfig { max_depth: 2, ..Default::default() };
+    let outcome = search(&start(), &config, letters, |_| false).unwrap();
+    assert!(outcome.found.is_none());
+    assert!(outcome.duplicates > 0);
+    assert_eq!(outcome.states + outcome.duplicates, 1 + 3 + 9);
+}
+
+#[test]
+fn stops_at_the_depth_limit() {
+    let config = SearchConfig { max_depth: 2, ..Default::default() };
+    let outcome = search(&start(), &config, letters, |output| output.contains("open")).unwrap();
+    assert!(outcome.found.is_none());
+}
@@ -0,0 +1,72 @@
+//! The bookkeeping around host functions bound to extension opcodes.
+
+use std::io;
+
+use oscon_2012_vm_challenge::asm::assemble;
+use oscon_2012_vm_challenge::vm::{encode_image, HaltCause, HaltReason, VM};
+
+fn vm_with(source: &str) -> VM {
+    let mut vm = VM::new(io::empty(), io::sink());
+    vm.load(&encode_image(&assemble(source).unwrap())).unwrap();
+    vm
+}
+
+/// Stores r0 at the address in r1 and counts calls in r2.
+fn bind_store(vm: &mut VM) {
+    vm.register_syscall(22, |vm| {
+        let [value, address, calls, ..] = *vm.registers();
+        vm.set_memory(address, value);
+        vm.registers_mut()[2] = calls + 1;
+        vm.stack_mut().push(value);
+        Ok(())
+    });
+}
+
+#[test]
+fn stepping_back_over_a_syscall_restores_what_it_changed() {
+    let mut vm = vm_with(
+        "
+                set r0 7
+                set r1 slot
+                data 22
+                halt
+        slot:   data 3
+        ",
+    );
+    bind_store(&mut vm);
+    vm.set_recording(Some(10));
+    vm.run_for(3).unwrap();
+    assert_eq!((vm.memory(8), vm.registers()[2], vm.stack(), vm.steps()), (Some(7), 1, &[7][..], 3));
+
+    assert!(vm.step_back());
+    assert_eq!((vm.memory(8), vm.registers()[2], vm.stack(), vm.steps()), (Some(3), 0, &[][..], 2));
+    assert_eq!(vm.instruction_ptr(), 6);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted(HaltCause::Instruction));
+    assert_eq!(vm.memory(8), Some(7));
+}
+
+#[test]
+fn syscalls_are_charged_fuel() {
+    let mut vm = vm_with("loop: data 22\n jmp loop");
+    bind_store(&mut vm);
+    vm.registers_mut()[1] = 100;
+    vm.set_fuel_cost(6, 2);
+    assert_eq!(vm.run_with_fuel(9).unwrap(), (HaltReason::OutOfFuel, 9));
+    assert_eq!((vm.steps(), vm.registers()[2]), (6, 3));
+
+    let mut vm = vm_with("loop: data 22 22 22 22\n jmp loop");
+    bind_store(&mut vm);
+    vm.registers_mut()[1] = 100;
+    assert_eq!(vm.run_with_fuel(3).unwrap(), (HaltReason::OutOfFuel, 3));
+    assert_eq!(vm.registers()[2], 3);
+}
+
+#[test]
+fn syscalls_count_as_executed_code() {
+    let mut vm = vm_with("set r1 100\n data 22\n halt");
+    bind_store(&mut vm);
+    vm.set_coverage(true);
+    vm.run().unwrap();
+    let coverage = vm.coverage().unwrap();
+    assert!([0, 3, 4].iter().all(|&address| coverage.is_executed(address)));
+}